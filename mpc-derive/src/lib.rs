@@ -0,0 +1,720 @@
+//! Derive macros for mpc
+//!
+//! Use them through the `derive` feature of the `mpc` crate, which re-exports them. Both read
+//! `#[mpc(...)]` attributes: `tag` for `MpcFromAst`, and `token`, `end` and `sep` for `MpcParse`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, GenericArgument, Ident, LitStr, PathArguments, Type};
+
+/// Implements `mpc::ast::MpcFromAst` for a struct or enum.
+///
+/// A struct takes the children of its node in field order, skipping untagged leaves. A field
+/// with `#[mpc(tag = "...")]` takes the next child with that tag instead, an `Option` field is
+/// None when there is no such child and a `Vec` field takes all that are left. A struct with
+/// `#[mpc(tag = "...")]` checks the tag of its node first. A struct with a single unnamed field
+/// is converted from the node itself.
+///
+/// An enum picks the variant whose tag the node has: the variant's `#[mpc(tag = "...")]`, or
+/// its name in snake case. Its fields are read as a struct's are.
+#[proc_macro_derive(MpcFromAst, attributes(mpc))]
+pub fn derive_from_ast(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match from_ast(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn from_ast(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let check = match tag_attr(&input.attrs)? {
+                Some(tag) => quote! {
+                    if !ast.tag_matches(#tag) {
+                        return Err(::mpc::ast::mpc_ast_error(ast, &format!("expected a {} node", #tag)));
+                    }
+                },
+                None => quote! {},
+            };
+            let build = build_fields(quote! { #name }, &data.fields)?;
+            quote! {
+                #check
+                Ok(#build)
+            }
+        }
+        Data::Enum(data) => {
+            let mut arms = Vec::new();
+            let mut tags = Vec::new();
+            for variant in &data.variants {
+                let ident = &variant.ident;
+                let tag = match tag_attr(&variant.attrs)? {
+                    Some(tag) => tag,
+                    None => snake_case(ident),
+                };
+                let build = build_fields(quote! { #name::#ident }, &variant.fields)?;
+                arms.push(quote! {
+                    if ast.tag_matches(#tag) {
+                        return Ok(#build);
+                    }
+                });
+                tags.push(tag);
+            }
+            let expected = format!("expected one of {}", tags.join(", "));
+            quote! {
+                #(#arms)*
+                Err(::mpc::ast::mpc_ast_error(ast, #expected))
+            }
+        }
+        Data::Union(_) => return Err(syn::Error::new_spanned(name, "MpcFromAst cannot be derived for unions")),
+    };
+    Ok(quote! {
+        impl #impl_generics ::mpc::ast::MpcFromAst for #name #ty_generics #where_clause {
+            fn from_ast(ast: &::mpc::ast::MpcAst) -> ::std::result::Result<Self, ::std::string::String> {
+                #body
+            }
+        }
+    })
+}
+
+/// Implements `mpc::tokens::MpcParse` for a struct or enum, for quick config and DSL formats.
+///
+/// A struct is its fields one after another, each parsed by its type's `MpcParse`, with
+/// whitespace allowed after every token. `#[mpc(token = "...")]` on a field matches a literal
+/// before it, and on the struct before everything; `#[mpc(end = "...")]` on the struct matches
+/// one after everything. A `Vec` field with `#[mpc(sep = ",")]` takes items separated by that
+/// literal.
+///
+/// An enum tries its variants in order. A unit variant is the keyword `#[mpc(token = "...")]`,
+/// or its name in snake case; other variants are read as structs. A type may contain itself,
+/// through `Box`, `Option` or `Vec`, as long as it doesn't start with itself; generic types
+/// can't derive it.
+///
+/// ```ignore
+/// #[derive(MpcParse)]
+/// #[mpc(token = "point", end = ";")]
+/// struct Point {
+///     #[mpc(token = "(")]
+///     x: i64,
+///     #[mpc(token = ",")]
+///     y: i64,
+///     #[mpc(token = ")")]
+///     label: Option<String>,
+/// }
+///
+/// let p = Point::mpc_parse_str("input", "point (1, -2) \"origin\";")?;
+/// ```
+#[proc_macro_derive(MpcParse, attributes(mpc))]
+pub fn derive_parse(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match parse(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn parse(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(&input.generics, "MpcParse cannot be derived for generic types"));
+    }
+    let attrs = mpc_attrs(&input.attrs)?;
+    let body = match &input.data {
+        Data::Struct(data) => sequence(name, quote! { #name }, &data.fields, &attrs)?,
+        Data::Enum(data) => {
+            let mut variants = Vec::new();
+            for variant in &data.variants {
+                let ident = &variant.ident;
+                let mut variant_attrs = mpc_attrs(&variant.attrs)?;
+                if matches!(variant.fields, Fields::Unit) && variant_attrs.token.is_none() {
+                    variant_attrs.token = Some(snake_case(ident));
+                }
+                variants.push(sequence(name, quote! { #name::#ident }, &variant.fields, &variant_attrs)?);
+            }
+            let choice = quote! { ::mpc::combinators::mpc_or(vec![#(#variants),*]) };
+            // The enum's own token and end go around whichever variant matches
+            let before: Vec<_> = attrs.token.as_deref().map(literal).into_iter().collect();
+            let after: Vec<_> = attrs.end.as_deref().map(literal).into_iter().collect();
+            if before.is_empty() && after.is_empty() {
+                choice
+            } else {
+                let skip = before.len();
+                quote! {{
+                    fn fold(_n: i32, xs: ::std::vec::Vec<::mpc::input::MpcVal>) -> ::mpc::input::MpcVal {
+                        xs.into_iter().nth(#skip).unwrap()
+                    }
+                    ::mpc::combinators::mpc_and(vec![#(#before,)* #choice #(, #after)*], fold)
+                }}
+            }
+        }
+        Data::Union(_) => return Err(syn::Error::new_spanned(name, "MpcParse cannot be derived for unions")),
+    };
+    Ok(quote! {
+        impl ::mpc::tokens::MpcParse for #name {
+            // Built through `mpc_lazy`, so a type containing itself, directly or through
+            // other derived types, refers back to its parser instead of building it forever
+            fn mpc_parser() -> ::mpc::parser::MpcParser {
+                fn build() -> ::mpc::parser::MpcParser {
+                    #body
+                }
+                ::mpc::primitives::mpc_lazy(build)
+            }
+        }
+    })
+}
+
+// The parser for literal `s` and the whitespace after it; a keyword has to end at a word boundary
+fn literal(s: &str) -> TokenStream2 {
+    if s.ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+        let expected = format!("{:?}", s);
+        quote! {
+            ::mpc::tokens::mpc_tok(::mpc::combinators::mpc_expect(::mpc::combinators::mpc_and(vec![::mpc::primitives::mpc_string(#s), ::mpc::tokens::mpc_boundary()], ::mpc::combinators::mpcf_fst), #expected))
+        }
+    } else {
+        quote! { ::mpc::tokens::mpc_sym(#s) }
+    }
+}
+
+// The parser for `path` built from `fields` between the item's literals, with a fold that
+// takes each field's `Result` out of the values and stops at the first error
+fn sequence(name: &Ident, path: TokenStream2, fields: &Fields, attrs: &MpcAttrs) -> syn::Result<TokenStream2> {
+    let mut parsers = Vec::new();
+    let mut takes = Vec::new();
+    let mut values = Vec::new();
+    if let Some(token) = &attrs.token {
+        parsers.push(literal(token));
+        takes.push(quote! { xs.next(); });
+    }
+    for (i, field) in fields.iter().enumerate() {
+        let field_attrs = mpc_attrs(&field.attrs)?;
+        if let Some(token) = &field_attrs.token {
+            parsers.push(literal(token));
+            takes.push(quote! { xs.next(); });
+        }
+        let ty = &field.ty;
+        parsers.push(match (&field_attrs.sep, vec_item(ty)) {
+            (Some(sep), Some(item)) => quote! { ::mpc::tokens::mpc_parse_sepby::<#item>(#sep) },
+            (Some(_), None) => return Err(syn::Error::new_spanned(ty, "`sep` is only for Vec fields")),
+            (None, _) => quote! { <#ty as ::mpc::tokens::MpcParse>::mpc_parser() },
+        });
+        let var = quote::format_ident!("field_{}", i);
+        takes.push(quote! {
+            let #var = match *xs.next().unwrap().downcast::<::std::result::Result<#ty, ::mpc::error::MpcErr>>().unwrap() {
+                Ok(value) => value,
+                Err(e) => return Box::new(::std::result::Result::<#name, ::mpc::error::MpcErr>::Err(e)),
+            };
+        });
+        values.push(match &field.ident {
+            Some(ident) => quote! { #ident: #var },
+            None => quote! { #var },
+        });
+    }
+    if let Some(end) = &attrs.end {
+        parsers.push(literal(end));
+    }
+    let value = match fields {
+        Fields::Named(_) => quote! { #path { #(#values),* } },
+        Fields::Unnamed(_) => quote! { #path(#(#values),*) },
+        Fields::Unit => quote! { #path },
+    };
+    Ok(quote! {{
+        #[allow(unused_mut, unused_variables)]
+        fn fold(_n: i32, xs: ::std::vec::Vec<::mpc::input::MpcVal>) -> ::mpc::input::MpcVal {
+            let mut xs = xs.into_iter();
+            #(#takes)*
+            Box::new(::std::result::Result::<#name, ::mpc::error::MpcErr>::Ok(#value))
+        }
+        ::mpc::combinators::mpc_and(vec![#(#parsers),*], fold)
+    }})
+}
+
+// The expression building `path` from `ast`, field by field
+fn build_fields(path: TokenStream2, fields: &Fields) -> syn::Result<TokenStream2> {
+    match fields {
+        Fields::Unit => Ok(path),
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 && tag_attr(&unnamed.unnamed[0].attrs)?.is_none() => {
+            let ty = &unnamed.unnamed[0].ty;
+            Ok(quote! { #path(<#ty as ::mpc::ast::MpcFromAst>::from_ast(ast)?) })
+        }
+        Fields::Unnamed(unnamed) => {
+            let mut values = Vec::new();
+            for (i, field) in unnamed.unnamed.iter().enumerate() {
+                values.push(field_value(&field.attrs, &field.ty, &i.to_string())?);
+            }
+            Ok(quote! {{
+                let mut fields = ::mpc::ast::MpcAstFields::new(ast);
+                #path(#(#values),*)
+            }})
+        }
+        Fields::Named(named) => {
+            let mut values = Vec::new();
+            for field in &named.named {
+                let ident = field.ident.as_ref().expect("named field");
+                let value = field_value(&field.attrs, &field.ty, &ident.to_string())?;
+                values.push(quote! { #ident: #value });
+            }
+            Ok(quote! {{
+                let mut fields = ::mpc::ast::MpcAstFields::new(ast);
+                #path { #(#values),* }
+            }})
+        }
+    }
+}
+
+// The expression taking one field from the `fields` cursor
+fn field_value(attrs: &[Attribute], ty: &Type, field: &str) -> syn::Result<TokenStream2> {
+    let tag = match tag_attr(attrs)? {
+        Some(tag) => quote! { Some(#tag) },
+        None => quote! { None },
+    };
+    Ok(match wrapper(ty) {
+        Some("Option") => quote! { fields.optional(#tag, #field)? },
+        Some("Vec") => quote! { fields.many(#tag, #field)? },
+        _ => quote! { fields.next::<#ty>(#tag, #field)? },
+    })
+}
+
+// The `T` of `Vec<T>`
+fn vec_item(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else { return None };
+    let last = path.path.segments.last()?;
+    if last.ident != "Vec" {
+        return None;
+    }
+    match &last.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// `Option` or `Vec` when `ty` is one of them
+fn wrapper(ty: &Type) -> Option<&'static str> {
+    let Type::Path(path) = ty else { return None };
+    let last = path.path.segments.last()?;
+    match last.ident.to_string().as_str() {
+        "Option" => Some("Option"),
+        "Vec" => Some("Vec"),
+        _ => None,
+    }
+}
+
+// The `#[mpc(...)]` attributes of an item, variant or field
+#[derive(Default)]
+struct MpcAttrs {
+    tag: Option<String>,  // Tag of the node, for MpcFromAst
+    token: Option<String>,  // Literal before the item or field, for MpcParse
+    end: Option<String>,  // Literal after the item, for MpcParse
+    sep: Option<String>,  // Separator between the items of a Vec field, for MpcParse
+}
+
+fn mpc_attrs(attrs: &[Attribute]) -> syn::Result<MpcAttrs> {
+    let mut found = MpcAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("mpc")) {
+        attr.parse_nested_meta(|meta| {
+            let slot = if meta.path.is_ident("tag") {
+                &mut found.tag
+            } else if meta.path.is_ident("token") {
+                &mut found.token
+            } else if meta.path.is_ident("end") {
+                &mut found.end
+            } else if meta.path.is_ident("sep") {
+                &mut found.sep
+            } else {
+                return Err(meta.error("unknown mpc attribute, expected `tag`, `token`, `end` or `sep`"));
+            };
+            if slot.is_some() {
+                return Err(meta.error("duplicate mpc attribute"));
+            }
+            *slot = Some(meta.value()?.parse::<LitStr>()?.value());
+            Ok(())
+        })?;
+    }
+    Ok(found)
+}
+
+// The value of `#[mpc(tag = "...")]` among `attrs`
+fn tag_attr(attrs: &[Attribute]) -> syn::Result<Option<String>> {
+    Ok(mpc_attrs(attrs)?.tag)
+}
+
+// `BinaryOp` as `binary_op`
+fn snake_case(ident: &Ident) -> String {
+    let mut out = String::new();
+    for (i, c) in ident.to_string().chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Builds an `mpc::grammar::MpcGrammar` from PEG text at compile time. The notation and the parsers
+/// are those of `mpc::grammar::mpc_peg`, but the text is read while compiling: a syntax error or a
+/// reference to a rule that isn't defined is a compile error, and the expansion defines the
+/// rules directly, with nothing left to parse at run time.
+///
+/// ```ignore
+/// let grammar = mpc_peg!(r#"
+///     list  <- '[' value (',' value)* ']'
+///     value <- [0-9]+ / list
+/// "#);
+/// ```
+#[proc_macro]
+pub fn mpc_peg(input: TokenStream) -> TokenStream {
+    let text = parse_macro_input!(input as LitStr);
+    let rules = match PegReader::new(&text.value()).grammar() {
+        Ok(rules) => rules,
+        Err(message) => return syn::Error::new(text.span(), message).to_compile_error().into(),
+    };
+    let mut defined = std::collections::HashSet::new();
+    for (name, _) in &rules {
+        defined.insert(name.as_str());
+    }
+    let mut refs = Vec::new();
+    for (_, definition) in &rules {
+        definition.rule_refs(&mut refs);
+    }
+    if let Some(missing) = refs.iter().find(|name| !defined.contains(name.as_str())) {
+        let message = format!("rule {} is used but not defined", missing);
+        return syn::Error::new(text.span(), message).to_compile_error().into();
+    }
+    let defines = rules.iter().map(|(name, definition)| {
+        let parser = definition.to_tokens();
+        quote! { grammar.define(#name, #parser); }
+    });
+    quote! {{
+        let mut grammar = ::mpc::grammar::MpcGrammar::new();
+        #(#defines)*
+        grammar
+    }}
+    .into()
+}
+
+// A PEG expression as read by `mpc_peg!`
+enum Peg {
+    Rule(String),
+    Literal(String),
+    Class(Vec<(char, bool)>),  // The characters as unescaped, each with whether it was escaped
+    Any,
+    Sequence(Vec<Peg>),
+    Choice(Vec<Peg>),
+    Lookahead(Box<Peg>),
+    Not(Box<Peg>),
+    Optional(Box<Peg>),
+    Many(Box<Peg>),
+    Many1(Box<Peg>),
+}
+
+impl Peg {
+    fn rule_refs(&self, refs: &mut Vec<String>) {
+        match self {
+            Peg::Rule(name) => refs.push(name.clone()),
+            Peg::Sequence(items) | Peg::Choice(items) => items.iter().for_each(|item| item.rule_refs(refs)),
+            Peg::Lookahead(p) | Peg::Not(p) | Peg::Optional(p) | Peg::Many(p) | Peg::Many1(p) => p.rule_refs(refs),
+            Peg::Literal(_) | Peg::Class(_) | Peg::Any => {}
+        }
+    }
+
+    // The parser `mpc_peg` builds for this expression
+    fn to_tokens(&self) -> TokenStream2 {
+        match self {
+            Peg::Rule(name) => quote! { ::mpc::primitives::mpc_rule(#name) },
+            Peg::Literal(text) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => quote! { ::mpc::primitives::mpc_char(#c) },
+                    _ => quote! { ::mpc::primitives::mpc_string(#text) },
+                }
+            }
+            Peg::Class(chars) => {
+                let mut singles = String::new();
+                let mut parsers = Vec::new();
+                let mut i = 0;
+                while i < chars.len() {
+                    if i + 2 < chars.len() && chars[i + 1] == ('-', false) {
+                        let (start, end) = (chars[i].0, chars[i + 2].0);
+                        parsers.push(quote! { ::mpc::primitives::mpc_range(#start, #end) });
+                        i += 3;
+                    } else {
+                        singles.push(chars[i].0);
+                        i += 1;
+                    }
+                }
+                match singles.chars().count() {
+                    0 => {}
+                    1 => {
+                        let c = singles.chars().next().unwrap();
+                        parsers.push(quote! { ::mpc::primitives::mpc_char(#c) });
+                    }
+                    _ => parsers.push(quote! { ::mpc::primitives::mpc_oneof(#singles) }),
+                }
+                match parsers.len() {
+                    0 => quote! { ::mpc::primitives::mpc_fail("empty character class") },
+                    1 => parsers.remove(0),
+                    _ => quote! { ::mpc::combinators::mpc_or(vec![#(#parsers),*]) },
+                }
+            }
+            Peg::Any => quote! { ::mpc::primitives::mpc_any() },
+            Peg::Sequence(items) if items.len() == 1 => items[0].to_tokens(),
+            Peg::Sequence(items) => {
+                let items = items.iter().map(Peg::to_tokens);
+                quote! { ::mpc::combinators::mpc_and_assoc(vec![#(#items),*], ::mpc::combinators::MpcAssocFold::Str) }
+            }
+            Peg::Choice(items) if items.len() == 1 => items[0].to_tokens(),
+            Peg::Choice(items) => {
+                let items = items.iter().map(Peg::to_tokens);
+                quote! { ::mpc::combinators::mpc_or(vec![#(#items),*]) }
+            }
+            Peg::Lookahead(p) => {
+                let p = p.to_tokens();
+                quote! { ::mpc::combinators::mpc_and(vec![::mpc::combinators::mpc_lookahead(#p)], ::mpc::combinators::mpcf_null) }
+            }
+            Peg::Not(p) => {
+                let p = p.to_tokens();
+                quote! { ::mpc::combinators::mpc_not(#p) }
+            }
+            Peg::Optional(p) => {
+                let p = p.to_tokens();
+                quote! { ::mpc::combinators::mpc_or(vec![#p, ::mpc::primitives::mpc_pass()]) }
+            }
+            Peg::Many(p) => {
+                let p = p.to_tokens();
+                quote! { ::mpc::combinators::mpc_many(#p, ::mpc::combinators::mpcf_strfold) }
+            }
+            Peg::Many1(p) => {
+                let p = p.to_tokens();
+                quote! { ::mpc::combinators::mpc_many1(#p, ::mpc::combinators::mpcf_strfold) }
+            }
+        }
+    }
+}
+
+// Recursive descent over PEG text, following the grammar `mpc_peg` reads it with. The crate
+// can't call `mpc_peg` itself, so tests/peg_macro.rs checks the two build the same grammar.
+struct PegReader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl PegReader {
+    fn new(text: &str) -> Self {
+        PegReader { chars: text.chars().collect(), pos: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    // The current position, as `line:column`
+    fn position(&self) -> String {
+        let before = &self.chars[..self.pos];
+        let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        format!("{}:{}", line, column)
+    }
+
+    // An error at the current position, saying what was found there
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        let found = match self.peek() {
+            Some(c) => format!("{:?}", c),
+            None => "end of input".to_string(),
+        };
+        Err(format!("{}: {}, found {}", self.position(), message, found))
+    }
+
+    fn spacing(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '#' {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+            } else if " \t\r\n".contains(c) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    // Consumes `s` and the spacing after it, if it comes next
+    fn eat(&mut self, s: &str) -> bool {
+        let matched = s.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c));
+        if matched {
+            self.pos += s.chars().count();
+            self.spacing();
+        }
+        matched
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        let start = self.pos;
+        if !self.peek().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') {
+            return None;
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        let name = self.chars[start..self.pos].iter().collect();
+        self.spacing();
+        Some(name)
+    }
+
+    // Whether an identifier followed by `<-`, the start of the next definition, comes next
+    fn at_definition(&mut self) -> bool {
+        let start = self.pos;
+        let found = self.identifier().is_some() && self.eat("<-");
+        self.pos = start;
+        found
+    }
+
+    fn grammar(&mut self) -> Result<Vec<(String, Peg)>, String> {
+        self.spacing();
+        let mut rules = Vec::new();
+        loop {
+            let Some(name) = self.identifier() else {
+                return self.error("expected identifier");
+            };
+            if !self.eat("<-") {
+                return self.error("expected \"<-\"");
+            }
+            let definition = self.expression()?;
+            rules.push((name, definition));
+            if self.peek().is_none() {
+                return Ok(rules);
+            }
+        }
+    }
+
+    fn expression(&mut self) -> Result<Peg, String> {
+        let mut choices = vec![self.sequence()?];
+        while self.eat("/") {
+            choices.push(self.sequence()?);
+        }
+        Ok(Peg::Choice(choices))
+    }
+
+    fn sequence(&mut self) -> Result<Peg, String> {
+        let mut items = Vec::new();
+        while self.peek().is_some_and(|c| "&!('\"[.".contains(c) || c.is_ascii_alphabetic() || c == '_') && !self.at_definition() {
+            items.push(self.prefix()?);
+        }
+        Ok(Peg::Sequence(items))
+    }
+
+    fn prefix(&mut self) -> Result<Peg, String> {
+        if self.eat("&") {
+            Ok(Peg::Lookahead(Box::new(self.suffix()?)))
+        } else if self.eat("!") {
+            Ok(Peg::Not(Box::new(self.suffix()?)))
+        } else {
+            self.suffix()
+        }
+    }
+
+    fn suffix(&mut self) -> Result<Peg, String> {
+        let primary = self.primary()?;
+        Ok(if self.eat("?") {
+            Peg::Optional(Box::new(primary))
+        } else if self.eat("*") {
+            Peg::Many(Box::new(primary))
+        } else if self.eat("+") {
+            Peg::Many1(Box::new(primary))
+        } else {
+            primary
+        })
+    }
+
+    fn primary(&mut self) -> Result<Peg, String> {
+        if let Some(name) = self.identifier() {
+            return Ok(Peg::Rule(name));
+        }
+        if self.eat("(") {
+            let expression = self.expression()?;
+            if !self.eat(")") {
+                return self.error("expected \")\"");
+            }
+            return Ok(Peg::Sequence(vec![expression]));
+        }
+        if self.eat(".") {
+            return Ok(Peg::Any);
+        }
+        match self.peek() {
+            Some(quote @ ('\'' | '"')) => {
+                let raw = self.raw(quote)?;
+                Ok(Peg::Literal(peg_unescape(&raw).into_iter().map(|(c, _)| c).collect()))
+            }
+            Some('[') => {
+                let raw = self.raw(']')?;
+                Ok(Peg::Class(peg_unescape(&raw)))
+            }
+            _ => self.error("expected expression"),
+        }
+    }
+
+    // The text between the delimiter at the current position and `end`, escapes as written
+    fn raw(&mut self, end: char) -> Result<String, String> {
+        let open = self.pos;
+        self.pos += 1;
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some('\\') if self.pos + 1 < self.chars.len() => self.pos += 2,
+                Some(c) if c == end => break,
+                Some(_) => self.pos += 1,
+                None => {
+                    self.pos = open;
+                    return Err(format!("{}: unterminated, expected a closing {:?}", self.position(), end));
+                }
+            }
+        }
+        let raw = self.chars[start..self.pos].iter().collect();
+        self.pos += 1;
+        self.spacing();
+        Ok(raw)
+    }
+}
+
+// Decodes the escapes of PEG literals and classes as `mpc_peg` does: \n \r \t \' \" \[ \] \\
+// and octal \ooo, each character with whether it was escaped
+fn peg_unescape(raw: &str) -> Vec<(char, bool)> {
+    let mut chars = Vec::new();
+    let mut rest = raw.chars().peekable();
+    while let Some(c) = rest.next() {
+        if c != '\\' {
+            chars.push((c, false));
+            continue;
+        }
+        match rest.next() {
+            Some('n') => chars.push(('\n', true)),
+            Some('r') => chars.push(('\r', true)),
+            Some('t') => chars.push(('\t', true)),
+            Some(d @ '0'..='7') => {
+                let mut code = d.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match rest.peek().and_then(|d| d.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            rest.next();
+                        }
+                        None => break,
+                    }
+                }
+                chars.extend(char::from_u32(code).map(|c| (c, true)));
+            }
+            Some(other) => chars.push((other, true)),
+            None => chars.push(('\\', false)),
+        }
+    }
+    chars
+}
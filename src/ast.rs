@@ -0,0 +1,1626 @@
+//! Abstract syntax trees: building them while parsing, walking, comparing and storing them,
+//! and converting them into typed Rust data
+
+use crate::items::*;
+
+/// What a visitor wants done after entering a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpcVisit {
+    Continue,
+    SkipChildren,  // Go on with the node's next sibling, still calling `exit` on the node
+    Stop,  // End the walk without calling any more hooks
+}
+
+/// Hooks for `MpcAst::walk`. Both default to doing nothing, so a visitor only writes the ones it
+/// needs; `depth` is 0 at the node the walk started from.
+pub trait MpcAstVisitor {
+    fn enter(&mut self, _ast: &MpcAst, _depth: usize) -> MpcVisit {
+        MpcVisit::Continue
+    }
+
+    /// Called after the node's children; returning false stops the walk
+    fn exit(&mut self, _ast: &MpcAst, _depth: usize) -> bool {
+        true
+    }
+}
+
+/// Hooks for `MpcAst::walk_mut`, which may change the nodes they are given
+pub trait MpcAstVisitorMut {
+    fn enter(&mut self, _ast: &mut MpcAst, _depth: usize) -> MpcVisit {
+        MpcVisit::Continue
+    }
+
+    /// Called after the node's children; returning false stops the walk
+    fn exit(&mut self, _ast: &mut MpcAst, _depth: usize) -> bool {
+        true
+    }
+}
+
+// `s` in double quotes, with quotes, backslashes and control characters escaped as in Rust
+fn mpc_quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// `s` as a JSON string literal
+fn mpc_json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Depth-first iterator over a tree, see `MpcAst::iter`
+pub struct MpcAstIter<'a> {
+    stack: Vec<(usize, &'a MpcAst)>,
+}
+
+impl<'a> Iterator for MpcAstIter<'a> {
+    type Item = (usize, &'a MpcAst);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (depth, ast) = self.stack.pop()?;
+        self.stack.extend(ast.children.iter().rev().map(|child| (depth + 1, &**child)));
+        Some((depth, ast))
+    }
+}
+
+/// Breadth-first iterator over a tree, see `MpcAst::iter_bfs`
+pub struct MpcAstBfsIter<'a> {
+    queue: std::collections::VecDeque<(usize, &'a MpcAst)>,
+}
+
+impl<'a> Iterator for MpcAstBfsIter<'a> {
+    type Item = (usize, &'a MpcAst);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (depth, ast) = self.queue.pop_front()?;
+        self.queue.extend(ast.children.iter().map(|child| (depth + 1, &**child)));
+        Some((depth, ast))
+    }
+}
+
+/// AST Type. Each node owns its tag and contents, so a tag like `expr|term|number` is repeated in
+/// every node carrying it; `MpcAstArena` stores each tag once, for large trees kept after parsing.
+/// With the `serde` feature a tree is written and read a level of the call stack per level of
+/// the tree, as serde does, so very deep trees are better kept as an `MpcAstArena`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MpcAst {
+    pub tag: String,
+    pub contents: String,
+    pub state: MpcState,  // Where the node starts in the input, as in C
+    pub span: MpcSpan,  // Input the node covers, from `state` to where it ends
+    pub children_num: i32,
+    pub children: Vec<Box<MpcAst>>,
+    pub leading_trivia: String,  // Whitespace and comments kept by mpca_trivia just before the node
+    pub trailing_trivia: String,  // ... and just after it
+}
+
+impl MpcAst {
+    pub fn new(tag: &str, contents: &str) -> Self {
+        MpcAst {
+            tag: tag.to_string(),
+            contents: contents.to_string(),
+            state: MpcState::default(),
+            span: MpcSpan::default(),
+            children_num: 0,
+            children: Vec::new(),
+            leading_trivia: String::new(),
+            trailing_trivia: String::new(),
+        }
+    }
+
+    pub fn print(&self) {
+        print!("{}", self);
+    }
+
+    /// Writes what `print` shows to `w`, like `mpc_ast_print_to` in C
+    pub fn print_to<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        write!(w, "{}", self)
+    }
+
+    /// Prefixes the tag with `segment` and a `|`, like `mpc_ast_add_tag` in C. An empty tag just
+    /// becomes `segment`.
+    pub fn add_tag(&mut self, segment: &str) {
+        self.tag = if self.tag.is_empty() { segment.to_string() } else { format!("{}|{}", segment, self.tag) };
+    }
+
+    /// Adds `segment` to the end of the tag, after a `|`
+    pub fn append_tag(&mut self, segment: &str) {
+        if !self.tag.is_empty() {
+            self.tag.push('|');
+        }
+        self.tag.push_str(segment);
+    }
+
+    /// The `|`-separated parts of the tag, outermost first
+    pub fn tag_segments(&self) -> impl Iterator<Item = &str> {
+        self.tag.split('|').filter(|segment| !segment.is_empty())
+    }
+
+    /// Whether one of the tag's segments is `segment`, e.g. `number` in `expr|number`
+    pub fn has_tag(&self, segment: &str) -> bool {
+        self.tag_segments().any(|s| s == segment)
+    }
+
+    /// Whether the segments of `pattern` appear together, in order, among those of the tag.
+    /// This is what C code means by `strstr(a->tag, "number")`, but anchored at segment
+    /// boundaries: `number` and `term|number` match `expr|term|number|regex`, while `num` and
+    /// `expr|number` don't.
+    pub fn tag_matches(&self, pattern: &str) -> bool {
+        let segments: Vec<&str> = self.tag_segments().collect();
+        let pattern: Vec<&str> = pattern.split('|').filter(|segment| !segment.is_empty()).collect();
+        !pattern.is_empty() && segments.windows(pattern.len()).any(|window| window == pattern.as_slice())
+    }
+
+    /// Byte offsets of the input the node covers
+    pub fn byte_range(&self) -> std::ops::Range<usize> {
+        self.span.start.pos as usize..self.span.end.pos as usize
+    }
+
+    /// The exact text of `source`, the string that was parsed, that the node covers. Unlike
+    /// `contents`, this is kept for nodes whose text was spread over their children.
+    pub fn source_text<'a>(&self, source: &'a str) -> &'a str {
+        self.span.slice(source)
+    }
+
+    /// Equality on everything but the `state` and `span` of every node: tags, contents, trivia
+    /// and shape. `mpc_ast_eq` is the same comparison under its C name.
+    pub fn eq_ignore_position(&self, other: &MpcAst) -> bool {
+        self.eq_by(other, |_, _| true)
+    }
+
+    // Whether the trees have the same shape, tags, contents and trivia, and `same` holds for
+    // every pair of nodes at the same place
+    fn eq_by(&self, other: &MpcAst, same: impl Fn(&MpcAst, &MpcAst) -> bool) -> bool {
+        let mut pairs = vec![(self, other)];
+        while let Some((a, b)) = pairs.pop() {
+            let equal = a.tag == b.tag
+                && a.contents == b.contents
+                && a.leading_trivia == b.leading_trivia
+                && a.trailing_trivia == b.trailing_trivia
+                && a.children_num == b.children_num
+                && a.children.len() == b.children.len()
+                && same(a, b);
+            if !equal {
+                return false;
+            }
+            pairs.extend(a.children.iter().zip(&b.children).map(|(x, y)| (&**x, &**y)));
+        }
+        true
+    }
+
+    // A copy of the node without its children
+    fn bare(&self) -> MpcAst {
+        MpcAst {
+            tag: self.tag.clone(),
+            contents: self.contents.clone(),
+            state: self.state,
+            span: self.span,
+            children_num: self.children_num,
+            children: Vec::with_capacity(self.children.len()),
+            leading_trivia: self.leading_trivia.clone(),
+            trailing_trivia: self.trailing_trivia.clone(),
+        }
+    }
+
+    /// Index of the first child with `tag` among its tag's segments, like `mpc_ast_get_index`
+    pub fn get_index(&self, tag: &str) -> Option<usize> {
+        self.get_index_lb(tag, 0)
+    }
+
+    /// Like `get_index`, starting the search at child `lb`, like `mpc_ast_get_index_lb`
+    pub fn get_index_lb(&self, tag: &str, lb: usize) -> Option<usize> {
+        self.children.iter().skip(lb).position(|child| child.has_tag(tag)).map(|i| i + lb)
+    }
+
+    /// First child with `tag` among its tag's segments, like `mpc_ast_get_child`
+    pub fn get_child(&self, tag: &str) -> Option<&MpcAst> {
+        self.get_child_lb(tag, 0)
+    }
+
+    /// Like `get_child`, starting the search at child `lb`, like `mpc_ast_get_child_lb`
+    pub fn get_child_lb(&self, tag: &str, lb: usize) -> Option<&MpcAst> {
+        self.get_index_lb(tag, lb).map(|i| &*self.children[i])
+    }
+
+    /// Children whose tag matches `tag`, see `tag_matches`
+    pub fn children_with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a MpcAst> + 'a {
+        self.children.iter().map(|child| &**child).filter(move |child| child.tag_matches(tag))
+    }
+
+    /// Follows a `|`-separated path down the tree: a number picks the child at that index, and
+    /// anything else the first child with that tag segment, so `expr|term|0` is the first child
+    /// of the first `term` under the first `expr`. None if any step is missing; an empty path
+    /// is the node itself.
+    pub fn get(&self, path: &str) -> Option<&MpcAst> {
+        self.try_get(path).ok()
+    }
+
+    /// Like `get`, saying which step of the path is missing when it fails
+    pub fn try_get(&self, path: &str) -> Result<&MpcAst, MpcAstPathErr> {
+        let mut ast = self;
+        for (i, step) in path.split('|').enumerate().filter(|(_, step)| !step.is_empty()) {
+            let next = match step.parse::<usize>() {
+                Ok(index) => ast.children.get(index).map(|child| &**child),
+                Err(_) => ast.get_child(step),
+            };
+            ast = next.ok_or_else(|| MpcAstPathErr {
+                path: path.to_string(),
+                step: i,
+                segment: step.to_string(),
+                tag: ast.tag.clone(),
+            })?;
+        }
+        Ok(ast)
+    }
+
+    /// The node and all its descendants depth first, parents before children, each with its
+    /// depth below this node
+    pub fn iter(&self) -> MpcAstIter<'_> {
+        MpcAstIter { stack: vec![(0, self)] }
+    }
+
+    /// The node and all its descendants breadth first, a level at a time, each with its depth
+    pub fn iter_bfs(&self) -> MpcAstBfsIter<'_> {
+        MpcAstBfsIter { queue: std::collections::VecDeque::from([(0, self)]) }
+    }
+
+    /// Descendants, not counting this node, whose tag matches `tag` (see `tag_matches`), in
+    /// depth first order
+    pub fn descendants_with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = (usize, &'a MpcAst)> + 'a {
+        self.iter().skip(1).filter(move |(_, ast)| ast.tag_matches(tag))
+    }
+
+    /// The tree cleaned up as `options` asks, working up from the leaves: dropped nodes go first,
+    /// then neighbouring leaves are merged, and a node left with one child is hoisted last.
+    /// The root itself is never dropped.
+    pub fn simplify(mut self, options: &MpcSimplify) -> MpcAst {
+        // Nodes whose children are being simplified, each with the children still to go
+        let children = std::mem::take(&mut self.children).into_iter();
+        let mut stack = vec![(self, children)];
+        loop {
+            let (_, pending) = stack.last_mut().unwrap();
+            if let Some(mut child) = pending.next() {
+                if !options.drop_tags.iter().any(|tag| child.tag_matches(tag)) {
+                    let children = std::mem::take(&mut child.children).into_iter();
+                    stack.push((*child, children));
+                }
+                continue;
+            }
+            let (ast, _) = stack.pop().unwrap();
+            let ast = ast.hoisted(options);
+            match stack.last_mut() {
+                Some((parent, _)) => parent.push_simplified(ast, options),
+                None => return ast,
+            }
+        }
+    }
+
+    // Adds a simplified child, merged into the last one if both are plain leaves
+    fn push_simplified(&mut self, child: MpcAst, options: &MpcSimplify) {
+        match self.children.last_mut() {
+            Some(last) if options.merge_leaves && last.tag.is_empty() && child.tag.is_empty() && last.children.is_empty() && child.children.is_empty()
+                && last.trailing_trivia.is_empty() && child.leading_trivia.is_empty() => {
+                last.contents.push_str(&child.contents);
+                if last.span == MpcSpan::default() {
+                    last.state = child.state;
+                    last.span = child.span;
+                } else if child.span != MpcSpan::default() {
+                    last.span = last.span.merge(&child.span);
+                }
+            }
+            _ => self.children.push(Box::new(child)),
+        }
+    }
+
+    // The node once its children are simplified: its single child in its place, if asked for
+    fn hoisted(mut self, options: &MpcSimplify) -> MpcAst {
+        self.children_num = self.children.len() as i32;
+        if options.hoist_single && self.children.len() == 1 && self.contents.is_empty() {
+            let mut child = *self.children.pop().unwrap();
+            if !self.tag.is_empty() && self.tag != ">" {
+                child.add_tag(&self.tag);
+            }
+            child.leading_trivia.insert_str(0, &self.leading_trivia);
+            child.trailing_trivia.push_str(&self.trailing_trivia);
+            return child;
+        }
+        self
+    }
+
+    /// The text the tree was parsed from, put back together from the contents of its nodes and
+    /// the trivia around them. With every skipped part of the input kept by `mpca_trivia`, this
+    /// is the original input exactly, layout and comments included.
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+        self.source_pieces(&mut |text, _| out.push_str(text));
+        out
+    }
+
+    /// The text of the tree in a normalized layout: the contents of its nodes in order with
+    /// `separator` between them and the trivia left out, so a transformed tree prints the same
+    /// way however the input was spaced. Use `" "` for a one-line form of most languages.
+    pub fn to_source_normalized(&self, separator: &str) -> String {
+        let mut out = String::new();
+        self.source_pieces(&mut |text, trivia| {
+            if trivia || text.is_empty() {
+                return;
+            }
+            if !out.is_empty() {
+                out.push_str(separator);
+            }
+            out.push_str(text);
+        });
+        out
+    }
+
+    // Hands the trivia and contents of the tree to `f` in input order, with whether each is trivia
+    fn source_pieces(&self, f: &mut impl FnMut(&str, bool)) {
+        // Nodes being written, each with where its contents go and the index of its next child
+        let mut stack = Vec::new();
+        let mut entering = Some(self);
+        loop {
+            if let Some(ast) = entering.take() {
+                f(&ast.leading_trivia, true);
+                stack.push((ast, ast.contents_index(), 0));
+            }
+            let Some(&(ast, at, i)) = stack.last() else {
+                return;
+            };
+            if i == at {
+                f(&ast.contents, false);
+            }
+            match ast.children.get(i) {
+                Some(child) => {
+                    stack.last_mut().unwrap().2 += 1;
+                    entering = Some(child);
+                }
+                None => {
+                    f(&ast.trailing_trivia, true);
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    // Where the node's own text goes among its children, going by their spans: before them when
+    // the node starts first, as a prefix operator does, in the first gap between them, as an
+    // infix operator does, and otherwise after them. Without spans it goes first.
+    fn contents_index(&self) -> usize {
+        let positioned = self.span != MpcSpan::default() && self.children.iter().all(|child| child.span != MpcSpan::default());
+        let Some(first) = self.children.first().filter(|_| positioned && !self.contents.is_empty()) else {
+            return 0;
+        };
+        if self.span.start.pos < first.span.start.pos {
+            return 0;
+        }
+        self.children.windows(2)
+            .position(|pair| pair[0].span.end.pos < pair[1].span.start.pos)
+            .map_or(self.children.len(), |i| i + 1)
+    }
+
+    /// Ids, parent links and child indices for every node of the tree, see `MpcAstIndex`
+    pub fn index(&self) -> MpcAstIndex<'_> {
+        MpcAstIndex::new(self)
+    }
+
+    /// Walks the tree depth first, calling the visitor's `enter` before a node's children and
+    /// `exit` after them
+    pub fn walk<V: MpcAstVisitor + ?Sized>(&self, visitor: &mut V) {
+        // Nodes being visited, each with the index of its next child
+        let mut stack = Vec::new();
+        let mut entering = Some(self);
+        loop {
+            if let Some(ast) = entering.take() {
+                match visitor.enter(ast, stack.len()) {
+                    MpcVisit::Stop => return,
+                    MpcVisit::SkipChildren => stack.push((ast, ast.children.len())),
+                    MpcVisit::Continue => stack.push((ast, 0)),
+                }
+            }
+            let Some(&(ast, i)) = stack.last() else {
+                return;
+            };
+            match ast.children.get(i) {
+                Some(child) => {
+                    stack.last_mut().unwrap().1 += 1;
+                    entering = Some(child);
+                }
+                None => {
+                    stack.pop();
+                    if !visitor.exit(ast, stack.len()) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Like `walk`, letting the visitor change nodes, including replacing their children from
+    /// `enter` before they are visited. `children_num` is brought up to date after each node.
+    pub fn walk_mut<V: MpcAstVisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        // The children of the nodes being visited are taken out of them, each run of them with
+        // the index of the one being visited, and put back once their parent is done with
+        let mut stack: Vec<(Vec<Box<MpcAst>>, usize)> = Vec::new();
+        let mut entering = true;
+        let stopped = loop {
+            if entering {
+                entering = false;
+                let depth = stack.len();
+                let ast = mpc_ast_walked(self, &mut stack, depth);
+                let children = match visitor.enter(ast, depth) {
+                    MpcVisit::Stop => {
+                        ast.children_num = ast.children.len() as i32;
+                        break true;
+                    }
+                    MpcVisit::SkipChildren => Vec::new(),
+                    MpcVisit::Continue => std::mem::take(&mut ast.children),
+                };
+                stack.push((children, 0));
+            }
+            let (children, i) = stack.last().unwrap();
+            if *i < children.len() {
+                entering = true;
+                continue;
+            }
+            let (children, _) = stack.pop().unwrap();
+            let depth = stack.len();
+            let ast = mpc_ast_walked(self, &mut stack, depth);
+            if !children.is_empty() {
+                ast.children = children;
+            }
+            ast.children_num = ast.children.len() as i32;
+            if !visitor.exit(ast, depth) {
+                break true;
+            }
+            match stack.last_mut() {
+                Some((_, i)) => *i += 1,
+                None => break false,
+            }
+        };
+        while stopped && !stack.is_empty() {
+            let (children, _) = stack.pop().unwrap();
+            let depth = stack.len();
+            let ast = mpc_ast_walked(self, &mut stack, depth);
+            if !children.is_empty() {
+                ast.children = children;
+            }
+            ast.children_num = ast.children.len() as i32;
+        }
+    }
+
+    fn fmt_depth(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (depth, ast) in self.iter() {
+            let indent = "  ".repeat(depth);
+            writeln!(f, "{}{}", indent, ast.tag)?;
+            if !ast.contents.is_empty() {
+                writeln!(f, "{}  \"{}\"", indent, ast.contents)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The tree as compact JSON, for tools outside Rust. Every node is an object with the keys
+    /// `tag`, `contents`, `start`, `end` and `children`, always in that order; `start` and `end`
+    /// are objects with the zero-based `pos` (in bytes), `row` and `col` of the node's span.
+    pub fn to_json(&self) -> String {
+        let mut out = Vec::new();
+        // Writing to a Vec can't fail
+        self.write_json(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Writes `to_json` to `w` as it goes, without building the whole text first
+    pub fn write_json<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        fn state(s: &MpcState) -> String {
+            format!("{{\"pos\":{},\"row\":{},\"col\":{}}}", s.pos, s.row, s.col)
+        }
+        // Nodes being written, each with the index of its next child
+        let mut stack = Vec::new();
+        let mut entering = Some(self);
+        loop {
+            if let Some(ast) = entering.take() {
+                write!(w, "{{\"tag\":{},\"contents\":{},\"start\":{},\"end\":{},\"children\":[", mpc_json_string(&ast.tag), mpc_json_string(&ast.contents), state(&ast.span.start), state(&ast.span.end))?;
+                stack.push((ast, 0));
+            }
+            let Some(&(ast, i)) = stack.last() else {
+                return Ok(());
+            };
+            match ast.children.get(i) {
+                Some(child) => {
+                    if i > 0 {
+                        w.write_all(b",")?;
+                    }
+                    stack.last_mut().unwrap().1 += 1;
+                    entering = Some(child);
+                }
+                None => {
+                    w.write_all(b"]}")?;
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    /// The tree as a one-line S-expression, e.g. `(expr (term "3") "+" (term "4"))`: a tagged
+    /// node is a list of its tag, its quoted contents if any, and its children, while an
+    /// untagged leaf is just its quoted contents
+    pub fn to_sexpr(&self) -> String {
+        let mut out = String::new();
+        self.sexpr_into(&mut out);
+        out
+    }
+
+    fn sexpr_into(&self, out: &mut String) {
+        // Lists being written, each with the index of the node's next child
+        let mut stack = Vec::new();
+        let mut entering = Some(self);
+        loop {
+            if let Some(ast) = entering.take() {
+                if ast.tag.is_empty() && ast.children.is_empty() {
+                    out.push_str(&mpc_quote(&ast.contents));
+                } else {
+                    out.push('(');
+                    out.push_str(if ast.tag.is_empty() { ">" } else { &ast.tag });
+                    if !ast.contents.is_empty() {
+                        out.push(' ');
+                        out.push_str(&mpc_quote(&ast.contents));
+                    }
+                    stack.push((ast, 0));
+                }
+            }
+            let Some(&(ast, i)) = stack.last() else {
+                return;
+            };
+            match ast.children.get(i) {
+                Some(child) => {
+                    out.push(' ');
+                    stack.last_mut().unwrap().1 += 1;
+                    entering = Some(child);
+                }
+                None => {
+                    out.push(')');
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    /// Stable text form of the tree for snapshot tests: one node per line, indented two spaces
+    /// per level, with the tag followed by the contents quoted and escaped, e.g.
+    /// `number "42"`. It depends only on tags, contents and shape, not on positions or `Debug`.
+    pub fn to_snapshot(&self) -> String {
+        let mut out = String::new();
+        self.snapshot_into(&mut out);
+        out
+    }
+
+    fn snapshot_into(&self, out: &mut String) {
+        for (depth, ast) in self.iter() {
+            out.push_str(&"  ".repeat(depth));
+            out.push_str(&ast.tag);
+            if !ast.contents.is_empty() {
+                out.push(' ');
+                out.push_str(&mpc_quote(&ast.contents));
+            }
+            out.push('\n');
+        }
+    }
+}
+
+/// Skipped input kept by `mpca_trivia`, on its way to being attached to an AST node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MpcTrivia {
+    pub text: String,
+    pub span: MpcSpan,
+}
+
+impl MpcTrivia {
+    // This trivia followed by `other`
+    pub(crate) fn join(self, other: &MpcTrivia) -> MpcTrivia {
+        MpcTrivia { text: self.text + &other.text, span: self.span.merge(&other.span) }
+    }
+}
+
+/// Which clean-ups `MpcAst::simplify` makes. All are off by default.
+#[derive(Debug, Clone, Default)]
+pub struct MpcSimplify {
+    pub drop_tags: Vec<String>,  // Remove nodes whose tag matches one of these, see `MpcAst::tag_matches`
+    pub merge_leaves: bool,  // Join neighbouring untagged leaves, such as the characters of a lexeme, into one
+    pub hoist_single: bool,  // Replace a node without contents by its only child, prefixing the child's tag
+}
+
+impl MpcSimplify {
+    pub fn new() -> Self {
+        MpcSimplify::default()
+    }
+
+    /// All clean-ups, dropping nodes tagged with any of `drop_tags`
+    pub fn all(drop_tags: &[&str]) -> Self {
+        MpcSimplify {
+            drop_tags: drop_tags.iter().map(|tag| tag.to_string()).collect(),
+            merge_leaves: true,
+            hoist_single: true,
+        }
+    }
+}
+
+/// The indented tree that `MpcAst::print` shows
+impl std::fmt::Display for MpcAst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_depth(f)
+    }
+}
+
+/// Frees the tree with a stack on the heap, so trees as deep as `mpc_parse_vm` can build don't
+/// overflow the call stack the way dropping each child in turn would. It means fields can't be
+/// moved out of a node; `std::mem::take` them instead, as CHANGELOG.md describes.
+impl Drop for MpcAst {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(mut child) = stack.pop() {
+            stack.append(&mut child.children);
+        }
+    }
+}
+
+// The node at `depth` of a `walk_mut`: the root, or the child being visited of the node above
+#[allow(clippy::vec_box)]  // The children as `MpcAst::children` holds them, taken out whole
+fn mpc_ast_walked<'a>(root: &'a mut MpcAst, stack: &'a mut [(Vec<Box<MpcAst>>, usize)], depth: usize) -> &'a mut MpcAst {
+    match depth.checked_sub(1) {
+        None => root,
+        Some(above) => {
+            let (children, i) = &mut stack[above];
+            &mut children[*i]
+        }
+    }
+}
+
+impl Clone for MpcAst {
+    fn clone(&self) -> Self {
+        // Nodes being copied, each with its copy so far
+        let mut stack = vec![(self, self.bare())];
+        loop {
+            let &mut (ast, ref copy) = stack.last_mut().unwrap();
+            match ast.children.get(copy.children.len()) {
+                Some(child) => stack.push((child, child.bare())),
+                None => {
+                    let (_, copy) = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some((_, parent)) => parent.children.push(Box::new(copy)),
+                        None => return copy,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Written as `#[derive(Debug)]` would write it, `{:#?}` included, but with a stack on the heap,
+/// so a failing `assert_eq!` can print a tree of any depth
+impl std::fmt::Debug for MpcAst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Nodes whose children are being written, each with how many have been
+        let mut stack = vec![(self, 0)];
+        self.fmt_head(f, 0)?;
+        while let Some(&(ast, written)) = stack.last() {
+            let depth = stack.len() - 1;
+            match ast.children.get(written) {
+                Some(child) => {
+                    match (f.alternate(), written) {
+                        (true, _) => write!(f, "\n{:1$}", "", 8 * depth + 8)?,
+                        (false, 0) => {}
+                        (false, _) => f.write_str(", ")?,
+                    }
+                    stack[depth].1 += 1;
+                    child.fmt_head(f, depth + 1)?;
+                    stack.push((child, 0));
+                }
+                None => {
+                    ast.fmt_tail(f, depth)?;
+                    stack.pop();
+                    if f.alternate() && !stack.is_empty() {
+                        f.write_str(",")?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MpcAst {
+    // The fields up to the opening of the children, for a node `depth` levels down
+    fn fmt_head(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        f.write_str("MpcAst {")?;
+        let fields: [(&str, &dyn std::fmt::Debug); 5] = [
+            ("tag", &self.tag),
+            ("contents", &self.contents),
+            ("state", &self.state),
+            ("span", &self.span),
+            ("children_num", &self.children_num),
+        ];
+        for (name, value) in fields {
+            mpc_ast_fmt_field(f, depth, name, value, false)?;
+        }
+        match f.alternate() {
+            true => write!(f, "\n{:1$}children: [", "", 8 * depth + 4),
+            false => f.write_str(" children: ["),
+        }
+    }
+
+    // The rest of the fields after the children
+    fn fmt_tail(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        match f.alternate() {
+            true if !self.children.is_empty() => write!(f, "\n{:1$}],", "", 8 * depth + 4)?,
+            true => f.write_str("],")?,
+            false => f.write_str("],")?,
+        }
+        mpc_ast_fmt_field(f, depth, "leading_trivia", &self.leading_trivia, false)?;
+        mpc_ast_fmt_field(f, depth, "trailing_trivia", &self.trailing_trivia, true)?;
+        match f.alternate() {
+            true => write!(f, "\n{:1$}}}", "", 8 * depth),
+            false => f.write_str(" }"),
+        }
+    }
+}
+
+// One field of a node `depth` levels down, followed by a comma unless it is the `last` on one line
+fn mpc_ast_fmt_field(f: &mut std::fmt::Formatter<'_>, depth: usize, name: &str, value: &dyn std::fmt::Debug, last: bool) -> std::fmt::Result {
+    if !f.alternate() {
+        return write!(f, " {}: {:?}{}", name, value, if last { "" } else { "," });
+    }
+    let indent = format!("\n{:1$}", "", 8 * depth + 4);
+    write!(f, "{}{}: {},", indent, name, format!("{:#?}", value).replace('\n', &indent))
+}
+
+impl PartialEq for MpcAst {
+    fn eq(&self, other: &MpcAst) -> bool {
+        self.eq_by(other, |a, b| a.state == b.state && a.span == b.span)
+    }
+}
+
+impl Eq for MpcAst {}
+
+impl std::hash::Hash for MpcAst {
+    fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+        // Each node's fields in depth-first order, with its number of children for the shape
+        for (_, ast) in self.iter() {
+            ast.tag.hash(hasher);
+            ast.contents.hash(hasher);
+            ast.state.hash(hasher);
+            ast.span.hash(hasher);
+            ast.children_num.hash(hasher);
+            ast.children.len().hash(hasher);
+            ast.leading_trivia.hash(hasher);
+            ast.trailing_trivia.hash(hasher);
+        }
+    }
+}
+
+/// Whether two trees are equal but for where their nodes were found, like `mpc_ast_eq` in C,
+/// so golden trees still match when the input around them moves. The same comparison as
+/// `MpcAst::eq_ignore_position`.
+pub fn mpc_ast_eq(a: &MpcAst, b: &MpcAst) -> bool {
+    a.eq_ignore_position(b)
+}
+
+/// One difference found by `mpc_ast_diff`. Paths are child indices separated by '|', as
+/// `MpcAst::get` takes them, and "" for the root. A removed node's path is into the old tree;
+/// the others are into the new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MpcAstChange {
+    Added { path: String, node: MpcAst },  // Only in the new tree
+    Removed { path: String, node: MpcAst },  // Only in the old tree
+    Changed { path: String, old: Box<MpcAst>, new: Box<MpcAst> },  // In both, with another tag or contents
+}
+
+impl MpcAstChange {
+    pub fn path(&self) -> &str {
+        match self {
+            MpcAstChange::Added { path, .. }
+            | MpcAstChange::Removed { path, .. }
+            | MpcAstChange::Changed { path, .. } => path,
+        }
+    }
+}
+
+/// One line per change: `+`, `-` or `~`, the path, then the tag and quoted contents
+impl std::fmt::Display for MpcAstChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let node = |ast: &MpcAst| format!("{} {}", ast.tag, mpc_quote(&ast.contents));
+        match self {
+            MpcAstChange::Added { path, node: ast } => write!(f, "+ [{}] {}", path, node(ast)),
+            MpcAstChange::Removed { path, node: ast } => write!(f, "- [{}] {}", path, node(ast)),
+            MpcAstChange::Changed { path, old, new } => {
+                write!(f, "~ [{}] {} -> {}", path, node(old), node(new))
+            }
+        }
+    }
+}
+
+/// Error of `MpcAst::try_get`: the step of the path with no matching child
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MpcAstPathErr {
+    pub path: String,
+    pub step: usize,  // Index of the missing step among the `|`-separated parts of the path
+    pub segment: String,  // The missing step, a tag segment or a child index
+    pub tag: String,  // Tag of the node that has no such child
+}
+
+/// `<tag> has no child <segment>, at step <n> of path "<path>"`, counting steps from 1
+impl std::fmt::Display for MpcAstPathErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tag = if self.tag.is_empty() { "<untagged>" } else { &self.tag };
+        write!(f, "{} has no child {}, at step {} of path {:?}", tag, self.segment, self.step + 1, self.path)
+    }
+}
+
+impl std::error::Error for MpcAstPathErr {}
+
+/// The structural differences between two trees, ignoring positions and trivia. Children are
+/// lined up by their longest common subsequence of tags and contents; a node with the same tag
+/// is looked into, and where nodes differ at the same place it is reported as changed rather
+/// than as a removal and an addition. An empty result means the trees are the same shape.
+pub fn mpc_ast_diff(a: &MpcAst, b: &MpcAst) -> Vec<MpcAstChange> {
+    let mut changes = Vec::new();
+    // What is left to do, the next step last: pairs of nodes to compare and changes to report
+    let mut work = vec![MpcDiffWork::Compare(a, b, String::new(), String::new())];
+    while let Some(step) = work.pop() {
+        match step {
+            MpcDiffWork::Report(change) => changes.push(change),
+            MpcDiffWork::Compare(a, b, path_a, path_b) => {
+                let start = work.len();
+                mpc_ast_diff_step(a, b, &path_a, &path_b, &mut changes, &mut work);
+                work[start..].reverse();
+            }
+        }
+    }
+    changes
+}
+
+// A step of `mpc_ast_diff`
+enum MpcDiffWork<'a> {
+    Compare(&'a MpcAst, &'a MpcAst, String, String),
+    Report(MpcAstChange),
+}
+
+// Reports a change to `a` and `b` themselves, and adds what comparing their children takes to
+// `work` in the order it is to be done
+fn mpc_ast_diff_step<'a>(a: &'a MpcAst, b: &'a MpcAst, path_a: &str, path_b: &str, changes: &mut Vec<MpcAstChange>, work: &mut Vec<MpcDiffWork<'a>>) {
+    if a.tag != b.tag || a.contents != b.contents {
+        changes.push(MpcAstChange::Changed { path: path_b.to_string(), old: Box::new(a.bare()), new: Box::new(b.bare()) });
+    }
+    let child_path = |path: &str, i: usize| if path.is_empty() { i.to_string() } else { format!("{}|{}", path, i) };
+    let xs: Vec<&MpcAst> = a.children.iter().map(|child| &**child).collect();
+    let ys: Vec<&MpcAst> = b.children.iter().map(|child| &**child).collect();
+    let same = |x: &MpcAst, y: &MpcAst| x.tag == y.tag && x.contents == y.contents;
+    // common[i][j]: length of the longest common subsequence of xs[i..] and ys[j..]
+    let mut common = vec![vec![0; ys.len() + 1]; xs.len() + 1];
+    for i in (0..xs.len()).rev() {
+        for j in (0..ys.len()).rev() {
+            common[i][j] = if same(xs[i], ys[j]) { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    loop {
+        let matched = i < xs.len() && j < ys.len() && same(xs[i], ys[j]);
+        if matched || (i == xs.len() && j == ys.len()) {
+            // Between two matches, nodes removed and added at the same place pair up as changes
+            let pairs = removed.len().min(added.len());
+            for (&x, &y) in removed.iter().zip(added.iter()) {
+                work.push(MpcDiffWork::Compare(xs[x], ys[y], child_path(path_a, x), child_path(path_b, y)));
+            }
+            for &x in &removed[pairs..] {
+                work.push(MpcDiffWork::Report(MpcAstChange::Removed { path: child_path(path_a, x), node: xs[x].clone() }));
+            }
+            for &y in &added[pairs..] {
+                work.push(MpcDiffWork::Report(MpcAstChange::Added { path: child_path(path_b, y), node: ys[y].clone() }));
+            }
+            removed.clear();
+            added.clear();
+            if !matched {
+                break;
+            }
+            work.push(MpcDiffWork::Compare(xs[i], ys[j], child_path(path_a, i), child_path(path_b, j)));
+            i += 1;
+            j += 1;
+        } else if i < xs.len() && (j == ys.len() || common[i + 1][j] >= common[i][j + 1]) {
+            removed.push(i);
+            i += 1;
+        } else {
+            added.push(j);
+            j += 1;
+        }
+    }
+}
+
+/// Stable id of a node in an `MpcAstIndex`: its position in a depth-first walk of the tree, so
+/// the root is 0 and side tables can be plain vectors indexed by `id.0`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MpcAstId(pub usize);
+
+/// A tree with ids, parent links and child indices for every node, see `MpcAst::index`.
+/// Analyses use it to walk upward from a node or to keep per-node data without hashing
+/// subtrees.
+#[derive(Debug, Clone)]
+pub struct MpcAstIndex<'a> {
+    nodes: Vec<&'a MpcAst>,
+    parents: Vec<Option<MpcAstId>>,
+    child_indices: Vec<usize>,
+    children: Vec<Vec<MpcAstId>>,
+    depths: Vec<usize>,
+}
+
+impl<'a> MpcAstIndex<'a> {
+    pub fn new(root: &'a MpcAst) -> Self {
+        let mut index = MpcAstIndex {
+            nodes: Vec::new(),
+            parents: Vec::new(),
+            child_indices: Vec::new(),
+            children: Vec::new(),
+            depths: Vec::new(),
+        };
+        let mut stack = vec![(root, None, 0)];
+        while let Some((ast, parent, child_index)) = stack.pop() {
+            let id = MpcAstId(index.nodes.len());
+            let depth = match parent {
+                Some(MpcAstId(p)) => {
+                    index.children[p].push(id);
+                    index.depths[p] + 1
+                }
+                None => 0,
+            };
+            index.nodes.push(ast);
+            index.parents.push(parent);
+            index.child_indices.push(child_index);
+            index.children.push(Vec::with_capacity(ast.children.len()));
+            index.depths.push(depth);
+            stack.extend(ast.children.iter().enumerate().rev().map(|(i, child)| (&**child, Some(id), i)));
+        }
+        index
+    }
+
+    pub fn root(&self) -> MpcAstId {
+        MpcAstId(0)
+    }
+
+    /// Number of nodes in the tree
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Every id, in depth-first order
+    pub fn ids(&self) -> impl Iterator<Item = MpcAstId> {
+        (0..self.nodes.len()).map(MpcAstId)
+    }
+
+    pub fn node(&self, id: MpcAstId) -> &'a MpcAst {
+        self.nodes[id.0]
+    }
+
+    /// The node's parent, or None for the root
+    pub fn parent(&self, id: MpcAstId) -> Option<MpcAstId> {
+        self.parents[id.0]
+    }
+
+    pub fn children(&self, id: MpcAstId) -> &[MpcAstId] {
+        &self.children[id.0]
+    }
+
+    /// Position of the node among its parent's children, 0 for the root
+    pub fn child_index(&self, id: MpcAstId) -> usize {
+        self.child_indices[id.0]
+    }
+
+    pub fn depth(&self, id: MpcAstId) -> usize {
+        self.depths[id.0]
+    }
+
+    /// The node's parent, its parent's parent and so on up to the root
+    pub fn ancestors(&self, id: MpcAstId) -> impl Iterator<Item = MpcAstId> + '_ {
+        std::iter::successors(self.parent(id), move |&parent| self.parent(parent))
+    }
+
+    /// The id of `ast` if it is a node of this tree, found by address
+    pub fn id_of(&self, ast: &MpcAst) -> Option<MpcAstId> {
+        self.nodes.iter().position(|&node| std::ptr::eq(node, ast)).map(MpcAstId)
+    }
+
+    /// The path from the root to the node, as `MpcAst::get` and `mpc_ast_diff` write them
+    pub fn path(&self, id: MpcAstId) -> String {
+        let mut steps: Vec<String> = std::iter::once(id)
+            .chain(self.ancestors(id))
+            .filter(|&step| self.parent(step).is_some())
+            .map(|step| self.child_index(step).to_string())
+            .collect();
+        steps.reverse();
+        steps.join("|")
+    }
+}
+
+/// A node of an `MpcAstArena`. Its strings live in the arena's text buffer and its children are
+/// linked by id, so the node itself owns no heap memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MpcArenaNode {
+    pub state: MpcState,
+    pub span: MpcSpan,
+    tag: std::ops::Range<usize>,
+    contents: std::ops::Range<usize>,
+    leading_trivia: std::ops::Range<usize>,
+    trailing_trivia: std::ops::Range<usize>,
+    parent: Option<MpcAstId>,
+    first_child: Option<MpcAstId>,
+    last_child: Option<MpcAstId>,
+    next_sibling: Option<MpcAstId>,
+    children_num: usize,
+}
+
+/// Trees stored flat: all nodes in one vector and all their text in one string, instead of a
+/// `Box` and a few `String`s per node, so large trees kept around after parsing allocate per
+/// distinct string rather than per node. Tags, and contents up to `MPC_INTERN_MAX` bytes such
+/// as operators and keywords, are interned: a tag like `expr|term|number` is stored once
+/// however many nodes carry it. A list of 10,000 such numbers under 100 takes a few hundred
+/// bytes of text where an `MpcAst` holds about 180KB. Build one with `MpcAstArena::from_ast`,
+/// node by node with `push`, or by parsing into it with `mpc_parse_arena`.
+#[derive(Debug, Clone, Default)]
+pub struct MpcAstArena {
+    nodes: Vec<MpcArenaNode>,
+    text: String,
+    interned: std::collections::HashMap<String, std::ops::Range<usize>>,
+}
+
+/// Longest contents an `MpcAstArena` interns; tags are interned whatever their length
+pub const MPC_INTERN_MAX: usize = 16;
+
+impl MpcAstArena {
+    pub fn new() -> Self {
+        MpcAstArena::default()
+    }
+
+    /// Room for `nodes` nodes and `text` bytes of tags, contents and trivia
+    pub fn with_capacity(nodes: usize, text: usize) -> Self {
+        MpcAstArena { nodes: Vec::with_capacity(nodes), text: String::with_capacity(text), ..MpcAstArena::default() }
+    }
+
+    /// Copies `ast` into a new arena, giving each node the id `MpcAst::index` would
+    pub fn from_ast(ast: &MpcAst) -> Self {
+        let mut arena = MpcAstArena::new();
+        arena.push_ast(None, ast);
+        arena
+    }
+
+    fn push_ast(&mut self, parent: Option<MpcAstId>, ast: &MpcAst) {
+        let mut stack = vec![(parent, ast)];
+        while let Some((parent, ast)) = stack.pop() {
+            let id = self.push(parent, &ast.tag, &ast.contents);
+            let leading_trivia = self.push_text(&ast.leading_trivia);
+            let trailing_trivia = self.push_text(&ast.trailing_trivia);
+            let node = &mut self.nodes[id.0];
+            node.state = ast.state;
+            node.span = ast.span;
+            node.leading_trivia = leading_trivia;
+            node.trailing_trivia = trailing_trivia;
+            stack.extend(ast.children.iter().rev().map(|child| (Some(id), &**child)));
+        }
+    }
+
+    fn push_text(&mut self, s: &str) -> std::ops::Range<usize> {
+        let start = self.text.len();
+        self.text.push_str(s);
+        start..self.text.len()
+    }
+
+    // Where `s` is in the text, storing it only the first time it is seen
+    fn intern(&mut self, s: &str) -> std::ops::Range<usize> {
+        if let Some(range) = self.interned.get(s) {
+            return range.clone();
+        }
+        let range = self.push_text(s);
+        self.interned.insert(s.to_string(), range.clone());
+        range
+    }
+
+    /// Adds a node as the last child of `parent`, or as a root when `parent` is None
+    pub fn push(&mut self, parent: Option<MpcAstId>, tag: &str, contents: &str) -> MpcAstId {
+        let id = MpcAstId(self.nodes.len());
+        let tag = self.intern(tag);
+        let contents = if contents.len() <= MPC_INTERN_MAX { self.intern(contents) } else { self.push_text(contents) };
+        let at = self.text.len();
+        self.nodes.push(MpcArenaNode {
+            state: MpcState::default(),
+            span: MpcSpan::default(),
+            tag,
+            contents,
+            leading_trivia: at..at,
+            trailing_trivia: at..at,
+            parent,
+            first_child: None,
+            last_child: None,
+            next_sibling: None,
+            children_num: 0,
+        });
+        if let Some(MpcAstId(p)) = parent {
+            match self.nodes[p].last_child {
+                Some(MpcAstId(last)) => self.nodes[last].next_sibling = Some(id),
+                None => self.nodes[p].first_child = Some(id),
+            }
+            self.nodes[p].last_child = Some(id);
+            self.nodes[p].children_num += 1;
+        }
+        id
+    }
+
+    /// Number of nodes in the arena
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Bytes of text the arena holds for all its nodes, after interning
+    pub fn text_len(&self) -> usize {
+        self.text.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, id: MpcAstId) -> &MpcArenaNode {
+        &self.nodes[id.0]
+    }
+
+    /// The node, to set its `state` and `span`
+    pub fn node_mut(&mut self, id: MpcAstId) -> &mut MpcArenaNode {
+        &mut self.nodes[id.0]
+    }
+
+    pub fn tag(&self, id: MpcAstId) -> &str {
+        &self.text[self.nodes[id.0].tag.clone()]
+    }
+
+    pub fn contents(&self, id: MpcAstId) -> &str {
+        &self.text[self.nodes[id.0].contents.clone()]
+    }
+
+    pub fn leading_trivia(&self, id: MpcAstId) -> &str {
+        &self.text[self.nodes[id.0].leading_trivia.clone()]
+    }
+
+    pub fn trailing_trivia(&self, id: MpcAstId) -> &str {
+        &self.text[self.nodes[id.0].trailing_trivia.clone()]
+    }
+
+    /// The node's parent, or None for a root
+    pub fn parent(&self, id: MpcAstId) -> Option<MpcAstId> {
+        self.nodes[id.0].parent
+    }
+
+    pub fn children_num(&self, id: MpcAstId) -> usize {
+        self.nodes[id.0].children_num
+    }
+
+    /// The node's children, in order
+    pub fn children(&self, id: MpcAstId) -> impl Iterator<Item = MpcAstId> + '_ {
+        std::iter::successors(self.nodes[id.0].first_child, move |&child| self.nodes[child.0].next_sibling)
+    }
+
+    /// The subtree at `id` as an ordinary `MpcAst`
+    pub fn to_ast(&self, id: MpcAstId) -> MpcAst {
+        let bare = |id: MpcAstId| {
+            let node = &self.nodes[id.0];
+            let mut ast = MpcAst::new(self.tag(id), self.contents(id));
+            ast.state = node.state;
+            ast.span = node.span;
+            ast.leading_trivia = self.leading_trivia(id).to_string();
+            ast.trailing_trivia = self.trailing_trivia(id).to_string();
+            ast
+        };
+        // Nodes being built, each with the ids of its children still to go
+        let mut stack = vec![(bare(id), self.children(id))];
+        loop {
+            let (_, pending) = stack.last_mut().unwrap();
+            if let Some(child) = pending.next() {
+                stack.push((bare(child), self.children(child)));
+                continue;
+            }
+            let (mut ast, _) = stack.pop().unwrap();
+            ast.children_num = ast.children.len() as i32;
+            match stack.last_mut() {
+                Some((parent, _)) => parent.children.push(Box::new(ast)),
+                None => return ast,
+            }
+        }
+    }
+}
+
+// Typed ASTs
+
+#[cfg(feature = "derive")]
+pub use mpc_derive::MpcFromAst;
+
+/// Conversion of a tree into typed Rust data. `#[derive(MpcFromAst)]`, with the `derive`
+/// feature, writes it for structs and enums from their tags and the order of their fields.
+/// The error says where in the input the tree didn't have the shape the type wants.
+pub trait MpcFromAst: Sized {
+    fn from_ast(ast: &MpcAst) -> Result<Self, String>;
+}
+
+/// An error about `ast` for `MpcFromAst`, prefixed with where the node starts, e.g.
+/// `2:5: expected a number, found "x", in number node`
+pub fn mpc_ast_error(ast: &MpcAst, message: &str) -> String {
+    let tag = if ast.tag.is_empty() { "<untagged>" } else { &ast.tag };
+    format!("{}:{}: {}, in {} node", ast.state.row + 1, ast.state.col + 1, message, tag)
+}
+
+/// The whole tree, for fields that keep it untyped
+impl MpcFromAst for MpcAst {
+    fn from_ast(ast: &MpcAst) -> Result<Self, String> {
+        Ok(ast.clone())
+    }
+}
+
+/// The node's text: its contents, or the contents of its leaves for a node with children
+impl MpcFromAst for String {
+    fn from_ast(ast: &MpcAst) -> Result<Self, String> {
+        Ok(if ast.children.is_empty() { ast.contents.clone() } else { ast.to_source_normalized("") })
+    }
+}
+
+macro_rules! mpc_from_ast_parse {
+    ($($t:ty),*) => {$(
+        /// The node's text, parsed with `str::parse`
+        impl MpcFromAst for $t {
+            fn from_ast(ast: &MpcAst) -> Result<Self, String> {
+                let text = String::from_ast(ast)?;
+                text.parse().map_err(|_| mpc_ast_error(ast, &format!("expected {}, found {}", stringify!($t), mpc_quote(&text))))
+            }
+        }
+    )*};
+}
+
+mpc_from_ast_parse!(bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+impl<T: MpcFromAst> MpcFromAst for Box<T> {
+    fn from_ast(ast: &MpcAst) -> Result<Self, String> {
+        T::from_ast(ast).map(Box::new)
+    }
+}
+
+/// Every tagged child of the node, in order
+impl<T: MpcFromAst> MpcFromAst for Vec<T> {
+    fn from_ast(ast: &MpcAst) -> Result<Self, String> {
+        MpcAstFields::new(ast).many(None, "item")
+    }
+}
+
+/// Hands the children of a node out to the fields of a type in order, for `MpcFromAst`.
+/// Untagged leaves, such as punctuation matched by plain parsers, are skipped.
+pub struct MpcAstFields<'a> {
+    ast: &'a MpcAst,
+    next: usize,
+}
+
+impl<'a> MpcAstFields<'a> {
+    pub fn new(ast: &'a MpcAst) -> Self {
+        MpcAstFields { ast, next: 0 }
+    }
+
+    // Index of the next child to hand out, with `tag` among its tag's segments if given
+    fn find(&self, tag: Option<&str>) -> Option<usize> {
+        self.ast.children[self.next..]
+            .iter()
+            .position(|child| match tag {
+                Some(tag) => child.tag_matches(tag),
+                None => !child.tag.is_empty() || !child.children.is_empty(),
+            })
+            .map(|i| i + self.next)
+    }
+
+    fn take<T: MpcFromAst>(&mut self, i: usize, field: &str) -> Result<T, String> {
+        self.next = i + 1;
+        T::from_ast(&self.ast.children[i]).map_err(|e| format!("{}, for field {}", e, field))
+    }
+
+    /// The next child, with `tag` if given, for `field`; an error if there is none
+    pub fn next<T: MpcFromAst>(&mut self, tag: Option<&str>, field: &str) -> Result<T, String> {
+        match self.find(tag) {
+            Some(i) => self.take(i, field),
+            None => Err(mpc_ast_error(self.ast, &match tag {
+                Some(tag) => format!("missing {} child for field {}", tag, field),
+                None => format!("missing child for field {}", field),
+            })),
+        }
+    }
+
+    /// The next child, with `tag` if given, or None when there is none left
+    pub fn optional<T: MpcFromAst>(&mut self, tag: Option<&str>, field: &str) -> Result<Option<T>, String> {
+        match self.find(tag) {
+            Some(i) => self.take(i, field).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// All the children left, or all of them with `tag` if given
+    pub fn many<T: MpcFromAst>(&mut self, tag: Option<&str>, field: &str) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        while let Some(i) = self.find(tag) {
+            items.push(self.take(i, field)?);
+        }
+        Ok(items)
+    }
+}
+
+// AST Combinators
+
+pub fn mpca_tag(parser: MpcParser, tag: &str) -> MpcParser {
+    MpcParser {
+        name: format!("tag:{}", tag),
+        parser_type: MpcParserType::Tag(std::sync::Arc::new(parser), tag.to_string()),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Prefixes the tag of the AST `parser` produces with `tag` and a `|`, like `mpca_add_tag` in C,
+/// so nested rules give tags such as `expr|term|number`
+pub fn mpca_add_tag(parser: MpcParser, tag: &str) -> MpcParser {
+    MpcParser {
+        name: format!("add_tag:{}", tag),
+        parser_type: MpcParserType::AddTag(std::sync::Arc::new(parser), tag.to_string()),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpca_root(parser: MpcParser) -> MpcParser {
+    MpcParser {
+        name: "root".to_string(),
+        parser_type: MpcParserType::Root(std::sync::Arc::new(parser)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Sets the position of the AST `parser` produces to where it started, like `mpca_state` in C.
+/// Tagged nodes already carry their positions; this is for nodes built by hand in a fold.
+pub fn mpca_state(parser: MpcParser) -> MpcParser {
+    mpc_and(vec![mpc_spanned(parser)], mpcf_state_ast)
+}
+
+/// Matches `parser`, typically whitespace or comments, keeping the text as trivia instead of
+/// dropping it. The `mpca_*` folds attach it to the next node as leading trivia, or to the
+/// previous one as trailing trivia at the end of a sequence, so the tree becomes a concrete
+/// syntax tree that `MpcAst::to_source` turns back into the exact input. Separators that
+/// `mpca_sepby` drops take their text with them, so spell lists out with `mpca_and` and
+/// `mpca_many` when the layout matters.
+pub fn mpca_trivia(parser: MpcParser) -> MpcParser {
+    mpc_and(vec![mpc_spanned(mpc_recognize(parser))], mpcf_trivia)
+}
+
+/// Sequence building an AST: the children of the parsers are gathered under one `>` node.
+/// Like the other `mpca_*` combinators, it records where each result came from, so text
+/// becomes leaves with spans of their own.
+pub fn mpca_and(parsers: Vec<MpcParser>) -> MpcParser {
+    mpc_and(parsers.into_iter().map(mpc_spanned).collect(), mpcf_fold_ast)
+}
+
+pub fn mpca_or(parsers: Vec<MpcParser>) -> MpcParser {
+    mpc_or(parsers)
+}
+
+pub fn mpca_many(parser: MpcParser) -> MpcParser {
+    mpc_many(mpc_spanned(parser), mpcf_fold_ast)
+}
+
+pub fn mpca_many1(parser: MpcParser) -> MpcParser {
+    mpc_many1(mpc_spanned(parser), mpcf_fold_ast)
+}
+
+pub fn mpca_count(n: i32, parser: MpcParser) -> MpcParser {
+    mpc_count(n, mpc_spanned(parser), mpcf_fold_ast)
+}
+
+/// Separated list building an AST from the items; separators are dropped, as in `mpc_sepby`
+pub fn mpca_sepby(parser: MpcParser, sep: MpcParser) -> MpcParser {
+    mpc_sepby(mpc_spanned(parser), sep, mpcf_fold_ast)
+}
+
+pub fn mpca_sepby1(parser: MpcParser, sep: MpcParser) -> MpcParser {
+    mpc_sepby1(mpc_spanned(parser), sep, mpcf_fold_ast)
+}
+
+// The AST for a parser's value: ASTs are kept, text becomes a leaf with an empty tag, trivia an
+// empty leaf with the trivia in front, and anything else an empty leaf. Zero-copy spans, and the
+// pieces mpcf_strfold makes of text that isn't contiguous, are read out of `source` when it is
+// known, and the `(span, value)` pairs of mpc_spanned position a node that has no position yet.
+pub(crate) fn mpc_ast_of(val: MpcVal, source: &str) -> MpcAst {
+    let val = match val.downcast::<(MpcSpan, MpcVal)>() {
+        Ok(spanned) => {
+            let (span, val) = *spanned;
+            let mut ast = mpc_ast_of(val, source);
+            if ast.span == MpcSpan::default() {
+                ast.state = span.start;
+                ast.span = span;
+            }
+            return ast;
+        }
+        Err(val) => val,
+    };
+    let val = match val.downcast::<MpcAst>() {
+        Ok(ast) => return *ast,
+        Err(val) => val,
+    };
+    let val = match val.downcast::<String>() {
+        Ok(s) => return MpcAst::new("", &s),
+        Err(val) => val,
+    };
+    let val = match val.downcast::<MpcTrivia>() {
+        Ok(trivia) => {
+            let mut ast = MpcAst::new("", "");
+            ast.leading_trivia = trivia.text;
+            return ast;
+        }
+        Err(val) => val,
+    };
+    let val = match val.downcast::<MpcSpan>() {
+        Ok(span) => {
+            let mut ast = MpcAst::new("", span.slice(source));
+            ast.state = span.start;
+            ast.span = *span;
+            return ast;
+        }
+        Err(val) => val,
+    };
+    match val.downcast::<Vec<MpcSpan>>() {
+        Ok(pieces) => {
+            let text: String = pieces.iter().map(|span| span.slice(source)).collect();
+            let mut ast = MpcAst::new("", &text);
+            if let (Some(first), Some(last)) = (pieces.first(), pieces.last()) {
+                ast.state = first.start;
+                ast.span = MpcSpan { start: first.start, end: last.end };
+            }
+            ast
+        }
+        Err(_) => MpcAst::new("", ""),
+    }
+}
+
+// Result of mpca_tag, or with `add` of mpca_add_tag: the value as an AST, with its tag replaced
+// or prefixed
+pub(crate) fn mpc_ast_tagged(val: MpcVal, tag: &str, add: bool, span: MpcSpan, source: &str) -> MpcAst {
+    let mut ast = mpc_ast_of(val, source);
+    if add {
+        ast.add_tag(tag);
+    } else {
+        ast.tag = tag.to_string();
+    }
+    if ast.span == MpcSpan::default() {
+        ast.state = span.start;
+        ast.span = span;
+    }
+    ast
+}
+
+// Result of mpca_root: the value as an AST under a `>` node, unless it already is one
+pub(crate) fn mpc_ast_root(val: MpcVal, span: MpcSpan, source: &str) -> MpcAst {
+    let ast = mpc_ast_of(val, source);
+    if ast.tag == ">" {
+        return ast;
+    }
+    let mut root = MpcAst::new(">", "");
+    root.span = if ast.span == MpcSpan::default() { span } else { ast.span };
+    root.state = root.span.start;
+    root.children.push(Box::new(ast));
+    root.children_num = 1;
+    root
+}
+
+// Events
+
+/// A step through the input in an event-mode parse, see `mpc_parse_events`. Spans locate the
+/// text in the input, for `MpcSpan::slice`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MpcEvent {
+    Enter(String, MpcSpan),  // Start of a tagged node with tagged nodes inside
+    Exit(String, MpcSpan),  // End of the node entered with the same tag
+    Token(String, MpcSpan),  // Tagged node with no tagged nodes inside, such as a number
+}
+
+impl MpcEvent {
+    pub fn tag(&self) -> &str {
+        match self {
+            MpcEvent::Enter(tag, _) | MpcEvent::Exit(tag, _) | MpcEvent::Token(tag, _) => tag,
+        }
+    }
+
+    pub fn span(&self) -> MpcSpan {
+        match self {
+            MpcEvent::Enter(_, span) | MpcEvent::Exit(_, span) | MpcEvent::Token(_, span) => *span,
+        }
+    }
+
+    fn tag_mut(&mut self) -> &mut String {
+        match self {
+            MpcEvent::Enter(tag, _) | MpcEvent::Exit(tag, _) | MpcEvent::Token(tag, _) => tag,
+        }
+    }
+}
+
+// Events of mpca_tag, or with `add` of mpca_add_tag, once its parser has matched `span` and
+// added the events from `at` on; mpca_tag pushed an Enter at `at` before it ran. The tags
+// come out as mpc_ast_tagged would give them.
+pub(crate) fn mpc_events_tagged(events: &mut Vec<MpcEvent>, at: usize, tag: &str, add: bool, span: MpcSpan) {
+    let first = if add { at } else { at + 1 };
+    if events.len() == first {
+        events.truncate(at);
+        events.push(MpcEvent::Token(tag.to_string(), span));
+        return;
+    }
+    if mpc_event_node_end(events, first) == events.len() - 1 && events[first].span() == span {
+        // A single node covering everything matched, which gets the tag itself
+        if !add {
+            events.remove(at);
+        }
+        let last = events.len() - 1;
+        for i in [at, last] {
+            let node_tag = events[i].tag_mut();
+            *node_tag = if add && !node_tag.is_empty() { format!("{}|{}", tag, node_tag) } else { tag.to_string() };
+            if at == last {
+                break;
+            }
+        }
+        return;
+    }
+    // Several nodes, gathered under one as a `>` node would be
+    let tag = if add { format!("{}|>", tag) } else { tag.to_string() };
+    if add {
+        events.insert(at, MpcEvent::Enter(tag.clone(), span));
+    } else {
+        events[at] = MpcEvent::Enter(tag.clone(), span);
+    }
+    events.push(MpcEvent::Exit(tag, span));
+}
+
+// Index of the last event of the node starting at `start`
+fn mpc_event_node_end(events: &[MpcEvent], start: usize) -> usize {
+    let mut depth = 0;
+    for (i, event) in events.iter().enumerate().skip(start) {
+        match event {
+            MpcEvent::Enter(..) => depth += 1,
+            MpcEvent::Exit(..) => depth -= 1,
+            MpcEvent::Token(..) => {}
+        }
+        if depth == 0 {
+            return i;
+        }
+    }
+    events.len() - 1
+}
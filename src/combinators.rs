@@ -0,0 +1,547 @@
+//! Combinators that build parsers out of other parsers, and the fold functions they use
+
+use crate::items::*;
+
+/// Operator Associativity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MpcAssoc {
+    Left,
+    Right,
+    None,
+}
+
+/// Operator for `mpc_expr`, with its precedence (higher binds tighter)
+#[derive(Debug, Clone)]
+pub enum MpcOperator {
+    Prefix(MpcParser, i32),
+    Infix(MpcParser, i32, MpcAssoc),
+    Postfix(MpcParser, i32),
+}
+
+// Combinator Parsers
+
+/// Sequences parsers, passing their results to `fold`.
+pub fn mpc_and(parsers: Vec<MpcParser>, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "and".to_string(),
+        parser_type: MpcParserType::And(parsers.into_iter().map(std::sync::Arc::new).collect(), fold, None),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Built-in sequence folds that give the same value however a sequence is split into nested
+/// ones, so `mpc_and_assoc` can splice in the sequences folded the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpcAssocFold {
+    Str,  // mpcf_strfold
+    Fst,  // mpcf_fst
+    Null,  // mpcf_null
+}
+
+impl MpcAssocFold {
+    pub fn fold(self) -> fn(i32, Vec<MpcVal>) -> MpcVal {
+        match self {
+            MpcAssocFold::Str => mpcf_strfold,
+            MpcAssocFold::Fst => mpcf_fst,
+            MpcAssocFold::Null => mpcf_null,
+        }
+    }
+}
+
+/// Sequences parsers like `mpc_and` with one of the associative folds. Children that are
+/// themselves sequences made this way with the same fold, and not renamed, are spliced in,
+/// so grammars built up two parsers at a time don't nest a level for each.
+pub fn mpc_and_assoc(parsers: Vec<MpcParser>, fold: MpcAssocFold) -> MpcParser {
+    let mut flat = Vec::with_capacity(parsers.len());
+    for parser in parsers {
+        match parser.parser_type {
+            MpcParserType::And(children, _, Some(inner)) if inner == fold && parser.name == "and" && !children.is_empty() => {
+                flat.extend(children);
+            }
+            parser_type => flat.push(std::sync::Arc::new(MpcParser { name: parser.name, parser_type, compiled: parser.compiled })),
+        }
+    }
+    MpcParser {
+        name: "and".to_string(),
+        parser_type: MpcParserType::And(flat, fold.fold(), Some(fold)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Sequences parsers like `mpc_and`, producing a tuple of their results instead of going
+/// through a fold. With `parser => Type` entries the tuple is typed; without types it holds the
+/// raw `MpcVal`s:
+///
+/// ```
+/// use mpc::prelude::*;
+///
+/// let pair = mpc_seq!(mpc_alpha() => String, mpc_digits() => String);
+/// for val in [mpc_parse("test", "x1", &pair).unwrap(), mpc_parse_zero_copy("test", "x1", &pair).unwrap()] {
+///     assert_eq!(*val.downcast::<(String, String)>().unwrap(), ("x".to_string(), "1".to_string()));
+/// }
+/// ```
+///
+/// # Panics
+///
+/// The typed form panics while parsing, naming the type it wanted, if a parser's value isn't of
+/// the type given for it, as with `mpc_digits() => i64` where `mpc_digits` gives a `String`.
+/// Convert values with `mpc_apply` first. The spans that parsers give in zero-copy mode are
+/// turned into the text they cover for a `String` entry.
+#[macro_export]
+macro_rules! mpc_seq {
+    // The next value of the sequence, standing for `$parser` so that there is one per parser
+    (@next $xs:ident $parser:expr) => {
+        $xs.next().unwrap()
+    };
+    ($($parser:expr => $ty:ty),+ $(,)?) => {
+        $crate::combinators::mpc_and(vec![$($crate::combinators::mpc_seq_item::<$ty>($parser)),+], |_, xs| {
+            let mut xs = xs.into_iter();
+            Box::new(($(
+                *$crate::combinators::mpc_seq_value::<$ty>(xs.next().unwrap()).downcast::<$ty>()
+                    .expect(concat!("mpc_seq!: result is not a ", stringify!($ty))),
+            )+))
+        })
+    };
+    ($($parser:expr),+ $(,)?) => {
+        $crate::combinators::mpc_and(vec![$($parser),+], |_, xs| {
+            let mut xs = xs.into_iter();
+            Box::new(($($crate::mpc_seq!(@next xs $parser),)+))
+        })
+    };
+}
+
+// A `String` item of a typed `mpc_seq!` keeps the text it matched, and where, alongside its
+// value: in zero-copy mode the value is a span, which the fold has no input to resolve against
+#[doc(hidden)]
+pub fn mpc_seq_item<T: 'static>(parser: MpcParser) -> MpcParser {
+    if std::any::TypeId::of::<T>() == std::any::TypeId::of::<String>() {
+        mpc_spanned(mpc_consumed(parser))
+    } else {
+        parser
+    }
+}
+
+// The value of a typed `mpc_seq!` item, with the spans of a `String` item resolved against the
+// text it matched
+#[doc(hidden)]
+pub fn mpc_seq_value<T: 'static>(val: MpcVal) -> MpcVal {
+    if std::any::TypeId::of::<T>() != std::any::TypeId::of::<String>() {
+        return val;
+    }
+    let (matched, consumed) = *val.downcast::<(MpcSpan, MpcVal)>().unwrap();
+    let (text, val) = *consumed.downcast::<(String, MpcVal)>().unwrap();
+    let slice = |span: &MpcSpan| {
+        let from = (span.start.pos - matched.start.pos) as usize;
+        text.get(from..from + span.len()).unwrap_or("").to_string()
+    };
+    if let Some(span) = val.downcast_ref::<MpcSpan>() {
+        Box::new(slice(span))
+    } else if let Some(spans) = val.downcast_ref::<Vec<MpcSpan>>() {
+        Box::new(spans.iter().map(slice).collect::<String>())
+    } else {
+        val
+    }
+}
+
+/// Tries each parser in turn. Children that are themselves alternatives, and not renamed, are
+/// spliced in, so nested choices are flattened into one.
+pub fn mpc_or(parsers: Vec<MpcParser>) -> MpcParser {
+    MpcParser {
+        name: "or".to_string(),
+        parser_type: {
+            let mut flat = Vec::with_capacity(parsers.len());
+            for parser in parsers {
+                match parser.parser_type {
+                    MpcParserType::Or(children, _) if parser.name == "or" && !children.is_empty() => flat.extend(children),
+                    parser_type => flat.push(std::sync::Arc::new(MpcParser { name: parser.name, parser_type, compiled: parser.compiled })),
+                }
+            }
+            let parsers = flat;
+            let dispatch = MpcDispatch::new(&parsers);
+            MpcParserType::Or(parsers, dispatch)
+        },
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Error recovery: if `parser` fails, its error is recorded on the input, input is skipped up to
+/// and including the next match of `sync`, and `default()` is returned so parsing can go on.
+pub fn mpc_recover_with(parser: MpcParser, sync: MpcParser, default: fn() -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: format!("recover:{}", parser.name),
+        parser_type: MpcParserType::Recover(std::sync::Arc::new(parser), std::sync::Arc::new(sync), default),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Succeeds, consuming no input and returning `()`, only where `parser` fails: PEG's `!`.
+pub fn mpc_not(parser: MpcParser) -> MpcParser {
+    MpcParser {
+        name: format!("not:{}", parser.name),
+        parser_type: MpcParserType::Not(std::sync::Arc::new(parser)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// `parser` with its value passed through `f`, like `mpc_apply` in C
+pub fn mpc_apply(parser: MpcParser, f: fn(MpcVal) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: format!("apply:{}", parser.name),
+        parser_type: MpcParserType::Apply(std::sync::Arc::new(parser), f),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Runs `parser` and returns its value, but leaves the input where it was: PEG's `&`.
+pub fn mpc_lookahead(parser: MpcParser) -> MpcParser {
+    MpcParser {
+        name: format!("lookahead:{}", parser.name),
+        parser_type: MpcParserType::Lookahead(std::sync::Arc::new(parser)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Reports a failure of `parser` as simply "expected `expected`", e.g. "identifier".
+pub fn mpc_expect(parser: MpcParser, expected: &str) -> MpcParser {
+    MpcParser {
+        name: format!("expect:{}", expected),
+        parser_type: MpcParserType::Expect(std::sync::Arc::new(parser), expected.to_string()),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Parses each `(parser, optional)` exactly once, in any order. Results are passed to `fold`
+/// in the order given, with `()` standing in for optional parsers that did not match.
+pub fn mpc_permutation(parsers: Vec<(MpcParser, bool)>, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "permutation".to_string(),
+        parser_type: MpcParserType::Permutation(parsers.into_iter().map(|(p, optional)| (std::sync::Arc::new(p), optional)).collect(), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_many(parser: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "many".to_string(),
+        parser_type: MpcParserType::Many(std::sync::Arc::new(parser), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_many1(parser: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "many1".to_string(),
+        parser_type: MpcParserType::Many1(std::sync::Arc::new(parser), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Zero or more, discarding the results without collecting or folding them.
+pub fn mpc_skip_many(parser: MpcParser) -> MpcParser {
+    MpcParser {
+        name: "skip_many".to_string(),
+        parser_type: MpcParserType::SkipMany(std::sync::Arc::new(parser)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// One or more, discarding the results without collecting or folding them.
+pub fn mpc_skip_many1(parser: MpcParser) -> MpcParser {
+    MpcParser {
+        name: "skip_many1".to_string(),
+        parser_type: MpcParserType::SkipMany1(std::sync::Arc::new(parser)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Zero or more, folding each result into an accumulator started from `init()`.
+pub fn mpc_fold_many(init: fn() -> MpcVal, parser: MpcParser, fold: fn(MpcVal, MpcVal) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "fold_many".to_string(),
+        parser_type: MpcParserType::FoldMany(init, std::sync::Arc::new(parser), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_count(n: i32, parser: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: format!("count:{}", n),
+        parser_type: MpcParserType::Count(n, std::sync::Arc::new(parser), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Matches `parser` at least `min` times and at most `max` times (unbounded if `None`).
+/// Unless `0 <= min <= max` the returned parser always fails, naming the bad bounds.
+pub fn mpc_repeat(min: i32, max: Option<i32>, parser: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    if min < 0 {
+        return mpc_fail(&format!("mpc_repeat: min is {}, which is negative", min));
+    }
+    if let Some(max) = max.filter(|&max| max < min) {
+        return mpc_fail(&format!("mpc_repeat: min is {}, more than max {}", min, max));
+    }
+    MpcParser {
+        name: match max {
+            Some(max) => format!("repeat:{}-{}", min, max),
+            None => format!("repeat:{}-", min),
+        },
+        parser_type: MpcParserType::Repeat(min, max, std::sync::Arc::new(parser), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_sepby(parser: MpcParser, sep: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "sepby".to_string(),
+        parser_type: MpcParserType::SepBy(std::sync::Arc::new(parser), std::sync::Arc::new(sep), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_sepby1(parser: MpcParser, sep: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "sepby1".to_string(),
+        parser_type: MpcParserType::SepBy1(std::sync::Arc::new(parser), std::sync::Arc::new(sep), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Like `mpc_sepby`, but also consumes an optional trailing separator.
+pub fn mpc_sependby(parser: MpcParser, sep: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "sependby".to_string(),
+        parser_type: MpcParserType::SepEndBy(std::sync::Arc::new(parser), std::sync::Arc::new(sep), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Like `mpc_sepby1`, but also consumes an optional trailing separator.
+pub fn mpc_sependby1(parser: MpcParser, sep: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "sependby1".to_string(),
+        parser_type: MpcParserType::SepEndBy1(std::sync::Arc::new(parser), std::sync::Arc::new(sep), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Parses one or more `term` separated by `op`, folding `fold(lhs, op, rhs)` left-associatively.
+pub fn mpc_chainl1(term: MpcParser, op: MpcParser, fold: fn(MpcVal, MpcVal, MpcVal) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "chainl1".to_string(),
+        parser_type: MpcParserType::ChainL1(std::sync::Arc::new(term), std::sync::Arc::new(op), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Parses one or more `term` separated by `op`, folding `fold(lhs, op, rhs)` right-associatively.
+pub fn mpc_chainr1(term: MpcParser, op: MpcParser, fold: fn(MpcVal, MpcVal, MpcVal) -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "chainr1".to_string(),
+        parser_type: MpcParserType::ChainR1(std::sync::Arc::new(term), std::sync::Arc::new(op), fold),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Builds an expression parser over `atom` from a table of prefix, infix and postfix operators.
+/// Operators are tried in the order given; the result is an `MpcAst` tagged `prefix`, `infix`,
+/// `postfix` or `atom`, with the operator text as contents and the operands as children. A
+/// non-associative operator can't follow one of the same precedence: `1 = 2 = 3` is an error
+/// at the second `=`.
+pub fn mpc_expr(atom: MpcParser, operators: Vec<MpcOperator>) -> MpcParser {
+    MpcParser {
+        name: "expr".to_string(),
+        parser_type: MpcParserType::Expr(std::sync::Arc::new(atom), operators),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Runs `parser` but returns the exact input text it consumed, discarding its value.
+pub fn mpc_recognize(parser: MpcParser) -> MpcParser {
+    MpcParser {
+        name: format!("recognize:{}", parser.name),
+        parser_type: MpcParserType::Recognize(std::sync::Arc::new(parser)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Runs `parser` and returns a `(String, MpcVal)` pair of the consumed input text and its value.
+pub fn mpc_consumed(parser: MpcParser) -> MpcParser {
+    MpcParser {
+        name: format!("consumed:{}", parser.name),
+        parser_type: MpcParserType::Consumed(std::sync::Arc::new(parser)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Runs `parser` and returns a `(MpcSpan, MpcVal)` pair of the states before and after it and its value.
+pub fn mpc_spanned(parser: MpcParser) -> MpcParser {
+    MpcParser {
+        name: format!("spanned:{}", parser.name),
+        parser_type: MpcParserType::Spanned(std::sync::Arc::new(parser)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Consumes input up to, but not including, the first point where `terminator` matches.
+/// Fails if the end of input is reached before the terminator is found.
+pub fn mpc_take_until(terminator: MpcParser) -> MpcParser {
+    MpcParser {
+        name: format!("take_until:{}", terminator.name),
+        parser_type: MpcParserType::TakeUntil(std::sync::Arc::new(terminator)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Like `mpc_take_until`, but the terminator is a literal string.
+pub fn mpc_string_until(s: &str) -> MpcParser {
+    MpcParser {
+        name: format!("string_until:{}", s),
+        parser_type: MpcParserType::StringUntil(s.to_string()),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+// Common Fold Functions
+
+pub fn mpcf_strfold(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    // Zero-copy lexemes fold into one span when they follow on from each other in the input,
+    // and otherwise into a Vec of the pieces, so that the text is the same as it would have been
+    // folded from Strings. Where some pieces are Strings the parse has made them all Strings.
+    if xs.iter().any(|x| x.is::<MpcSpan>() || x.is::<Vec<MpcSpan>>()) {
+        let mut pieces: Vec<MpcSpan> = Vec::new();
+        for x in xs {
+            let spans = match x.downcast::<MpcSpan>() {
+                Ok(span) => vec![*span],
+                Err(x) => match x.downcast::<Vec<MpcSpan>>() {
+                    Ok(spans) => *spans,
+                    Err(_) => continue,
+                },
+            };
+            for span in spans {
+                match pieces.last_mut() {
+                    Some(last) if last.end.pos == span.start.pos => last.end = span.end,
+                    Some(last) if last.is_empty() => *last = span,
+                    Some(_) if span.is_empty() => {}
+                    _ => pieces.push(span),
+                }
+            }
+        }
+        return match pieces.as_slice() {
+            [span] => Box::new(*span),
+            _ => Box::new(pieces),
+        };
+    }
+    let mut result = String::new();
+    for x in xs {
+        if let Ok(s) = x.downcast::<String>() {
+            result.push_str(&s);
+        }
+    }
+    Box::new(result)
+}
+
+pub fn mpcf_fst(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    if xs.is_empty() {
+        Box::new(())
+    } else {
+        xs.into_iter().next().unwrap()
+    }
+}
+
+/// Returns the second result, like `mpcf_snd` in C
+pub fn mpcf_snd(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    xs.into_iter().nth(1).unwrap_or_else(|| Box::new(()))
+}
+
+pub fn mpcf_null(_n: i32, _xs: Vec<MpcVal>) -> MpcVal {
+    Box::new(())
+}
+
+/// Fold for `mpca_trivia`: takes the `(span, text)` pair from `mpc_spanned` over
+/// `mpc_recognize` and returns it as `MpcTrivia`
+pub fn mpcf_trivia(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let Some(spanned) = xs.into_iter().next() else { return Box::new(()) };
+    let (span, text) = match spanned.downcast::<(MpcSpan, MpcVal)>() {
+        Ok(spanned) => *spanned,
+        Err(text) => (MpcSpan::default(), text),
+    };
+    let text = text.downcast::<String>().map_or_else(|_| String::new(), |text| *text);
+    Box::new(MpcTrivia { text, span })
+}
+
+/// Fold for `mpca_state`: takes the `(span, value)` pair from `mpc_spanned` and returns the
+/// value as an AST positioned at that span
+pub fn mpcf_state_ast(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let Some(spanned) = xs.into_iter().next() else { return Box::new(()) };
+    let (span, val) = match spanned.downcast::<(MpcSpan, MpcVal)>() {
+        Ok(spanned) => *spanned,
+        Err(val) => (MpcSpan::default(), val),
+    };
+    let mut ast = mpc_ast_of(val, "");
+    ast.state = span.start;
+    ast.span = span;
+    Box::new(ast)
+}
+
+/// Folds values into an AST like `mpcf_fold_ast` in C. Unit values are dropped and text becomes
+/// leaves; a single value is returned as it is, and several are gathered under a new `>` node,
+/// with the children of other `>` nodes moved up into it so sequences nest flat. Tagged nodes
+/// stay whole, as children. Values from `mpc_spanned` become nodes positioned at their span.
+pub fn mpcf_fold_ast(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let mut asts: Vec<MpcAst> = Vec::new();
+    let mut trivia: Option<MpcTrivia> = None;  // Waiting for the next node
+    for x in xs {
+        let x = match x.downcast::<(MpcSpan, MpcVal)>() {
+            Ok(spanned) if spanned.1.is::<()>() || spanned.1.is::<MpcTrivia>() => spanned.1,
+            Ok(spanned) => spanned,
+            Err(x) => x,
+        };
+        if x.is::<()>() {
+            continue;
+        }
+        match x.downcast::<MpcTrivia>() {
+            Ok(more) => {
+                trivia = Some(match trivia {
+                    Some(trivia) => trivia.join(&more),
+                    None => *more,
+                });
+            }
+            Err(x) => {
+                let mut ast = mpc_ast_of(x, "");
+                if let Some(trivia) = trivia.take() {
+                    ast.leading_trivia.insert_str(0, &trivia.text);
+                }
+                asts.push(ast);
+            }
+        }
+    }
+    match (asts.last_mut(), trivia) {
+        (Some(last), Some(trivia)) => last.trailing_trivia.push_str(&trivia.text),
+        (None, Some(trivia)) => return Box::new(trivia),
+        _ => {}
+    }
+    match asts.len() {
+        0 => return Box::new(()),
+        1 => return Box::new(asts.pop().unwrap()),
+        _ => {}
+    }
+    let mut group = MpcAst::new(">", "");
+    let mut span: Option<MpcSpan> = None;
+    for mut ast in asts {
+        if ast.span != MpcSpan::default() {
+            span = Some(span.map_or(ast.span, |span| span.merge(&ast.span)));
+        }
+        if ast.tag == ">" && !ast.children.is_empty() {
+            // The group's own trivia moves to the children at its ends
+            ast.children[0].leading_trivia.insert_str(0, &ast.leading_trivia);
+            ast.children.last_mut().unwrap().trailing_trivia.push_str(&ast.trailing_trivia);
+            group.children.append(&mut ast.children);
+        } else {
+            group.children.push(Box::new(ast));
+        }
+    }
+    group.span = span.unwrap_or_default();
+    group.state = group.span.start;
+    group.children_num = group.children.len() as i32;
+    Box::new(group)
+}
@@ -0,0 +1,511 @@
+//! Parse errors, their diagnostics, and the result of a parse
+
+use crate::items::*;
+
+/// Error Type
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MpcErr {
+    pub state: MpcState,
+    pub expected_num: i32,
+    pub filename: String,
+    pub failure: String,
+    pub expected: Vec<String>,
+    pub received: char,
+    pub source_line: String,  // Input line containing the error, for rendering
+    pub context: Vec<String>,  // Enclosing rules that failed, innermost first
+    pub suggestion: Option<(String, String)>,  // Unknown word at the error and the closest expected keyword
+    pub kind: MpcErrKind,
+}
+
+/// What an error reports: input that didn't parse, input that couldn't be read, or a parse
+/// that was stopped or went over its limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MpcErrKind {
+    #[default]
+    Parse,
+    Io,  // Reading the input failed, with the reason in `failure`
+    Cancelled,  // The cancellation flag was set, see mpc_parse_cancellable; the position is where it stopped
+    ResourceExhausted,  // The parse went over one of its MpcLimits, see mpc_parse_limited
+}
+
+impl MpcErr {
+    pub fn new(state: MpcState, expected: Vec<String>, failure: String, received: char) -> Self {
+        MpcErr {
+            state,
+            expected_num: expected.len() as i32,
+            filename: String::new(),
+            failure,
+            expected,
+            received,
+            source_line: String::new(),
+            context: Vec::new(),
+            suggestion: None,
+            kind: MpcErrKind::Parse,
+        }
+    }
+
+    /// Error for input that couldn't be read from `filename`
+    pub fn io(filename: &str, e: &std::io::Error) -> Self {
+        MpcErr {
+            filename: filename.to_string(),
+            kind: MpcErrKind::Io,
+            ..MpcErr::new(MpcState::default(), vec![], e.to_string(), '\0')
+        }
+    }
+
+    /// Combines the errors of failed alternatives, like `mpc_err_or` in C: the errors that got
+    /// furthest into the input win, and their expected sets are merged.
+    pub fn or(errs: Vec<MpcErr>) -> Option<MpcErr> {
+        let furthest = errs.iter().map(|e| e.state.pos).max()?;
+        let mut errs = errs.into_iter().filter(|e| e.state.pos == furthest);
+        let mut merged = errs.next()?;
+        let mut combined = false;
+        // The longest context describes the failure from furthest out
+        let mut context = std::mem::take(&mut merged.context);
+        for e in errs {
+            if e.context.len() > context.len() {
+                context = e.context;
+            }
+            for expected in e.expected {
+                if !merged.expected.contains(&expected) {
+                    merged.expected.push(expected);
+                    combined = true;
+                }
+            }
+        }
+        merged.context = context;
+        if combined {
+            merged.expected = mpc_err_expected_normalize(merged.expected);
+            merged.expected_num = merged.expected.len() as i32;
+            merged.failure = format!("expected {}", mpc_err_expected_string(&merged.expected));
+        }
+        Some(merged)
+    }
+
+    /// Equality on everything but where the error happened, i.e. `state` and `source_line`
+    pub fn eq_ignore_position(&self, other: &MpcErr) -> bool {
+        MpcErr { state: other.state, source_line: other.source_line.clone(), ..self.clone() } == *other
+    }
+
+    /// One-line description, e.g. `expected 'a' or 'b' at 'c'`
+    pub fn message(&self) -> String {
+        let mut message = self.summary();
+        for (i, context) in self.context.iter().enumerate() {
+            message.push_str(if i == 0 { " while parsing " } else { ", while parsing " });
+            message.push_str(context);
+        }
+        message
+    }
+
+    // The message without the enclosing rules
+    fn summary(&self) -> String {
+        if let Some((ref word, ref keyword)) = self.suggestion {
+            format!("unknown keyword '{}', did you mean '{}'?", word, keyword)
+        } else if self.expected.is_empty() {
+            self.failure.clone()
+        } else {
+            let received = match self.received {
+                '\0' => "end of input".to_string(),
+                c => format!("{:?}", c),
+            };
+            format!("expected {} at {}", mpc_err_expected_string(&self.expected), received)
+        }
+    }
+
+    /// Full rendering: `<filename>:<row>:<col>: error: <message>`, followed by the offending
+    /// source line with a caret under the column when the line is known. I/O errors have no
+    /// position, and render as `<filename>: error: <message>`.
+    pub fn render(&self) -> String {
+        if self.kind == MpcErrKind::Io {
+            return format!("{}: error: {}", self.filename, self.message());
+        }
+        let mut out = format!("{}:{}:{}: error: {}", self.filename, self.state.row + 1, self.state.col + 1, self.message());
+        if !self.source_line.is_empty() {
+            let pad: String = self.source_line.chars()
+                .take(self.state.col as usize)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            out.push_str(&format!("\n{}\n{}^", self.source_line, pad));
+        }
+        out
+    }
+
+    pub fn print(&self) {
+        println!("{}", self.render());
+    }
+
+    /// Writes what `print` shows to `w`, like `mpc_err_print_to` in C
+    pub fn print_to<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(w, "{}", self.render())
+    }
+
+    /// Colored rendering in the style of rustc, with the enclosing rules and any suggestion
+    /// as notes underneath.
+    #[cfg(feature = "color")]
+    pub fn write_fancy<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let mut diagnostic = MpcDiagnostic::from(self);
+        diagnostic.message = self.summary();
+        let mut notes: Vec<String> = self.context.iter()
+            .map(|context| format!("note: while parsing {}", context))
+            .collect();
+        if let Some((_, ref keyword)) = self.suggestion {
+            notes.push(format!("help: did you mean '{}'?", keyword));
+        }
+        let line = |row: i64| if row == self.state.row { Some(self.source_line.as_str()) } else { None };
+        mpc_write_fancy(w, &diagnostic, &line, &notes)
+    }
+}
+
+impl std::fmt::Display for MpcErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+impl std::error::Error for MpcErr {}
+
+/// Diagnostic Severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MpcSeverity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+/// Secondary location attached to a diagnostic
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MpcRelated {
+    pub span: MpcSpan,
+    pub message: String,
+}
+
+/// Diagnostic Type, a structured form of `MpcErr` for editors and language servers.
+/// Rows and columns in the span are zero-based, as in LSP.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MpcDiagnostic {
+    pub severity: MpcSeverity,
+    pub code: String,
+    pub filename: String,
+    pub span: MpcSpan,
+    pub message: String,
+    pub related: Vec<MpcRelated>,
+}
+
+impl From<&MpcErr> for MpcDiagnostic {
+    fn from(e: &MpcErr) -> Self {
+        // The span covers the offending character, or is empty at the end of input; a newline
+        // ends at the start of the next line
+        let mut end = e.state;
+        if e.received == '\n' {
+            end.pos += 1;
+            end.row += 1;
+            end.col = 0;
+        } else if e.received != '\0' {
+            end.pos += e.received.len_utf8() as i64;
+            end.col += 1;
+        }
+        let code = if e.kind == MpcErrKind::Io {
+            "mpc::io"
+        } else if e.kind == MpcErrKind::Cancelled {
+            "mpc::cancelled"
+        } else if e.kind == MpcErrKind::ResourceExhausted {
+            "mpc::resource_exhausted"
+        } else if e.suggestion.is_some() {
+            "mpc::unknown_keyword"
+        } else if e.expected.is_empty() {
+            "mpc::failure"
+        } else {
+            "mpc::expected"
+        };
+        MpcDiagnostic {
+            severity: MpcSeverity::Error,
+            code: code.to_string(),
+            filename: e.filename.clone(),
+            span: MpcSpan { start: e.state, end },
+            message: e.message(),
+            related: Vec::new(),
+        }
+    }
+}
+
+impl From<MpcErr> for MpcDiagnostic {
+    fn from(e: MpcErr) -> Self {
+        MpcDiagnostic::from(&e)
+    }
+}
+
+#[cfg(feature = "color")]
+impl MpcDiagnostic {
+    /// Colored rendering in the style of rustc. `source` is the whole parsed input, so spans
+    /// running over several lines are underlined on each of them, and related locations get
+    /// their own labelled excerpt.
+    pub fn write_fancy<W: std::io::Write>(&self, w: &mut W, source: &str) -> std::io::Result<()> {
+        let line = |row: i64| {
+            let line = source.split('\n').nth(usize::try_from(row).ok()?)?;
+            Some(line.strip_suffix('\r').unwrap_or(line))
+        };
+        mpc_write_fancy(w, self, &line, &[])
+    }
+}
+
+#[cfg(feature = "color")]
+const MPC_ANSI_RESET: &str = "\x1b[0m";
+#[cfg(feature = "color")]
+const MPC_ANSI_BOLD: &str = "\x1b[1m";
+#[cfg(feature = "color")]
+const MPC_ANSI_GUTTER: &str = "\x1b[1;34m";
+
+#[cfg(feature = "color")]
+fn mpc_write_fancy<'a, W: std::io::Write>(w: &mut W, d: &MpcDiagnostic, line: &dyn Fn(i64) -> Option<&'a str>, notes: &[String]) -> std::io::Result<()> {
+    let (severity, color) = match d.severity {
+        MpcSeverity::Error => ("error", "\x1b[1;31m"),
+        MpcSeverity::Warning => ("warning", "\x1b[1;33m"),
+        MpcSeverity::Information => ("info", "\x1b[1;36m"),
+        MpcSeverity::Hint => ("hint", "\x1b[1;32m"),
+    };
+    let last_row = d.related.iter().map(|r| r.span.end.row).fold(d.span.end.row, i64::max);
+    let gutter = (last_row + 1).to_string().len();
+    let blank = " ".repeat(gutter);
+
+    writeln!(w, "{}{}[{}]{}{}: {}{}", color, severity, d.code, MPC_ANSI_RESET, MPC_ANSI_BOLD, d.message, MPC_ANSI_RESET)?;
+    writeln!(w, "{}{}-->{} {}:{}:{}", blank, MPC_ANSI_GUTTER, MPC_ANSI_RESET, d.filename, d.span.start.row + 1, d.span.start.col + 1)?;
+    writeln!(w, "{} {}|{}", blank, MPC_ANSI_GUTTER, MPC_ANSI_RESET)?;
+    mpc_write_excerpt(w, gutter, line, &d.span, '^', color, "")?;
+    for related in &d.related {
+        writeln!(w, "{} {}...{}", blank, MPC_ANSI_GUTTER, MPC_ANSI_RESET)?;
+        mpc_write_excerpt(w, gutter, line, &related.span, '-', MPC_ANSI_GUTTER, &related.message)?;
+    }
+    if !notes.is_empty() {
+        writeln!(w, "{} {}|{}", blank, MPC_ANSI_GUTTER, MPC_ANSI_RESET)?;
+    }
+    for note in notes {
+        writeln!(w, "{} {}={} {}", blank, MPC_ANSI_GUTTER, MPC_ANSI_RESET, note)?;
+    }
+    Ok(())
+}
+
+// Writes the lines covered by `span`, each followed by an underline of the part in the span.
+// The label goes after the underline on the last line.
+#[cfg(feature = "color")]
+fn mpc_write_excerpt<'a, W: std::io::Write>(w: &mut W, gutter: usize, line: &dyn Fn(i64) -> Option<&'a str>, span: &MpcSpan, mark: char, color: &str, label: &str) -> std::io::Result<()> {
+    for row in span.start.row..=span.end.row {
+        let Some(text) = line(row) else { continue };
+        let width = text.chars().count();
+        let from = if row == span.start.row { span.start.col as usize } else { 0 };
+        let to = if row == span.end.row { span.end.col as usize } else { width };
+        // Empty spans and the tail of a multi-line span ending at column 0 still get a mark
+        let to = to.max(from + 1);
+        let pad: String = text.chars()
+            .take(from)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let marks = mark.to_string().repeat(to - from);
+        let label = if row == span.end.row && !label.is_empty() { format!(" {}", label) } else { String::new() };
+        writeln!(w, "{}{:>gutter$} |{} {}", MPC_ANSI_GUTTER, row + 1, MPC_ANSI_RESET, text, gutter = gutter)?;
+        writeln!(w, "{}{} |{} {}{}{}{}{}", MPC_ANSI_GUTTER, " ".repeat(gutter), MPC_ANSI_RESET, pad, color, marks, label, MPC_ANSI_RESET)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "miette")]
+impl MpcErr {
+    // Byte offset of the start of `source_line` in the input
+    fn line_offset(&self) -> usize {
+        let col = self.source_line.char_indices()
+            .nth(self.state.col as usize)
+            .map_or(self.source_line.len(), |(i, _)| i);
+        (self.state.pos as usize).saturating_sub(col)
+    }
+}
+
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for MpcErr {
+    fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(MpcDiagnostic::from(self).code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        let (_, keyword) = self.suggestion.as_ref()?;
+        Some(Box::new(format!("did you mean '{}'?", keyword)))
+    }
+
+    // An I/O error has no input to point into
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        if self.kind == MpcErrKind::Io {
+            return None;
+        }
+        Some(self)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        if self.kind == MpcErrKind::Io {
+            return None;
+        }
+        let label = if self.expected.is_empty() {
+            self.failure.clone()
+        } else {
+            format!("expected {}", mpc_err_expected_string(&self.expected))
+        };
+        let len = if self.received == '\0' { 0 } else { self.received.len_utf8() };
+        let span = miette::LabeledSpan::new(Some(label), self.state.pos as usize, len);
+        Some(Box::new(std::iter::once(span)))
+    }
+}
+
+// The error carries only the line it occurred on, so that is the source miette gets to see
+#[cfg(feature = "miette")]
+impl miette::SourceCode for MpcErr {
+    fn read_span<'a>(&'a self, _span: &miette::SourceSpan, _before: usize, _after: usize) -> Result<Box<dyn miette::SpanContents<'a> + 'a>, miette::MietteError> {
+        let line = miette::SourceSpan::new(self.line_offset().into(), self.source_line.len());
+        Ok(Box::new(miette::MietteSpanContents::new_named(
+            self.filename.clone(),
+            self.source_line.as_bytes(),
+            line,
+            self.state.row as usize,
+            0,
+            1,
+        )))
+    }
+}
+
+// Longest expected list rendered in full before the rest is summarised as "and N more"
+const MPC_ERR_EXPECTED_MAX: usize = 8;
+
+// Joins expected items as "a, b or c"
+pub(crate) fn mpc_err_expected_string(expected: &[String]) -> String {
+    if expected.len() > MPC_ERR_EXPECTED_MAX {
+        let shown = &expected[..MPC_ERR_EXPECTED_MAX];
+        return format!("{} … and {} more", shown.join(", "), expected.len() - MPC_ERR_EXPECTED_MAX);
+    }
+    match expected.split_last() {
+        None => String::new(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+// Sorts and deduplicates expected items. Characters and character ranges that overlap or
+// touch are merged, and become a range once they cover more than two characters.
+fn mpc_err_expected_normalize(expected: Vec<String>) -> Vec<String> {
+    let mut ranges = Vec::new();
+    let mut items = Vec::new();
+    for e in expected {
+        match mpc_err_expected_class(&e) {
+            // A reversed range matches nothing, so there is nothing to expect of it
+            Some((start, end)) if start > end => {}
+            Some(range) => ranges.push(range),
+            None => items.push(e),
+        }
+    }
+    ranges.sort();
+    let mut merged: Vec<(char, char)> = Vec::new();
+    for (start, end) in ranges {
+        if let Some(last) = merged.last_mut() {
+            if start as u32 <= last.1 as u32 + 1 {
+                last.1 = last.1.max(end);
+                continue;
+            }
+        }
+        merged.push((start, end));
+    }
+    for (start, end) in merged {
+        if (end as u32).saturating_sub(start as u32) < 2 {
+            items.extend((start..=end).map(|c| format!("{:?}", c)));
+        } else {
+            items.push(format!("character between {:?} and {:?}", start, end));
+        }
+    }
+    items.sort();
+    items.dedup();
+    items
+}
+
+// Reads back an expected item written by mpc_char or mpc_range
+fn mpc_err_expected_class(e: &str) -> Option<(char, char)> {
+    fn quoted(s: &str) -> Option<char> {
+        let mut chars = s.strip_prefix('\'')?.strip_suffix('\'')?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c != '\\' => Some(c),
+            _ => None,
+        }
+    }
+    if let Some(range) = e.strip_prefix("character between ") {
+        let (start, end) = range.split_once(" and ")?;
+        return Some((quoted(start)?, quoted(end)?));
+    }
+    quoted(e).map(|c| (c, c))
+}
+
+/// Result Type
+#[derive(Debug)]
+pub enum MpcResult {
+    Ok(MpcVal),
+    Err(MpcErr),
+}
+
+impl MpcResult {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, MpcResult::Ok(_))
+    }
+
+    pub fn is_err(&self) -> bool {
+        matches!(self, MpcResult::Err(_))
+    }
+
+    pub fn ok(self) -> Option<MpcVal> {
+        match self {
+            MpcResult::Ok(val) => Some(val),
+            MpcResult::Err(_) => None,
+        }
+    }
+
+    pub fn err(self) -> Option<MpcErr> {
+        match self {
+            MpcResult::Ok(_) => None,
+            MpcResult::Err(e) => Some(e),
+        }
+    }
+
+    pub fn unwrap(self) -> MpcVal {
+        match self {
+            MpcResult::Ok(val) => val,
+            MpcResult::Err(e) => panic!("called `MpcResult::unwrap()` on an `Err` value:\n{}", e),
+        }
+    }
+
+    pub fn map(self, f: impl FnOnce(MpcVal) -> MpcVal) -> MpcResult {
+        match self {
+            MpcResult::Ok(val) => MpcResult::Ok(f(val)),
+            MpcResult::Err(e) => MpcResult::Err(e),
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn into_result(self) -> Result<MpcVal, MpcErr> {
+        self.into()
+    }
+}
+
+impl From<MpcResult> for Result<MpcVal, MpcErr> {
+    fn from(result: MpcResult) -> Self {
+        match result {
+            MpcResult::Ok(val) => Ok(val),
+            MpcResult::Err(e) => Err(e),
+        }
+    }
+}
+
+impl From<Result<MpcVal, MpcErr>> for MpcResult {
+    fn from(result: Result<MpcVal, MpcErr>) -> Self {
+        match result {
+            Ok(val) => MpcResult::Ok(val),
+            Err(e) => MpcResult::Err(e),
+        }
+    }
+}
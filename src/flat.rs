@@ -0,0 +1,778 @@
+//! The flat names from before the crate was split into modules, kept so that code written
+//! against them still builds, with a warning that points at where each name lives now
+
+// The wrappers' signatures name the deprecated aliases next to them
+#![allow(deprecated)]
+
+use crate::items::*;
+
+#[deprecated(note = "use mpc::ast::MpcVisit")]
+pub type MpcVisit = crate::ast::MpcVisit;
+
+#[deprecated(note = "use mpc::ast::MpcAstIter")]
+pub type MpcAstIter<'a> = crate::ast::MpcAstIter<'a>;
+
+#[deprecated(note = "use mpc::ast::MpcAstBfsIter")]
+pub type MpcAstBfsIter<'a> = crate::ast::MpcAstBfsIter<'a>;
+
+#[deprecated(note = "use mpc::ast::MpcAst")]
+pub type MpcAst = crate::ast::MpcAst;
+
+#[deprecated(note = "use mpc::ast::MpcTrivia")]
+pub type MpcTrivia = crate::ast::MpcTrivia;
+
+#[deprecated(note = "use mpc::ast::MpcSimplify")]
+pub type MpcSimplify = crate::ast::MpcSimplify;
+
+#[deprecated(note = "use mpc::ast::mpc_ast_eq")]
+pub fn mpc_ast_eq(a: &MpcAst, b: &MpcAst) -> bool {
+    crate::ast::mpc_ast_eq(a, b)
+}
+
+#[deprecated(note = "use mpc::ast::MpcAstChange")]
+pub type MpcAstChange = crate::ast::MpcAstChange;
+
+#[deprecated(note = "use mpc::ast::mpc_ast_diff")]
+pub fn mpc_ast_diff(a: &MpcAst, b: &MpcAst) -> Vec<MpcAstChange> {
+    crate::ast::mpc_ast_diff(a, b)
+}
+
+#[deprecated(note = "use mpc::ast::MpcAstIndex")]
+pub type MpcAstIndex<'a> = crate::ast::MpcAstIndex<'a>;
+
+#[deprecated(note = "use mpc::ast::MpcArenaNode")]
+pub type MpcArenaNode = crate::ast::MpcArenaNode;
+
+#[deprecated(note = "use mpc::ast::MpcAstArena")]
+pub type MpcAstArena = crate::ast::MpcAstArena;
+
+#[deprecated(note = "use mpc::ast::MPC_INTERN_MAX")]
+pub const MPC_INTERN_MAX: usize = crate::ast::MPC_INTERN_MAX;
+
+#[deprecated(note = "use mpc::ast::mpc_ast_error")]
+pub fn mpc_ast_error(ast: &MpcAst, message: &str) -> String {
+    crate::ast::mpc_ast_error(ast, message)
+}
+
+#[deprecated(note = "use mpc::ast::MpcAstFields")]
+pub type MpcAstFields<'a> = crate::ast::MpcAstFields<'a>;
+
+#[deprecated(note = "use mpc::ast::mpca_tag")]
+pub fn mpca_tag(parser: MpcParser, tag: &str) -> MpcParser {
+    crate::ast::mpca_tag(parser, tag)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_add_tag")]
+pub fn mpca_add_tag(parser: MpcParser, tag: &str) -> MpcParser {
+    crate::ast::mpca_add_tag(parser, tag)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_root")]
+pub fn mpca_root(parser: MpcParser) -> MpcParser {
+    crate::ast::mpca_root(parser)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_state")]
+pub fn mpca_state(parser: MpcParser) -> MpcParser {
+    crate::ast::mpca_state(parser)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_trivia")]
+pub fn mpca_trivia(parser: MpcParser) -> MpcParser {
+    crate::ast::mpca_trivia(parser)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_and")]
+pub fn mpca_and(parsers: Vec<MpcParser>) -> MpcParser {
+    crate::ast::mpca_and(parsers)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_or")]
+pub fn mpca_or(parsers: Vec<MpcParser>) -> MpcParser {
+    crate::ast::mpca_or(parsers)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_many")]
+pub fn mpca_many(parser: MpcParser) -> MpcParser {
+    crate::ast::mpca_many(parser)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_many1")]
+pub fn mpca_many1(parser: MpcParser) -> MpcParser {
+    crate::ast::mpca_many1(parser)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_count")]
+pub fn mpca_count(n: i32, parser: MpcParser) -> MpcParser {
+    crate::ast::mpca_count(n, parser)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_sepby")]
+pub fn mpca_sepby(parser: MpcParser, sep: MpcParser) -> MpcParser {
+    crate::ast::mpca_sepby(parser, sep)
+}
+
+#[deprecated(note = "use mpc::ast::mpca_sepby1")]
+pub fn mpca_sepby1(parser: MpcParser, sep: MpcParser) -> MpcParser {
+    crate::ast::mpca_sepby1(parser, sep)
+}
+
+#[deprecated(note = "use mpc::ast::MpcEvent")]
+pub type MpcEvent = crate::ast::MpcEvent;
+
+#[deprecated(note = "use mpc::combinators::MpcAssoc")]
+pub type MpcAssoc = crate::combinators::MpcAssoc;
+
+#[deprecated(note = "use mpc::combinators::MpcOperator")]
+pub type MpcOperator = crate::combinators::MpcOperator;
+
+#[deprecated(note = "use mpc::combinators::mpc_and")]
+pub fn mpc_and(parsers: Vec<MpcParser>, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_and(parsers, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_or")]
+pub fn mpc_or(parsers: Vec<MpcParser>) -> MpcParser {
+    crate::combinators::mpc_or(parsers)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_recover_with")]
+pub fn mpc_recover_with(parser: MpcParser, sync: MpcParser, default: fn() -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_recover_with(parser, sync, default)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_not")]
+pub fn mpc_not(parser: MpcParser) -> MpcParser {
+    crate::combinators::mpc_not(parser)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_apply")]
+pub fn mpc_apply(parser: MpcParser, f: fn(MpcVal) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_apply(parser, f)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_lookahead")]
+pub fn mpc_lookahead(parser: MpcParser) -> MpcParser {
+    crate::combinators::mpc_lookahead(parser)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_expect")]
+pub fn mpc_expect(parser: MpcParser, expected: &str) -> MpcParser {
+    crate::combinators::mpc_expect(parser, expected)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_permutation")]
+pub fn mpc_permutation(parsers: Vec<(MpcParser, bool)>, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_permutation(parsers, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_many")]
+pub fn mpc_many(parser: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_many(parser, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_many1")]
+pub fn mpc_many1(parser: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_many1(parser, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_skip_many")]
+pub fn mpc_skip_many(parser: MpcParser) -> MpcParser {
+    crate::combinators::mpc_skip_many(parser)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_skip_many1")]
+pub fn mpc_skip_many1(parser: MpcParser) -> MpcParser {
+    crate::combinators::mpc_skip_many1(parser)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_fold_many")]
+pub fn mpc_fold_many(init: fn() -> MpcVal, parser: MpcParser, fold: fn(MpcVal, MpcVal) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_fold_many(init, parser, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_count")]
+pub fn mpc_count(n: i32, parser: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_count(n, parser, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_repeat")]
+pub fn mpc_repeat(min: i32, max: Option<i32>, parser: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_repeat(min, max, parser, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_sepby")]
+pub fn mpc_sepby(parser: MpcParser, sep: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_sepby(parser, sep, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_sepby1")]
+pub fn mpc_sepby1(parser: MpcParser, sep: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_sepby1(parser, sep, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_sependby")]
+pub fn mpc_sependby(parser: MpcParser, sep: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_sependby(parser, sep, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_sependby1")]
+pub fn mpc_sependby1(parser: MpcParser, sep: MpcParser, fold: fn(i32, Vec<MpcVal>) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_sependby1(parser, sep, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_chainl1")]
+pub fn mpc_chainl1(term: MpcParser, op: MpcParser, fold: fn(MpcVal, MpcVal, MpcVal) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_chainl1(term, op, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_chainr1")]
+pub fn mpc_chainr1(term: MpcParser, op: MpcParser, fold: fn(MpcVal, MpcVal, MpcVal) -> MpcVal) -> MpcParser {
+    crate::combinators::mpc_chainr1(term, op, fold)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_expr")]
+pub fn mpc_expr(atom: MpcParser, operators: Vec<MpcOperator>) -> MpcParser {
+    crate::combinators::mpc_expr(atom, operators)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_recognize")]
+pub fn mpc_recognize(parser: MpcParser) -> MpcParser {
+    crate::combinators::mpc_recognize(parser)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_consumed")]
+pub fn mpc_consumed(parser: MpcParser) -> MpcParser {
+    crate::combinators::mpc_consumed(parser)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_spanned")]
+pub fn mpc_spanned(parser: MpcParser) -> MpcParser {
+    crate::combinators::mpc_spanned(parser)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_take_until")]
+pub fn mpc_take_until(terminator: MpcParser) -> MpcParser {
+    crate::combinators::mpc_take_until(terminator)
+}
+
+#[deprecated(note = "use mpc::combinators::mpc_string_until")]
+pub fn mpc_string_until(s: &str) -> MpcParser {
+    crate::combinators::mpc_string_until(s)
+}
+
+#[deprecated(note = "use mpc::combinators::mpcf_strfold")]
+pub fn mpcf_strfold(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    crate::combinators::mpcf_strfold(_n, xs)
+}
+
+#[deprecated(note = "use mpc::combinators::mpcf_fst")]
+pub fn mpcf_fst(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    crate::combinators::mpcf_fst(_n, xs)
+}
+
+#[deprecated(note = "use mpc::combinators::mpcf_snd")]
+pub fn mpcf_snd(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    crate::combinators::mpcf_snd(_n, xs)
+}
+
+#[deprecated(note = "use mpc::combinators::mpcf_null")]
+pub fn mpcf_null(_n: i32, _xs: Vec<MpcVal>) -> MpcVal {
+    crate::combinators::mpcf_null(_n, _xs)
+}
+
+#[deprecated(note = "use mpc::combinators::mpcf_trivia")]
+pub fn mpcf_trivia(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    crate::combinators::mpcf_trivia(_n, xs)
+}
+
+#[deprecated(note = "use mpc::combinators::mpcf_state_ast")]
+pub fn mpcf_state_ast(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    crate::combinators::mpcf_state_ast(_n, xs)
+}
+
+#[deprecated(note = "use mpc::combinators::mpcf_fold_ast")]
+pub fn mpcf_fold_ast(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    crate::combinators::mpcf_fold_ast(_n, xs)
+}
+
+#[deprecated(note = "use mpc::error::MpcErr")]
+pub type MpcErr = crate::error::MpcErr;
+
+#[deprecated(note = "use mpc::error::MpcErrKind")]
+pub type MpcErrKind = crate::error::MpcErrKind;
+
+#[deprecated(note = "use mpc::error::MpcSeverity")]
+pub type MpcSeverity = crate::error::MpcSeverity;
+
+#[deprecated(note = "use mpc::error::MpcRelated")]
+pub type MpcRelated = crate::error::MpcRelated;
+
+#[deprecated(note = "use mpc::error::MpcDiagnostic")]
+pub type MpcDiagnostic = crate::error::MpcDiagnostic;
+
+#[deprecated(note = "use mpc::error::MpcResult")]
+pub type MpcResult = crate::error::MpcResult;
+
+#[deprecated(note = "use mpc::generate::MpcGenerator")]
+pub type MpcGenerator<'g> = crate::generate::MpcGenerator<'g>;
+
+#[cfg(feature = "proptest")]
+#[deprecated(note = "use mpc::generate::MpcStrategy")]
+pub type MpcStrategy<'g> = crate::generate::MpcStrategy<'g>;
+
+#[cfg(feature = "proptest")]
+#[deprecated(note = "use mpc::generate::mpc_strategy")]
+pub fn mpc_strategy<'g>(grammar: &'g MpcGrammar, rule: &str) -> MpcStrategy<'g> {
+    crate::generate::mpc_strategy(grammar, rule)
+}
+
+#[cfg(feature = "proptest")]
+#[deprecated(note = "use mpc::generate::MpcValueTree")]
+pub type MpcValueTree<'g> = crate::generate::MpcValueTree<'g>;
+
+#[deprecated(note = "use mpc::generate::MpcCoverage")]
+pub type MpcCoverage<'g> = crate::generate::MpcCoverage<'g>;
+
+#[deprecated(note = "use mpc::grammar::MpcGrammar")]
+pub type MpcGrammar = crate::grammar::MpcGrammar;
+
+#[deprecated(note = "use mpc::grammar::mpc_abnf")]
+#[allow(clippy::result_large_err)]
+pub fn mpc_abnf(filename: &str, abnf: &str) -> Result<MpcGrammar, MpcErr> {
+    crate::grammar::mpc_abnf(filename, abnf)
+}
+
+#[deprecated(note = "use mpc::grammar::mpc_peg")]
+#[allow(clippy::result_large_err)]
+pub fn mpc_peg(filename: &str, peg: &str) -> Result<MpcGrammar, MpcErr> {
+    crate::grammar::mpc_peg(filename, peg)
+}
+
+#[deprecated(note = "use mpc::input::MpcVal")]
+pub type MpcVal = crate::input::MpcVal;
+
+#[deprecated(note = "use mpc::input::MpcState")]
+pub type MpcState = crate::input::MpcState;
+
+#[deprecated(note = "use mpc::input::MpcSpan")]
+pub type MpcSpan = crate::input::MpcSpan;
+
+#[deprecated(note = "use mpc::input::MpcMark")]
+pub type MpcMark = crate::input::MpcMark;
+
+#[deprecated(note = "use mpc::input::MpcInput")]
+pub type MpcInput<'a> = crate::input::MpcInput<'a>;
+
+#[deprecated(note = "use mpc::input::MpcLimits")]
+pub type MpcLimits = crate::input::MpcLimits;
+
+#[deprecated(note = "use mpc::parser::MpcParserType")]
+pub type MpcParserType = crate::parser::MpcParserType;
+
+#[deprecated(note = "use mpc::parser::MpcParser")]
+pub type MpcParser = crate::parser::MpcParser;
+
+#[deprecated(note = "use mpc::parser::MpcCharSet")]
+pub type MpcCharSet = crate::parser::MpcCharSet;
+
+#[deprecated(note = "use mpc::parser::MpcDispatch")]
+pub type MpcDispatch = crate::parser::MpcDispatch;
+
+#[deprecated(note = "use mpc::parser::mpc_parse")]
+pub fn mpc_parse(filename: &str, string: &str, parser: &MpcParser) -> MpcResult {
+    crate::parser::mpc_parse(filename, string, parser)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_partial")]
+#[allow(clippy::result_large_err)]
+pub fn mpc_parse_partial<'s>(
+    filename: &str,
+    string: &'s str,
+    parser: &MpcParser,
+) -> Result<(MpcVal, MpcState, &'s str), MpcErr> {
+    crate::parser::mpc_parse_partial(filename, string, parser)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_cancellable")]
+pub fn mpc_parse_cancellable(
+    filename: &str,
+    string: &str,
+    parser: &MpcParser,
+    cancel: &std::sync::atomic::AtomicBool,
+) -> MpcResult {
+    crate::parser::mpc_parse_cancellable(filename, string, parser, cancel)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_limited")]
+pub fn mpc_parse_limited(filename: &str, string: &str, parser: &MpcParser, limits: MpcLimits) -> MpcResult {
+    crate::parser::mpc_parse_limited(filename, string, parser, limits)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_file")]
+pub fn mpc_parse_file(path: impl AsRef<std::path::Path>, parser: &MpcParser) -> MpcResult {
+    crate::parser::mpc_parse_file(path, parser)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_reader")]
+pub fn mpc_parse_reader(filename: &str, reader: impl std::io::Read, parser: &MpcParser) -> MpcResult {
+    crate::parser::mpc_parse_reader(filename, reader, parser)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_lines")]
+pub fn mpc_parse_lines(
+    filename: &str,
+    reader: impl std::io::BufRead,
+    parser: &MpcParser,
+) -> (Vec<MpcVal>, Vec<MpcErr>) {
+    crate::parser::mpc_parse_lines(filename, reader, parser)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_owned")]
+pub fn mpc_parse_owned(input: &mut MpcInput<'static>, parser: &MpcParser) -> MpcResult {
+    crate::parser::mpc_parse_owned(input, parser)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_pipe")]
+pub fn mpc_parse_pipe(filename: &str, pipe: &mut impl std::io::BufRead, parser: &MpcParser) -> Option<MpcResult> {
+    crate::parser::mpc_parse_pipe(filename, pipe, parser)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_all_errors")]
+pub fn mpc_parse_all_errors(filename: &str, string: &str, parser: &MpcParser) -> Result<MpcVal, Vec<MpcErr>> {
+    crate::parser::mpc_parse_all_errors(filename, string, parser)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_grammar")]
+pub fn mpc_parse_grammar(filename: &str, string: &str, grammar: &MpcGrammar, start: &str) -> MpcResult {
+    crate::parser::mpc_parse_grammar(filename, string, grammar, start)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_memo")]
+pub fn mpc_parse_memo(filename: &str, string: &str, parser: &MpcParser) -> MpcResult {
+    crate::parser::mpc_parse_memo(filename, string, parser, MpcLimits::default())
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_zero_copy")]
+pub fn mpc_parse_zero_copy(filename: &str, string: &str, parser: &MpcParser) -> MpcResult {
+    crate::parser::mpc_parse_zero_copy(filename, string, parser)
+}
+
+#[cfg(feature = "rayon")]
+#[deprecated(note = "use mpc::parser::mpc_parse_many_parallel")]
+pub fn mpc_parse_many_parallel<F, S, T>(
+    inputs: &[(F, S)],
+    parser: &MpcParser,
+    convert: fn(MpcVal) -> T,
+) -> Vec<Result<T, MpcErr>>
+where
+    F: AsRef<str> + Sync,
+    S: AsRef<str> + Sync,
+    T: Send,
+{
+    crate::parser::mpc_parse_many_parallel(inputs, parser, convert)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_parse_events")]
+#[allow(clippy::result_large_err)]
+pub fn mpc_parse_events(
+    filename: &str,
+    string: &str,
+    parser: &MpcParser,
+    on_event: impl FnMut(MpcEvent),
+) -> Result<(), MpcErr> {
+    crate::parser::mpc_parse_events(filename, string, parser, on_event)
+}
+
+#[deprecated(note = "use mpc::parser::mpc_val_clone")]
+pub fn mpc_val_clone(val: &MpcVal) -> Option<MpcVal> {
+    crate::parser::mpc_val_clone(val)
+}
+
+#[deprecated(note = "use mpc::parser::MpcFeed")]
+pub type MpcFeed = crate::parser::MpcFeed;
+
+#[deprecated(note = "use mpc::parser::MpcSession")]
+pub type MpcSession<'p> = crate::parser::MpcSession<'p>;
+
+#[deprecated(note = "use mpc::parser::MpcIter")]
+pub type MpcIter<'p, R> = crate::parser::MpcIter<'p, R>;
+
+#[deprecated(note = "use mpc::parser::mpc_iterate")]
+pub fn mpc_iterate<'p, R: std::io::Read>(filename: &str, reader: R, parser: &'p MpcParser) -> MpcIter<'p, R> {
+    crate::parser::mpc_iterate(filename, reader, parser, MpcLimits::default())
+}
+
+#[deprecated(note = "use mpc::parser::MpcRecords")]
+pub type MpcRecords<'p> = crate::parser::MpcRecords<'p>;
+
+#[cfg(feature = "async")]
+#[deprecated(note = "use mpc::parser::mpc_parse_async")]
+pub async fn mpc_parse_async(
+    filename: &str,
+    reader: impl tokio::io::AsyncRead + Unpin,
+    parser: &MpcParser,
+) -> MpcResult {
+    crate::parser::mpc_parse_async(filename, reader, parser).await
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_any")]
+pub fn mpc_any() -> MpcParser {
+    crate::primitives::mpc_any()
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_char")]
+pub fn mpc_char(c: char) -> MpcParser {
+    crate::primitives::mpc_char(c)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_range")]
+pub fn mpc_range(s: char, e: char) -> MpcParser {
+    crate::primitives::mpc_range(s, e)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_oneof")]
+pub fn mpc_oneof(s: &str) -> MpcParser {
+    crate::primitives::mpc_oneof(s)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_noneof")]
+pub fn mpc_noneof(s: &str) -> MpcParser {
+    crate::primitives::mpc_noneof(s)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_satisfy")]
+pub fn mpc_satisfy(f: fn(char) -> bool) -> MpcParser {
+    crate::primitives::mpc_satisfy(f)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_string")]
+pub fn mpc_string(s: &str) -> MpcParser {
+    crate::primitives::mpc_string(s)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_pass")]
+pub fn mpc_pass() -> MpcParser {
+    crate::primitives::mpc_pass()
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_fail")]
+pub fn mpc_fail(m: &str) -> MpcParser {
+    crate::primitives::mpc_fail(m)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_lift")]
+pub fn mpc_lift(f: fn() -> MpcVal) -> MpcParser {
+    crate::primitives::mpc_lift(f)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_lift_val")]
+pub fn mpc_lift_val(f: fn() -> MpcVal) -> MpcParser {
+    crate::primitives::mpc_lift_val(f)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_anchor")]
+pub fn mpc_anchor(f: fn(char, char) -> bool) -> MpcParser {
+    crate::primitives::mpc_anchor(f)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_state")]
+pub fn mpc_state() -> MpcParser {
+    crate::primitives::mpc_state()
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_rest")]
+pub fn mpc_rest() -> MpcParser {
+    crate::primitives::mpc_rest()
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_rest_of_line")]
+pub fn mpc_rest_of_line() -> MpcParser {
+    crate::primitives::mpc_rest_of_line()
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_rule")]
+pub fn mpc_rule(name: &str) -> MpcParser {
+    crate::primitives::mpc_rule(name)
+}
+
+#[deprecated(note = "use mpc::primitives::mpc_lazy")]
+pub fn mpc_lazy(build: fn() -> MpcParser) -> MpcParser {
+    crate::primitives::mpc_lazy(build)
+}
+
+#[deprecated(note = "use mpc::testing::mpc_test_pass")]
+pub fn mpc_test_pass<T: PartialEq + std::fmt::Debug + 'static>(parser: &MpcParser, input: &str, expected: &T) {
+    crate::testing::mpc_test_pass(parser, input, expected)
+}
+
+#[deprecated(note = "use mpc::testing::mpc_test_pass_with")]
+pub fn mpc_test_pass_with<T: std::fmt::Debug + 'static>(parser: &MpcParser, input: &str, expected: &T, tester: impl Fn(&T, &T) -> bool) {
+    crate::testing::mpc_test_pass_with(parser, input, expected, tester)
+}
+
+#[deprecated(note = "use mpc::testing::mpc_test_fail")]
+pub fn mpc_test_fail<T: PartialEq + std::fmt::Debug + 'static>(parser: &MpcParser, input: &str, expected: &T) {
+    crate::testing::mpc_test_fail(parser, input, expected)
+}
+
+#[deprecated(note = "use mpc::testing::mpc_test_fail_with")]
+pub fn mpc_test_fail_with<T: std::fmt::Debug + 'static>(parser: &MpcParser, input: &str, expected: &T, tester: impl Fn(&T, &T) -> bool) {
+    crate::testing::mpc_test_fail_with(parser, input, expected, tester)
+}
+
+#[deprecated(note = "use mpc::testing::mpc_test_snapshot")]
+pub fn mpc_test_snapshot(ast: &MpcAst, path: impl AsRef<std::path::Path>) {
+    crate::testing::mpc_test_snapshot(ast, path)
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_eoi")]
+pub fn mpc_eoi() -> MpcParser {
+    crate::tokens::mpc_eoi()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_soi")]
+pub fn mpc_soi() -> MpcParser {
+    crate::tokens::mpc_soi()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_boundary")]
+pub fn mpc_boundary() -> MpcParser {
+    crate::tokens::mpc_boundary()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_boundary_newline")]
+pub fn mpc_boundary_newline() -> MpcParser {
+    crate::tokens::mpc_boundary_newline()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_whitespace")]
+pub fn mpc_whitespace() -> MpcParser {
+    crate::tokens::mpc_whitespace()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_whitespaces")]
+pub fn mpc_whitespaces() -> MpcParser {
+    crate::tokens::mpc_whitespaces()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_tok")]
+pub fn mpc_tok(parser: MpcParser) -> MpcParser {
+    crate::tokens::mpc_tok(parser)
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_sym")]
+pub fn mpc_sym(s: &str) -> MpcParser {
+    crate::tokens::mpc_sym(s)
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_startwith")]
+pub fn mpc_startwith(parser: MpcParser) -> MpcParser {
+    crate::tokens::mpc_startwith(parser)
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_endwith")]
+pub fn mpc_endwith(parser: MpcParser) -> MpcParser {
+    crate::tokens::mpc_endwith(parser)
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_blank")]
+pub fn mpc_blank() -> MpcParser {
+    crate::tokens::mpc_blank()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_newline")]
+pub fn mpc_newline() -> MpcParser {
+    crate::tokens::mpc_newline()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_tab")]
+pub fn mpc_tab() -> MpcParser {
+    crate::tokens::mpc_tab()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_escape")]
+pub fn mpc_escape() -> MpcParser {
+    crate::tokens::mpc_escape()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_digit")]
+pub fn mpc_digit() -> MpcParser {
+    crate::tokens::mpc_digit()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_hexdigit")]
+pub fn mpc_hexdigit() -> MpcParser {
+    crate::tokens::mpc_hexdigit()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_octdigit")]
+pub fn mpc_octdigit() -> MpcParser {
+    crate::tokens::mpc_octdigit()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_digits")]
+pub fn mpc_digits() -> MpcParser {
+    crate::tokens::mpc_digits()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_hexdigits")]
+pub fn mpc_hexdigits() -> MpcParser {
+    crate::tokens::mpc_hexdigits()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_octdigits")]
+pub fn mpc_octdigits() -> MpcParser {
+    crate::tokens::mpc_octdigits()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_lower")]
+pub fn mpc_lower() -> MpcParser {
+    crate::tokens::mpc_lower()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_upper")]
+pub fn mpc_upper() -> MpcParser {
+    crate::tokens::mpc_upper()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_alpha")]
+pub fn mpc_alpha() -> MpcParser {
+    crate::tokens::mpc_alpha()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_underscore")]
+pub fn mpc_underscore() -> MpcParser {
+    crate::tokens::mpc_underscore()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_alphanum")]
+pub fn mpc_alphanum() -> MpcParser {
+    crate::tokens::mpc_alphanum()
+}
+
+#[deprecated(note = "use mpc::tokens::mpc_parse_sepby")]
+pub fn mpc_parse_sepby<T: MpcParse>(sep: &str) -> MpcParser {
+    crate::tokens::mpc_parse_sepby::<T>(sep)
+}
+
+#[deprecated(note = "use mpc::vm::MpcOp")]
+pub type MpcOp<'p> = crate::vm::MpcOp<'p>;
+
+#[deprecated(note = "use mpc::vm::MpcProgram")]
+pub type MpcProgram<'p> = crate::vm::MpcProgram<'p>;
+
+#[deprecated(note = "use mpc::vm::mpc_compile")]
+pub fn mpc_compile(parser: &MpcParser) -> MpcProgram<'_> {
+    crate::vm::mpc_compile(parser)
+}
+
+#[deprecated(note = "use mpc::vm::mpc_compile_grammar")]
+pub fn mpc_compile_grammar<'p>(grammar: &'p MpcGrammar, start: &str) -> Option<MpcProgram<'p>> {
+    crate::vm::mpc_compile_grammar(grammar, start)
+}
+
+#[deprecated(note = "use mpc::vm::mpc_parse_vm")]
+pub fn mpc_parse_vm(filename: &str, string: &str, program: &MpcProgram) -> MpcResult {
+    crate::vm::mpc_parse_vm(filename, string, program)
+}
@@ -0,0 +1,566 @@
+//! Generating inputs from a grammar, and measuring how much of a grammar inputs reach
+
+use crate::items::*;
+
+// Input Generation
+
+// Fewest characters a match of `parser` can take, given the rules' minimums. usize::MAX means
+// no match is known (yet), as for a parser that always fails.
+fn mpc_min_size(parser: &MpcParser, rules: &std::collections::HashMap<String, usize>) -> usize {
+    let sum = |parsers: &mut dyn Iterator<Item = &MpcParser>| parsers.fold(0usize, |total, p| total.saturating_add(mpc_min_size(p, rules)));
+    match &parser.parser_type {
+        MpcParserType::Any
+        | MpcParserType::Char(_)
+        | MpcParserType::Range(_, _)
+        | MpcParserType::OneOf(_, _)
+        | MpcParserType::NoneOf(_, _)
+        | MpcParserType::Satisfy(_) => 1,
+        MpcParserType::String(s) => s.chars().count(),
+        MpcParserType::Fail(_) => usize::MAX,
+        MpcParserType::And(parsers, _, _) => sum(&mut parsers.iter().map(|p| &**p)),
+        MpcParserType::Or(parsers, _) => parsers.iter().map(|p| mpc_min_size(p, rules)).min().unwrap_or(usize::MAX),
+        MpcParserType::Permutation(parsers, _) => sum(&mut parsers.iter().filter(|(_, optional)| !optional).map(|(p, _)| &**p)),
+        MpcParserType::Many1(p, _)
+        | MpcParserType::SkipMany1(p)
+        | MpcParserType::SepBy1(p, _, _)
+        | MpcParserType::SepEndBy1(p, _, _)
+        | MpcParserType::ChainL1(p, _, _)
+        | MpcParserType::ChainR1(p, _, _)
+        | MpcParserType::Expr(p, _)
+        | MpcParserType::Recognize(p)
+        | MpcParserType::Apply(p, _)
+        | MpcParserType::Consumed(p)
+        | MpcParserType::Spanned(p)
+        | MpcParserType::Expect(p, _)
+        | MpcParserType::Recover(p, _, _)
+        | MpcParserType::Tag(p, _)
+        | MpcParserType::AddTag(p, _)
+        | MpcParserType::Root(p) => mpc_min_size(p, rules),
+        MpcParserType::Count(n, p, _) | MpcParserType::Repeat(n, _, p, _) => mpc_min_size(p, rules).saturating_mul(*n as usize),
+        MpcParserType::Rule(name) => rules.get(name).copied().unwrap_or(usize::MAX),
+        _ => 0,
+    }
+}
+
+// Whether `parser` matches all of `string`
+fn mpc_accepts(grammar: Option<&MpcGrammar>, parser: &MpcParser, string: &str) -> bool {
+    let mut input = MpcInput::new("generated", string);
+    input.grammar = grammar;
+    matches!(parser.parse(&mut input), MpcResult::Ok(_)) && input.abort.is_none() && input.pos == string.len()
+}
+
+/// Random input generator: produces strings that a parser, or a rule of a grammar, accepts in
+/// full. Candidates are built by walking the parser tree, making random choices, and each one is
+/// checked by running the parser on it, so lookahead, anchors and the like are respected.
+///
+/// ```
+/// use mpc::generate::MpcGenerator;
+/// use mpc::prelude::*;
+///
+/// let number = mpc_and(vec![mpc_many1(mpc_digit(), mpcf_strfold), mpc_char(';')], mpcf_strfold);
+/// let mut generator = MpcGenerator::new(42);
+/// let input = generator.generate(&number).unwrap();
+/// assert!(input.ends_with(';') && input[..input.len() - 1].chars().all(|c| c.is_ascii_digit()));
+/// ```
+pub struct MpcGenerator<'g> {
+    pub max_depth: usize,  // Rule nesting past which the shortest alternatives are taken
+    pub max_repeat: usize,  // Most repetitions added beyond a repetition's minimum
+    pub attempts: usize,  // Candidates tried before giving up
+    grammar: Option<&'g MpcGrammar>,
+    min_sizes: std::collections::HashMap<String, usize>,
+    rng: u64,
+}
+
+impl<'g> MpcGenerator<'g> {
+    pub fn new(seed: u64) -> Self {
+        MpcGenerator {
+            max_depth: 8,
+            max_repeat: 4,
+            attempts: 100,
+            grammar: None,
+            min_sizes: std::collections::HashMap::new(),
+            rng: seed,
+        }
+    }
+
+    /// Generator for the rules of `grammar`, which `mpc_rule` references are looked up in
+    pub fn with_grammar(grammar: &'g MpcGrammar, seed: u64) -> Self {
+        let mut min_sizes: std::collections::HashMap<String, usize> = grammar.rules()
+            .map(|(name, _)| (name.to_string(), usize::MAX))
+            .collect();
+        // Shrinks to a fixed point, as rules' minimums depend on each other
+        loop {
+            let mut changed = false;
+            for (name, parser) in grammar.rules() {
+                let size = mpc_min_size(parser, &min_sizes);
+                if size < min_sizes[name] {
+                    min_sizes.insert(name.to_string(), size);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        MpcGenerator { grammar: Some(grammar), min_sizes, ..MpcGenerator::new(seed) }
+    }
+
+    /// A random string `parser` matches in full, or None if none was found in `attempts` tries.
+    /// Also None straight away when no match of `parser` is finite, as for `a <- '(' a ')'`.
+    pub fn generate(&mut self, parser: &MpcParser) -> Option<String> {
+        if mpc_min_size(parser, &self.min_sizes) == usize::MAX {
+            return None;
+        }
+        for _ in 0..self.attempts {
+            let mut candidate = String::new();
+            if !self.gen(parser, 0, &mut candidate) {
+                continue;
+            }
+            if mpc_accepts(self.grammar, parser, &candidate) {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    /// A random string the rule `name` matches in full
+    pub fn generate_rule(&mut self, name: &str) -> Option<String> {
+        self.generate(&mpc_rule(name))
+    }
+
+    // SplitMix64
+    fn next(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in 0..n, for n > 0
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn printable(&mut self) -> char {
+        (b' ' + self.below(95) as u8) as char
+    }
+
+    // A random character satisfying `f`: printable ASCII first, then the first 64K code points
+    fn char_where(&mut self, f: impl Fn(char) -> bool) -> Option<char> {
+        (0..100).map(|_| self.printable()).find(|&c| f(c)).or_else(|| (0..=0xFFFF).filter_map(char::from_u32).find(|&c| f(c)))
+    }
+
+    // Appends `n` random characters satisfying `f`
+    fn chars(&mut self, out: &mut String, n: usize, f: &dyn Fn(char) -> bool) -> bool {
+        for _ in 0..n {
+            match self.char_where(f) {
+                Some(c) => out.push(c),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // Appends `min` or `min + 1` operators of an expression, each picked from `operators`
+    fn gen_operators(&mut self, operators: &[&MpcParser], min: usize, depth: usize, out: &mut String) -> bool {
+        if operators.is_empty() {
+            return true;
+        }
+        let n = self.count(min, Some(min + 1), depth);
+        (0..n).all(|_| {
+            let operator = operators[self.below(operators.len())];
+            self.gen(operator, depth, out)
+        })
+    }
+
+    // How many times to repeat, given the minimum and maximum
+    fn count(&mut self, min: usize, max: Option<usize>, depth: usize) -> usize {
+        let max = max.map_or(min + self.max_repeat, |max| max.min(min + self.max_repeat));
+        if depth > self.max_depth || max <= min {
+            min
+        } else {
+            min + self.below(max - min + 1)
+        }
+    }
+
+    // Appends a random match of `parser` to `out`, returning false if one can't be made
+    fn gen(&mut self, parser: &MpcParser, depth: usize, out: &mut String) -> bool {
+        match &parser.parser_type {
+            MpcParserType::Any => self.chars(out, 1, &|_| true),
+            MpcParserType::Char(c) => {
+                out.push(*c);
+                true
+            }
+            MpcParserType::Range(start, end) => {
+                let (start, end) = (*start as u32, *end as u32);
+                if start > end {
+                    return false;
+                }
+                let c = (0..100).find_map(|_| char::from_u32(start + self.below((end - start + 1) as usize) as u32));
+                out.extend(c);
+                c.is_some()
+            }
+            MpcParserType::OneOf(s, _) => {
+                let options: Vec<char> = s.chars().collect();
+                if options.is_empty() {
+                    return false;
+                }
+                out.push(options[self.below(options.len())]);
+                true
+            }
+            MpcParserType::NoneOf(_, set) => self.chars(out, 1, &|c| !set.contains(c)),
+            MpcParserType::Satisfy(f) => self.chars(out, 1, &|c| f(c)),
+            MpcParserType::String(s) => {
+                out.push_str(s);
+                true
+            }
+            MpcParserType::Fail(_) => false,
+            MpcParserType::Rest => {
+                let n = self.count(0, None, depth);
+                self.chars(out, n, &|_| true)
+            }
+            MpcParserType::RestOfLine => {
+                let n = self.count(0, None, depth);
+                self.chars(out, n, &|c| c != '\n' && c != '\r')
+            }
+            MpcParserType::StringUntil(end) => {
+                let n = self.count(0, None, depth);
+                let start = out.len();
+                self.chars(out, n, &|_| true) && !out[start..].contains(end.as_str())
+            }
+            MpcParserType::TakeUntil(_) => {
+                let n = self.count(0, None, depth);
+                self.chars(out, n, &|_| true)
+            }
+            MpcParserType::And(parsers, _, _) => parsers.iter().all(|p| self.gen(p, depth, out)),
+            MpcParserType::Or(parsers, _) => {
+                if parsers.is_empty() {
+                    return false;
+                }
+                let i = if depth > self.max_depth {
+                    let sizes: Vec<usize> = parsers.iter().map(|p| mpc_min_size(p, &self.min_sizes)).collect();
+                    let i = (0..parsers.len()).min_by_key(|&i| sizes[i]).unwrap();
+                    // Every alternative recurses without end
+                    if sizes[i] == usize::MAX {
+                        return false;
+                    }
+                    i
+                } else {
+                    self.below(parsers.len())
+                };
+                self.gen(&parsers[i], depth, out)
+            }
+            MpcParserType::Permutation(parsers, _) => {
+                let mut order: Vec<usize> = (0..parsers.len()).collect();
+                for i in (1..order.len()).rev() {
+                    order.swap(i, self.below(i + 1));
+                }
+                order.into_iter().all(|i| {
+                    let (p, optional) = &parsers[i];
+                    let skip = *optional && (depth > self.max_depth || self.below(2) == 0);
+                    skip || self.gen(p, depth, out)
+                })
+            }
+            MpcParserType::Many(p, _) | MpcParserType::SkipMany(p) | MpcParserType::FoldMany(_, p, _) => {
+                let n = self.count(0, None, depth);
+                (0..n).all(|_| self.gen(p, depth, out))
+            }
+            MpcParserType::Many1(p, _) | MpcParserType::SkipMany1(p) => {
+                let n = self.count(1, None, depth);
+                (0..n).all(|_| self.gen(p, depth, out))
+            }
+            MpcParserType::Count(n, p, _) => (0..*n).all(|_| self.gen(p, depth, out)),
+            MpcParserType::Repeat(min, max, p, _) => {
+                let n = self.count(*min as usize, max.map(|max| max as usize), depth);
+                (0..n).all(|_| self.gen(p, depth, out))
+            }
+            MpcParserType::SepBy(p, sep, _)
+            | MpcParserType::SepBy1(p, sep, _)
+            | MpcParserType::SepEndBy(p, sep, _)
+            | MpcParserType::SepEndBy1(p, sep, _)
+            | MpcParserType::ChainL1(p, sep, _)
+            | MpcParserType::ChainR1(p, sep, _) => {
+                let min = match parser.parser_type {
+                    MpcParserType::SepBy(_, _, _) | MpcParserType::SepEndBy(_, _, _) => 0,
+                    _ => 1,
+                };
+                let n = self.count(min, None, depth);
+                let ok = (0..n).all(|i| (i == 0 || self.gen(sep, depth, out)) && self.gen(p, depth, out));
+                let trailing = n > 0
+                    && matches!(parser.parser_type, MpcParserType::SepEndBy(_, _, _) | MpcParserType::SepEndBy1(_, _, _))
+                    && depth <= self.max_depth
+                    && self.below(2) == 0;
+                ok && (!trailing || self.gen(sep, depth, out))
+            }
+            MpcParserType::Expr(atom, operators) => {
+                let pick = |kind: fn(&MpcOperator) -> Option<&MpcParser>| operators.iter().filter_map(kind).collect::<Vec<_>>();
+                let prefix = pick(|o| if let MpcOperator::Prefix(p, _) = o { Some(p) } else { None });
+                let infix = pick(|o| if let MpcOperator::Infix(p, _, _) = o { Some(p) } else { None });
+                let postfix = pick(|o| if let MpcOperator::Postfix(p, _) = o { Some(p) } else { None });
+                let n = if infix.is_empty() { 1 } else { self.count(1, None, depth) };
+                (0..n).all(|i| {
+                    (i == 0 || self.gen_operators(&infix, 1, depth, out))
+                        && self.gen_operators(&prefix, 0, depth, out)
+                        && self.gen(atom, depth, out)
+                        && self.gen_operators(&postfix, 0, depth, out)
+                })
+            }
+            MpcParserType::Rule(name) => {
+                let Some(rule) = self.grammar.and_then(|grammar| grammar.rule(name)) else {
+                    return false;
+                };
+                if depth > self.max_depth && self.min_sizes.get(name).map_or(true, |&size| size == usize::MAX) {
+                    return false;
+                }
+                self.gen(rule, depth + 1, out)
+            }
+            MpcParserType::Lazy(build, cell) => self.gen(&cell.body(*build), depth + 1, out),
+            MpcParserType::Recognize(p)
+            | MpcParserType::Apply(p, _)
+            | MpcParserType::Consumed(p)
+            | MpcParserType::Spanned(p)
+            | MpcParserType::Expect(p, _)
+            | MpcParserType::Recover(p, _, _)
+            | MpcParserType::Tag(p, _)
+            | MpcParserType::AddTag(p, _)
+            | MpcParserType::Root(p) => self.gen(p, depth, out),
+            // Consume nothing; whether they hold is left to the check of the whole candidate
+            MpcParserType::Pass
+            | MpcParserType::Lift(_)
+            | MpcParserType::LiftVal(_)
+            | MpcParserType::Anchor(_)
+            | MpcParserType::State
+            | MpcParserType::Not(_)
+            | MpcParserType::Lookahead(_) => true,
+        }
+    }
+}
+
+/// Proptest strategy generating inputs that a rule of a grammar accepts, see `mpc_strategy`
+#[cfg(feature = "proptest")]
+pub struct MpcStrategy<'g> {
+    pub max_depth: usize,
+    pub max_repeat: usize,
+    grammar: &'g MpcGrammar,
+    rule: String,
+}
+
+/// Strategy for property tests over inputs the rule `rule` of `grammar` accepts, made by
+/// `MpcGenerator`. Failing inputs shrink by deleting ever smaller pieces of them, keeping only
+/// deletions the rule still accepts.
+///
+/// ```ignore
+/// proptest! {
+///     #[test]
+///     fn round_trips(input in mpc_strategy(&grammar, "json")) { ... }
+/// }
+/// ```
+#[cfg(feature = "proptest")]
+pub fn mpc_strategy<'g>(grammar: &'g MpcGrammar, rule: &str) -> MpcStrategy<'g> {
+    let defaults = MpcGenerator::new(0);
+    MpcStrategy { max_depth: defaults.max_depth, max_repeat: defaults.max_repeat, grammar, rule: rule.to_string() }
+}
+
+#[cfg(feature = "proptest")]
+impl std::fmt::Debug for MpcStrategy<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MpcStrategy({})", self.rule)
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl<'g> proptest::strategy::Strategy for MpcStrategy<'g> {
+    type Tree = MpcValueTree<'g>;
+    type Value = String;
+
+    fn new_tree(&self, runner: &mut proptest::test_runner::TestRunner) -> proptest::strategy::NewTree<Self> {
+        use proptest::prelude::Rng;
+        let mut generator = MpcGenerator::with_grammar(self.grammar, runner.rng().next_u64());
+        generator.max_depth = self.max_depth;
+        generator.max_repeat = self.max_repeat;
+        match generator.generate_rule(&self.rule) {
+            Some(current) => Ok(MpcValueTree { grammar: self.grammar, rule: mpc_rule(&self.rule), current, previous: None, chunk: 0, offset: 0 }),
+            None => Err(format!("no input found that rule {} accepts", self.rule).into()),
+        }
+    }
+}
+
+/// Generated input being shrunk: each step deletes a piece of `chunk` characters at `offset`,
+/// trying halves first and single characters last
+#[cfg(feature = "proptest")]
+pub struct MpcValueTree<'g> {
+    grammar: &'g MpcGrammar,
+    rule: MpcParser,
+    current: String,
+    previous: Option<String>,
+    chunk: usize,
+    offset: usize,
+}
+
+#[cfg(feature = "proptest")]
+impl std::fmt::Debug for MpcValueTree<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MpcValueTree({:?})", self.current)
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl proptest::strategy::ValueTree for MpcValueTree<'_> {
+    type Value = String;
+
+    fn current(&self) -> String {
+        self.current.clone()
+    }
+
+    fn simplify(&mut self) -> bool {
+        loop {
+            let chars: Vec<(usize, char)> = self.current.char_indices().collect();
+            if self.chunk == 0 {
+                self.chunk = (chars.len() / 2).max(1);
+                self.offset = 0;
+            }
+            if self.offset + self.chunk > chars.len() {
+                if self.chunk == 1 {
+                    return false;
+                }
+                self.chunk /= 2;
+                self.offset = 0;
+                continue;
+            }
+            let start = chars[self.offset].0;
+            let end = chars.get(self.offset + self.chunk).map_or(self.current.len(), |&(i, _)| i);
+            let candidate = format!("{}{}", &self.current[..start], &self.current[end..]);
+            if mpc_accepts(Some(self.grammar), &self.rule, &candidate) {
+                self.previous = Some(std::mem::replace(&mut self.current, candidate));
+                return true;
+            }
+            self.offset += 1;
+        }
+    }
+
+    fn complicate(&mut self) -> bool {
+        match self.previous.take() {
+            // The deletion made the test pass, so it is kept out and the next one tried
+            Some(previous) => {
+                self.current = previous;
+                self.offset += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl MpcGenerator<'_> {
+    /// Input the rule `name` accepts, seeded from the bytes of `u`, for fuzz targets
+    pub fn arbitrary_rule(&mut self, u: &mut arbitrary::Unstructured, name: &str) -> arbitrary::Result<String> {
+        self.rng = u.arbitrary()?;
+        self.generate_rule(name).ok_or(arbitrary::Error::IncorrectFormat)
+    }
+}
+
+// Grammar Coverage
+
+/// Which rules and alternatives of a grammar a corpus of inputs exercises. Each input is parsed
+/// with `parse`, recording every parser that matched, even where the parse later backtracked
+/// out of it; `unreached` then lists the rules and alternatives that never matched.
+///
+/// ```
+/// use mpc::generate::MpcCoverage;
+/// use mpc::prelude::*;
+///
+/// let grammar = mpc_peg("bool.peg", "value <- 'true' / 'false' / 'null'").unwrap();
+/// let mut coverage = MpcCoverage::new(&grammar);
+/// for input in ["true", "false"] {
+///     assert!(coverage.parse("test", input, "value").is_ok());
+/// }
+/// assert_eq!(coverage.unreached(), ["value: alternative \"null\" never matched in (\"true\" | \"false\" | \"null\")"]);
+/// ```
+pub struct MpcCoverage<'g> {
+    grammar: &'g MpcGrammar,
+    hits: std::collections::HashSet<usize>,
+}
+
+impl<'g> MpcCoverage<'g> {
+    pub fn new(grammar: &'g MpcGrammar) -> Self {
+        MpcCoverage { grammar, hits: std::collections::HashSet::new() }
+    }
+
+    /// Parses `string` from rule `start` like `mpc_parse_grammar`, adding to the coverage
+    pub fn parse(&mut self, filename: &str, string: &str, start: &str) -> MpcResult {
+        let mut input = MpcInput::new(filename, string);
+        input.grammar = Some(self.grammar);
+        input.coverage = Some(std::mem::take(&mut self.hits));
+        let result = mpc_parse_input(&mut input, &mpc_rule(start));
+        self.hits = input.coverage.take().unwrap_or_default();
+        result
+    }
+
+    /// Rules and alternatives that never matched, each described in a sentence. Alternatives
+    /// inside a rule or alternative that never matched are left out, as they could not have.
+    pub fn unreached(&self) -> Vec<String> {
+        let mut unreached = Vec::new();
+        self.count(&mut unreached);
+        unreached
+    }
+
+    /// Number of rules matched and defined, and of alternatives matched and written
+    pub fn counts(&self) -> ((usize, usize), (usize, usize)) {
+        self.count(&mut Vec::new())
+    }
+
+    /// Summary line followed by the `unreached` list, one per line
+    pub fn report(&self) -> String {
+        let mut unreached = Vec::new();
+        let ((rules, rule_total), (alternatives, alternative_total)) = self.count(&mut unreached);
+        let mut report = format!("{} of {} rules and {} of {} alternatives matched\n", rules, rule_total, alternatives, alternative_total);
+        for line in unreached {
+            report.push_str("  ");
+            report.push_str(&line);
+            report.push('\n');
+        }
+        report
+    }
+
+    fn count(&self, unreached: &mut Vec<String>) -> ((usize, usize), (usize, usize)) {
+        let mut rules = (0, 0);
+        let mut alternatives = (0, 0);
+        for (name, parser) in self.grammar.rules() {
+            let hit = self.hits.contains(&(parser as *const MpcParser as usize));
+            rules.1 += 1;
+            if hit {
+                rules.0 += 1;
+            } else {
+                unreached.push(format!("{}: never matched", name));
+            }
+            mpc_coverage(parser, &self.hits, name, hit, &mut alternatives, unreached);
+        }
+        (rules, alternatives)
+    }
+}
+
+// Counts the alternatives under `parser` that matched, and how many there are. Unmatched ones
+// are described in `unreached` while `listed` holds, i.e. while everything around them matched.
+fn mpc_coverage(parser: &MpcParser, hits: &std::collections::HashSet<usize>, rule: &str, listed: bool, counts: &mut (usize, usize), unreached: &mut Vec<String>) {
+    let MpcParserType::Or(ref ps, _) = parser.parser_type else {
+        for p in parser.children() {
+            mpc_coverage(p, hits, rule, listed, counts, unreached);
+        }
+        return;
+    };
+    for (i, p) in ps.iter().enumerate() {
+        let hit = hits.contains(&(&**p as *const MpcParser as usize));
+        counts.1 += 1;
+        if hit {
+            counts.0 += 1;
+        } else if listed {
+            match p.parser_type {
+                MpcParserType::Pass if i + 1 == ps.len() => unreached.push(format!("{}: {} never left out", rule, parser)),
+                _ => unreached.push(format!("{}: alternative {} never matched in {}", rule, p, parser)),
+            }
+        }
+        mpc_coverage(p, hits, rule, listed && hit, counts, unreached);
+    }
+}
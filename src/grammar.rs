@@ -0,0 +1,1535 @@
+//! Grammars of named rules: analysis, printing as EBNF, railroad diagrams and Graphviz, import
+//! from ABNF and PEG, and the `parser!` DSL
+
+use crate::items::*;
+
+/// Grammar: named rules that can refer to each other, and to themselves, through `mpc_rule`.
+/// This plays the part of `mpc_new` and `mpc_define` in C.
+#[derive(Default)]
+pub struct MpcGrammar {
+    pub(crate) rules: Vec<(String, MpcParser)>,
+    pub(crate) index: std::collections::HashMap<String, usize>,
+}
+
+impl MpcGrammar {
+    pub fn new() -> Self {
+        MpcGrammar::default()
+    }
+
+    /// Defines rule `name` as `parser`, replacing any earlier definition
+    pub fn define(&mut self, name: &str, parser: MpcParser) {
+        match self.index.get(name) {
+            Some(&i) => self.rules[i].1 = parser,
+            None => {
+                self.index.insert(name.to_string(), self.rules.len());
+                self.rules.push((name.to_string(), parser));
+            }
+        }
+    }
+
+    pub fn rule(&self, name: &str) -> Option<&MpcParser> {
+        self.index.get(name).map(|&i| &self.rules[i].1)
+    }
+
+    /// Prints every rule as `name : definition ;`
+    pub fn print(&self) {
+        print!("{}", self);
+    }
+
+    /// Renders the grammar as ISO 14977 EBNF, one `name = definition ;` line per rule. Things
+    /// EBNF has no notation for (large character classes, anchors, predicates, permutations) are
+    /// written as `? ... ?` special sequences, and parsers that consume nothing as the empty
+    /// sequence.
+    pub fn to_ebnf(&self) -> String {
+        self.rules.iter()
+            .map(|(name, parser)| format!("{} = {} ;\n", name, mpc_ebnf(parser).0))
+            .collect()
+    }
+
+    /// Graphviz graph of every rule, each in its own cluster. A reference to a rule is drawn as
+    /// an ellipse with a dashed edge to the rule, so shared rules and cycles show up.
+    pub fn to_dot(&self) -> String {
+        use std::fmt::Write;
+        let roots: std::collections::HashMap<&str, String> = self.rules.iter()
+            .enumerate()
+            .map(|(i, (name, _))| (name.as_str(), format!("r{}", i)))
+            .collect();
+        let mut dot = String::from("digraph grammar {\n  node [shape=box];\n");
+        let mut next = 0;
+        let mut links = String::new();
+        for (i, (name, parser)) in self.rules.iter().enumerate() {
+            let name = name.replace('"', "\\\"");
+            let _ = writeln!(dot, "  subgraph cluster_{} {{\n  label=\"{}\";", i, name);
+            let _ = writeln!(dot, "  r{} [label=\"{}\", shape=plaintext];", i, name);
+            let root = mpc_dot_nodes(parser, &roots, &mut next, &mut dot, &mut links);
+            let _ = writeln!(dot, "  r{} -> {};\n  }}", i, root);
+        }
+        dot.push_str(&links);
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// One railroad diagram per rule, as `(name, svg)` pairs in the order the rules were defined
+    pub fn to_railroad(&self) -> Vec<(String, String)> {
+        self.rules.iter()
+            .map(|(name, parser)| (name.clone(), mpc_rail(parser).to_svg(name)))
+            .collect()
+    }
+
+    /// Rules in the order they were defined
+    pub fn rules(&self) -> impl Iterator<Item = (&str, &MpcParser)> {
+        self.rules.iter().map(|(name, parser)| (name.as_str(), parser))
+    }
+
+    /// Finds a rule that can reach itself without consuming input, directly or through other
+    /// rules. Returns the cycle, e.g. `["expr", "term", "expr"]`.
+    pub fn left_recursion(&self) -> Option<Vec<String>> {
+        let nullable = self.nullable_rules();
+        let calls: Vec<Vec<String>> = self.rules.iter()
+            .map(|(_, parser)| {
+                let mut calls = Vec::new();
+                mpc_left_calls(parser, &nullable, &mut calls);
+                calls
+            })
+            .collect();
+        // Depth-first search, with the rules on the current path kept in order
+        fn visit(grammar: &MpcGrammar, calls: &[Vec<String>], rule: usize, path: &mut Vec<usize>, done: &mut [bool]) -> Option<Vec<String>> {
+            if let Some(start) = path.iter().position(|&r| r == rule) {
+                let mut cycle: Vec<String> = path[start..].iter().map(|&r| grammar.rules[r].0.clone()).collect();
+                cycle.push(grammar.rules[rule].0.clone());
+                return Some(cycle);
+            }
+            if done[rule] {
+                return None;
+            }
+            path.push(rule);
+            for name in &calls[rule] {
+                if let Some(&next) = grammar.index.get(name) {
+                    if let Some(cycle) = visit(grammar, calls, next, path, done) {
+                        return Some(cycle);
+                    }
+                }
+            }
+            path.pop();
+            done[rule] = true;
+            None
+        }
+        let mut done = vec![false; self.rules.len()];
+        (0..self.rules.len()).find_map(|rule| visit(self, &calls, rule, &mut Vec::new(), &mut done))
+    }
+
+    // Indices of the rules that are part of a left-recursive cycle
+    pub(crate) fn left_recursive_rules(&self) -> std::collections::HashSet<usize> {
+        let nullable = self.nullable_rules();
+        let calls: Vec<Vec<usize>> = self.rules.iter()
+            .map(|(_, parser)| {
+                let mut calls = Vec::new();
+                mpc_left_calls(parser, &nullable, &mut calls);
+                calls.iter().filter_map(|name| self.index.get(name).copied()).collect()
+            })
+            .collect();
+        (0..self.rules.len())
+            .filter(|&rule| {
+                let mut seen = vec![false; self.rules.len()];
+                let mut stack = calls[rule].clone();
+                while let Some(next) = stack.pop() {
+                    if next == rule {
+                        return true;
+                    }
+                    if !std::mem::replace(&mut seen[next], true) {
+                        stack.extend_from_slice(&calls[next]);
+                    }
+                }
+                false
+            })
+            .collect()
+    }
+
+    /// Repetitions in any rule whose body can match without consuming input, see
+    /// `MpcParser::empty_loops`
+    pub fn empty_loops(&self) -> Vec<String> {
+        let nullable = self.nullable_rules();
+        let mut loops = Vec::new();
+        for (name, parser) in &self.rules {
+            let mut found = Vec::new();
+            mpc_empty_loops(parser, &nullable, &mut found);
+            loops.extend(found.into_iter().map(|found| format!("in rule {}: {}", name, found)));
+        }
+        loops
+    }
+
+    /// Names the rules refer to through `mpc_rule` without a rule of that name being defined,
+    /// each once, in the order they are first referred to
+    pub fn undefined_rules(&self) -> Vec<String> {
+        let mut refs = Vec::new();
+        for (_, parser) in &self.rules {
+            mpc_rule_refs(parser, &mut refs);
+        }
+        let mut undefined: Vec<String> = Vec::new();
+        for name in refs {
+            if !self.index.contains_key(name) && !undefined.iter().any(|u| u == name) {
+                undefined.push(name.to_string());
+            }
+        }
+        undefined
+    }
+
+    /// Rules that parsing from `start` can never reach, in the order they were defined
+    pub fn unused_rules(&self, start: &str) -> Vec<String> {
+        let mut reached = vec![false; self.rules.len()];
+        let mut stack: Vec<usize> = self.index.get(start).copied().into_iter().collect();
+        while let Some(rule) = stack.pop() {
+            if std::mem::replace(&mut reached[rule], true) {
+                continue;
+            }
+            let mut refs = Vec::new();
+            mpc_rule_refs(&self.rules[rule].1, &mut refs);
+            stack.extend(refs.into_iter().filter_map(|name| self.index.get(name).copied()));
+        }
+        self.rules.iter()
+            .zip(reached)
+            .filter(|(_, reached)| !reached)
+            .map(|((name, _), _)| name.clone())
+            .collect()
+    }
+
+    /// Checks the grammar before parsing from `start`: that `start` and every rule referred to
+    /// are defined, and that every rule can be reached from `start`. Each problem is described
+    /// in a sentence, undefined rules first.
+    pub fn check(&self, start: &str) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if !self.index.contains_key(start) {
+            problems.push(format!("start rule {} is not defined", start));
+        }
+        for (name, parser) in &self.rules {
+            let mut refs = Vec::new();
+            mpc_rule_refs(parser, &mut refs);
+            let mut reported: Vec<&str> = Vec::new();
+            for r in refs {
+                if !self.index.contains_key(r) && !reported.contains(&r) {
+                    problems.push(format!("rule {} refers to undefined rule {}", name, r));
+                    reported.push(r);
+                }
+            }
+        }
+        if self.index.contains_key(start) {
+            problems.extend(self.unused_rules(start).into_iter().map(|name| format!("rule {} is never used", name)));
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+
+    // Which rules can succeed without consuming input, found by iterating to a fixed point
+    fn nullable_rules(&self) -> std::collections::HashMap<String, bool> {
+        let mut nullable: std::collections::HashMap<String, bool> = self.rules.iter()
+            .map(|(name, _)| (name.clone(), false))
+            .collect();
+        loop {
+            let mut changed = false;
+            for (name, parser) in &self.rules {
+                if !nullable[name] && mpc_nullable(parser, &nullable) {
+                    nullable.insert(name.clone(), true);
+                    changed = true;
+                }
+            }
+            if !changed {
+                return nullable;
+            }
+        }
+    }
+}
+
+/// Renders one rule per line as `name : definition ;`, in the order they were defined, with the
+/// definitions printed like `MpcParser`'s `Display`.
+impl std::fmt::Display for MpcGrammar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, parser) in &self.rules {
+            writeln!(f, "{} : {} ;", name, parser)?;
+        }
+        Ok(())
+    }
+}
+
+// Whether `parser` can succeed without consuming input, given which rules can
+pub(crate) fn mpc_nullable(parser: &MpcParser, rules: &std::collections::HashMap<String, bool>) -> bool {
+    match parser.parser_type {
+        MpcParserType::Any
+        | MpcParserType::Char(_)
+        | MpcParserType::Range(_, _)
+        | MpcParserType::OneOf(_, _)
+        | MpcParserType::NoneOf(_, _)
+        | MpcParserType::Satisfy(_)
+        | MpcParserType::Fail(_) => false,
+        MpcParserType::String(ref s) => s.is_empty(),
+        MpcParserType::Pass
+        | MpcParserType::Lift(_)
+        | MpcParserType::LiftVal(_)
+        | MpcParserType::Anchor(_)
+        | MpcParserType::State
+        | MpcParserType::Rest
+        | MpcParserType::RestOfLine
+        | MpcParserType::Many(_, _)
+        | MpcParserType::SkipMany(_)
+        | MpcParserType::FoldMany(_, _, _)
+        | MpcParserType::SepBy(_, _, _)
+        | MpcParserType::SepEndBy(_, _, _)
+        | MpcParserType::TakeUntil(_)
+        | MpcParserType::StringUntil(_)
+        | MpcParserType::Not(_)
+        | MpcParserType::Lookahead(_) => true,
+        MpcParserType::Count(n, ref p, _) | MpcParserType::Repeat(n, _, ref p, _) => n <= 0 || mpc_nullable(p, rules),
+        MpcParserType::And(ref ps, _, _) => ps.iter().all(|p| mpc_nullable(p, rules)),
+        MpcParserType::Or(ref ps, _) => ps.iter().any(|p| mpc_nullable(p, rules)),
+        MpcParserType::Permutation(ref ps, _) => ps.iter().all(|(p, optional)| *optional || mpc_nullable(p, rules)),
+        MpcParserType::Many1(ref p, _)
+        | MpcParserType::SkipMany1(ref p)
+        | MpcParserType::SepBy1(ref p, _, _)
+        | MpcParserType::SepEndBy1(ref p, _, _)
+        | MpcParserType::ChainL1(ref p, _, _)
+        | MpcParserType::ChainR1(ref p, _, _)
+        | MpcParserType::Expr(ref p, _)
+        | MpcParserType::Recognize(ref p)
+        | MpcParserType::Apply(ref p, _)
+        | MpcParserType::Consumed(ref p)
+        | MpcParserType::Spanned(ref p)
+        | MpcParserType::Expect(ref p, _)
+        | MpcParserType::Recover(ref p, _, _)
+        | MpcParserType::Tag(ref p, _)
+        | MpcParserType::AddTag(ref p, _)
+        | MpcParserType::Root(ref p) => mpc_nullable(p, rules),
+        MpcParserType::Rule(ref name) => rules.get(name).copied().unwrap_or(true),
+        MpcParserType::Lazy(_, _) => true,
+    }
+}
+
+// Collects repetitions whose body is nullable, given which rules are
+pub(crate) fn mpc_empty_loops(parser: &MpcParser, rules: &std::collections::HashMap<String, bool>, loops: &mut Vec<String>) {
+    let body = match parser.parser_type {
+        MpcParserType::Many(ref p, _)
+        | MpcParserType::Many1(ref p, _)
+        | MpcParserType::SkipMany(ref p)
+        | MpcParserType::SkipMany1(ref p)
+        | MpcParserType::FoldMany(_, ref p, _)
+        | MpcParserType::Repeat(_, None, ref p, _) => Some(&**p),
+        MpcParserType::SepBy(ref p, ref sep, _)
+        | MpcParserType::SepBy1(ref p, ref sep, _)
+        | MpcParserType::SepEndBy(ref p, ref sep, _)
+        | MpcParserType::SepEndBy1(ref p, ref sep, _)
+        | MpcParserType::ChainL1(ref p, ref sep, _)
+        | MpcParserType::ChainR1(ref p, ref sep, _) if mpc_nullable(sep, rules) => Some(&**p),
+        _ => None,
+    };
+    if let Some(body) = body {
+        if mpc_nullable(body, rules) {
+            loops.push(format!("{} repeats {}, which can match without consuming input", parser.name, body.name));
+        }
+    }
+    for child in parser.children() {
+        mpc_empty_loops(child, rules, loops);
+    }
+}
+
+// Names of the rules `parser` refers to
+fn mpc_rule_refs<'p>(parser: &'p MpcParser, refs: &mut Vec<&'p str>) {
+    if let MpcParserType::Rule(ref name) = parser.parser_type {
+        refs.push(name);
+    }
+    for child in parser.children() {
+        mpc_rule_refs(child, refs);
+    }
+}
+
+// Collects the rules `parser` can call before it has consumed any input
+fn mpc_left_calls(parser: &MpcParser, nullable: &std::collections::HashMap<String, bool>, calls: &mut Vec<String>) {
+    match parser.parser_type {
+        MpcParserType::Rule(ref name) => calls.push(name.clone()),
+        MpcParserType::Not(ref p) | MpcParserType::Lookahead(ref p) => mpc_left_calls(p, nullable, calls),
+        MpcParserType::And(ref ps, _, _) => {
+            for p in ps {
+                mpc_left_calls(p, nullable, calls);
+                if !mpc_nullable(p, nullable) {
+                    break;
+                }
+            }
+        }
+        MpcParserType::Or(ref ps, _) => {
+            for p in ps {
+                mpc_left_calls(p, nullable, calls);
+            }
+        }
+        MpcParserType::Permutation(ref ps, _) => {
+            for (p, _) in ps {
+                mpc_left_calls(p, nullable, calls);
+            }
+        }
+        MpcParserType::Expr(ref atom, ref operators) => {
+            mpc_left_calls(atom, nullable, calls);
+            for operator in operators {
+                if let MpcOperator::Prefix(ref p, _) = operator {
+                    mpc_left_calls(p, nullable, calls);
+                }
+            }
+        }
+        // A failed parser is rewound before the sync point is looked for
+        MpcParserType::Recover(ref p, ref sync, _) => {
+            mpc_left_calls(p, nullable, calls);
+            mpc_left_calls(sync, nullable, calls);
+        }
+        MpcParserType::Many(ref p, _)
+        | MpcParserType::Many1(ref p, _)
+        | MpcParserType::SkipMany(ref p)
+        | MpcParserType::SkipMany1(ref p)
+        | MpcParserType::FoldMany(_, ref p, _)
+        | MpcParserType::Count(_, ref p, _)
+        | MpcParserType::Repeat(_, _, ref p, _)
+        | MpcParserType::SepBy(ref p, _, _)
+        | MpcParserType::SepBy1(ref p, _, _)
+        | MpcParserType::SepEndBy(ref p, _, _)
+        | MpcParserType::SepEndBy1(ref p, _, _)
+        | MpcParserType::ChainL1(ref p, _, _)
+        | MpcParserType::ChainR1(ref p, _, _)
+        | MpcParserType::Recognize(ref p)
+        | MpcParserType::Apply(ref p, _)
+        | MpcParserType::Consumed(ref p)
+        | MpcParserType::Spanned(ref p)
+        | MpcParserType::TakeUntil(ref p)
+        | MpcParserType::Expect(ref p, _)
+        | MpcParserType::Tag(ref p, _)
+        | MpcParserType::AddTag(ref p, _)
+        | MpcParserType::Root(ref p) => mpc_left_calls(p, nullable, calls),
+        _ => {}
+    }
+}
+
+// EBNF precedence levels, loosest first: a term rendered at one level needs brackets anywhere
+// a tighter one is expected
+const MPC_EBNF_ALTERNATION: u8 = 0;
+const MPC_EBNF_CONCATENATION: u8 = 1;
+const MPC_EBNF_EXCEPTION: u8 = 2;
+const MPC_EBNF_FACTOR: u8 = 3;
+const MPC_EBNF_PRIMARY: u8 = 4;
+
+// Classes up to this size are written out as alternatives, larger ones as special sequences
+const MPC_EBNF_CLASS_MAX: usize = 26;
+
+// Renders `s` as EBNF terminals. EBNF has no escapes, so control characters become special
+// sequences, and a string holding both kinds of quote is split between differently quoted parts.
+fn mpc_ebnf_terminal(s: &str) -> (String, u8) {
+    fn quote(part: &str) -> String {
+        if part.contains('"') { format!("'{}'", part) } else { format!("\"{}\"", part) }
+    }
+    let mut parts = Vec::new();
+    let mut run = String::new();
+    for c in s.chars() {
+        if c.is_control() {
+            if !run.is_empty() {
+                parts.push(quote(&std::mem::take(&mut run)));
+            }
+            parts.push(match c {
+                '\n' => "? newline ?".to_string(),
+                '\r' => "? carriage return ?".to_string(),
+                '\t' => "? tab ?".to_string(),
+                _ => format!("? U+{:04X} ?", c as u32),
+            });
+            continue;
+        }
+        let other = if c == '"' { '\'' } else { '"' };
+        if (c == '"' || c == '\'') && run.contains(other) {
+            parts.push(quote(&std::mem::take(&mut run)));
+        }
+        run.push(c);
+    }
+    if !run.is_empty() {
+        parts.push(quote(&run));
+    }
+    let level = if parts.len() == 1 { MPC_EBNF_PRIMARY } else { MPC_EBNF_CONCATENATION };
+    (parts.join(" , "), level)
+}
+
+// Renders a set of characters as alternatives, or as a special sequence when it is large
+fn mpc_ebnf_class(chars: &[char], description: impl FnOnce() -> String) -> (String, u8) {
+    match chars {
+        [] => ("? no character ?".to_string(), MPC_EBNF_PRIMARY),
+        [c] => mpc_ebnf_terminal(&c.to_string()),
+        _ if chars.len() <= MPC_EBNF_CLASS_MAX => {
+            let alternatives: Vec<String> = chars.iter().map(|c| mpc_ebnf_terminal(&c.to_string()).0).collect();
+            (alternatives.join(" | "), MPC_EBNF_ALTERNATION)
+        }
+        _ => (format!("? {} ?", description()), MPC_EBNF_PRIMARY),
+    }
+}
+
+// Brackets a rendered term if it binds more loosely than `level`
+fn mpc_ebnf_wrap((text, own): (String, u8), level: u8) -> String {
+    if own < level { format!("( {} )", text) } else { text }
+}
+
+// Joins terms into a concatenation, leaving out empty ones
+fn mpc_ebnf_concat(terms: Vec<(String, u8)>) -> (String, u8) {
+    let mut terms: Vec<(String, u8)> = terms.into_iter().filter(|(text, _)| !text.is_empty()).collect();
+    match terms.len() {
+        0 => (String::new(), MPC_EBNF_PRIMARY),
+        1 => terms.remove(0),
+        _ => {
+            let terms: Vec<String> = terms.into_iter().map(|term| mpc_ebnf_wrap(term, MPC_EBNF_CONCATENATION)).collect();
+            (terms.join(" , "), MPC_EBNF_CONCATENATION)
+        }
+    }
+}
+
+// Renders `parser` as an EBNF term, together with its precedence level. An empty string stands
+// for the empty sequence, i.e. a parser that consumes nothing.
+fn mpc_ebnf(parser: &MpcParser) -> (String, u8) {
+    let primary = |text: String| (text, MPC_EBNF_PRIMARY);
+    let any = || "? any character ?".to_string();
+    let repeated = |p: &MpcParser| format!("{{ {} }}", mpc_ebnf(p).0);
+    let list = |p: &MpcParser, sep: &MpcParser, trailing: bool| {
+        let tail = mpc_ebnf_concat(vec![mpc_ebnf(sep), mpc_ebnf(p)]).0;
+        let mut terms = vec![mpc_ebnf(p), primary(format!("{{ {} }}", tail))];
+        if trailing {
+            terms.push(primary(format!("[ {} ]", mpc_ebnf(sep).0)));
+        }
+        mpc_ebnf_concat(terms)
+    };
+    let optional = |(text, _): (String, u8)| primary(format!("[ {} ]", text));
+
+    match &parser.parser_type {
+        MpcParserType::Any => primary(any()),
+        MpcParserType::Char(c) => mpc_ebnf_terminal(&c.to_string()),
+        MpcParserType::Range(start, end) => {
+            let chars: Vec<char> = (*start..=*end).take(MPC_EBNF_CLASS_MAX + 1).collect();
+            mpc_ebnf_class(&chars, || format!("character between '{}' and '{}'", start.escape_default(), end.escape_default()))
+        }
+        MpcParserType::OneOf(s, _) => {
+            let chars: Vec<char> = s.chars().collect();
+            mpc_ebnf_class(&chars, || format!("one of \"{}\"", s.escape_default()))
+        }
+        MpcParserType::NoneOf(s, _) if s.is_empty() => primary(any()),
+        MpcParserType::NoneOf(s, _) => {
+            let chars: Vec<char> = s.chars().collect();
+            let excluded = mpc_ebnf_class(&chars, || format!("one of \"{}\"", s.escape_default()));
+            (format!("{} - {}", any(), mpc_ebnf_wrap(excluded, MPC_EBNF_FACTOR)), MPC_EBNF_EXCEPTION)
+        }
+        MpcParserType::Satisfy(_) | MpcParserType::Anchor(_) => primary(format!("? {} ?", parser.name)),
+        MpcParserType::String(s) => mpc_ebnf_terminal(s),
+        MpcParserType::Fail(m) => primary(format!("? fail: {} ?", m.replace('?', ""))),
+        MpcParserType::Pass | MpcParserType::Lift(_) | MpcParserType::LiftVal(_) | MpcParserType::State => primary(String::new()),
+        MpcParserType::Rest => primary(format!("{{ {} }}", any())),
+        MpcParserType::RestOfLine => primary(format!("{{ {} - ? newline ? }}", any())),
+        MpcParserType::And(parsers, _, _) => mpc_ebnf_concat(parsers.iter().map(|p| mpc_ebnf(p)).collect()),
+        MpcParserType::Or(parsers, _) => {
+            let alternatives: Vec<(String, u8)> = parsers.iter().map(|p| mpc_ebnf(p)).collect();
+            let empty = alternatives.iter().any(|(text, _)| text.is_empty());
+            let mut alternatives: Vec<(String, u8)> = alternatives.into_iter().filter(|(text, _)| !text.is_empty()).collect();
+            let choice = match alternatives.len() {
+                0 => return primary(String::new()),
+                1 => alternatives.remove(0),
+                _ => {
+                    let alternatives: Vec<String> = alternatives.into_iter().map(|a| a.0).collect();
+                    (alternatives.join(" | "), MPC_EBNF_ALTERNATION)
+                }
+            };
+            if empty { optional(choice) } else { choice }
+        }
+        // EBNF can't say "in any order", so a special sequence says it before the parsers
+        MpcParserType::Permutation(parsers, _) => {
+            let mut terms = vec![primary("? in any order ?".to_string())];
+            terms.extend(parsers.iter().map(|(p, is_optional)| if *is_optional { optional(mpc_ebnf(p)) } else { mpc_ebnf(p) }));
+            mpc_ebnf_concat(terms)
+        }
+        MpcParserType::Many(p, _) | MpcParserType::SkipMany(p) | MpcParserType::FoldMany(_, p, _) => primary(repeated(p)),
+        MpcParserType::Many1(p, _) | MpcParserType::SkipMany1(p) => mpc_ebnf_concat(vec![mpc_ebnf(p), primary(repeated(p))]),
+        MpcParserType::Count(n, p, _) => (format!("{} * {}", n, mpc_ebnf_wrap(mpc_ebnf(p), MPC_EBNF_PRIMARY)), MPC_EBNF_FACTOR),
+        MpcParserType::Repeat(min, max, p, _) => {
+            let mut terms = Vec::new();
+            if *min > 0 {
+                terms.push((format!("{} * {}", min, mpc_ebnf_wrap(mpc_ebnf(p), MPC_EBNF_PRIMARY)), MPC_EBNF_FACTOR));
+            }
+            match max {
+                Some(max) if max > min => terms.push((format!("{} * [ {} ]", max - min, mpc_ebnf(p).0), MPC_EBNF_FACTOR)),
+                Some(_) => {}
+                None => terms.push(primary(repeated(p))),
+            }
+            mpc_ebnf_concat(terms)
+        }
+        MpcParserType::SepBy(p, sep, _) => optional(list(p, sep, false)),
+        MpcParserType::SepBy1(p, sep, _) | MpcParserType::ChainL1(p, sep, _) | MpcParserType::ChainR1(p, sep, _) => list(p, sep, false),
+        MpcParserType::SepEndBy(p, sep, _) => optional(list(p, sep, true)),
+        MpcParserType::SepEndBy1(p, sep, _) => list(p, sep, true),
+        MpcParserType::Expr(atom, operators) => {
+            let choice = |kind: fn(&MpcOperator) -> Option<&MpcParser>| -> Option<String> {
+                let alternatives: Vec<String> = operators.iter().filter_map(kind).map(|p| mpc_ebnf(p).0).collect();
+                if alternatives.is_empty() { None } else { Some(alternatives.join(" | ")) }
+            };
+            let prefix = choice(|o| if let MpcOperator::Prefix(p, _) = o { Some(p) } else { None });
+            let infix = choice(|o| if let MpcOperator::Infix(p, _, _) = o { Some(p) } else { None });
+            let postfix = choice(|o| if let MpcOperator::Postfix(p, _) = o { Some(p) } else { None });
+            let mut operand = Vec::new();
+            operand.extend(prefix.map(|prefix| primary(format!("{{ {} }}", prefix))));
+            operand.push(mpc_ebnf(atom));
+            operand.extend(postfix.map(|postfix| primary(format!("{{ {} }}", postfix))));
+            let operand = mpc_ebnf_concat(operand);
+            match infix {
+                None => operand,
+                Some(infix) => {
+                    let tail = mpc_ebnf_concat(vec![(infix, MPC_EBNF_ALTERNATION), operand.clone()]).0;
+                    mpc_ebnf_concat(vec![operand, primary(format!("{{ {} }}", tail))])
+                }
+            }
+        }
+        MpcParserType::TakeUntil(end) => {
+            primary(format!("{{ {} - {} }}", any(), mpc_ebnf_wrap(mpc_ebnf(end), MPC_EBNF_FACTOR)))
+        }
+        MpcParserType::StringUntil(end) => {
+            primary(format!("{{ {} - {} }}", any(), mpc_ebnf_wrap(mpc_ebnf_terminal(end), MPC_EBNF_FACTOR)))
+        }
+        MpcParserType::Rule(name) => primary(name.clone()),
+        MpcParserType::Lazy(_, _) => primary(parser.name.clone()),
+        MpcParserType::Not(p) => primary(format!("? not followed by {} ?", p.to_string().replace('?', ""))),
+        MpcParserType::Lookahead(p) => primary(format!("? followed by {} ?", p.to_string().replace('?', ""))),
+        MpcParserType::Recover(p, _, _)
+        | MpcParserType::Expect(p, _)
+        | MpcParserType::Recognize(p)
+        | MpcParserType::Apply(p, _)
+        | MpcParserType::Consumed(p)
+        | MpcParserType::Spanned(p)
+        | MpcParserType::Tag(p, _)
+        | MpcParserType::AddTag(p, _)
+        | MpcParserType::Root(p) => mpc_ebnf(p),
+    }
+}
+
+// Railroad diagram layout: boxes sit on a horizontal track, and each node measures its width
+// and how far it reaches above and below the track
+const MPC_RAIL_CHAR_WIDTH: f64 = 8.0;
+const MPC_RAIL_BOX_HEIGHT: f64 = 22.0;
+const MPC_RAIL_GAP: f64 = 10.0;  // Between nodes in a row, and between rows
+const MPC_RAIL_ARC: f64 = 10.0;  // Radius of the curves into and out of branches
+
+pub(crate) enum MpcRail {
+    Terminal(String),  // Rounded box, for text that is matched literally
+    NonTerminal(String),  // Square box, for a rule
+    Special(String),  // Dashed box, for anything else that consumes or checks input
+    Comment(String),  // Text without a box
+    Skip,  // Straight track
+    Sequence(Vec<MpcRail>),
+    Choice(Vec<MpcRail>),  // The first alternative on the track, the others below it
+    Repeat(Box<MpcRail>, Box<MpcRail>),  // One or more, passing through the second node to go round again
+}
+
+impl MpcRail {
+    fn optional(self) -> MpcRail {
+        MpcRail::Choice(vec![MpcRail::Skip, self])
+    }
+
+    fn many(self) -> MpcRail {
+        MpcRail::Repeat(Box::new(self), Box::new(MpcRail::Skip)).optional()
+    }
+
+    fn text_width(text: &str) -> f64 {
+        text.chars().count() as f64 * MPC_RAIL_CHAR_WIDTH + 2.0 * MPC_RAIL_GAP
+    }
+
+    // (width, extent above the track, extent below it)
+    fn measure(&self) -> (f64, f64, f64) {
+        let half = MPC_RAIL_BOX_HEIGHT / 2.0;
+        match self {
+            MpcRail::Terminal(text) | MpcRail::NonTerminal(text) | MpcRail::Special(text) => (MpcRail::text_width(text), half, half),
+            MpcRail::Comment(text) => (MpcRail::text_width(text), half, half),
+            MpcRail::Skip => (0.0, 0.0, 0.0),
+            MpcRail::Sequence(items) => items.iter().map(|item| item.measure()).enumerate().fold((0.0, 0.0, 0.0), |(w, up, down), (i, (iw, iup, idown))| {
+                (w + iw + if i > 0 { MPC_RAIL_GAP } else { 0.0 }, f64::max(up, iup), f64::max(down, idown))
+            }),
+            MpcRail::Choice(items) => {
+                let sizes: Vec<(f64, f64, f64)> = items.iter().map(|item| item.measure()).collect();
+                let width = sizes.iter().map(|s| s.0).fold(0.0, f64::max) + 4.0 * MPC_RAIL_ARC;
+                let (_, up, first_down) = sizes[0];
+                let down = sizes[1..].iter().fold(first_down, |down, (_, iup, idown)| down + MPC_RAIL_GAP + f64::max(*iup, MPC_RAIL_ARC) + idown);
+                (width, up, down)
+            }
+            MpcRail::Repeat(item, back) => {
+                let (iw, iup, idown) = item.measure();
+                let (bw, bup, bdown) = back.measure();
+                (f64::max(iw, bw) + 4.0 * MPC_RAIL_ARC, iup, idown + MPC_RAIL_GAP + f64::max(bup, MPC_RAIL_ARC) + bdown)
+            }
+        }
+    }
+
+    // Draws the node with its track starting at (x, y) and running `width` to the right
+    fn render(&self, x: f64, y: f64, width: f64, svg: &mut String) {
+        use std::fmt::Write;
+        let (own, _, _) = self.measure();
+        let half = MPC_RAIL_BOX_HEIGHT / 2.0;
+        let arc = MPC_RAIL_ARC;
+        let boxed = |svg: &mut String, text: &str, rx: f64, class: &str| {
+            let _ = write!(svg, "<rect class=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"/>", class, x, y - half, own, MPC_RAIL_BOX_HEIGHT, rx);
+            let _ = write!(svg, "<text x=\"{}\" y=\"{}\">{}</text>", x + own / 2.0, y + 4.0, mpc_xml_escape(text));
+        };
+        match self {
+            MpcRail::Terminal(text) => boxed(svg, text, half, "terminal"),
+            MpcRail::NonTerminal(text) => boxed(svg, text, 0.0, "nonterminal"),
+            MpcRail::Special(text) => boxed(svg, text, 0.0, "special"),
+            MpcRail::Comment(text) => {
+                let _ = write!(svg, "<text class=\"comment\" x=\"{}\" y=\"{}\">{}</text>", x + own / 2.0, y + 4.0, mpc_xml_escape(text));
+            }
+            MpcRail::Skip => {}
+            MpcRail::Sequence(items) => {
+                let mut cursor = x;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        let _ = write!(svg, "<path d=\"M {} {} h {}\"/>", cursor, y, MPC_RAIL_GAP);
+                        cursor += MPC_RAIL_GAP;
+                    }
+                    let (iw, _, _) = item.measure();
+                    item.render(cursor, y, iw, svg);
+                    cursor += iw;
+                }
+            }
+            MpcRail::Choice(items) => {
+                let inner = own - 4.0 * arc;
+                let left = x + 2.0 * arc;
+                let right = x + own - 2.0 * arc;
+                let (_, _, mut below) = items[0].measure();
+                for (i, item) in items.iter().enumerate() {
+                    let (iw, iup, idown) = item.measure();
+                    let row = if i == 0 {
+                        y
+                    } else {
+                        let row = y + below + MPC_RAIL_GAP + f64::max(iup, arc);
+                        below = row - y + idown;
+                        row
+                    };
+                    let start = left + (inner - iw) / 2.0;
+                    if i == 0 {
+                        let _ = write!(svg, "<path d=\"M {} {} H {}\"/>", x, y, start);
+                    } else {
+                        let _ = write!(svg, "<path d=\"M {} {} q {} 0 {} {} V {} q 0 {} {} {} H {}\"/>", x, y, arc, arc, arc, row - arc, arc, arc, arc, start);
+                    }
+                    item.render(start, row, iw, svg);
+                    if i == 0 {
+                        let _ = write!(svg, "<path d=\"M {} {} H {}\"/>", start + iw, y, x + own);
+                    } else {
+                        let _ = write!(svg, "<path d=\"M {} {} H {} q {} 0 {} {} V {} q 0 {} {} {}\"/>", start + iw, row, right, arc, arc, -arc, y + arc, -arc, arc, -arc);
+                    }
+                }
+            }
+            MpcRail::Repeat(item, back) => {
+                let (iw, _, idown) = item.measure();
+                let (bw, bup, _) = back.measure();
+                let inner = own - 4.0 * arc;
+                let left = x + 2.0 * arc;
+                let right = x + own - 2.0 * arc;
+                let start = left + (inner - iw) / 2.0;
+                let _ = write!(svg, "<path d=\"M {} {} H {}\"/>", x, y, start);
+                item.render(start, y, iw, svg);
+                let _ = write!(svg, "<path d=\"M {} {} H {}\"/>", start + iw, y, x + own);
+                // The way back runs right to left underneath, through `back`
+                let row = y + idown + MPC_RAIL_GAP + f64::max(bup, arc);
+                let back_start = left + (inner - bw) / 2.0;
+                let _ = write!(svg, "<path d=\"M {} {} q {} 0 {} {} V {} q 0 {} {} {} H {}\"/>", right, y, arc, arc, arc, row - arc, arc, -arc, arc, back_start + bw);
+                back.render(back_start, row, bw, svg);
+                let _ = write!(svg, "<path d=\"M {} {} H {} q {} 0 {} {} V {} q 0 {} {} {}\"/>", back_start, row, left, -arc, -arc, -arc, y + arc, -arc, arc, -arc);
+            }
+        }
+        if width > own {
+            let _ = write!(svg, "<path d=\"M {} {} H {}\"/>", x + own, y, x + width);
+        }
+    }
+
+    // The whole diagram as an SVG document, with a bar at each end of the track
+    pub(crate) fn to_svg(&self, title: &str) -> String {
+        let (width, up, down) = self.measure();
+        let pad = 2.0 * MPC_RAIL_GAP;
+        let y = pad + up;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" class=\"railroad-diagram\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\
+<title>{}</title><style>path,rect{{fill:none;stroke:#000;stroke-width:1.5}}rect{{fill:#ffc}}rect.special{{fill:#eee;stroke-dasharray:4 2}}\
+text{{font:14px monospace;text-anchor:middle}}text.comment{{font-style:italic}}</style>",
+            width + 2.0 * pad + 2.0 * MPC_RAIL_GAP, y + down + pad, width + 2.0 * pad + 2.0 * MPC_RAIL_GAP, y + down + pad, mpc_xml_escape(title),
+        );
+        let bar = MPC_RAIL_GAP;
+        svg.push_str(&format!("<path d=\"M {} {} v {} M {} {} h {}\"/>", pad, y - bar, 2.0 * bar, pad, y, MPC_RAIL_GAP));
+        self.render(pad + MPC_RAIL_GAP, y, width, &mut svg);
+        let end = pad + MPC_RAIL_GAP + width;
+        svg.push_str(&format!("<path d=\"M {} {} h {} m 0 {} v {}\"/>", end, y, MPC_RAIL_GAP, -bar, 2.0 * bar));
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+fn mpc_xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Builds the diagram of `parser`, following the same reading of each parser as `mpc_ebnf`
+pub(crate) fn mpc_rail(parser: &MpcParser) -> MpcRail {
+    let class = || MpcRail::Terminal(parser.to_string());
+    let list = |p: &MpcParser, sep: &MpcParser| MpcRail::Repeat(Box::new(mpc_rail(p)), Box::new(mpc_rail(sep)));
+    let times = |p: &MpcParser, text: String| MpcRail::Repeat(Box::new(mpc_rail(p)), Box::new(MpcRail::Comment(text)));
+    match &parser.parser_type {
+        MpcParserType::Any => MpcRail::Special("any character".to_string()),
+        MpcParserType::Char(c) => MpcRail::Terminal(c.escape_default().to_string()),
+        MpcParserType::String(s) if s.is_empty() => MpcRail::Skip,
+        MpcParserType::String(s) => MpcRail::Terminal(s.escape_default().to_string()),
+        MpcParserType::Range(_, _) | MpcParserType::OneOf(_, _) | MpcParserType::NoneOf(_, _) => class(),
+        MpcParserType::Satisfy(_) | MpcParserType::Anchor(_) => MpcRail::Special(parser.name.clone()),
+        MpcParserType::Fail(m) => MpcRail::Special(format!("fail: {}", m)),
+        MpcParserType::Pass | MpcParserType::Lift(_) | MpcParserType::LiftVal(_) | MpcParserType::State => MpcRail::Skip,
+        MpcParserType::Rest => MpcRail::Special("rest of input".to_string()),
+        MpcParserType::RestOfLine => MpcRail::Special("rest of line".to_string()),
+        MpcParserType::And(parsers, _, _) => {
+            let items: Vec<MpcRail> = parsers.iter().map(|p| mpc_rail(p)).filter(|item| !matches!(item, MpcRail::Skip)).collect();
+            match items.len() {
+                0 => MpcRail::Skip,
+                1 => items.into_iter().next().unwrap(),
+                _ => MpcRail::Sequence(items),
+            }
+        }
+        MpcParserType::Or(parsers, _) => {
+            let items: Vec<MpcRail> = parsers.iter().map(|p| mpc_rail(p)).collect();
+            let empty = items.iter().any(|item| matches!(item, MpcRail::Skip));
+            let mut items: Vec<MpcRail> = items.into_iter().filter(|item| !matches!(item, MpcRail::Skip)).collect();
+            let choice = match items.len() {
+                0 => return MpcRail::Skip,
+                1 => items.remove(0),
+                _ => MpcRail::Choice(items),
+            };
+            if empty { choice.optional() } else { choice }
+        }
+        MpcParserType::Permutation(parsers, _) => {
+            let mut items = vec![MpcRail::Comment("in any order".to_string())];
+            items.extend(parsers.iter().map(|(p, optional)| if *optional { mpc_rail(p).optional() } else { mpc_rail(p) }));
+            MpcRail::Sequence(items)
+        }
+        MpcParserType::Many(p, _) | MpcParserType::SkipMany(p) | MpcParserType::FoldMany(_, p, _) => mpc_rail(p).many(),
+        MpcParserType::Many1(p, _) | MpcParserType::SkipMany1(p) => MpcRail::Repeat(Box::new(mpc_rail(p)), Box::new(MpcRail::Skip)),
+        MpcParserType::Count(n, p, _) => times(p, format!("{} times", n)),
+        MpcParserType::Repeat(min, max, p, _) => {
+            let text = match max {
+                Some(max) => format!("{} to {} times", min, max),
+                None => format!("{} or more times", min),
+            };
+            if *min == 0 { times(p, text).optional() } else { times(p, text) }
+        }
+        MpcParserType::SepBy(p, sep, _) => list(p, sep).optional(),
+        MpcParserType::SepBy1(p, sep, _) | MpcParserType::ChainL1(p, sep, _) | MpcParserType::ChainR1(p, sep, _) => list(p, sep),
+        MpcParserType::SepEndBy(p, sep, _) => MpcRail::Sequence(vec![list(p, sep), mpc_rail(sep).optional()]).optional(),
+        MpcParserType::SepEndBy1(p, sep, _) => MpcRail::Sequence(vec![list(p, sep), mpc_rail(sep).optional()]),
+        MpcParserType::Expr(atom, operators) => {
+            let choice = |kind: fn(&MpcOperator) -> Option<&MpcParser>| -> Option<MpcRail> {
+                let mut items: Vec<MpcRail> = operators.iter().filter_map(kind).map(mpc_rail).collect();
+                match items.len() {
+                    0 => None,
+                    1 => Some(items.remove(0)),
+                    _ => Some(MpcRail::Choice(items)),
+                }
+            };
+            let mut operand = Vec::new();
+            operand.extend(choice(|o| if let MpcOperator::Prefix(p, _) = o { Some(p) } else { None }).map(MpcRail::many));
+            operand.push(mpc_rail(atom));
+            operand.extend(choice(|o| if let MpcOperator::Postfix(p, _) = o { Some(p) } else { None }).map(MpcRail::many));
+            let operand = if operand.len() == 1 { operand.remove(0) } else { MpcRail::Sequence(operand) };
+            match choice(|o| if let MpcOperator::Infix(p, _, _) = o { Some(p) } else { None }) {
+                Some(infix) => MpcRail::Repeat(Box::new(operand), Box::new(infix)),
+                None => operand,
+            }
+        }
+        MpcParserType::TakeUntil(end) => MpcRail::Special(format!("anything up to {}", end)),
+        MpcParserType::StringUntil(end) => MpcRail::Special(format!("anything up to {:?}", end)),
+        MpcParserType::Rule(name) => MpcRail::NonTerminal(name.clone()),
+        MpcParserType::Lazy(_, _) => MpcRail::NonTerminal(parser.name.clone()),
+        MpcParserType::Not(p) => MpcRail::Special(format!("not followed by {}", p)),
+        MpcParserType::Lookahead(p) => MpcRail::Special(format!("followed by {}", p)),
+        MpcParserType::Recover(p, _, _)
+        | MpcParserType::Expect(p, _)
+        | MpcParserType::Recognize(p)
+        | MpcParserType::Apply(p, _)
+        | MpcParserType::Consumed(p)
+        | MpcParserType::Spanned(p)
+        | MpcParserType::Tag(p, _)
+        | MpcParserType::AddTag(p, _)
+        | MpcParserType::Root(p) => mpc_rail(p),
+    }
+}
+
+// Graphviz output: one node per parser, labelled with its kind, or with its `mpc_print` text
+// for parsers that have no children
+fn mpc_dot_label(parser: &MpcParser) -> String {
+    let kind = match &parser.parser_type {
+        MpcParserType::And(_, _, _) => "and".to_string(),
+        MpcParserType::Or(_, _) => "or".to_string(),
+        MpcParserType::Permutation(_, _) => "permutation".to_string(),
+        MpcParserType::Many(_, _) => "many".to_string(),
+        MpcParserType::Many1(_, _) => "many1".to_string(),
+        MpcParserType::SkipMany(_) => "skip_many".to_string(),
+        MpcParserType::SkipMany1(_) => "skip_many1".to_string(),
+        MpcParserType::FoldMany(_, _, _) => "fold_many".to_string(),
+        MpcParserType::Count(n, _, _) => format!("count {}", n),
+        MpcParserType::Repeat(min, Some(max), _, _) => format!("repeat {}..{}", min, max),
+        MpcParserType::Repeat(min, None, _, _) => format!("repeat {}..", min),
+        MpcParserType::SepBy(_, _, _) => "sepby".to_string(),
+        MpcParserType::SepBy1(_, _, _) => "sepby1".to_string(),
+        MpcParserType::SepEndBy(_, _, _) => "sependby".to_string(),
+        MpcParserType::SepEndBy1(_, _, _) => "sependby1".to_string(),
+        MpcParserType::ChainL1(_, _, _) => "chainl1".to_string(),
+        MpcParserType::ChainR1(_, _, _) => "chainr1".to_string(),
+        MpcParserType::Expr(_, _) => "expr".to_string(),
+        MpcParserType::Apply(_, _) => "apply".to_string(),
+        MpcParserType::Recognize(_) => "recognize".to_string(),
+        MpcParserType::Consumed(_) => "consumed".to_string(),
+        MpcParserType::Spanned(_) => "spanned".to_string(),
+        MpcParserType::TakeUntil(_) => "take_until".to_string(),
+        MpcParserType::Expect(_, expected) => format!("expect {}", expected),
+        MpcParserType::Recover(_, _, _) => "recover".to_string(),
+        MpcParserType::Tag(_, tag) => format!("tag {}", tag),
+        MpcParserType::AddTag(_, tag) => format!("add tag {}", tag),
+        MpcParserType::Root(_) => "root".to_string(),
+        MpcParserType::Not(_) => "not".to_string(),
+        MpcParserType::Lookahead(_) => "lookahead".to_string(),
+        _ => parser.to_string(),
+    };
+    kind.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Writes `parser` and everything under it, returning the id of its node. Rule references
+// become ellipses, linked to the rule's own node through `rules` when it has one. Those links
+// go to `links`, as Graphviz puts a node in the cluster where it is first mentioned.
+pub(crate) fn mpc_dot_nodes(parser: &MpcParser, rules: &std::collections::HashMap<&str, String>, next: &mut usize, dot: &mut String, links: &mut String) -> String {
+    use std::fmt::Write;
+    let id = format!("n{}", next);
+    *next += 1;
+    match &parser.parser_type {
+        MpcParserType::Rule(name) => {
+            let _ = writeln!(dot, "  {} [label=\"{}\", shape=ellipse];", id, mpc_dot_label(parser).trim_matches(|c| c == '<' || c == '>'));
+            if let Some(target) = rules.get(name.as_str()) {
+                let _ = writeln!(links, "  {} -> {} [style=dashed];", id, target);
+            }
+        }
+        _ => {
+            let _ = writeln!(dot, "  {} [label=\"{}\"];", id, mpc_dot_label(parser));
+            for (i, child) in parser.children().into_iter().enumerate() {
+                let child_id = mpc_dot_nodes(child, rules, next, dot, links);
+                let _ = writeln!(dot, "  {} -> {} [label=\"{}\"];", id, child_id, i);
+            }
+        }
+    }
+    id
+}
+
+// Grammar Import
+
+// Rule read by an importer: name, whether it adds alternatives to an earlier definition (ABNF's
+// `=/`), and definition
+type MpcImportRule = (String, bool, MpcParser);
+
+// The parsers among the values of a fold, including ones already collected into a Vec
+fn mpc_import_parsers(xs: Vec<MpcVal>) -> Vec<MpcParser> {
+    let mut parsers = Vec::new();
+    for x in xs {
+        match x.downcast::<MpcParser>() {
+            Ok(parser) => parsers.push(*parser),
+            Err(x) => {
+                if let Ok(list) = x.downcast::<Vec<MpcParser>>() {
+                    parsers.extend(*list);
+                }
+            }
+        }
+    }
+    parsers
+}
+
+fn mpcf_import_list(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    Box::new(mpc_import_parsers(xs))
+}
+
+fn mpcf_import_option(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let mut parsers = mpc_import_parsers(xs);
+    parsers.push(mpc_pass());
+    Box::new(mpc_or(parsers))
+}
+
+fn mpcf_import_sequence(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let mut parsers = mpc_import_parsers(xs);
+    Box::new(if parsers.len() == 1 { parsers.remove(0) } else { mpc_and_assoc(parsers, MpcAssocFold::Str) })
+}
+
+fn mpcf_import_choice(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let mut parsers = mpc_import_parsers(xs);
+    Box::new(if parsers.len() == 1 { parsers.remove(0) } else { mpc_or(parsers) })
+}
+
+// The values are the rule name, the operator defining it (`=`, `=/`, `<-`), and the definition,
+// with whitespace in between
+fn mpcf_import_rule(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let mut text = Vec::new();
+    let mut rest = Vec::new();
+    for x in xs {
+        match x.downcast::<String>() {
+            Ok(s) => text.push(*s),
+            Err(x) => rest.push(x),
+        }
+    }
+    let definition = mpc_import_parsers(rest).remove(0);
+    Box::new((text[0].clone(), text[1] == "=/", definition))
+}
+
+fn mpcf_import_rules(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let mut rules: Vec<MpcImportRule> = Vec::new();
+    for x in xs {
+        if let Ok(list) = x.downcast::<Vec<MpcVal>>() {
+            rules.extend(list.into_iter().filter_map(|x| x.downcast::<MpcImportRule>().ok().map(|rule| *rule)));
+        }
+    }
+    Box::new(rules)
+}
+
+fn mpcf_import_collect(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    Box::new(xs)
+}
+
+// ABNF Import
+
+// Core rules of RFC 5234 Appendix B.1, added to imported grammars that use them
+const MPC_ABNF_CORE: &str = "\
+ALPHA = %x41-5A / %x61-7A
+BIT = \"0\" / \"1\"
+CHAR = %x01-7F
+CR = %x0D
+CRLF = CR LF
+CTL = %x00-1F / %x7F
+DIGIT = %x30-39
+DQUOTE = %x22
+HEXDIG = DIGIT / \"A\" / \"B\" / \"C\" / \"D\" / \"E\" / \"F\"
+HTAB = %x09
+LF = %x0A
+LWSP = *(WSP / CRLF WSP)
+OCTET = %x00-FF
+SP = %x20
+VCHAR = %x21-7E
+WSP = SP / HTAB
+";
+
+fn mpcf_abnf_rulename(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let name = xs.into_iter().next().and_then(|x| x.downcast::<String>().ok()).map_or_else(String::new, |name| *name);
+    Box::new(mpc_rule(&name.to_ascii_lowercase()))
+}
+
+// A quoted string: case-insensitive unless it has RFC 7405's %s prefix
+fn mpcf_abnf_char_val(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let mut xs = xs.into_iter().map(|x| x.downcast::<String>().map_or_else(|_| String::new(), |s| *s));
+    let prefix = xs.next().unwrap_or_default();
+    let text = xs.nth(1).unwrap_or_default();
+    if prefix == "%s" || !text.chars().any(|c| c.is_ascii_alphabetic()) {
+        return Box::new(mpc_string(&text));
+    }
+    let mut parsers = Vec::new();
+    let mut run = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphabetic() {
+            if !run.is_empty() {
+                parsers.push(mpc_string(&std::mem::take(&mut run)));
+            }
+            parsers.push(mpc_oneof(&format!("{}{}", c.to_ascii_lowercase(), c.to_ascii_uppercase())));
+        } else {
+            run.push(c);
+        }
+    }
+    if !run.is_empty() {
+        parsers.push(mpc_string(&run));
+    }
+    Box::new(if parsers.len() == 1 { parsers.remove(0) } else { mpc_and_assoc(parsers, MpcAssocFold::Str) })
+}
+
+// %b, %d or %x followed by a value, a range `a-b`, or a string of values `a.b.c`
+fn mpcf_abnf_num_val(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let text = mpcf_strfold(0, xs).downcast::<String>().map_or_else(|_| String::new(), |s| *s);
+    let radix = match text.as_bytes().get(1) {
+        Some(b'b') | Some(b'B') => 2,
+        Some(b'd') | Some(b'D') => 10,
+        _ => 16,
+    };
+    let value = |digits: &str| u32::from_str_radix(digits, radix).ok().and_then(char::from_u32);
+    let body = &text[2..];
+    let parser = if let Some((start, end)) = body.split_once('-') {
+        value(start).zip(value(end)).map(|(start, end)| mpc_range(start, end))
+    } else {
+        body.split('.').map(value).collect::<Option<String>>().map(|s| match s.chars().count() {
+            1 => mpc_char(s.chars().next().unwrap()),
+            _ => mpc_string(&s),
+        })
+    };
+    Box::new(parser.unwrap_or_else(|| mpc_fail(&format!("{} is not a character", text))))
+}
+
+fn mpcf_abnf_prose_val(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let text = mpcf_strfold(0, xs).downcast::<String>().map_or_else(|_| String::new(), |s| *s);
+    Box::new(mpc_fail(&format!("prose description {} can't be matched", text)))
+}
+
+// `n*m element`, where either bound may be left out, or `n element`
+fn mpcf_abnf_repetition(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let mut xs = xs.into_iter();
+    let spec = xs.next().and_then(|x| x.downcast::<String>().ok()).map(|s| *s);
+    let parser = mpc_import_parsers(xs.collect()).remove(0);
+    let bound = |s: &str| s.parse::<i32>().ok();
+    Box::new(match spec {
+        None => parser,
+        Some(spec) => match spec.split_once('*') {
+            None => mpc_count(bound(&spec).unwrap_or(1), parser, mpcf_strfold),
+            Some((min, max)) => match (bound(min).unwrap_or(0), bound(max)) {
+                (0, None) => mpc_many(parser, mpcf_strfold),
+                (1, None) => mpc_many1(parser, mpcf_strfold),
+                (min, Some(max)) if max < min => mpc_fail(&format!("repetition {} allows fewer than its minimum", spec)),
+                (min, max) => mpc_repeat(min, max, parser, mpcf_strfold),
+            },
+        },
+    })
+}
+
+// ABNF's own grammar (RFC 5234 section 4), written with the combinators of this crate. Line
+// ends may be LF as well as CRLF, and the last line needn't end in one.
+fn mpc_abnf_syntax() -> MpcGrammar {
+    let wsp = || mpc_oneof(" \t");
+    let newline = || mpc_or(vec![mpc_string("\r\n"), mpc_char('\n')]);
+    let comment = || mpc_and(vec![mpc_char(';'), mpc_rest_of_line()], mpcf_null);
+    // A comment or line break, then whitespace continuing the rule on the next line
+    let c_nl = || mpc_or(vec![mpc_and(vec![comment(), mpc_or(vec![newline(), mpc_eoi()])], mpcf_null), newline()]);
+    let c_wsp = || mpc_expect(mpc_or(vec![wsp(), mpc_and(vec![c_nl(), wsp()], mpcf_null)]), "whitespace");
+    let spaces = || mpc_skip_many(c_wsp());
+    let digits = |set: &str| mpc_many1(mpc_oneof(set), mpcf_strfold);
+    let num_val = |base: &str, set: &str| mpc_and(vec![
+        mpc_oneof(base),
+        digits(set),
+        mpc_or(vec![
+            mpc_many1(mpc_and(vec![mpc_char('.'), digits(set)], mpcf_strfold), mpcf_strfold),
+            mpc_and(vec![mpc_char('-'), digits(set)], mpcf_strfold),
+            mpc_pass(),
+        ]),
+    ], mpcf_strfold);
+    let rulename = || mpc_expect(mpc_and(vec![
+        mpc_alpha(),
+        mpc_many(mpc_or(vec![mpc_alphanum(), mpc_char('-')]), mpcf_strfold),
+    ], mpcf_strfold), "rule name");
+
+    let mut grammar = MpcGrammar::new();
+    grammar.define("rulelist", mpc_and(vec![
+        mpc_many(mpc_or(vec![
+            mpc_rule("rule"),
+            mpc_and(vec![mpc_skip_many(wsp()), c_nl()], mpcf_null),
+        ]), mpcf_import_collect),
+        mpc_skip_many(wsp()),
+        mpc_eoi(),
+    ], mpcf_import_rules));
+    grammar.define("rule", mpc_and(vec![
+        rulename(),
+        spaces(),
+        mpc_expect(mpc_or(vec![mpc_string("=/"), mpc_string("=")]), "'=' or '=/'"),
+        spaces(),
+        mpc_rule("alternation"),
+        spaces(),
+        mpc_or(vec![c_nl(), mpc_eoi()]),
+    ], mpcf_import_rule));
+    grammar.define("alternation", mpc_and(vec![
+        mpc_rule("concatenation"),
+        mpc_many(mpc_and(vec![spaces(), mpc_char('/'), spaces(), mpc_rule("concatenation")], mpcf_import_list), mpcf_import_list),
+    ], mpcf_import_choice));
+    grammar.define("concatenation", mpc_and(vec![
+        mpc_rule("repetition"),
+        mpc_many(mpc_and(vec![mpc_skip_many1(c_wsp()), mpc_rule("repetition")], mpcf_import_list), mpcf_import_list),
+    ], mpcf_import_sequence));
+    grammar.define("repetition", mpc_and(vec![
+        mpc_or(vec![
+            mpc_recognize(mpc_and(vec![mpc_many(mpc_digit(), mpcf_strfold), mpc_char('*'), mpc_many(mpc_digit(), mpcf_strfold)], mpcf_strfold)),
+            digits("0123456789"),
+            mpc_pass(),
+        ]),
+        mpc_rule("element"),
+    ], mpcf_abnf_repetition));
+    grammar.define("element", mpc_or(vec![
+        mpc_and(vec![rulename()], mpcf_abnf_rulename),
+        mpc_and(vec![mpc_char('('), spaces(), mpc_rule("alternation"), spaces(), mpc_char(')')], mpcf_import_sequence),
+        mpc_and(vec![mpc_char('['), spaces(), mpc_rule("alternation"), spaces(), mpc_char(']')], mpcf_import_option),
+        mpc_and(vec![
+            mpc_or(vec![mpc_string("%s"), mpc_string("%i"), mpc_pass()]),
+            mpc_char('"'),
+            mpc_many(mpc_noneof("\"\r\n"), mpcf_strfold),
+            mpc_char('"'),
+        ], mpcf_abnf_char_val),
+        mpc_and(vec![
+            mpc_char('%'),
+            mpc_or(vec![num_val("bB", "01"), num_val("dD", "0123456789"), num_val("xX", "0123456789abcdefABCDEF")]),
+        ], mpcf_abnf_num_val),
+        mpc_and(vec![mpc_char('<'), mpc_many(mpc_noneof(">\r\n"), mpcf_strfold), mpc_char('>')], mpcf_abnf_prose_val),
+    ]));
+    grammar
+}
+
+#[allow(clippy::result_large_err)]
+fn mpc_abnf_rules(filename: &str, abnf: &str) -> Result<Vec<MpcImportRule>, MpcErr> {
+    match mpc_parse_grammar(filename, abnf, &mpc_abnf_syntax(), "rulelist") {
+        MpcResult::Ok(rules) => {
+            let rules = *rules.downcast::<Vec<MpcImportRule>>().unwrap();
+            Ok(rules.into_iter().map(|(name, incremental, definition)| (name.to_ascii_lowercase(), incremental, definition)).collect())
+        }
+        MpcResult::Err(e) => Err(e),
+    }
+}
+
+/// Builds a grammar from ABNF (RFC 5234) text, such as the grammars in IETF RFCs. Rule names
+/// are case-insensitive in ABNF, so they are lower-cased: `mpc_parse_grammar(.., "uri")` for a
+/// rule written `URI`. Quoted strings match case-insensitively unless written `%s"..."` (RFC
+/// 7405), rules added to with `=/` get the new alternatives after the old ones, and the core
+/// rules (`ALPHA`, `DIGIT`, `CRLF`, ...) are added when used and not defined.
+///
+/// ABNF alternatives are unordered, but they are tried in order here like any `mpc_or`, so an
+/// alternative that is a prefix of a later one (`"a" / "ab"`) should come after it. Values are
+/// the matched text. Prose descriptions (`<...>`) can't be matched and always fail.
+#[allow(clippy::result_large_err)]
+pub fn mpc_abnf(filename: &str, abnf: &str) -> Result<MpcGrammar, MpcErr> {
+    let mut rules: Vec<(String, MpcParser)> = Vec::new();
+    for (name, incremental, definition) in mpc_abnf_rules(filename, abnf)? {
+        match rules.iter().position(|(defined, _)| *defined == name) {
+            Some(i) if incremental => {
+                let previous = std::mem::replace(&mut rules[i].1, mpc_pass());
+                rules[i].1 = mpc_or(vec![previous, definition]);
+            }
+            Some(i) => rules[i].1 = definition,
+            None => rules.push((name, definition)),
+        }
+    }
+
+    let mut core = mpc_abnf_rules("core", MPC_ABNF_CORE)?;
+    loop {
+        let mut refs = Vec::new();
+        for (_, parser) in &rules {
+            mpc_rule_refs(parser, &mut refs);
+        }
+        let missing: Vec<String> = refs.into_iter()
+            .filter(|name| !rules.iter().any(|(defined, _)| defined == name))
+            .map(str::to_string)
+            .collect();
+        let before = rules.len();
+        for name in missing {
+            if let Some(i) = core.iter().position(|(core_name, _, _)| *core_name == name) {
+                let (name, _, definition) = core.remove(i);
+                rules.push((name, definition));
+            }
+        }
+        if rules.len() == before {
+            break;
+        }
+    }
+
+    let mut grammar = MpcGrammar::new();
+    for (name, definition) in rules {
+        grammar.define(&name, definition);
+    }
+    Ok(grammar)
+}
+
+// PEG Import
+
+// Decodes the escapes of PEG literals and classes: \n \r \t \' \" \[ \] \\ and octal \ooo.
+// Each character comes with whether it was escaped, as an escaped `-` in a class is no range.
+fn mpc_peg_unescape(raw: &str) -> Vec<(char, bool)> {
+    let mut chars = Vec::new();
+    let mut rest = raw.chars().peekable();
+    while let Some(c) = rest.next() {
+        if c != '\\' {
+            chars.push((c, false));
+            continue;
+        }
+        match rest.next() {
+            Some('n') => chars.push(('\n', true)),
+            Some('r') => chars.push(('\r', true)),
+            Some('t') => chars.push(('\t', true)),
+            Some(d @ '0'..='7') => {
+                let mut code = d.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match rest.peek().and_then(|d| d.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            rest.next();
+                        }
+                        None => break,
+                    }
+                }
+                chars.extend(char::from_u32(code).map(|c| (c, true)));
+            }
+            Some(other) => chars.push((other, true)),
+            None => chars.push(('\\', false)),
+        }
+    }
+    chars
+}
+
+fn mpcf_peg_identifier(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let name = xs.into_iter().next().and_then(|x| x.downcast::<String>().ok()).map_or_else(String::new, |s| *s);
+    Box::new(mpc_rule(&name))
+}
+
+// The values are the opening quote, the literal as written, and the closing quote
+fn mpcf_peg_literal(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let raw = xs.into_iter().nth(1).and_then(|x| x.downcast::<String>().ok()).map_or_else(String::new, |s| *s);
+    let text: String = mpc_peg_unescape(&raw).into_iter().map(|(c, _)| c).collect();
+    let mut chars = text.chars();
+    Box::new(match (chars.next(), chars.next()) {
+        (Some(c), None) => mpc_char(c),
+        _ => mpc_string(&text),
+    })
+}
+
+// The values are `[`, the class as written, and `]`
+fn mpcf_peg_class(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let raw = xs.into_iter().nth(1).and_then(|x| x.downcast::<String>().ok()).map_or_else(String::new, |s| *s);
+    let chars = mpc_peg_unescape(&raw);
+    let mut singles = String::new();
+    let mut parsers = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if i + 2 < chars.len() && chars[i + 1] == ('-', false) {
+            parsers.push(mpc_range(chars[i].0, chars[i + 2].0));
+            i += 3;
+        } else {
+            singles.push(chars[i].0);
+            i += 1;
+        }
+    }
+    match singles.chars().count() {
+        0 => {}
+        1 => parsers.push(mpc_char(singles.chars().next().unwrap())),
+        _ => parsers.push(mpc_oneof(&singles)),
+    }
+    Box::new(match parsers.len() {
+        0 => mpc_fail("empty character class"),
+        1 => parsers.remove(0),
+        _ => mpc_or(parsers),
+    })
+}
+
+fn mpcf_peg_dot(_n: i32, _xs: Vec<MpcVal>) -> MpcVal {
+    Box::new(mpc_any())
+}
+
+// The values are `&`, `!` or nothing, then the parser. Lookahead matches no text, so its value
+// is dropped.
+fn mpcf_peg_prefix(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let mut xs = xs.into_iter();
+    let prefix = xs.next().and_then(|x| x.downcast::<String>().ok()).map(|s| *s);
+    let parser = mpc_import_parsers(xs.collect()).remove(0);
+    Box::new(match prefix.as_deref() {
+        Some("&") => mpc_and(vec![mpc_lookahead(parser)], mpcf_null),
+        Some("!") => mpc_not(parser),
+        _ => parser,
+    })
+}
+
+// The values are the parser, then `?`, `*`, `+` or nothing
+fn mpcf_peg_suffix(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let mut xs = xs.into_iter();
+    let parser = mpc_import_parsers(xs.next().into_iter().collect()).remove(0);
+    let suffix = xs.next().and_then(|x| x.downcast::<String>().ok()).map(|s| *s);
+    Box::new(match suffix.as_deref() {
+        Some("?") => mpc_or(vec![parser, mpc_pass()]),
+        Some("*") => mpc_many(parser, mpcf_strfold),
+        Some("+") => mpc_many1(parser, mpcf_strfold),
+        _ => parser,
+    })
+}
+
+// PEG's own grammar, from Ford's "Parsing Expression Grammars" (2004), written with the
+// combinators of this crate
+fn mpc_peg_syntax() -> MpcGrammar {
+    let spacing = || mpc_skip_many(mpc_or(vec![
+        mpc_oneof(" \t\r\n"),
+        mpc_and(vec![mpc_char('#'), mpc_rest_of_line()], mpcf_null),
+    ]));
+    let token = |parser: MpcParser| mpc_and(vec![parser, spacing()], mpcf_fst);
+    let identifier = || token(mpc_expect(mpc_and(vec![
+        mpc_or(vec![mpc_alpha(), mpc_underscore()]),
+        mpc_many(mpc_or(vec![mpc_alphanum(), mpc_underscore()]), mpcf_strfold),
+    ], mpcf_strfold), "identifier"));
+    let left_arrow = || token(mpc_string("<-"));
+    // Characters as written, escapes included, up to the closing delimiter
+    let raw = |end: &str| mpc_many(mpc_or(vec![
+        mpc_and(vec![mpc_char('\\'), mpc_any()], mpcf_strfold),
+        mpc_noneof(&format!("{}\\", end)),
+    ]), mpcf_strfold);
+
+    let mut grammar = MpcGrammar::new();
+    grammar.define("grammar", mpc_and(vec![
+        spacing(),
+        mpc_many1(mpc_rule("definition"), mpcf_import_collect),
+        mpc_eoi(),
+    ], mpcf_import_rules));
+    grammar.define("definition", mpc_and(vec![
+        identifier(),
+        left_arrow(),
+        mpc_rule("expression"),
+    ], mpcf_import_rule));
+    grammar.define("expression", mpc_and(vec![
+        mpc_rule("sequence"),
+        mpc_many(mpc_and(vec![token(mpc_char('/')), mpc_rule("sequence")], mpcf_import_list), mpcf_import_list),
+    ], mpcf_import_choice));
+    grammar.define("sequence", mpc_many(mpc_rule("prefix"), mpcf_import_sequence));
+    grammar.define("prefix", mpc_and(vec![
+        mpc_or(vec![token(mpc_oneof("&!")), mpc_pass()]),
+        mpc_rule("suffix"),
+    ], mpcf_peg_prefix));
+    grammar.define("suffix", mpc_and(vec![
+        mpc_rule("primary"),
+        mpc_or(vec![token(mpc_oneof("?*+")), mpc_pass()]),
+    ], mpcf_peg_suffix));
+    grammar.define("primary", mpc_or(vec![
+        // An identifier followed by `<-` starts the next definition
+        mpc_and(vec![identifier(), mpc_not(left_arrow())], mpcf_peg_identifier),
+        mpc_and(vec![token(mpc_char('(')), mpc_rule("expression"), token(mpc_char(')'))], mpcf_import_sequence),
+        token(mpc_and(vec![mpc_char('\''), raw("'"), mpc_char('\'')], mpcf_peg_literal)),
+        token(mpc_and(vec![mpc_char('"'), raw("\""), mpc_char('"')], mpcf_peg_literal)),
+        token(mpc_and(vec![mpc_char('['), raw("]"), mpc_char(']')], mpcf_peg_class)),
+        mpc_and(vec![token(mpc_char('.'))], mpcf_peg_dot),
+    ]));
+    grammar
+}
+
+/// `mpc_peg!("...")` reads the grammar at compile time instead, see `mpc_derive::mpc_peg`
+#[cfg(feature = "derive")]
+pub use mpc_derive::mpc_peg;
+
+/// Builds a grammar from conventional PEG notation: `name <- expression`, with `/` for ordered
+/// choice, `&` and `!` for lookahead, `?`, `*` and `+` for repetition, `'...'` or `"..."` for
+/// literals, `[a-z]` for classes, `.` for any character and `#` for comments. Choices are
+/// ordered and repetitions greedy, as in the crate's own combinators. Values are the matched
+/// text.
+#[allow(clippy::result_large_err)]
+pub fn mpc_peg(filename: &str, peg: &str) -> Result<MpcGrammar, MpcErr> {
+    let rules = match mpc_parse_grammar(filename, peg, &mpc_peg_syntax(), "grammar") {
+        MpcResult::Ok(rules) => *rules.downcast::<Vec<MpcImportRule>>().unwrap(),
+        MpcResult::Err(e) => return Err(e),
+    };
+    let mut grammar = MpcGrammar::new();
+    for (name, _, definition) in rules {
+        grammar.define(&name, definition);
+    }
+    Ok(grammar)
+}
+
+// Parser DSL
+
+/// Builds parsers with PEG-like operators instead of nested calls. `a ~ b` is a sequence, `a | b`
+/// an ordered choice, `a*`, `a+` and `a?` repetitions, `!a` and `&a` lookahead; string literals
+/// match themselves, `.` any character, a name refers to a rule and `{ ... }` is any Rust
+/// expression giving an `MpcParser`. Sequences and repetitions fold into the matched text, as
+/// `mpc_peg` does. A list of `name = ...;` rules gives an `MpcGrammar`, anything else an
+/// `MpcParser`.
+///
+/// ```
+/// use mpc::prelude::*;
+/// let grammar = parser! {
+///     list = "[" ~ value ~ ("," ~ value)* ~ "]";
+///     value = { mpc_digit() }+ | list;
+/// };
+/// assert!(mpc_parse_grammar("input", "[1,[2,3]]", &grammar, "list").is_ok());
+///
+/// let sign = parser!("+" | "-");
+/// assert!(mpc_parse("input", "-", &sign).is_ok());
+/// ```
+#[macro_export]
+macro_rules! parser {
+    // Rules: each `name = ...` up to the next `;`
+    (@rules $grammar:ident) => {};
+    (@rules $grammar:ident $name:ident = $($rest:tt)*) => {
+        $crate::parser!(@rule $grammar $name [] $($rest)*)
+    };
+    (@rule $grammar:ident $name:ident [$($body:tt)*] ; $($rest:tt)*) => {
+        $grammar.define(stringify!($name), $crate::parser!(@alt [] [] $($body)*));
+        $crate::parser!(@rules $grammar $($rest)*)
+    };
+    (@rule $grammar:ident $name:ident [$($body:tt)*]) => {
+        $grammar.define(stringify!($name), $crate::parser!(@alt [] [] $($body)*));
+    };
+    (@rule $grammar:ident $name:ident [$($body:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::parser!(@rule $grammar $name [$($body)* $next] $($rest)*)
+    };
+
+    // Alternatives, split at `|`
+    (@alt [$($cur:tt)*] [$($alts:tt)*] | $($rest:tt)*) => {
+        $crate::parser!(@alt [] [$($alts)* ($($cur)*)] $($rest)*)
+    };
+    (@alt [$($cur:tt)*] [$($alts:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::parser!(@alt [$($cur)* $next] [$($alts)*] $($rest)*)
+    };
+    (@alt [$($cur:tt)*] [$($alts:tt)*]) => {
+        $crate::parser!(@or $($alts)* ($($cur)*))
+    };
+    (@or ($($only:tt)*)) => {
+        $crate::parser!(@seq [] [] $($only)*)
+    };
+    (@or $(($($alt:tt)*))+) => {
+        $crate::combinators::mpc_or(vec![$($crate::parser!(@seq [] [] $($alt)*)),+])
+    };
+
+    // Sequences, split at `~`
+    (@seq [$($cur:tt)*] [$($items:tt)*] ~ $($rest:tt)*) => {
+        $crate::parser!(@seq [] [$($items)* ($($cur)*)] $($rest)*)
+    };
+    (@seq [$($cur:tt)*] [$($items:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::parser!(@seq [$($cur)* $next] [$($items)*] $($rest)*)
+    };
+    (@seq [$($cur:tt)*] [$($items:tt)*]) => {
+        $crate::parser!(@and $($items)* ($($cur)*))
+    };
+    (@and ($($only:tt)*)) => {
+        $crate::parser!(@item $($only)*)
+    };
+    (@and $(($($item:tt)*))+) => {
+        $crate::combinators::mpc_and_assoc(vec![$($crate::parser!(@item $($item)*)),+], $crate::combinators::MpcAssocFold::Str)
+    };
+
+    // An atom with its prefix and postfix operators
+    (@item ! $($rest:tt)+) => {
+        $crate::combinators::mpc_not($crate::parser!(@item $($rest)+))
+    };
+    (@item & $($rest:tt)+) => {
+        $crate::combinators::mpc_and(vec![$crate::combinators::mpc_lookahead($crate::parser!(@item $($rest)+))], $crate::combinators::mpcf_null)
+    };
+    (@item $atom:tt $($ops:tt)*) => {
+        $crate::parser!(@postfix [$crate::parser!(@atom $atom)] $($ops)*)
+    };
+    (@postfix [$parser:expr] * $($ops:tt)*) => {
+        $crate::parser!(@postfix [$crate::combinators::mpc_many($parser, $crate::combinators::mpcf_strfold)] $($ops)*)
+    };
+    (@postfix [$parser:expr] + $($ops:tt)*) => {
+        $crate::parser!(@postfix [$crate::combinators::mpc_many1($parser, $crate::combinators::mpcf_strfold)] $($ops)*)
+    };
+    (@postfix [$parser:expr] ? $($ops:tt)*) => {
+        $crate::parser!(@postfix [$crate::combinators::mpc_or(vec![$parser, $crate::primitives::mpc_pass()])] $($ops)*)
+    };
+    (@postfix [$parser:expr]) => {
+        $parser
+    };
+
+    (@atom .) => {
+        $crate::primitives::mpc_any()
+    };
+    (@atom $name:ident) => {
+        $crate::primitives::mpc_rule(stringify!($name))
+    };
+    (@atom $literal:literal) => {
+        $crate::primitives::mpc_string($literal)
+    };
+    (@atom ($($inner:tt)*)) => {
+        $crate::parser!(@alt [] [] $($inner)*)
+    };
+    (@atom {$($parser:tt)*}) => {
+        {$($parser)*}
+    };
+
+    ($name:ident = $($rest:tt)*) => {{
+        let mut grammar = $crate::grammar::MpcGrammar::new();
+        $crate::parser!(@rules grammar $name = $($rest)*);
+        grammar
+    }};
+    ($($parser:tt)+) => {
+        $crate::parser!(@alt [] [] $($parser)+)
+    };
+}
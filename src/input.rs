@@ -0,0 +1,503 @@
+//! Input positions and the input stream parsers read from
+
+use crate::items::*;
+
+pub type MpcVal = Box<dyn std::any::Any>;
+
+/// State Type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MpcState {
+    /// Byte offset into the input, not a character count, so `&input[state.pos as usize..]` is
+    /// the text from here on. `col` counts characters.
+    pub pos: i64,
+    pub row: i64,
+    pub col: i64,
+    pub term: i32,
+}
+
+/// Span Type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MpcSpan {
+    pub start: MpcState,
+    pub end: MpcState,
+}
+
+impl MpcSpan {
+    pub fn new(start: MpcState, end: MpcState) -> Self {
+        MpcSpan { start, end }
+    }
+
+    /// Number of bytes covered by the span
+    pub fn len(&self) -> usize {
+        (self.end.pos - self.start.pos).max(0) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the part of `source` (the string that was parsed) covered by the span
+    pub fn slice<'a>(&self, source: &'a str) -> &'a str {
+        source.get(self.start.pos as usize..self.end.pos as usize).unwrap_or("")
+    }
+
+    /// Smallest span covering both `self` and `other`
+    pub fn merge(&self, other: &MpcSpan) -> MpcSpan {
+        MpcSpan {
+            start: if other.start.pos < self.start.pos { other.start } else { self.start },
+            end: if other.end.pos > self.end.pos { other.end } else { self.end },
+        }
+    }
+}
+
+// Input Stream
+
+/// Saved input position, used to backtrack after a lookahead
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MpcMark {
+    pub pos: usize,
+    pub state: MpcState,
+    pub last: char,
+    pub errors: usize,
+    pub events: usize,
+}
+
+pub struct MpcInput<'a> {
+    pub filename: String,
+    pub state: MpcState,
+    pub string: std::borrow::Cow<'a, str>,  // Input so far; with a reader, more is added as parsers need it
+    pub pos: usize,
+    pub last: char,  // Last consumed character, '\0' at the start of input
+    pub zero_copy: bool,
+    pub furthest: Option<MpcErr>,  // Failure that got furthest into the input, kept across backtracking
+    pub errors: Vec<MpcErr>,  // Errors recovered from by mpc_recover_with
+    pub memoize: bool,  // Cache parser results by input position (packrat parsing)
+    pub(crate) memo: std::collections::HashMap<(usize, usize), MpcMemo>,
+    pub grammar: Option<&'a MpcGrammar>,  // Rules that mpc_rule references are looked up in
+    pub(crate) seeds: std::collections::HashMap<(usize, usize), Option<MpcSeed>>,  // Left-recursive rules being grown, by rule and position
+    pub abort: Option<MpcErr>,  // Error that ends the parse, whatever alternatives are left to try
+    pub(crate) coverage: Option<std::collections::HashSet<usize>>,  // Addresses of the parsers that have matched, see MpcCoverage
+    pub(crate) source: Option<MpcSource<'a>>,  // Reader the rest of the input comes from, see from_reader
+    pub partial: bool,  // More input may follow the end of the string, see MpcSession
+    pub(crate) starved: bool,  // A parser looked past the end of partial input
+    pub(crate) events: Option<Vec<MpcEvent>>,  // Events of tagged parsers instead of ASTs, see mpc_parse_events
+    pub(crate) on_event: Option<&'a mut dyn FnMut(MpcEvent)>,  // Where events go once nothing can take them back
+    pub(crate) settled: usize,  // Events handed to on_event already, counted in marks as if still there
+    pub(crate) holds: usize,  // Parsers running that could take back or rewrite events, see settle_events
+    pub cancel: Option<&'a std::sync::atomic::AtomicBool>,  // Stops the parse once set, see mpc_parse_cancellable
+    pub(crate) steps: u64,  // Steps so far, see MpcLimits
+    pub(crate) depth: usize,  // Parsers running inside one another right now
+    pub limits: MpcLimits,
+}
+
+/// Bounds on the work a parse may do, so that input crafted to make a grammar backtrack
+/// exponentially, or nested millions of levels deep, can't tie up a service. A parse that goes
+/// over one fails with an error of kind `MpcErrKind::ResourceExhausted`. A step is a parser
+/// invocation, counting every retry after backtracking, or a character consumed by a scan
+/// standing for one invocation per character, as in `mpc_many` of a character class,
+/// `mpc_take_until` and `mpc_rest`. Parsers run on the VM, with explicit stacks on the heap, so
+/// however deeply the input nests it can't overflow the call stack of the parsing thread, and
+/// `depth` only bounds the memory the nesting takes. The default has neither limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MpcLimits {
+    pub steps: Option<u64>,  // Most steps
+    pub depth: Option<usize>,  // Most parsers running inside one another
+}
+
+// Reader that input is taken from as parsers need it
+pub(crate) struct MpcSource<'a> {
+    pub(crate) reader: Box<dyn std::io::Read + 'a>,
+    pub(crate) pending: Vec<u8>,  // Start of a character split between reads
+}
+
+// Longest match so far of a left-recursive rule, handed out to its recursive calls
+pub(crate) struct MpcSeed {
+    pub(crate) val: MpcVal,
+    pub(crate) pos: usize,
+    pub(crate) state: MpcState,
+    pub(crate) last: char,
+    pub(crate) errors: Vec<MpcErr>,
+}
+
+// What a tracked parser started with, see MpcInput::track
+pub(crate) struct MpcEntered {
+    memo: Option<(usize, usize)>,  // Parser and position its result is cached under, when memoizing
+    errors: usize,  // Errors recovered from before it started
+    starved: bool,  // Whether a parser before it looked past the end of partial input
+    holds: bool,  // Whether it holds back events from being settled
+}
+
+// Packrat table entry: what a parser did when run at some input position
+pub(crate) struct MpcMemo {
+    pub(crate) result: Result<MpcVal, MpcErr>,
+    pub(crate) pos: usize,
+    pub(crate) state: MpcState,
+    pub(crate) last: char,
+    pub(crate) errors: Vec<MpcErr>,  // Errors recovered from while parsing
+    pub(crate) furthest: Option<MpcErr>,
+}
+
+impl<'a> MpcInput<'a> {
+    pub fn new(filename: &str, string: &'a str) -> Self {
+        MpcInput {
+            filename: filename.to_string(),
+            state: MpcState::default(),
+            string: std::borrow::Cow::Borrowed(string),
+            pos: 0,
+            last: '\0',
+            zero_copy: false,
+            furthest: None,
+            errors: Vec::new(),
+            memoize: false,
+            memo: std::collections::HashMap::new(),
+            grammar: None,
+            seeds: std::collections::HashMap::new(),
+            abort: None,
+            coverage: None,
+            source: None,
+            partial: false,
+            starved: false,
+            events: None,
+            on_event: None,
+            settled: 0,
+            holds: 0,
+            cancel: None,
+            steps: 0,
+            depth: 0,
+            limits: MpcLimits::default(),
+        }
+    }
+
+    /// Input that owns its text, so it isn't tied to the lifetime of a string and can be stored
+    /// or returned from a function along with what was parsed from it
+    pub fn owned(filename: &str, string: impl Into<String>) -> Self {
+        MpcInput {
+            string: std::borrow::Cow::Owned(string.into()),
+            ..MpcInput::new(filename, "")
+        }
+    }
+
+    /// Input read from `reader` as parsers need it, instead of all up front. Everything read is
+    /// kept, as parsers can backtrack to any earlier position, but nothing is read past the
+    /// furthest point a parser looked at.
+    pub fn from_reader(filename: &str, reader: impl std::io::Read + 'a) -> Self {
+        MpcInput {
+            string: std::borrow::Cow::Owned(String::new()),
+            source: Some(MpcSource { reader: Box::new(reader), pending: Vec::new() }),
+            ..MpcInput::new(filename, "")
+        }
+    }
+
+    /// Appends the next chunk of the reader to the input. Returns false when there is no reader
+    /// or it has run out; a read error, or bytes that aren't UTF-8, abort the parse.
+    pub fn read_more(&mut self) -> bool {
+        let Some(source) = self.source.as_mut() else {
+            self.starved |= self.partial;
+            return false;
+        };
+        let mut chunk = [0u8; 8192];
+        let failure = loop {
+            let n = match source.reader.read(&mut chunk) {
+                Ok(0) if source.pending.is_empty() => {
+                    self.source = None;
+                    return false;
+                }
+                Ok(0) => break std::io::Error::new(std::io::ErrorKind::InvalidData, "input ends inside a UTF-8 character"),
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => break e,
+            };
+            source.pending.extend_from_slice(&chunk[..n]);
+            let valid = match mpc_utf8_prefix(&source.pending) {
+                Ok(valid) => valid,
+                Err(e) => break e,
+            };
+            if valid > 0 {
+                self.string.to_mut().push_str(std::str::from_utf8(&source.pending[..valid]).unwrap());
+                source.pending.drain(..valid);
+                return true;
+            }
+        };
+        self.source = None;
+        if self.abort.is_none() {
+            self.abort = Some(MpcErr::io(&self.filename, &failure));
+        }
+        false
+    }
+
+    /// Reads until at least `len` bytes of input are there, or the reader runs out
+    pub fn fill(&mut self, len: usize) {
+        while self.string.len() < len && self.read_more() {}
+    }
+
+    /// Value for the text consumed since `start`: an owned `String`, or in zero-copy mode an
+    /// `MpcSpan` that can be sliced out of the original input without allocating.
+    pub fn lexeme(&self, start: MpcState) -> MpcVal {
+        if self.zero_copy {
+            Box::new(MpcSpan { start, end: self.state })
+        } else {
+            Box::new(self.string[start.pos as usize..self.pos].to_string())
+        }
+    }
+
+    pub fn remaining(&self) -> &str {
+        &self.string[self.pos..]
+    }
+
+    pub fn peek(&mut self) -> Option<char> {
+        self.fill(self.pos + 1);
+        self.remaining().chars().next()
+    }
+
+    pub fn record_failure(&mut self, e: &MpcErr) {
+        match self.furthest {
+            Some(ref furthest) if furthest.state.pos > e.state.pos => {}
+            Some(ref furthest) if furthest.state.pos == e.state.pos => {
+                self.furthest = MpcErr::or(vec![furthest.clone(), e.clone()]);
+            }
+            _ => self.furthest = Some(e.clone()),
+        }
+    }
+
+    /// The error to report for a failed parse: `e`, or the furthest failure seen if it got deeper
+    pub fn furthest_err(&mut self, e: MpcErr) -> MpcErr {
+        match self.furthest.take() {
+            Some(furthest) => MpcErr::or(vec![e, furthest]).unwrap(),
+            None => e,
+        }
+    }
+
+    /// Fills in the filename, source line and suggestion of an error raised on this input
+    pub fn finish_err(&self, mut e: MpcErr) -> MpcErr {
+        e.filename = self.filename.clone();
+        e.source_line = self.line_at(e.state.pos as usize).to_string();
+        mpc_err_suggest(&mut e, self);
+        e
+    }
+
+    /// The full input line containing byte offset `pos`, without its line break
+    pub fn line_at(&self, pos: usize) -> &str {
+        let pos = pos.min(self.string.len());
+        let start = self.string[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.string[pos..].find('\n').map_or(self.string.len(), |i| pos + i);
+        self.string[start..end].trim_end_matches('\r')
+    }
+
+    /// Ends the parse with an error of `kind` here, whatever alternatives are left to try
+    pub fn abort(&mut self, kind: MpcErrKind, failure: String) -> MpcErr {
+        let received = self.peek().unwrap_or('\0');
+        let e = MpcErr { kind, ..MpcErr::new(self.state, vec![], failure, received) };
+        self.abort = Some(e.clone());
+        e
+    }
+
+    // Counts a parser invocation, failing once the parse has been aborted, cancelled or has
+    // run out of steps
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn step(&mut self) -> Result<(), MpcErr> {
+        self.charge(1)
+    }
+
+    // Counts `n` steps, as for the characters consumed by one scan over the input that stands
+    // for a parser invoked once per character, failing as step does
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn charge(&mut self, n: u64) -> Result<(), MpcErr> {
+        if let Some(ref e) = self.abort {
+            return Err(e.clone());
+        }
+        let before = self.steps;
+        self.steps += n;
+        if let Some(cancel) = self.cancel {
+            if before / MPC_CANCEL_INTERVAL != self.steps / MPC_CANCEL_INTERVAL && cancel.load(std::sync::atomic::Ordering::Relaxed) {
+                return Err(self.abort(MpcErrKind::Cancelled, "parse cancelled".to_string()));
+            }
+        }
+        if let Some(steps) = self.limits.steps {
+            if self.steps > steps {
+                let failure = format!("parse gave up after {} steps", steps);
+                return Err(self.abort(MpcErrKind::ResourceExhausted, failure));
+            }
+        }
+        Ok(())
+    }
+
+    // Charges a step for each character from `start` to the current position
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn charge_since(&mut self, start: usize) -> Result<(), MpcErr> {
+        let chars = self.string[start..self.pos].chars().count();
+        self.charge(chars as u64)
+    }
+
+    // Counts a parser starting, failing as step does or when it would nest parsers deeper than
+    // the limit. Every parser that enters leaves again, lowering the depth.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn enter(&mut self) -> Result<(), MpcErr> {
+        self.step()?;
+        if let Some(depth) = self.limits.depth {
+            if self.depth >= depth {
+                let failure = format!("input nested more than {} parsers deep", depth);
+                return Err(self.abort(MpcErrKind::ResourceExhausted, failure));
+            }
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // Whether parsers have to be tracked as they start and end, to memoize their results, settle
+    // events or record coverage
+    pub(crate) fn tracked(&self) -> bool {
+        self.memoize || self.events.is_some() || self.coverage.is_some()
+    }
+
+    // The result `parser` had here before, if it was cached, moving on to where it ended.
+    // Parsers are borrowed for the whole parse, so their address identifies them in the memo
+    // table.
+    pub(crate) fn recall(&mut self, parser: &MpcParser) -> Option<MpcResult> {
+        if !self.memoize {
+            return None;
+        }
+        let memo = self.memo.get(&(parser as *const MpcParser as usize, self.pos))?;
+        let result = match memo.result {
+            Ok(ref val) => MpcResult::Ok(mpc_val_clone(val).unwrap()),
+            Err(ref e) => MpcResult::Err(e.clone()),
+        };
+        let (errors, furthest) = (memo.errors.clone(), memo.furthest.clone());
+        self.pos = memo.pos;
+        self.state = memo.state;
+        self.last = memo.last;
+        self.errors.extend(errors);
+        if let Some(ref furthest) = furthest {
+            self.record_failure(furthest);
+        }
+        if let (Some(hits), MpcResult::Ok(_)) = (&mut self.coverage, &result) {
+            hits.insert(parser as *const MpcParser as usize);
+        }
+        Some(result)
+    }
+
+    // Starts tracking `parser`, which a recall didn't answer
+    pub(crate) fn track(&mut self, parser: &MpcParser) -> MpcEntered {
+        // Sequences pass their children's events through as they are, so only the rest hold
+        // them back from being settled
+        let holds = self.events.is_some() && !matches!(parser.parser_type, MpcParserType::And(..) | MpcParserType::Apply(..) | MpcParserType::Lazy(..));
+        self.holds += holds as usize;
+        MpcEntered {
+            memo: self.memoize.then_some((parser as *const MpcParser as usize, self.pos)),
+            errors: self.errors.len(),
+            starved: std::mem::take(&mut self.starved),
+            holds,
+        }
+    }
+
+    // Ends tracking `parser`, caching its result and recording it as covered if it matched.
+    // Successes are only cached when the value can be cloned, see mpc_val_clone, and nothing is
+    // cached that depends on how partial input goes on.
+    pub(crate) fn untrack(&mut self, parser: &MpcParser, entered: MpcEntered, result: Result<&MpcVal, &MpcErr>) {
+        self.holds -= entered.holds as usize;
+        // What looked past the end of partial input can change once more arrives
+        let settled = !self.starved;
+        self.starved |= entered.starved;
+        if let (Some(key), true) = (entered.memo, settled) {
+            let cached = match result {
+                Ok(val) => mpc_val_clone(val).map(Ok),
+                Err(e) => Some(Err(e.clone())),
+            };
+            if let Some(cached) = cached {
+                self.memo.insert(key, MpcMemo {
+                    result: cached,
+                    pos: self.pos,
+                    state: self.state,
+                    last: self.last,
+                    errors: self.errors[entered.errors..].to_vec(),
+                    furthest: self.furthest.clone(),
+                });
+            }
+        }
+        if let (Some(hits), Ok(_)) = (&mut self.coverage, result) {
+            hits.insert(parser as *const MpcParser as usize);
+        }
+    }
+
+    pub fn mark(&self) -> MpcMark {
+        MpcMark {
+            pos: self.pos,
+            state: self.state,
+            last: self.last,
+            errors: self.errors.len(),
+            events: self.settled + self.events.as_ref().map_or(0, Vec::len),
+        }
+    }
+
+    pub fn rewind(&mut self, mark: MpcMark) {
+        self.pos = mark.pos;
+        self.state = mark.state;
+        self.last = mark.last;
+        self.errors.truncate(mark.errors);
+        if let Some(events) = self.events.as_mut() {
+            events.truncate(mark.events.saturating_sub(self.settled));
+        }
+    }
+
+    // Called by repetitions between items: hands the events so far to on_event if the
+    // repetition is the only parser running that could take them back, with nothing around it
+    // that backtracks or tags
+    pub(crate) fn settle_events(&mut self) {
+        if self.holds != 1 {
+            return;
+        }
+        if let (Some(events), Some(on_event)) = (self.events.as_mut(), self.on_event.as_mut()) {
+            self.settled += events.len();
+            events.drain(..).for_each(on_event);
+        }
+    }
+
+    pub fn advance(&mut self) -> Option<char> {
+        if let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+            self.state.pos += c.len_utf8() as i64;
+            self.last = c;
+            if c == '\n' {
+                self.state.col = 0;
+                self.state.row += 1;
+            } else {
+                self.state.col += 1;
+            }
+            Some(c)
+        } else {
+            None
+        }
+    }
+
+    /// Moves forward to byte offset `end` in one step, counting lines and columns over the
+    /// skipped bytes instead of decoding them one character at a time
+    pub fn advance_to(&mut self, end: usize) {
+        let skipped = &self.string[self.pos..end];
+        let Some(last) = skipped.chars().next_back() else { return };
+        let bytes = skipped.as_bytes();
+        let line = match bytes.iter().rposition(|&b| b == b'\n') {
+            Some(i) => {
+                self.state.row += bytes.iter().filter(|&&b| b == b'\n').count() as i64;
+                self.state.col = 0;
+                &bytes[i + 1..]
+            }
+            None => bytes,
+        };
+        // Every byte that is not a UTF-8 continuation byte starts a character
+        self.state.col += line.iter().filter(|&&b| b & 0xC0 != 0x80).count() as i64;
+        self.state.pos += bytes.len() as i64;
+        self.pos = end;
+        self.last = last;
+    }
+}
+
+// Length of the longest start of `bytes` made of whole UTF-8 characters, leaving out one cut
+// short at the end. Bytes that can't be UTF-8 whatever follows are an error.
+pub(crate) fn mpc_utf8_prefix(bytes: &[u8]) -> std::io::Result<usize> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(text.len()),
+        Err(e) if e.error_len().is_none() => Ok(e.valid_up_to()),
+        Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "input is not valid UTF-8")),
+    }
+}
@@ -0,0 +1,1748 @@
+//! The parser type, and the engine that runs parsers over an input
+
+use crate::items::*;
+
+/// Parser Type
+#[derive(Debug, Clone)]
+pub enum MpcParserType {
+    Any,  // Matches any character
+    Char(char),  // Matches specific character
+    Range(char, char),  // Matches character in range
+    OneOf(String, MpcCharSet),  // Matches any char in string, with the string compiled to a set
+    NoneOf(String, MpcCharSet),  // Matches any char not in string, with the string compiled to a set
+    Satisfy(fn(char) -> bool),  // Matches char satisfying function
+    String(String),  // Matches exact string
+    Pass,  // Always succeeds, consumes no input
+    Fail(String),  // Always fails with message
+    Lift(fn() -> MpcVal),  // Consumes no input, returns result of function
+    LiftVal(fn() -> MpcVal),  // Consumes no input, returns value
+    Anchor(fn(char, char) -> bool),  // Consumes no input, checks condition
+    State,  // Consumes no input, returns parser state
+    Rest,  // Consumes and returns all remaining input
+    RestOfLine,  // Consumes and returns input up to the end of the line
+    // Combinators
+    And(Vec<std::sync::Arc<MpcParser>>, fn(i32, Vec<MpcVal>) -> MpcVal, Option<MpcAssocFold>),  // Sequence of parsers, with the associative fold it was made with, if any
+    Or(Vec<std::sync::Arc<MpcParser>>, Option<MpcDispatch>),  // Alternative parsers, with a jump table when they can be predicted
+    Permutation(Vec<(std::sync::Arc<MpcParser>, bool)>, fn(i32, Vec<MpcVal>) -> MpcVal),  // Each parser once, in any order
+    Many(std::sync::Arc<MpcParser>, fn(i32, Vec<MpcVal>) -> MpcVal),  // Zero or more
+    Many1(std::sync::Arc<MpcParser>, fn(i32, Vec<MpcVal>) -> MpcVal),  // One or more
+    SkipMany(std::sync::Arc<MpcParser>),  // Zero or more, results discarded
+    SkipMany1(std::sync::Arc<MpcParser>),  // One or more, results discarded
+    FoldMany(fn() -> MpcVal, std::sync::Arc<MpcParser>, fn(MpcVal, MpcVal) -> MpcVal),  // Zero or more, folded incrementally
+    Count(i32, std::sync::Arc<MpcParser>, fn(i32, Vec<MpcVal>) -> MpcVal),  // Exactly n times
+    Repeat(i32, Option<i32>, std::sync::Arc<MpcParser>, fn(i32, Vec<MpcVal>) -> MpcVal),  // Between min and max times
+    SepBy(std::sync::Arc<MpcParser>, std::sync::Arc<MpcParser>, fn(i32, Vec<MpcVal>) -> MpcVal),  // Separated by
+    SepBy1(std::sync::Arc<MpcParser>, std::sync::Arc<MpcParser>, fn(i32, Vec<MpcVal>) -> MpcVal),  // One or more separated by
+    SepEndBy(std::sync::Arc<MpcParser>, std::sync::Arc<MpcParser>, fn(i32, Vec<MpcVal>) -> MpcVal),  // Separated by, optional trailing separator
+    SepEndBy1(std::sync::Arc<MpcParser>, std::sync::Arc<MpcParser>, fn(i32, Vec<MpcVal>) -> MpcVal),  // One or more separated by, optional trailing separator
+    ChainL1(std::sync::Arc<MpcParser>, std::sync::Arc<MpcParser>, fn(MpcVal, MpcVal, MpcVal) -> MpcVal),  // Left-associative binary operators
+    ChainR1(std::sync::Arc<MpcParser>, std::sync::Arc<MpcParser>, fn(MpcVal, MpcVal, MpcVal) -> MpcVal),  // Right-associative binary operators
+    Expr(std::sync::Arc<MpcParser>, Vec<MpcOperator>),  // Operator-precedence expression
+    Apply(std::sync::Arc<MpcParser>, fn(MpcVal) -> MpcVal),  // Parser's value passed through a function
+    Recognize(std::sync::Arc<MpcParser>),  // Matched input text instead of the parser's value
+    Consumed(std::sync::Arc<MpcParser>),  // Matched input text together with the parser's value
+    Spanned(std::sync::Arc<MpcParser>),  // Parser's value together with the span it covered
+    TakeUntil(std::sync::Arc<MpcParser>),  // Everything up to (not including) a terminator parser
+    StringUntil(String),  // Everything up to (not including) a terminator string
+    Expect(std::sync::Arc<MpcParser>, String),  // Replaces the error of a failed parser with an expected name
+    Recover(std::sync::Arc<MpcParser>, std::sync::Arc<MpcParser>, fn() -> MpcVal),  // On failure, records the error and skips past a sync point
+    Not(std::sync::Arc<MpcParser>),  // Succeeds without consuming input where the parser fails
+    Lookahead(std::sync::Arc<MpcParser>),  // Parser's value, without consuming input
+    Rule(String),  // Reference to a rule of the grammar being parsed
+    Lazy(fn() -> MpcParser, MpcLazyCell),  // Parser built by a function the first time it runs
+    // AST Building
+    Tag(std::sync::Arc<MpcParser>, String),  // Add tag to result
+    AddTag(std::sync::Arc<MpcParser>, String),  // Prefix the result's tag with another
+    Root(std::sync::Arc<MpcParser>),  // Make root of AST
+}
+
+/// Parser
+#[derive(Debug, Clone)]
+pub struct MpcParser {
+    pub name: String,
+    pub parser_type: MpcParserType,
+    pub compiled: MpcCompiledCell,  // Program parse runs, see MpcParser::program
+}
+
+// Parsers and grammars hold only data and `fn` pointers, so one built at startup can be kept in
+// a `static` and used from any thread. Anything added to `MpcParserType` has to keep it so.
+const _: () = {
+    fn mpc_send_sync<T: Send + Sync>() {}
+    let _ = mpc_send_sync::<MpcParser>;
+    let _ = mpc_send_sync::<MpcGrammar>;
+    let _ = mpc_send_sync::<MpcProgram<'static>>;
+};
+
+impl MpcParser {
+    pub fn new(name: &str) -> Self {
+        MpcParser {
+            name: name.to_string(),
+            parser_type: MpcParserType::Any, // placeholder
+            compiled: MpcCompiledCell::default(),
+        }
+    }
+
+    /// Prints the parser as a grammar description, like `mpc_print` in C
+    pub fn print(&self) {
+        println!("{}", self);
+    }
+
+    /// Graphviz graph of the combinator structure, one node per parser with edges to its
+    /// children in order. Rule references are drawn as ellipses.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph parser {\n  node [shape=box];\n");
+        mpc_dot_nodes(self, &std::collections::HashMap::new(), &mut 0, &mut dot, &mut String::new());
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Renders the parser as a railroad diagram, returned as an SVG document
+    pub fn to_railroad(&self) -> String {
+        mpc_rail(self).to_svg(&self.name)
+    }
+
+    // Chainable forms of the constructors, so a parser reads left to right. Sequences and
+    // repetitions join the matched text with `mpcf_strfold`; use the free functions for
+    // other folds.
+
+    /// This parser then `next`, as `self + next`
+    pub fn then(self, next: MpcParser) -> MpcParser {
+        self + next
+    }
+
+    /// This parser or else `other`, as `self | other`
+    pub fn or(self, other: MpcParser) -> MpcParser {
+        self | other
+    }
+
+    /// `mpc_many(self, mpcf_strfold)`
+    pub fn many(self) -> MpcParser {
+        mpc_many(self, mpcf_strfold)
+    }
+
+    /// `mpc_many1(self, mpcf_strfold)`
+    pub fn many1(self) -> MpcParser {
+        mpc_many1(self, mpcf_strfold)
+    }
+
+    /// `mpc_apply(self, f)`
+    pub fn map(self, f: fn(MpcVal) -> MpcVal) -> MpcParser {
+        mpc_apply(self, f)
+    }
+
+    /// `mpc_sepby(self, sep, mpcf_strfold)`: the items' text, without the separators
+    pub fn sep_by(self, sep: MpcParser) -> MpcParser {
+        mpc_sepby(self, sep, mpcf_strfold)
+    }
+
+    /// `mpca_tag(self, tag)`
+    pub fn tag(self, tag: &str) -> MpcParser {
+        mpca_tag(self, tag)
+    }
+}
+
+/// Renders the parser tree in the notation of C's `mpc_print`: `'c'` for characters, `"s"` for
+/// strings, `[a-z]`, `[abc]` and `[^abc]` for classes, `(a b)` for sequences, `(a | b)` for
+/// choices, `p?`, `p*`, `p+` and `p{n}` for repetitions, and `<name>` for rules and
+/// expected names. Permutations print as `(a & b?)`; wrappers that only change the value
+/// (tags, `mpc_recognize`, ...) print as the parser they wrap.
+impl std::fmt::Display for MpcParser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Separated lists, "p (sep p)*", with `tail` after the repetition
+        fn list(f: &mut std::fmt::Formatter<'_>, p: &MpcParser, sep: &MpcParser, tail: &str) -> std::fmt::Result {
+            write!(f, "({} ({} {})*{})", p, sep, p, tail)
+        }
+        // Operators of one kind in an expression, as a single alternative
+        fn choice(operators: &[&MpcParser]) -> String {
+            match operators {
+                [operator] => operator.to_string(),
+                _ => format!("({})", operators.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(" | ")),
+            }
+        }
+
+        match &self.parser_type {
+            MpcParserType::Any => write!(f, "<.>"),
+            MpcParserType::Char(c) => write!(f, "{:?}", c),
+            MpcParserType::Range(start, end) => write!(f, "[{}-{}]", start.escape_default(), end.escape_default()),
+            MpcParserType::OneOf(s, _) => write!(f, "[{}]", s.escape_default()),
+            MpcParserType::NoneOf(s, _) => write!(f, "[^{}]", s.escape_default()),
+            MpcParserType::Satisfy(_) => write!(f, "<f>"),
+            MpcParserType::String(s) => write!(f, "{:?}", s),
+            MpcParserType::Pass => write!(f, "<:>"),
+            MpcParserType::Fail(_) => write!(f, "<!>"),
+            MpcParserType::Lift(_) | MpcParserType::LiftVal(_) => write!(f, "<#>"),
+            MpcParserType::Anchor(_) if self.name == "anchor" => write!(f, "<@>"),
+            MpcParserType::Anchor(_) => write!(f, "<{}>", self.name),
+            MpcParserType::State => write!(f, "<S>"),
+            MpcParserType::Rest => write!(f, "<rest>"),
+            MpcParserType::RestOfLine => write!(f, "<rest_of_line>"),
+            MpcParserType::And(parsers, _, _) => match parsers.as_slice() {
+                [] => write!(f, "<:>"),
+                [parser] => write!(f, "{}", parser),
+                _ => write!(f, "({})", parsers.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(" ")),
+            },
+            MpcParserType::Or(parsers, _) => match parsers.as_slice() {
+                [] => write!(f, "<!>"),
+                [parser] => write!(f, "{}", parser),
+                [parser, pass] if matches!(pass.parser_type, MpcParserType::Pass) => write!(f, "{}?", parser),
+                _ => write!(f, "({})", parsers.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(" | ")),
+            },
+            MpcParserType::Permutation(parsers, _) => {
+                let parts: Vec<String> = parsers.iter()
+                    .map(|(p, optional)| if *optional { format!("{}?", p) } else { p.to_string() })
+                    .collect();
+                write!(f, "({})", parts.join(" & "))
+            }
+            MpcParserType::Many(p, _) | MpcParserType::SkipMany(p) | MpcParserType::FoldMany(_, p, _) => write!(f, "{}*", p),
+            MpcParserType::Many1(p, _) | MpcParserType::SkipMany1(p) => write!(f, "{}+", p),
+            MpcParserType::Count(n, p, _) => write!(f, "{}{{{}}}", p, n),
+            MpcParserType::Repeat(min, Some(max), p, _) => write!(f, "{}{{{},{}}}", p, min, max),
+            MpcParserType::Repeat(min, None, p, _) => write!(f, "{}{{{},}}", p, min),
+            MpcParserType::SepBy(p, sep, _) => {
+                list(f, p, sep, "")?;
+                write!(f, "?")
+            }
+            MpcParserType::SepBy1(p, sep, _) | MpcParserType::ChainL1(p, sep, _) | MpcParserType::ChainR1(p, sep, _) => list(f, p, sep, ""),
+            MpcParserType::SepEndBy(p, sep, _) => {
+                list(f, p, sep, &format!(" {}?", sep))?;
+                write!(f, "?")
+            }
+            MpcParserType::SepEndBy1(p, sep, _) => list(f, p, sep, &format!(" {}?", sep)),
+            MpcParserType::Expr(atom, operators) => {
+                let pick = |kind: fn(&MpcOperator) -> Option<&MpcParser>| operators.iter().filter_map(kind).collect::<Vec<_>>();
+                let prefix = pick(|o| if let MpcOperator::Prefix(p, _) = o { Some(p) } else { None });
+                let infix = pick(|o| if let MpcOperator::Infix(p, _, _) = o { Some(p) } else { None });
+                let postfix = pick(|o| if let MpcOperator::Postfix(p, _) = o { Some(p) } else { None });
+                let mut operand = atom.to_string();
+                if !prefix.is_empty() {
+                    operand = format!("{}* {}", choice(&prefix), operand);
+                }
+                if !postfix.is_empty() {
+                    operand = format!("{} {}*", operand, choice(&postfix));
+                }
+                match (infix.is_empty(), prefix.is_empty() && postfix.is_empty()) {
+                    (true, true) => write!(f, "{}", operand),
+                    (true, false) => write!(f, "({})", operand),
+                    (false, _) => write!(f, "({} ({} {})*)", operand, choice(&infix), operand),
+                }
+            }
+            MpcParserType::TakeUntil(end) => write!(f, "(!{} <.>)*", end),
+            MpcParserType::StringUntil(end) => write!(f, "(!{:?} <.>)*", end),
+            MpcParserType::Expect(_, expected) => write!(f, "<{}>", expected),
+            MpcParserType::Recover(p, sync, _) => write!(f, "({} | (!{} <.>)* {})", p, sync, sync),
+            MpcParserType::Rule(name) => write!(f, "<{}>", name),
+            MpcParserType::Lazy(_, _) => write!(f, "<{}>", self.name),
+            MpcParserType::Not(p) => write!(f, "!{}", p),
+            MpcParserType::Lookahead(p) => write!(f, "&{}", p),
+            MpcParserType::Recognize(p)
+            | MpcParserType::Apply(p, _)
+            | MpcParserType::Consumed(p)
+            | MpcParserType::Spanned(p)
+            | MpcParserType::Tag(p, _)
+            | MpcParserType::AddTag(p, _)
+            | MpcParserType::Root(p) => write!(f, "{}", p),
+        }
+    }
+}
+
+/// `a | b` is `mpc_or(vec![a, b])`, so `a | b | c` is a single choice of three
+impl std::ops::BitOr for MpcParser {
+    type Output = MpcParser;
+
+    fn bitor(self, rhs: MpcParser) -> MpcParser {
+        mpc_or(vec![self, rhs])
+    }
+}
+
+/// `a + b` matches `a` then `b`, joining their text with `mpcf_strfold`; `a + b + c` is a single
+/// sequence of three
+impl std::ops::Add for MpcParser {
+    type Output = MpcParser;
+
+    fn add(self, rhs: MpcParser) -> MpcParser {
+        mpc_and_assoc(vec![self, rhs], MpcAssocFold::Str)
+    }
+}
+
+/// `a >> b` matches `a` then `b`, keeping only the value of `b`
+impl std::ops::Shr for MpcParser {
+    type Output = MpcParser;
+
+    fn shr(self, rhs: MpcParser) -> MpcParser {
+        mpc_and(vec![self, rhs], mpcf_snd)
+    }
+}
+
+/// `a << b` matches `a` then `b`, keeping only the value of `a`
+impl std::ops::Shl for MpcParser {
+    type Output = MpcParser;
+
+    fn shl(self, rhs: MpcParser) -> MpcParser {
+        mpc_and_assoc(vec![self, rhs], MpcAssocFold::Fst)
+    }
+}
+
+/// Character Set: a bitset for the first 256 code points, and ranges for the rest
+#[derive(Clone, Default, PartialEq)]
+pub struct MpcCharSet {
+    bits: [u64; 4],
+    ranges: Vec<(char, char)>,  // Sorted and non-overlapping, so lookups can binary search
+}
+
+impl MpcCharSet {
+    pub fn new() -> Self {
+        MpcCharSet::default()
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.insert_range(c, c);
+    }
+
+    pub fn insert_range(&mut self, start: char, end: char) {
+        for c in start as u32..=(end as u32).min(255) {
+            self.bits[c as usize / 64] |= 1 << (c % 64);
+        }
+        if end as u32 > 255 {
+            let start = char::from_u32((start as u32).max(256)).unwrap_or(start);
+            self.ranges.push((start, end));
+            self.normalize();
+        }
+    }
+
+    pub fn union(&mut self, other: &MpcCharSet) {
+        for (bits, other) in self.bits.iter_mut().zip(other.bits) {
+            *bits |= other;
+        }
+        if !other.ranges.is_empty() {
+            self.ranges.extend_from_slice(&other.ranges);
+            self.normalize();
+        }
+    }
+
+    // Sorts the ranges and merges those that overlap or touch
+    fn normalize(&mut self) {
+        self.ranges.sort();
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(self.ranges.len());
+        for &(start, end) in &self.ranges {
+            if let Some(last) = merged.last_mut() {
+                if start as u32 <= last.1 as u32 + 1 {
+                    last.1 = last.1.max(end);
+                    continue;
+                }
+            }
+            merged.push((start, end));
+        }
+        self.ranges = merged;
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        let c = c as u32;
+        if c < 256 {
+            self.bits[c as usize / 64] & (1 << (c % 64)) != 0
+        } else {
+            self.ranges.binary_search_by(|&(start, end)| {
+                if (end as u32) < c {
+                    std::cmp::Ordering::Less
+                } else if (start as u32) > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            }).is_ok()
+        }
+    }
+}
+
+/// Lists the characters and ranges in the set, as in `{'0'..='9', '_'}`
+impl std::fmt::Debug for MpcCharSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut runs = Vec::new();
+        let mut start = None;
+        for c in 0..=256u32 {
+            let hit = c < 256 && self.bits[c as usize / 64] & (1 << (c % 64)) != 0;
+            match (hit, start) {
+                (true, None) => start = Some(c),
+                (false, Some(s)) => {
+                    runs.push((char::from_u32(s).unwrap(), char::from_u32(c - 1).unwrap()));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        runs.extend_from_slice(&self.ranges);
+        let mut set = f.debug_set();
+        for &(start, end) in &runs {
+            if start == end {
+                set.entry(&start);
+            } else {
+                set.entry(&(start..=end));
+            }
+        }
+        set.finish()
+    }
+}
+
+impl FromIterator<char> for MpcCharSet {
+    fn from_iter<I: IntoIterator<Item = char>>(chars: I) -> Self {
+        let mut set = MpcCharSet::new();
+        for c in chars {
+            set.insert(c);
+        }
+        set
+    }
+}
+
+/// Jump table for `mpc_or`, built from the FIRST sets of its alternatives. Alternatives
+/// without a FIRST set are tried for every character.
+#[derive(Clone)]
+pub struct MpcDispatch {
+    table: Vec<Vec<usize>>,  // Alternatives to try for each character below 256
+    eoi: Vec<usize>,  // Alternatives to try at the end of input
+    firsts: Vec<Option<MpcCharSet>>,
+}
+
+impl MpcDispatch {
+    /// Builds the table, or returns None when no alternative can be predicted
+    pub fn new(parsers: &[std::sync::Arc<MpcParser>]) -> Option<Self> {
+        let firsts: Vec<Option<MpcCharSet>> = parsers.iter().map(|p| p.first()).collect();
+        if firsts.iter().all(Option::is_none) {
+            return None;
+        }
+        let candidates = |c: char| (0..firsts.len())
+            .filter(|&i| mpc_first_allows(&firsts[i], c))
+            .collect();
+        Some(MpcDispatch {
+            table: (0..=255u8).map(|b| candidates(b as char)).collect(),
+            eoi: (0..firsts.len()).filter(|&i| firsts[i].is_none()).collect(),
+            firsts,
+        })
+    }
+
+    /// Indices of the alternatives that can match when the next character is `c`
+    pub fn candidates(&self, c: Option<char>) -> std::borrow::Cow<'_, [usize]> {
+        match c {
+            None => std::borrow::Cow::Borrowed(&self.eoi),
+            Some(c) if (c as u32) < 256 => std::borrow::Cow::Borrowed(&self.table[c as usize]),
+            Some(c) => std::borrow::Cow::Owned((0..self.firsts.len())
+                .filter(|&i| mpc_first_allows(&self.firsts[i], c))
+                .collect()),
+        }
+    }
+
+    // Whether alternative `i` is one of the candidates for `c`, as the VM checks it
+    pub(crate) fn allows(&self, i: usize, c: Option<char>) -> bool {
+        match c {
+            None => self.firsts[i].is_none(),
+            Some(c) => mpc_first_allows(&self.firsts[i], c),
+        }
+    }
+
+    // Whether alternative `i` can be ruled out at all, having a FIRST set
+    pub(crate) fn predicts(&self, i: usize) -> bool {
+        self.firsts[i].is_some()
+    }
+}
+
+/// Shows the FIRST sets, which the table is built from and which are much shorter to read
+impl std::fmt::Debug for MpcDispatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MpcDispatch").field("firsts", &self.firsts).finish()
+    }
+}
+
+// An alternative without a FIRST set has to be tried whatever comes next
+fn mpc_first_allows(first: &Option<MpcCharSet>, c: char) -> bool {
+    match first {
+        Some(set) => set.contains(c),
+        None => true,
+    }
+}
+
+// Parsing
+
+// How many parser invocations go by between looks at the cancellation flag, a power of two
+pub(crate) const MPC_CANCEL_INTERVAL: u64 = 1024;
+
+impl MpcParser {
+    /// Runs the parser on `input`. Parsers made of others are compiled to VM code, once, and run
+    /// with explicit stacks, see `MpcParser::program`, so however deeply the input nests, and whether or not
+    /// the parse memoizes, reports events or records coverage, the call stack doesn't grow with
+    /// it. Parsers that match characters run directly.
+    pub fn parse<'a>(&self, input: &mut MpcInput<'a>) -> MpcResult {
+        match self.parser_type {
+            // One standing for a lazy parser further out, compiled with that one's body
+            MpcParserType::Lazy(build, ref cell) if cell.own(build).is_none() => {
+                let body = cell.body(build);
+                return mpc_compile_lazy(self, &body, input.grammar).run(input);
+            }
+            _ if !self.is_native(input.grammar) => {
+                return match input.grammar {
+                    None => self.program().run(input),
+                    Some(grammar) => mpc_compile_with(self, Some(grammar)).run(input),
+                };
+            }
+            _ => {}
+        }
+        if let Err(e) = input.enter() {
+            return MpcResult::Err(e);
+        }
+        let result = if !input.tracked() {
+            self.parse_native(input)
+        } else if let Some(result) = input.recall(self) {
+            result
+        } else {
+            let entered = input.track(self);
+            let result = self.parse_native(input);
+            let outcome = match result {
+                MpcResult::Ok(ref val) => Ok(val),
+                MpcResult::Err(ref e) => Err(e),
+            };
+            input.untrack(self, entered, outcome);
+            result
+        };
+        input.depth -= 1;
+        result
+    }
+
+    /// Whether the parser can succeed without consuming input. Rule references and lazy parsers
+    /// count as nullable, since they can't be resolved without their grammar or without being
+    /// built.
+    pub fn nullable(&self) -> bool {
+        mpc_nullable(self, &std::collections::HashMap::new())
+    }
+
+    /// Repetitions in this parser whose body can match without consuming input, and so would
+    /// loop forever if it did. Each is described in a sentence.
+    pub fn empty_loops(&self) -> Vec<String> {
+        let mut loops = Vec::new();
+        mpc_empty_loops(self, &std::collections::HashMap::new(), &mut loops);
+        loops
+    }
+
+    // Direct sub-parsers
+    pub(crate) fn children(&self) -> Vec<&MpcParser> {
+        match self.parser_type {
+            MpcParserType::And(ref ps, _, _) | MpcParserType::Or(ref ps, _) => ps.iter().map(|p| &**p).collect(),
+            MpcParserType::Permutation(ref ps, _) => ps.iter().map(|(p, _)| &**p).collect(),
+            MpcParserType::Expr(ref atom, ref operators) => std::iter::once(&**atom)
+                .chain(operators.iter().map(|operator| match operator {
+                    MpcOperator::Prefix(p, _) | MpcOperator::Infix(p, _, _) | MpcOperator::Postfix(p, _) => p,
+                }))
+                .collect(),
+            MpcParserType::SepBy(ref a, ref b, _)
+            | MpcParserType::SepBy1(ref a, ref b, _)
+            | MpcParserType::SepEndBy(ref a, ref b, _)
+            | MpcParserType::SepEndBy1(ref a, ref b, _)
+            | MpcParserType::ChainL1(ref a, ref b, _)
+            | MpcParserType::ChainR1(ref a, ref b, _)
+            | MpcParserType::Recover(ref a, ref b, _) => vec![a, b],
+            MpcParserType::Many(ref p, _)
+            | MpcParserType::Many1(ref p, _)
+            | MpcParserType::SkipMany(ref p)
+            | MpcParserType::SkipMany1(ref p)
+            | MpcParserType::FoldMany(_, ref p, _)
+            | MpcParserType::Count(_, ref p, _)
+            | MpcParserType::Repeat(_, _, ref p, _)
+            | MpcParserType::Recognize(ref p)
+            | MpcParserType::Apply(ref p, _)
+            | MpcParserType::Consumed(ref p)
+            | MpcParserType::Spanned(ref p)
+            | MpcParserType::TakeUntil(ref p)
+            | MpcParserType::Not(ref p)
+            | MpcParserType::Lookahead(ref p)
+            | MpcParserType::Expect(ref p, _)
+            | MpcParserType::Tag(ref p, _)
+            | MpcParserType::AddTag(ref p, _)
+            | MpcParserType::Root(ref p) => vec![p],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Characters a match must start with, or None when the parser can match the empty string
+    /// or the first character can't be worked out without running it.
+    pub fn first(&self) -> Option<MpcCharSet> {
+        match &self.parser_type {
+            MpcParserType::Char(c) => Some(std::iter::once(*c).collect()),
+            MpcParserType::Range(start, end) => {
+                let mut set = MpcCharSet::new();
+                set.insert_range(*start, *end);
+                Some(set)
+            }
+            MpcParserType::OneOf(_, set) => Some(set.clone()),
+            MpcParserType::String(s) => s.chars().next().map(|c| std::iter::once(c).collect()),
+            MpcParserType::And(parsers, _, _) => parsers.first()?.first(),
+            MpcParserType::Or(parsers, _) => {
+                let mut set = MpcCharSet::new();
+                for parser in parsers {
+                    set.union(&parser.first()?);
+                }
+                Some(set)
+            }
+            MpcParserType::Many1(parser, _)
+            | MpcParserType::SkipMany1(parser)
+            | MpcParserType::SepBy1(parser, _, _)
+            | MpcParserType::SepEndBy1(parser, _, _)
+            | MpcParserType::ChainL1(parser, _, _)
+            | MpcParserType::ChainR1(parser, _, _)
+            | MpcParserType::Recognize(parser)
+            | MpcParserType::Apply(parser, _)
+            | MpcParserType::Consumed(parser)
+            | MpcParserType::Spanned(parser)
+            | MpcParserType::Expect(parser, _)
+            | MpcParserType::Tag(parser, _)
+            | MpcParserType::AddTag(parser, _)
+            | MpcParserType::Root(parser) => parser.first(),
+            MpcParserType::Count(n, parser, _) | MpcParserType::Repeat(n, _, parser, _) if *n > 0 => parser.first(),
+            _ => None,
+        }
+    }
+
+    /// The failure the parser reports when the next character isn't in its FIRST set, worked out
+    /// without running it. `mpc_or` uses it for the alternatives its jump table rules out.
+    pub fn first_err(&self, input: &mut MpcInput) -> MpcErr {
+        let received = input.peek().unwrap_or('\0');
+        let expected = |what: String| MpcErr::new(input.state, vec![what.clone()], format!("expected {}", what), received);
+        match &self.parser_type {
+            MpcParserType::Char(c) => expected(format!("{:?}", c)),
+            MpcParserType::Range(start, end) => expected(format!("character between {:?} and {:?}", start, end)),
+            MpcParserType::OneOf(chars, _) => expected(format!("one of {:?}", chars)),
+            MpcParserType::String(s) => expected(format!("{:?}", s)),
+            MpcParserType::Expect(_, name) => expected(name.clone()),
+            MpcParserType::And(parsers, _, _) if !parsers.is_empty() => parsers[0].first_err(input),
+            MpcParserType::Or(parsers, _) if !parsers.is_empty() => {
+                MpcErr::or(parsers.iter().map(|p| p.first_err(input)).collect()).unwrap()
+            }
+            MpcParserType::Tag(parser, tag) | MpcParserType::AddTag(parser, tag) => {
+                let mut e = parser.first_err(input);
+                e.context.push(tag.clone());
+                e
+            }
+            MpcParserType::Many1(parser, _)
+            | MpcParserType::SkipMany1(parser)
+            | MpcParserType::SepBy1(parser, _, _)
+            | MpcParserType::SepEndBy1(parser, _, _)
+            | MpcParserType::ChainL1(parser, _, _)
+            | MpcParserType::ChainR1(parser, _, _)
+            | MpcParserType::Count(_, parser, _)
+            | MpcParserType::Repeat(_, _, parser, _)
+            | MpcParserType::Recognize(parser)
+            | MpcParserType::Apply(parser, _)
+            | MpcParserType::Consumed(parser)
+            | MpcParserType::Spanned(parser)
+            | MpcParserType::Root(parser) => parser.first_err(input),
+            _ => expected(self.to_string()),
+        }
+    }
+
+    // Runs a parser that is_native, recording where it failed
+    fn parse_native<'a>(&self, input: &mut MpcInput<'a>) -> MpcResult {
+        let result = self.parse_type(input);
+        if let MpcResult::Err(ref e) = result {
+            input.record_failure(e);
+        }
+        result
+    }
+
+    // Whether the parser runs as a whole instead of being compiled to VM code: those that match
+    // characters or no input, repetitions of a single character class, which are scanned in
+    // bulk, and references to rules that `grammar` doesn't have
+    pub(crate) fn is_native(&self, grammar: Option<&MpcGrammar>) -> bool {
+        match self.parser_type {
+            MpcParserType::Many(ref inner, _)
+            | MpcParserType::Many1(ref inner, _)
+            | MpcParserType::SkipMany(ref inner)
+            | MpcParserType::SkipMany1(ref inner) => inner.is_class(),
+            MpcParserType::Rule(ref name) => grammar.map_or(true, |grammar| !grammar.index.contains_key(name)),
+            MpcParserType::Any
+            | MpcParserType::Char(_)
+            | MpcParserType::Range(..)
+            | MpcParserType::OneOf(..)
+            | MpcParserType::NoneOf(..)
+            | MpcParserType::Satisfy(_)
+            | MpcParserType::String(_)
+            | MpcParserType::Pass
+            | MpcParserType::Fail(_)
+            | MpcParserType::Lift(_)
+            | MpcParserType::LiftVal(_)
+            | MpcParserType::Anchor(_)
+            | MpcParserType::State
+            | MpcParserType::Rest
+            | MpcParserType::RestOfLine
+            | MpcParserType::StringUntil(_) => true,
+            _ => false,
+        }
+    }
+
+    fn parse_type<'a>(&self, input: &mut MpcInput<'a>) -> MpcResult {
+        match &self.parser_type {
+            MpcParserType::Any => {
+                let start = input.state;
+                if input.advance().is_some() {
+                    MpcResult::Ok(input.lexeme(start))
+                } else {
+                    MpcResult::Err(MpcErr::new(input.state, vec!["any character".to_string()], "unexpected end of input".to_string(), '\0'))
+                }
+            }
+            MpcParserType::Char(expected) => {
+                if let Some(c) = input.peek() {
+                    if c == *expected {
+                        let start = input.state;
+                        input.advance();
+                        MpcResult::Ok(input.lexeme(start))
+                    } else {
+                        MpcResult::Err(MpcErr::new(input.state, vec![format!("{:?}", expected)], format!("expected {:?}", expected), c))
+                    }
+                } else {
+                    MpcResult::Err(MpcErr::new(input.state, vec![format!("{:?}", expected)], format!("expected {:?}", expected), '\0'))
+                }
+            }
+            MpcParserType::Range(start, end) => {
+                if let Some(c) = input.peek() {
+                    if c >= *start && c <= *end {
+                        let start = input.state;
+                        input.advance();
+                        MpcResult::Ok(input.lexeme(start))
+                    } else {
+                        MpcResult::Err(MpcErr::new(input.state, vec![format!("character between {:?} and {:?}", start, end)], format!("expected character between {:?} and {:?}", start, end), c))
+                    }
+                } else {
+                    MpcResult::Err(MpcErr::new(input.state, vec![format!("character between {:?} and {:?}", start, end)], format!("expected character between {:?} and {:?}", start, end), '\0'))
+                }
+            }
+            MpcParserType::OneOf(chars, set) => {
+                if let Some(c) = input.peek() {
+                    if set.contains(c) {
+                        let start = input.state;
+                        input.advance();
+                        MpcResult::Ok(input.lexeme(start))
+                    } else {
+                        MpcResult::Err(MpcErr::new(input.state, vec![format!("one of {:?}", chars)], format!("expected one of {:?}", chars), c))
+                    }
+                } else {
+                    MpcResult::Err(MpcErr::new(input.state, vec![format!("one of {:?}", chars)], format!("expected one of {:?}", chars), '\0'))
+                }
+            }
+            MpcParserType::NoneOf(chars, set) => {
+                if let Some(c) = input.peek() {
+                    if !set.contains(c) {
+                        let start = input.state;
+                        input.advance();
+                        MpcResult::Ok(input.lexeme(start))
+                    } else {
+                        MpcResult::Err(MpcErr::new(input.state, vec![format!("none of {:?}", chars)], format!("expected none of {:?}", chars), c))
+                    }
+                } else {
+                    MpcResult::Err(MpcErr::new(input.state, vec![format!("none of {:?}", chars)], format!("expected none of {:?}", chars), '\0'))
+                }
+            }
+            MpcParserType::Satisfy(f) => {
+                if let Some(c) = input.peek() {
+                    if f(c) {
+                        let start = input.state;
+                        input.advance();
+                        MpcResult::Ok(input.lexeme(start))
+                    } else {
+                        MpcResult::Err(MpcErr::new(input.state, vec!["character satisfying condition".to_string()], "char does not satisfy condition".to_string(), c))
+                    }
+                } else {
+                    MpcResult::Err(MpcErr::new(input.state, vec!["character satisfying condition".to_string()], "end of input".to_string(), '\0'))
+                }
+            }
+            MpcParserType::String(s) => {
+                let start = input.state;
+                input.fill(input.pos + s.len());
+                let mut matched = input.remaining().bytes().zip(s.bytes()).take_while(|(a, b)| a == b).count();
+                if matched == s.len() {
+                    input.advance_to(input.pos + matched);
+                    return MpcResult::Ok(input.lexeme(start));
+                }
+                // Like the C version, the error is at the first character that differs
+                while !s.is_char_boundary(matched) {
+                    matched -= 1;
+                }
+                input.advance_to(input.pos + matched);
+                MpcResult::Err(MpcErr::new(input.state, vec![format!("{:?}", s)], format!("expected {:?}", s), input.peek().unwrap_or('\0')))
+            }
+            MpcParserType::Pass => {
+                MpcResult::Ok(Box::new(()))
+            }
+            MpcParserType::Fail(msg) => {
+                MpcResult::Err(MpcErr::new(input.state, vec![], msg.clone(), '\0'))
+            }
+            MpcParserType::Lift(f) => {
+                MpcResult::Ok(f())
+            }
+            MpcParserType::LiftVal(f) => {
+                MpcResult::Ok(f())
+            }
+            MpcParserType::Anchor(f) => {
+                let next = input.peek().unwrap_or('\0');
+                if f(input.last, next) {
+                    MpcResult::Ok(Box::new(()))
+                } else {
+                    MpcResult::Err(MpcErr::new(input.state, vec![self.name.clone()], format!("expected {}", self.name), next))
+                }
+            }
+            MpcParserType::Rule(ref name) => {
+                MpcResult::Err(MpcErr::new(input.state, vec![], format!("undefined rule {}", name), '\0'))
+            }
+            MpcParserType::State => {
+                MpcResult::Ok(Box::new(input.state))
+            }
+            MpcParserType::Rest => {
+                let (start, from) = (input.state, input.pos);
+                while input.read_more() {}
+                input.advance_to(input.string.len());
+                if let Err(e) = input.charge_since(from) {
+                    return MpcResult::Err(e);
+                }
+                MpcResult::Ok(input.lexeme(start))
+            }
+            MpcParserType::RestOfLine => {
+                let (start, from) = (input.state, input.pos);
+                while input.remaining().find(['\n', '\r']).is_none() && input.read_more() {}
+                let end = input.remaining().find(['\n', '\r']).map_or(input.string.len(), |i| input.pos + i);
+                input.advance_to(end);
+                if let Err(e) = input.charge_since(from) {
+                    return MpcResult::Err(e);
+                }
+                MpcResult::Ok(input.lexeme(start))
+            }
+            MpcParserType::Many(ref parser, fold) => {
+                let mut results = Vec::new();
+                if !input.zero_copy {
+                    match parser.parse_run(input) {
+                        Ok(Some(run)) => results.extend(input.string[run].chars().map(|c| Box::new(c.to_string()) as MpcVal)),
+                        Ok(None) => {}
+                        Err(e) => return MpcResult::Err(e),
+                    }
+                }
+                loop {
+                    input.settle_events();
+                    let start = input.pos;
+                    match parser.parse_or_rewind(input) {
+                        Some(_) if input.pos == start => return MpcResult::Err(self.empty_loop_err(parser, input)),
+                        Some(val) => results.push(val),
+                        None => break,
+                    }
+                }
+                let folded = fold(results.len() as i32, results);
+                MpcResult::Ok(folded)
+            }
+            MpcParserType::Many1(ref parser, fold) => {
+                let mut results = Vec::new();
+                let first = match parser.parse(input) {
+                    MpcResult::Ok(val) => val,
+                    MpcResult::Err(e) => return MpcResult::Err(e),
+                };
+                results.push(first);
+                if !input.zero_copy {
+                    match parser.parse_run(input) {
+                        Ok(Some(run)) => results.extend(input.string[run].chars().map(|c| Box::new(c.to_string()) as MpcVal)),
+                        Ok(None) => {}
+                        Err(e) => return MpcResult::Err(e),
+                    }
+                }
+                loop {
+                    input.settle_events();
+                    let start = input.pos;
+                    match parser.parse_or_rewind(input) {
+                        Some(_) if input.pos == start => return MpcResult::Err(self.empty_loop_err(parser, input)),
+                        Some(val) => results.push(val),
+                        None => break,
+                    }
+                }
+                let folded = fold(results.len() as i32, results);
+                MpcResult::Ok(folded)
+            }
+            MpcParserType::SkipMany(ref parser) | MpcParserType::SkipMany1(ref parser) => {
+                if let MpcParserType::SkipMany1(_) = self.parser_type {
+                    if let MpcResult::Err(e) = parser.parse(input) {
+                        return MpcResult::Err(e);
+                    }
+                }
+                if let Err(e) = parser.parse_run(input) {
+                    return MpcResult::Err(e);
+                }
+                loop {
+                    input.settle_events();
+                    let mark = input.mark();
+                    if let MpcResult::Err(_) = parser.parse(input) {
+                        input.rewind(mark);
+                        break;
+                    }
+                    if input.pos == mark.pos {
+                        return MpcResult::Err(self.empty_loop_err(parser, input));
+                    }
+                }
+                MpcResult::Ok(Box::new(()))
+            }
+            MpcParserType::StringUntil(ref s) => {
+                let (start, from) = (input.state, input.pos);
+                while input.remaining().find(s.as_str()).is_none() && input.read_more() {}
+                let end = match input.remaining().find(s.as_str()) {
+                    Some(offset) => input.pos + offset,
+                    None => input.string.len(),
+                };
+                input.advance_to(end);
+                if let Err(e) = input.charge_since(from) {
+                    return MpcResult::Err(e);
+                }
+                if end == input.string.len() && !s.is_empty() {
+                    return MpcResult::Err(MpcErr::new(input.state, vec![format!("{:?}", s)], format!("expected {:?} before end of input", s), '\0'));
+                }
+                MpcResult::Ok(input.lexeme(start))
+            }
+            // Everything else is compiled to VM code, see is_native
+            _ => unreachable!("{} runs on the VM", self.name),
+        }
+    }
+}
+
+impl MpcParser {
+    // Fast path for repeating a parser that matches one character from a class: consumes the
+    // whole run in one scan and returns where its text is in the input. The caller still tries
+    // the parser once more at the end of the run, so the failure that stopped it is reported
+    // as usual. A run reaching the end of what has been read goes on into what the reader gives.
+    // Each character of the run counts as a step, as parsing them one at a time would.
+    #[allow(clippy::result_large_err)]
+    fn parse_run(&self, input: &mut MpcInput) -> Result<Option<std::ops::Range<usize>>, MpcErr> {
+        fn run(s: &str, f: impl Fn(char) -> bool) -> usize {
+            s.char_indices().find(|&(_, c)| !f(c)).map_or(s.len(), |(i, _)| i)
+        }
+        let start = input.pos;
+        loop {
+            let rest = input.remaining();
+            let len = match self.parser_type {
+                MpcParserType::Any => rest.len(),
+                MpcParserType::Char(x) if x.is_ascii() => rest.bytes().position(|b| b != x as u8).unwrap_or(rest.len()),
+                MpcParserType::Char(x) => run(rest, |c| c == x),
+                MpcParserType::Range(start, end) => run(rest, |c| c >= start && c <= end),
+                MpcParserType::OneOf(_, ref set) => run(rest, |c| set.contains(c)),
+                MpcParserType::NoneOf(_, ref set) => run(rest, |c| !set.contains(c)),
+                MpcParserType::Satisfy(f) => run(rest, f),
+                _ => return Ok(None),
+            };
+            let end = len == rest.len();
+            input.advance_to(input.pos + len);
+            if !end || !input.read_more() {
+                input.charge_since(start)?;
+                return Ok(Some(start..input.pos));
+            }
+        }
+    }
+
+    // Error for `mpc_not` of this parser where it matched
+    pub(crate) fn not_err(&self, input: &mut MpcInput) -> MpcErr {
+        let received = input.peek().unwrap_or('\0');
+        let expected = format!("anything but {}", self);
+        MpcErr::new(input.state, vec![expected.clone()], format!("expected {}", expected), received)
+    }
+
+    // Error for `mpc_take_until` of this terminator, which failed with `e` at the end of input.
+    // It lists what the terminator expected there, as `mpc_parse` would.
+    pub(crate) fn until_err(&self, e: MpcErr, input: &MpcInput) -> MpcErr {
+        let expected = match e.expected {
+            expected if !expected.is_empty() => expected,
+            _ => vec![self.to_string()],
+        };
+        let failure = format!("expected {} before end of input", mpc_err_expected_string(&expected));
+        MpcErr::new(input.state, expected, failure, '\0')
+    }
+
+    // Error for a repetition whose body matched without consuming input, so that repeating it
+    // would never end. This is a bug in the grammar, so it aborts the parse rather than letting
+    // another alternative be tried.
+    pub(crate) fn empty_loop_err(&self, body: &MpcParser, input: &mut MpcInput) -> MpcErr {
+        let failure = format!("{} repeats {}, which matched without consuming input and would loop forever", self.name, body.name);
+        let e = MpcErr::new(input.state, vec![], failure, input.peek().unwrap_or('\0'));
+        input.abort = Some(e.clone());
+        e
+    }
+
+    // Whether this parser matches exactly one character from a class, see parse_run
+    pub(crate) fn is_class(&self) -> bool {
+        matches!(self.parser_type,
+            MpcParserType::Any
+            | MpcParserType::Char(_)
+            | MpcParserType::Range(_, _)
+            | MpcParserType::OneOf(_, _)
+            | MpcParserType::NoneOf(_, _)
+            | MpcParserType::Satisfy(_))
+    }
+
+    // Parses one optional repetition, leaving the input untouched if it fails
+    fn parse_or_rewind(&self, input: &mut MpcInput) -> Option<MpcVal> {
+        let mark = input.mark();
+        match self.parse(input) {
+            MpcResult::Ok(val) => Some(val),
+            MpcResult::Err(_) => {
+                input.rewind(mark);
+                None
+            }
+        }
+    }
+}
+
+// Failure at a non-associative operator following one of the same precedence
+pub(crate) fn mpc_expr_chained_err(op: &MpcParser, input: &mut MpcInput) -> MpcErr {
+    let e = MpcErr::new(input.state, vec![], format!("{} is non-associative and can't be chained", op), input.peek().unwrap_or('\0'));
+    if input.furthest.as_ref().map_or(true, |furthest| furthest.state.pos <= e.state.pos) {
+        input.furthest = Some(e.clone());
+    }
+    e
+}
+
+pub(crate) fn mpc_expr_operand(val: MpcVal, span: MpcSpan) -> MpcAst {
+    let mut ast = match val.downcast::<MpcAst>() {
+        Ok(ast) => *ast,
+        Err(val) => match val.downcast::<String>() {
+            Ok(s) => MpcAst::new("atom", &s),
+            Err(_) => MpcAst::new("atom", ""),
+        },
+    };
+    if ast.span == MpcSpan::default() {
+        ast.state = span.start;
+        ast.span = span;
+    }
+    ast
+}
+
+pub(crate) fn mpc_expr_node(tag: &str, op: &MpcParser, val: MpcVal, operands: Vec<MpcAst>, span: MpcSpan) -> MpcAst {
+    let contents = match val.downcast::<String>() {
+        Ok(s) => *s,
+        Err(_) => op.name.clone(),
+    };
+    let mut ast = MpcAst::new(tag, &contents);
+    ast.state = span.start;
+    ast.span = span;
+    ast.children = operands.into_iter().map(Box::new).collect();
+    ast.children_num = ast.children.len() as i32;
+    ast
+}
+
+// Main parsing function
+pub fn mpc_parse(filename: &str, string: &str, parser: &MpcParser) -> MpcResult {
+    let mut input = MpcInput::new(filename, string);
+    mpc_parse_input(&mut input, parser)
+}
+
+/// Parses a prefix of `string`, returning the value with the state the parser stopped in and
+/// the input it left, so the caller can carry on from there with its own logic.
+#[allow(clippy::result_large_err)]
+pub fn mpc_parse_partial<'s>(
+    filename: &str,
+    string: &'s str,
+    parser: &MpcParser,
+) -> Result<(MpcVal, MpcState, &'s str), MpcErr> {
+    let mut input = MpcInput::new(filename, string);
+    let result = mpc_parse_input(&mut input, parser).into_result()?;
+    Ok((result, input.state, &string[input.pos..]))
+}
+
+// Runs `parser` and reports how the parse went, see mpc_parse_finish
+pub(crate) fn mpc_parse_input(input: &mut MpcInput, parser: &MpcParser) -> MpcResult {
+    let result = parser.parse(input);
+    mpc_parse_finish(input, result)
+}
+
+// Reports the furthest failure seen, with the source line and suggestion filled in
+pub(crate) fn mpc_parse_finish(input: &mut MpcInput, result: MpcResult) -> MpcResult {
+    if let Some(e) = input.abort.take() {
+        return MpcResult::Err(input.finish_err(e));
+    }
+    match result {
+        MpcResult::Ok(val) => MpcResult::Ok(val),
+        MpcResult::Err(e) => {
+            let e = input.furthest_err(e);
+            MpcResult::Err(input.finish_err(e))
+        }
+    }
+}
+
+/// Like `mpc_parse`, but gives up with an error of kind `MpcErrKind::Cancelled` soon after
+/// `cancel` is set, for instance by a UI thread or a server's timeout, so a runaway parse of
+/// hostile input can be stopped from outside. The flag is only looked at once every 1024 steps,
+/// as `MpcLimits` counts them, so a parse stops within that many steps of it being set, and one
+/// shorter than that runs to the end.
+pub fn mpc_parse_cancellable(
+    filename: &str,
+    string: &str,
+    parser: &MpcParser,
+    cancel: &std::sync::atomic::AtomicBool,
+) -> MpcResult {
+    let mut input = MpcInput::new(filename, string);
+    input.cancel = Some(cancel);
+    mpc_parse_input(&mut input, parser)
+}
+
+/// Like `mpc_parse`, but fails with an error of kind `MpcErrKind::ResourceExhausted` as soon as
+/// the parse goes over `limits`
+pub fn mpc_parse_limited(filename: &str, string: &str, parser: &MpcParser, limits: MpcLimits) -> MpcResult {
+    let mut input = MpcInput::new(filename, string);
+    input.limits = limits;
+    mpc_parse_input(&mut input, parser)
+}
+
+/// Reads the file at `path` and parses its contents, like `mpc_parse_contents` in C. Errors
+/// carry the path as their filename, and a file that can't be read gives an error of kind
+/// `MpcErrKind::Io`.
+pub fn mpc_parse_file(path: impl AsRef<std::path::Path>, parser: &MpcParser) -> MpcResult {
+    let filename = path.as_ref().display().to_string();
+    match std::fs::read_to_string(path) {
+        Ok(string) => mpc_parse(&filename, &string, parser),
+        Err(e) => MpcResult::Err(MpcErr::io(&filename, &e)),
+    }
+}
+
+/// Parses input read from `reader` as the parser needs it, instead of a string in memory, so
+/// parsing starts before all of it has arrived and stops reading once the parser is done. A
+/// read error, or input that isn't UTF-8, gives an error of kind `MpcErrKind::Io`.
+pub fn mpc_parse_reader(filename: &str, reader: impl std::io::Read, parser: &MpcParser) -> MpcResult {
+    let mut input = MpcInput::from_reader(filename, reader);
+    mpc_parse_input(&mut input, parser)
+}
+
+/// Parses each line of `reader` with `parser`, which has to match the whole line, collecting
+/// the values of the lines that parse and the errors of those that don't, so one bad line of a
+/// log doesn't stop the rest. Errors give their row and position in the whole input. A read
+/// error ends the input and is the last error.
+pub fn mpc_parse_lines(
+    filename: &str,
+    mut reader: impl std::io::BufRead,
+    parser: &MpcParser,
+) -> (Vec<MpcVal>, Vec<MpcErr>) {
+    // Compiled once for every line
+    let program = mpc_compile_whole(parser);
+    let (mut vals, mut errors) = (Vec::new(), Vec::new());
+    let mut line = String::new();
+    let (mut row, mut pos) = (0, 0);
+    loop {
+        line.clear();
+        let len = match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) => {
+                errors.push(MpcErr::io(filename, &e));
+                break;
+            }
+        };
+        let text = line.strip_suffix('\n').unwrap_or(&line);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        match mpc_parse_vm(filename, text, &program) {
+            MpcResult::Ok(val) => vals.push(val),
+            MpcResult::Err(e) => errors.push(mpc_err_at(e, MpcState { pos, row, col: 0, term: 0 })),
+        }
+        row += 1;
+        pos += len as i64;
+    }
+    (vals, errors)
+}
+
+// Moves an error from input parsed on its own to where that input starts in a larger one
+pub(crate) fn mpc_err_at(mut e: MpcErr, start: MpcState) -> MpcErr {
+    if e.state.row == 0 {
+        e.state.col += start.col;
+    }
+    e.state.row += start.row;
+    e.state.pos += start.pos;
+    e
+}
+
+/// Parses input made with `MpcInput::owned`, from its current position. The input keeps the
+/// text, so it can be kept next to the result, for instance to resolve `MpcSpan`s from a
+/// zero-copy parse after the function that read the text has returned.
+pub fn mpc_parse_owned(input: &mut MpcInput<'static>, parser: &MpcParser) -> MpcResult {
+    mpc_parse_input(input, parser)
+}
+
+/// Parses the next message from a pipe such as stdin a line at a time, for REPL-style programs.
+/// Each line is parsed with what came before it as though the input ended there; when that
+/// fails only for want of more input, like an unclosed bracket, the next line continues the
+/// message, re-parsing only what depended on the end of the last one as `MpcSession` does. So
+/// grammars anchored with `mpc_eoi` work, but the rest of the line after a message is dropped.
+/// Returns None once the pipe has ended with nothing left to parse.
+pub fn mpc_parse_pipe(filename: &str, pipe: &mut impl std::io::BufRead, parser: &MpcParser) -> Option<MpcResult> {
+    let mut session = MpcSession::new(filename, parser, MpcLimits::default());
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match pipe.read_line(&mut line) {
+            Ok(0) if session.buffered().is_empty() => return None,
+            Ok(0) => return Some(session.finish()),
+            Ok(_) => {}
+            Err(e) => return Some(MpcResult::Err(MpcErr::io(filename, &e))),
+        }
+        session.input.string.to_mut().push_str(&line);
+        let (result, starved) = session.run(true);
+        if result.is_ok() || !starved {
+            return Some(result);
+        }
+    }
+}
+
+/// Like `mpc_parse`, but collects every error recovered from by `mpc_recover_with` as well as
+/// the final failure, instead of stopping at the first one.
+pub fn mpc_parse_all_errors(filename: &str, string: &str, parser: &MpcParser) -> Result<MpcVal, Vec<MpcErr>> {
+    let mut input = MpcInput::new(filename, string);
+    let result = mpc_parse_input(&mut input, parser);
+    let mut errors = std::mem::take(&mut input.errors);
+    match result {
+        MpcResult::Ok(val) if errors.is_empty() => Ok(val),
+        MpcResult::Ok(_) => Err(errors),
+        MpcResult::Err(e) => {
+            errors.push(e);
+            Err(errors)
+        }
+    }
+}
+
+/// Parses with rule `start` of `grammar`, so rules can refer to each other through `mpc_rule`.
+/// Left-recursive rules such as `expr : expr '+' term | term` are supported by growing a seed
+/// match, as long as their values are types `mpc_val_clone` can copy.
+pub fn mpc_parse_grammar(filename: &str, string: &str, grammar: &MpcGrammar, start: &str) -> MpcResult {
+    let mut input = MpcInput::new(filename, string);
+    input.grammar = Some(grammar);
+    let parser = mpc_rule(start);
+    mpc_parse_input(&mut input, &parser)
+}
+
+/// Like `mpc_parse`, but caches the result of every parser at every input position, so
+/// grammars that backtrack heavily run in linear time. Results are only cached when their
+/// value is one of the types `mpc_val_clone` knows how to copy. The parse fails with an error
+/// of kind `MpcErrKind::ResourceExhausted` if it goes over `limits`, as with `mpc_parse_limited`.
+pub fn mpc_parse_memo(filename: &str, string: &str, parser: &MpcParser, limits: MpcLimits) -> MpcResult {
+    let mut input = MpcInput::new(filename, string);
+    input.memoize = true;
+    input.limits = limits;
+    mpc_parse_input(&mut input, parser)
+}
+
+/// Like `mpc_parse`, but primitive parsers and `mpcf_strfold` produce `MpcSpan`s into `string`
+/// instead of allocating a `String` per match. Use `MpcSpan::slice` to get the text. Text that
+/// isn't contiguous in `string`, such as `mpcf_strfold` of items with separators skipped between
+/// them, is a `Vec<MpcSpan>` of its pieces instead. Where a sequence or repetition has values
+/// that are `String`s already, made by `mpc_apply` or `mpc_lift`, its fold gets `String`s for
+/// all of them, so `mpcf_strfold` of them gives a `String` with all the text.
+pub fn mpc_parse_zero_copy(filename: &str, string: &str, parser: &MpcParser) -> MpcResult {
+    let mut input = MpcInput::new(filename, string);
+    input.zero_copy = true;
+    mpc_parse_input(&mut input, parser)
+}
+
+/// Parses independent documents, given as `(filename, text)` pairs, on rayon's thread pool.
+/// `MpcVal`s can't leave the thread they were made on, so each value is turned into a `T` by
+/// `convert` first, for instance with `MpcFromAst`. Results are in the order of `inputs`.
+#[cfg(feature = "rayon")]
+#[allow(clippy::result_large_err)]
+pub fn mpc_parse_many_parallel<F, S, T>(
+    inputs: &[(F, S)],
+    parser: &MpcParser,
+    convert: fn(MpcVal) -> T,
+) -> Vec<Result<T, MpcErr>>
+where
+    F: AsRef<str> + Sync,
+    S: AsRef<str> + Sync,
+    T: Send,
+{
+    use rayon::prelude::*;
+    // Compiled once for every document
+    let program = mpc_compile(parser);
+    inputs
+        .par_iter()
+        .map(|(filename, text)| mpc_parse_vm(filename.as_ref(), text.as_ref(), &program).into_result().map(convert))
+        .collect()
+}
+
+/// Parses without building `MpcAst`s: tagged parsers report `MpcEvent`s to `on_event` instead,
+/// entering and leaving nodes and passing on tokens, so a huge document can be processed without
+/// holding its tree. Untagged parsers still build their values, which are dropped at the nearest
+/// tag.
+///
+/// Backtracking can take events back, or a tag around them rewrite them, so they are held, one
+/// tag and span per node, until nothing can: between the items of a repetition with no tag,
+/// alternative or other backtracking parser around it, such as the records of a file, the
+/// events so far are handed to `on_event` as the parse goes, and the rest once it has
+/// succeeded. A failed parse may have reported the events of the items before the failure.
+#[allow(clippy::result_large_err)]
+pub fn mpc_parse_events(
+    filename: &str,
+    string: &str,
+    parser: &MpcParser,
+    mut on_event: impl FnMut(MpcEvent),
+) -> Result<(), MpcErr> {
+    let mut input = MpcInput::new(filename, string);
+    input.events = Some(Vec::new());
+    input.on_event = Some(&mut on_event);
+    mpc_parse_input(&mut input, parser).into_result()?;
+    let on_event = input.on_event.take().unwrap();
+    input.events.take().unwrap().into_iter().for_each(on_event);
+    Ok(())
+}
+
+/// Parses straight into an `MpcAstArena`, without a `Box` per node as an `MpcAst` takes: the
+/// events of `mpc_parse_events` become the arena's nodes, one for each tagged node of the tree
+/// with its tag and span. Untagged pieces such as punctuation get no node, so a leaf's
+/// contents are all the text it matched, and nodes with tagged children have none. Trivia
+/// isn't kept, and untagged parsers still build their values, dropped at the nearest tag.
+#[allow(clippy::result_large_err)]
+pub fn mpc_parse_arena(filename: &str, string: &str, parser: &MpcParser) -> Result<MpcAstArena, MpcErr> {
+    let mut arena = MpcAstArena::new();
+    let mut open = Vec::new();
+    mpc_parse_events(filename, string, parser, |event| {
+        let parent = open.last().copied();
+        let span = event.span();
+        let id = match event {
+            MpcEvent::Enter(ref tag, _) => arena.push(parent, tag, ""),
+            MpcEvent::Exit(..) => {
+                open.pop();
+                return;
+            }
+            MpcEvent::Token(ref tag, _) => arena.push(parent, tag, &string[span.start.pos as usize..span.end.pos as usize]),
+        };
+        let node = arena.node_mut(id);
+        node.state = span.start;
+        node.span = span;
+        if let MpcEvent::Enter(..) = event {
+            open.push(id);
+        }
+    })?;
+    Ok(arena)
+}
+
+/// Copies a parse result, for the value types produced by the built-in parsers and folds
+pub fn mpc_val_clone(val: &MpcVal) -> Option<MpcVal> {
+    macro_rules! try_clone {
+        ($($ty:ty),*) => {
+            $(if let Some(x) = val.downcast_ref::<$ty>() {
+                return Some(Box::new(x.clone()));
+            })*
+        };
+    }
+    try_clone!(String, (), char, bool, i32, i64, u32, u64, usize, f32, f64, MpcAst, MpcState, MpcSpan, MpcTrivia, Vec<String>, Vec<MpcSpan>);
+    None
+}
+
+// When the input at an error is a word close to an expected keyword, records it as a suggestion
+pub(crate) fn mpc_err_suggest(e: &mut MpcErr, input: &MpcInput) {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let pos = (e.state.pos as usize).min(input.string.len());
+    let start = input.string[..pos].rfind(|c: char| !is_word(c)).map_or(0, |i| i + input.string[i..].chars().next().unwrap().len_utf8());
+    let end = input.string[pos..].find(|c: char| !is_word(c)).map_or(input.string.len(), |i| pos + i);
+    let word = &input.string[start..end];
+    if word.is_empty() {
+        return;
+    }
+    let keywords = e.expected.iter()
+        .filter(|x| x.len() > 2 && x.starts_with('"') && x.ends_with('"'))
+        .map(|x| &x[1..x.len() - 1])
+        .filter(|x| x.chars().all(is_word));
+    let best = keywords
+        .map(|keyword| (mpc_edit_distance(word, keyword), keyword))
+        .filter(|(distance, keyword)| *distance > 0 && *distance <= 2.max(keyword.chars().count() / 3))
+        .min_by_key(|(distance, _)| *distance);
+    if let Some((_, keyword)) = best {
+        e.suggestion = Some((word.to_string(), keyword.to_string()));
+    }
+}
+
+// Levenshtein distance between two strings, in characters
+fn mpc_edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// Streaming
+
+/// What `MpcSession::feed` came to
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]  // Holds an MpcResult by value, like MpcResult itself
+pub enum MpcFeed {
+    Incomplete,  // The parser got to the end of the input so far, so more could change the result
+    Done(MpcResult),
+}
+
+/// Push-style parsing of input that arrives in pieces, as from a network connection. Each
+/// `feed` adds bytes and runs the parser again over the current message: when it had to look
+/// past the end of what has arrived, the result is `MpcFeed::Incomplete` instead of a failure
+/// at a premature end of input. Otherwise the result is final, and on success the input it
+/// consumed is dropped so the next message starts after it. Positions in results and errors
+/// count from the start of their message.
+///
+/// Results that didn't depend on the end of the input are kept between feeds, as
+/// `mpc_parse_memo` keeps them, so a feed only re-parses from where the last one ran out of
+/// input rather than the whole message. Successes are only kept for the value types of
+/// `mpc_val_clone`; parsers with other values run again on every feed. Each run of the parser
+/// over the message is held to `limits` afresh.
+pub struct MpcSession<'p> {
+    filename: String,
+    program: MpcProgram<'p>,  // The parser, compiled once for every run
+    input: MpcInput<'static>,  // Current message, with the results of the parses of it so far
+    pending: Vec<u8>,  // Start of a character split between chunks
+    consumed: usize,  // Length of the last message parsed
+}
+
+impl<'p> MpcSession<'p> {
+    pub fn new(filename: &str, parser: &'p MpcParser, limits: MpcLimits) -> Self {
+        let mut input = MpcInput::owned(filename, String::new());
+        input.memoize = true;
+        input.limits = limits;
+        MpcSession {
+            filename: filename.to_string(),
+            program: mpc_compile(parser),
+            input,
+            pending: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    /// Adds `chunk` to the input and parses as far as it allows. An empty chunk parses the next
+    /// message out of input that is already there.
+    ///
+    /// Bytes that aren't UTF-8 wait until the text before them has been parsed. If it holds a
+    /// finished message, that is the result, and the bytes are left for the next feed; if the
+    /// message runs on into them, it fails with an error of kind `MpcErrKind::Io` where they
+    /// are, and it and the bytes are dropped, so the next message starts after them.
+    pub fn feed(&mut self, chunk: &[u8]) -> MpcFeed {
+        self.pending.extend_from_slice(chunk);
+        let (valid, invalid) = match std::str::from_utf8(&self.pending) {
+            Ok(text) => (text.len(), None),
+            Err(e) => (e.valid_up_to(), e.error_len()),
+        };
+        self.input.string.to_mut().push_str(std::str::from_utf8(&self.pending[..valid]).unwrap());
+        self.pending.drain(..valid);
+        if let Some(result) = self.parse(true) {
+            return MpcFeed::Done(result);
+        }
+        match invalid {
+            Some(len) => {
+                let e = self.invalid_err("input is not valid UTF-8");
+                self.pending.drain(..len);
+                self.input.string.to_mut().clear();
+                self.input.memo.clear();
+                MpcFeed::Done(MpcResult::Err(e))
+            }
+            None => MpcFeed::Incomplete,
+        }
+    }
+
+    /// Ends the input, parsing what is left with nothing more to come
+    pub fn finish(&mut self) -> MpcResult {
+        if !self.pending.is_empty() {
+            return MpcResult::Err(self.invalid_err("input ends inside a UTF-8 character"));
+        }
+        self.parse(false).unwrap()
+    }
+
+    // Error for the bytes after the buffer, which aren't UTF-8, where they are in the message
+    fn invalid_err(&self, reason: &str) -> MpcErr {
+        let e = std::io::Error::new(std::io::ErrorKind::InvalidData, reason);
+        let mut state = MpcState::default();
+        mpc_state_advance(&mut state, &self.input.string);
+        MpcErr { state, ..MpcErr::io(&self.filename, &e) }
+    }
+
+    /// Input that has arrived but isn't part of a finished message yet
+    pub fn buffered(&self) -> &str {
+        &self.input.string
+    }
+
+    // The result of parsing the buffer, or None when it depends on input still to come
+    fn parse(&mut self, partial: bool) -> Option<MpcResult> {
+        let (result, starved) = self.run(partial);
+        if starved {
+            return None;
+        }
+        // Results at the end of input that isn't partial aren't the results further on
+        if !partial {
+            self.input.memo.clear();
+        }
+        if let MpcResult::Ok(_) = result {
+            let consumed = self.input.pos;
+            self.input.string.to_mut().drain(..consumed);
+            self.input.memo.clear();
+            self.consumed = consumed;
+        }
+        Some(result)
+    }
+
+    // Parses the message from its start again, with the memo table of the runs before,
+    // returning the result and whether it looked past the end of partial input. A fatal error
+    // stays fatal whatever comes next, so it never counts as having done so.
+    fn run(&mut self, partial: bool) -> (MpcResult, bool) {
+        let input = &mut self.input;
+        input.pos = 0;
+        input.state = MpcState::default();
+        input.last = '\0';
+        input.furthest = None;
+        input.errors.clear();
+        input.abort = None;
+        input.partial = partial;
+        input.starved = false;
+        input.steps = 0;
+        let result = self.program.run(input);
+        let starved = input.starved && input.abort.is_none();
+        (mpc_parse_finish(input, result), starved)
+    }
+}
+
+/// Iterator over records read from a reader, see `mpc_iterate`
+pub struct MpcIter<'p, R> {
+    session: MpcSession<'p>,
+    reader: R,
+    eof: bool,
+    done: bool,  // Set after an error or a record that consumed nothing
+}
+
+/// Parses `reader` as a run of records matched by `parser`, one per call to `next`, for files
+/// too big to hold in memory: input is read as it is needed and dropped once its record is
+/// parsed. Iteration ends with the input, after an error, or at a record matching nothing.
+/// Parsing each record is held to `limits`, as with `mpc_parse_limited`.
+pub fn mpc_iterate<'p, R: std::io::Read>(
+    filename: &str,
+    reader: R,
+    parser: &'p MpcParser,
+    limits: MpcLimits,
+) -> MpcIter<'p, R> {
+    MpcIter {
+        session: MpcSession::new(filename, parser, limits),
+        reader,
+        eof: false,
+        done: false,
+    }
+}
+
+impl<'p, R: std::io::Read> MpcIter<'p, R> {
+    fn record(&mut self, result: MpcResult) -> Option<Result<MpcVal, MpcErr>> {
+        match result {
+            MpcResult::Ok(_) if self.session.consumed == 0 => {
+                self.done = true;
+                None
+            }
+            MpcResult::Ok(val) => Some(Ok(val)),
+            MpcResult::Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<'p, R: std::io::Read> Iterator for MpcIter<'p, R> {
+    type Item = Result<MpcVal, MpcErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut chunk = [0u8; 8192];
+        // Input left over from the last record may hold the next one already
+        let mut feed = if self.eof { MpcFeed::Incomplete } else { self.session.feed(&[]) };
+        loop {
+            if self.eof {
+                if self.session.buffered().is_empty() && self.session.pending.is_empty() {
+                    return None;
+                }
+                let result = self.session.finish();
+                return self.record(result);
+            }
+            if let MpcFeed::Done(result) = feed {
+                return self.record(result);
+            }
+            match self.reader.read(&mut chunk) {
+                Ok(0) => self.eof = true,
+                Ok(n) => feed = self.session.feed(&chunk[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => feed = MpcFeed::Incomplete,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(MpcErr::io(&self.session.filename, &e)));
+                }
+            }
+        }
+    }
+}
+
+/// Splits input fed in chunks into records ended by `delimiter`, as in NDJSON, and parses each
+/// with `parser`, which has to match the whole record. A record is parsed once its delimiter
+/// has arrived, so chunks can end partway through one, and `finish` parses a last record with
+/// no delimiter after it. Empty records are skipped, a `\r` before a `\n` delimiter is dropped,
+/// and an error only fails its own record, bytes that aren't UTF-8 included: the record they
+/// are in fails, with where they are, and the records after it parse as usual. Errors give
+/// their position in the whole stream. `parser` is borrowed, and compiled once for all the
+/// records.
+pub struct MpcRecords<'p> {
+    filename: String,
+    program: MpcProgram<'p>,  // The parser and the end of the record, compiled once for every record
+    delimiter: char,
+    buffer: String,
+    pending: Vec<u8>,  // Start of a character split between chunks
+    state: MpcState,  // Where the buffer starts in the stream
+    invalid: Option<MpcErr>,  // Error of the record being skipped for bytes that aren't UTF-8, until its delimiter
+}
+
+impl<'p> MpcRecords<'p> {
+    pub fn new(filename: &str, parser: &'p MpcParser, delimiter: char) -> Self {
+        MpcRecords {
+            filename: filename.to_string(),
+            program: mpc_compile_whole(parser),
+            delimiter,
+            buffer: String::new(),
+            pending: Vec::new(),
+            state: MpcState::default(),
+            invalid: None,
+        }
+    }
+
+    /// Adds `chunk` to the input, parsing the records it completes
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<Result<MpcVal, MpcErr>> {
+        self.pending.extend_from_slice(chunk);
+        let mut records = Vec::new();
+        loop {
+            if let Some(e) = self.invalid.take() {
+                if !self.skip() {
+                    self.invalid = Some(e);
+                    break;
+                }
+                records.push(Err(e));
+            }
+            let (valid, invalid) = match std::str::from_utf8(&self.pending) {
+                Ok(text) => (text.len(), None),
+                Err(e) => (e.valid_up_to(), e.error_len()),
+            };
+            self.buffer.push_str(std::str::from_utf8(&self.pending[..valid]).unwrap());
+            self.pending.drain(..valid);
+            records.extend(self.records());
+            let Some(len) = invalid else {
+                break;
+            };
+            // What is left of the buffer starts the record the bytes are in
+            let record = std::mem::take(&mut self.buffer);
+            self.advance(&record);
+            self.invalid = Some(self.invalid_err("input is not valid UTF-8"));
+            let bytes: Vec<u8> = self.pending.drain(..len).collect();
+            self.advance_bytes(&bytes);
+        }
+        records
+    }
+
+    /// Ends the input, parsing a last record that has no delimiter after it
+    pub fn finish(&mut self) -> Option<Result<MpcVal, MpcErr>> {
+        if let Some(e) = self.invalid.take() {
+            self.pending.clear();
+            return Some(Err(e));
+        }
+        let record = std::mem::take(&mut self.buffer);
+        if !self.pending.is_empty() {
+            self.pending.clear();
+            self.advance(&record);
+            return Some(Err(self.invalid_err("input ends inside a UTF-8 character")));
+        }
+        self.parse(&record, "")
+    }
+
+    // Parses the records the buffer holds the whole of, leaving the start of the next one
+    fn records(&mut self) -> Vec<Result<MpcVal, MpcErr>> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let mut records = Vec::new();
+        let mut start = 0;
+        while let Some(end) = buffer[start..].find(self.delimiter) {
+            let end = start + end;
+            let next = end + self.delimiter.len_utf8();
+            records.extend(self.parse(&buffer[start..end], &buffer[end..next]));
+            start = next;
+        }
+        self.buffer = buffer[start..].to_string();
+        records
+    }
+
+    // Parses one record, moving the stream position past it and its delimiter
+    fn parse(&mut self, record: &str, delimiter: &str) -> Option<Result<MpcVal, MpcErr>> {
+        let start = self.state;
+        self.advance(record);
+        self.advance(delimiter);
+        let record = if delimiter == "\n" { record.strip_suffix('\r').unwrap_or(record) } else { record };
+        if record.is_empty() {
+            return None;
+        }
+        Some(mpc_parse_vm(&self.filename, record, &self.program).into_result().map_err(|e| mpc_err_at(e, start)))
+    }
+
+    // Drops input up to and including the next delimiter, returning whether it has arrived
+    fn skip(&mut self) -> bool {
+        let mut bytes = [0; 4];
+        let delimiter = self.delimiter.encode_utf8(&mut bytes).as_bytes();
+        let (skipped, found) = match self.pending.windows(delimiter.len()).position(|w| w == delimiter) {
+            Some(at) => (at + delimiter.len(), true),
+            // Keeping what could be the start of a delimiter split between chunks
+            None => (self.pending.len().saturating_sub(delimiter.len() - 1), false),
+        };
+        let skipped: Vec<u8> = self.pending.drain(..skipped).collect();
+        self.advance_bytes(&skipped);
+        found
+    }
+
+    fn advance(&mut self, text: &str) {
+        mpc_state_advance(&mut self.state, text);
+    }
+
+    // Moves past bytes that may not be UTF-8, each bad sequence counting as one character
+    fn advance_bytes(&mut self, bytes: &[u8]) {
+        let pos = self.state.pos + bytes.len() as i64;
+        self.advance(&String::from_utf8_lossy(bytes));
+        self.state.pos = pos;
+    }
+
+    fn invalid_err(&self, reason: &str) -> MpcErr {
+        let e = std::io::Error::new(std::io::ErrorKind::InvalidData, reason);
+        MpcErr { state: self.state, ..MpcErr::io(&self.filename, &e) }
+    }
+}
+
+// Moves `state` past `text`
+fn mpc_state_advance(state: &mut MpcState, text: &str) {
+    for c in text.chars() {
+        state.pos += c.len_utf8() as i64;
+        if c == '\n' {
+            state.row += 1;
+            state.col = 0;
+        } else {
+            state.col += 1;
+        }
+    }
+}
+
+/// Parses from an async reader without blocking the thread, feeding each chunk to an
+/// `MpcSession` until the result is settled. Like `mpc_parse_reader` it may read past the end
+/// of what the parser consumes.
+#[cfg(feature = "async")]
+pub async fn mpc_parse_async(
+    filename: &str,
+    mut reader: impl tokio::io::AsyncRead + Unpin,
+    parser: &MpcParser,
+) -> MpcResult {
+    use tokio::io::AsyncReadExt;
+    let mut session = MpcSession::new(filename, parser, MpcLimits::default());
+    let mut chunk = [0u8; 8192];
+    loop {
+        match reader.read(&mut chunk).await {
+            Ok(0) => return session.finish(),
+            Ok(n) => {
+                if let MpcFeed::Done(result) = session.feed(&chunk[..n]) {
+                    return result;
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return MpcResult::Err(MpcErr::io(filename, &e)),
+        }
+    }
+}
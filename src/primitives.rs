@@ -0,0 +1,248 @@
+//! Parsers that match single characters and strings, or no input at all
+
+use crate::items::*;
+
+// Basic Parsers
+
+pub fn mpc_any() -> MpcParser {
+    MpcParser {
+        name: "any".to_string(),
+        parser_type: MpcParserType::Any,
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_char(c: char) -> MpcParser {
+    MpcParser {
+        name: format!("char:{}", c),
+        parser_type: MpcParserType::Char(c),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_range(s: char, e: char) -> MpcParser {
+    MpcParser {
+        name: format!("range:{}-{}", s, e),
+        parser_type: MpcParserType::Range(s, e),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_oneof(s: &str) -> MpcParser {
+    MpcParser {
+        name: format!("oneof:{}", s),
+        parser_type: MpcParserType::OneOf(s.to_string(), s.chars().collect()),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_noneof(s: &str) -> MpcParser {
+    MpcParser {
+        name: format!("noneof:{}", s),
+        parser_type: MpcParserType::NoneOf(s.to_string(), s.chars().collect()),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Matches a single character for which `f` returns true. Consumes nothing on failure:
+///
+/// ```
+/// use mpc::prelude::*;
+///
+/// let parser = mpc_or(vec![mpc_satisfy(|c| c.is_ascii_digit()), mpc_char('x')]);
+/// match mpc_parse("test", "x", &parser) {
+///     MpcResult::Ok(val) => assert_eq!(val.downcast_ref::<String>().unwrap(), "x"),
+///     MpcResult::Err(e) => panic!("{}", e.failure),
+/// }
+/// ```
+pub fn mpc_satisfy(f: fn(char) -> bool) -> MpcParser {
+    MpcParser {
+        name: "satisfy".to_string(),
+        parser_type: MpcParserType::Satisfy(f),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_string(s: &str) -> MpcParser {
+    MpcParser {
+        name: format!("string:{}", s),
+        parser_type: MpcParserType::String(s.to_string()),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+// Other Parsers
+
+pub fn mpc_pass() -> MpcParser {
+    MpcParser {
+        name: "pass".to_string(),
+        parser_type: MpcParserType::Pass,
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_fail(m: &str) -> MpcParser {
+    MpcParser {
+        name: format!("fail:{}", m),
+        parser_type: MpcParserType::Fail(m.to_string()),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_lift(f: fn() -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "lift".to_string(),
+        parser_type: MpcParserType::Lift(f),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_lift_val(f: fn() -> MpcVal) -> MpcParser {
+    MpcParser {
+        name: "lift_val".to_string(),
+        parser_type: MpcParserType::LiftVal(f),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_anchor(f: fn(char, char) -> bool) -> MpcParser {
+    MpcParser {
+        name: "anchor".to_string(),
+        parser_type: MpcParserType::Anchor(f),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_state() -> MpcParser {
+    MpcParser {
+        name: "state".to_string(),
+        parser_type: MpcParserType::State,
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Consumes and returns everything left in the input.
+pub fn mpc_rest() -> MpcParser {
+    MpcParser {
+        name: "rest".to_string(),
+        parser_type: MpcParserType::Rest,
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Consumes and returns everything up to, but not including, the next line break.
+pub fn mpc_rest_of_line() -> MpcParser {
+    MpcParser {
+        name: "rest_of_line".to_string(),
+        parser_type: MpcParserType::RestOfLine,
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Refers to rule `name` of the grammar being parsed, which may be the rule being defined
+pub fn mpc_rule(name: &str) -> MpcParser {
+    MpcParser {
+        name: name.to_string(),
+        parser_type: MpcParserType::Rule(name.to_string()),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+/// Parser made by calling `build` the first time it runs, and kept from then on. A function
+/// building a parser can refer to itself this way, which is enough for simple recursive rules
+/// without an `MpcGrammar`. The lazy parsers of `build` that building it makes, directly or
+/// through other lazy parsers, stand for this one, so however deeply the input nests, a
+/// recursive rule is only built once for each `mpc_lazy` value.
+pub fn mpc_lazy(build: fn() -> MpcParser) -> MpcParser {
+    MpcParser {
+        name: "lazy".to_string(),
+        parser_type: MpcParserType::Lazy(build, MpcLazyCell::new(build)),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+type MpcLazyBody = std::sync::OnceLock<std::sync::Arc<MpcParser>>;
+
+/// Where an `mpc_lazy` parser keeps the parser it builds. A lazy parser made while building
+/// one of the same function holds a weak handle on that one's cell instead of a cell of its
+/// own, so recursion shares one parser without the parser owning itself.
+#[derive(Clone)]
+pub struct MpcLazyCell(MpcLazyLink);
+
+#[derive(Clone)]
+enum MpcLazyLink {
+    // Lazy parsers being built around this one when it was made, which its own build can refer to
+    Own(std::sync::Arc<MpcLazyBody>, Vec<(usize, std::sync::Weak<MpcLazyBody>)>),
+    // With a parser of its own in case the cell it stands for is dropped first
+    Shared(std::sync::Weak<MpcLazyBody>, std::sync::OnceLock<std::sync::Arc<MpcParser>>),
+}
+
+thread_local! {
+    // Lazy parsers whose build functions are running on this thread, innermost last
+    static MPC_LAZY_BUILDING: std::cell::RefCell<Vec<(usize, std::sync::Weak<MpcLazyBody>)>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+// Puts back the lazy parsers being built from before a build, even if it panics
+struct MpcLazyScope(Vec<(usize, std::sync::Weak<MpcLazyBody>)>);
+
+impl Drop for MpcLazyScope {
+    fn drop(&mut self) {
+        let outer = std::mem::take(&mut self.0);
+        MPC_LAZY_BUILDING.with(|building| *building.borrow_mut() = outer);
+    }
+}
+
+impl MpcLazyCell {
+    fn new(build: fn() -> MpcParser) -> Self {
+        let building = MPC_LAZY_BUILDING.with(|building| building.borrow().clone());
+        match building.iter().rev().find(|(key, _)| *key == build as usize) {
+            Some((_, cell)) => MpcLazyCell(MpcLazyLink::Shared(cell.clone(), std::sync::OnceLock::new())),
+            None => MpcLazyCell(MpcLazyLink::Own(std::sync::Arc::default(), building)),
+        }
+    }
+
+    // Address of the cell, the same for a lazy parser and those standing for it
+    pub(crate) fn key(&self) -> usize {
+        match self.0 {
+            MpcLazyLink::Own(ref cell, _) => std::sync::Arc::as_ptr(cell) as usize,
+            MpcLazyLink::Shared(ref cell, _) => cell.as_ptr() as usize,
+        }
+    }
+
+    // The parser built, if this lazy parser is the one keeping it, building it now if need be
+    pub(crate) fn own(&self, build: fn() -> MpcParser) -> Option<&std::sync::Arc<MpcParser>> {
+        let MpcLazyLink::Own(ref cell, ref outer) = self.0 else {
+            return None;
+        };
+        Some(cell.get_or_init(|| {
+            let mut building = outer.clone();
+            building.push((build as usize, std::sync::Arc::downgrade(cell)));
+            let _scope = MpcLazyScope(MPC_LAZY_BUILDING.with(|stack| stack.replace(building)));
+            std::sync::Arc::new(build())
+        }))
+    }
+
+    // The parser this lazy parser stands for
+    pub(crate) fn body(&self, build: fn() -> MpcParser) -> std::sync::Arc<MpcParser> {
+        if let Some(body) = self.own(build) {
+            return body.clone();
+        }
+        let MpcLazyLink::Shared(ref cell, ref own) = self.0 else {
+            unreachable!("lazy parser with neither a cell nor a handle on one");
+        };
+        match cell.upgrade() {
+            Some(cell) => cell.get().expect("lazy parser made by a build that never finished").clone(),
+            None => own.get_or_init(|| std::sync::Arc::new(mpc_lazy(build))).clone(),
+        }
+    }
+}
+
+/// Shows whether the parser has been built, rather than the parser, which may contain itself
+impl std::fmt::Debug for MpcLazyCell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            MpcLazyLink::Own(ref cell, _) => f.debug_struct("MpcLazyCell").field("built", &cell.get().is_some()).finish(),
+            MpcLazyLink::Shared(..) => f.debug_struct("MpcLazyCell").field("shared", &true).finish(),
+        }
+    }
+}
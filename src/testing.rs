@@ -0,0 +1,103 @@
+//! Assertions for testing parsers
+
+use crate::items::*;
+
+// Testing
+
+/// Asserts that `parser` parses `input` to a value equal to `expected`, like `mpc_test_pass` in
+/// C. On failure it panics with the parse error, or with the value got next to the one expected.
+///
+/// ```
+/// use mpc::prelude::*;
+/// use mpc::testing::*;
+///
+/// mpc_test_pass(&mpc_digits(), "123", &"123".to_string());
+/// mpc_test_fail(&mpc_digits(), "abc", &"abc".to_string());
+/// ```
+#[track_caller]
+pub fn mpc_test_pass<T: PartialEq + std::fmt::Debug + 'static>(parser: &MpcParser, input: &str, expected: &T) {
+    mpc_test_pass_with(parser, input, expected, |got, expected| got == expected)
+}
+
+/// Like `mpc_test_pass`, comparing the values with `tester` instead of `==`
+#[track_caller]
+pub fn mpc_test_pass_with<T: std::fmt::Debug + 'static>(parser: &MpcParser, input: &str, expected: &T, tester: impl Fn(&T, &T) -> bool) {
+    match mpc_parse("<test>", input, parser) {
+        MpcResult::Ok(val) => match val.downcast_ref::<T>() {
+            Some(got) if tester(got, expected) => {}
+            Some(got) => panic!("parse of {:?} gave the wrong value\n     got: {:?}\nexpected: {:?}", input, got, expected),
+            None => panic!("parse of {:?} gave a value that is not of type {}", input, std::any::type_name::<T>()),
+        },
+        MpcResult::Err(e) => panic!("parse of {:?} failed\n{}", input, e.render()),
+    }
+}
+
+/// Asserts that `parser` fails on `input`, or at least does not produce `expected`, like
+/// `mpc_test_fail` in C
+#[track_caller]
+pub fn mpc_test_fail<T: PartialEq + std::fmt::Debug + 'static>(parser: &MpcParser, input: &str, expected: &T) {
+    mpc_test_fail_with(parser, input, expected, |got, expected| got == expected)
+}
+
+/// Like `mpc_test_fail`, comparing the values with `tester` instead of `==`
+#[track_caller]
+pub fn mpc_test_fail_with<T: std::fmt::Debug + 'static>(parser: &MpcParser, input: &str, expected: &T, tester: impl Fn(&T, &T) -> bool) {
+    if let MpcResult::Ok(val) = mpc_parse("<test>", input, parser) {
+        if let Some(got) = val.downcast_ref::<T>() {
+            if tester(got, expected) {
+                panic!("parse of {:?} should have failed, but gave {:?}", input, got);
+            }
+        }
+    }
+}
+
+/// Asserts that `ast.to_snapshot()` matches the golden file at `path`, panicking with a line
+/// diff when it doesn't. A missing file is written instead, as is every file when the
+/// `MPC_UPDATE_SNAPSHOTS` environment variable is set, so accepting a change is one rerun.
+#[track_caller]
+pub fn mpc_test_snapshot(ast: &MpcAst, path: impl AsRef<std::path::Path>) {
+    let path = path.as_ref();
+    let got = ast.to_snapshot();
+    let expected = match std::fs::read_to_string(path) {
+        Ok(expected) if std::env::var_os("MPC_UPDATE_SNAPSHOTS").is_none() => expected,
+        Ok(_) | Err(_) => {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).unwrap_or_else(|e| panic!("can't create {}: {}", dir.display(), e));
+            }
+            std::fs::write(path, &got).unwrap_or_else(|e| panic!("can't write {}: {}", path.display(), e));
+            return;
+        }
+    };
+    if got != expected {
+        panic!("snapshot {} differs (-expected +got), rerun with MPC_UPDATE_SNAPSHOTS=1 to accept\n{}", path.display(), mpc_line_diff(&expected, &got));
+    }
+}
+
+// Lines of `a` and `b` aligned along their longest common subsequence, prefixed with `-` when
+// only in `a`, `+` when only in `b` and a space when in both
+fn mpc_line_diff(a: &str, b: &str) -> String {
+    let (a, b): (Vec<&str>, Vec<&str>) = (a.lines().collect(), b.lines().collect());
+    // common[i][j]: length of the longest common subsequence of a[i..] and b[j..]
+    let mut common = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i] == b[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            diff.push_str(&format!(" {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || common[i + 1][j] >= common[i][j + 1]) {
+            diff.push_str(&format!("-{}\n", a[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{}\n", b[j]));
+            j += 1;
+        }
+    }
+    diff
+}
@@ -0,0 +1,320 @@
+//! Ready-made parsers for common tokens, and parsing straight into Rust types
+
+use crate::items::*;
+
+// Utility Parsers
+
+pub fn mpc_eoi() -> MpcParser {
+    MpcParser {
+        name: "eoi".to_string(),
+        parser_type: MpcParserType::Anchor(|_prev, next| next == '\0'),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_soi() -> MpcParser {
+    MpcParser {
+        name: "soi".to_string(),
+        parser_type: MpcParserType::Anchor(|prev, _next| prev == '\0'),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_boundary() -> MpcParser {
+    MpcParser {
+        name: "boundary".to_string(),
+        parser_type: MpcParserType::Anchor(|prev, next| {
+            let is_word = |c: char| c.is_alphanumeric() || c == '_';
+            is_word(prev) != is_word(next)
+        }),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_boundary_newline() -> MpcParser {
+    MpcParser {
+        name: "boundary_newline".to_string(),
+        parser_type: MpcParserType::Anchor(|prev, _next| prev == '\n'),
+        compiled: MpcCompiledCell::default(),
+    }
+}
+
+pub fn mpc_whitespace() -> MpcParser {
+    mpc_oneof(" \t\n\r")
+}
+
+pub fn mpc_whitespaces() -> MpcParser {
+    mpc_many(mpc_whitespace(), mpcf_strfold)
+}
+
+/// `parser` followed by any whitespace, which is skipped, like `mpc_tok` in C
+pub fn mpc_tok(parser: MpcParser) -> MpcParser {
+    mpc_and(vec![parser, mpc_whitespaces()], mpcf_fst)
+}
+
+/// The string `s` followed by any whitespace, like `mpc_sym` in C
+pub fn mpc_sym(s: &str) -> MpcParser {
+    mpc_tok(mpc_string(s))
+}
+
+/// `parser`, matching only at the start of the input, like `mpc_startwith` in C
+pub fn mpc_startwith(parser: MpcParser) -> MpcParser {
+    mpc_and(vec![mpc_soi(), parser], mpcf_snd)
+}
+
+/// `parser`, matching only if it reaches the end of the input, like `mpc_endwith` in C
+pub fn mpc_endwith(parser: MpcParser) -> MpcParser {
+    mpc_and(vec![parser, mpc_eoi()], mpcf_fst)
+}
+
+pub fn mpc_blank() -> MpcParser {
+    mpc_oneof(" \t")
+}
+
+pub fn mpc_newline() -> MpcParser {
+    mpc_char('\n')
+}
+
+pub fn mpc_tab() -> MpcParser {
+    mpc_char('\t')
+}
+
+pub fn mpc_escape() -> MpcParser {
+    mpc_char('\\')
+}
+
+pub fn mpc_digit() -> MpcParser {
+    mpc_range('0', '9')
+}
+
+pub fn mpc_hexdigit() -> MpcParser {
+    mpc_or(vec![mpc_range('0', '9'), mpc_range('a', 'f'), mpc_range('A', 'F')])
+}
+
+pub fn mpc_octdigit() -> MpcParser {
+    mpc_range('0', '7')
+}
+
+pub fn mpc_digits() -> MpcParser {
+    mpc_many1(mpc_digit(), mpcf_strfold)
+}
+
+pub fn mpc_hexdigits() -> MpcParser {
+    mpc_many1(mpc_hexdigit(), mpcf_strfold)
+}
+
+pub fn mpc_octdigits() -> MpcParser {
+    mpc_many1(mpc_octdigit(), mpcf_strfold)
+}
+
+pub fn mpc_lower() -> MpcParser {
+    mpc_range('a', 'z')
+}
+
+pub fn mpc_upper() -> MpcParser {
+    mpc_range('A', 'Z')
+}
+
+pub fn mpc_alpha() -> MpcParser {
+    mpc_or(vec![mpc_lower(), mpc_upper()])
+}
+
+pub fn mpc_underscore() -> MpcParser {
+    mpc_char('_')
+}
+
+pub fn mpc_alphanum() -> MpcParser {
+    mpc_or(vec![mpc_alpha(), mpc_digit()])
+}
+
+// TODO: Implement int, hex, oct, number, real, float, char_lit, string_lit, regex_lit, ident
+
+// Parsing into Rust types
+
+#[cfg(feature = "derive")]
+pub use mpc_derive::MpcParse;
+
+/// Types that know how to parse themselves. `#[derive(MpcParse)]`, with the `derive` feature,
+/// writes the parser for record and enum shaped types from their fields; the numbers, `bool`,
+/// quoted `String`s, `Option` and `Vec` it builds on are implemented here.
+pub trait MpcParse: Sized + 'static {
+    /// A parser whose value is a `Result<Self, MpcErr>`, skipping whitespace after each token.
+    /// The error is for input that matched but makes no value, such as a number out of range.
+    fn mpc_parser() -> MpcParser;
+
+    /// Parses all of `string` as a `Self`, with whitespace allowed around it
+    #[allow(clippy::result_large_err)]
+    fn mpc_parse_str(filename: &str, string: &str) -> Result<Self, MpcErr> {
+        let val = match mpc_parse(filename, string, mpc_parse_str_parser::<Self>()) {
+            MpcResult::Ok(val) => val,
+            MpcResult::Err(e) => return Err(e),
+        };
+        match *val.downcast::<Result<Self, MpcErr>>().expect("MpcParse::mpc_parser gives Result<Self, MpcErr>") {
+            Ok(value) => Ok(value),
+            Err(mut e) => {
+                e.filename = filename.to_string();
+                e.source_line = string.lines().nth(e.state.row as usize).unwrap_or("").to_string();
+                Err(e)
+            }
+        }
+    }
+}
+
+// The parser `mpc_parse_str` runs for `T`, built and compiled on its first use and kept for
+// the rest of the program, one for each type parsed this way
+fn mpc_parse_str_parser<T: MpcParse>() -> &'static MpcParser {
+    type Parsers = std::collections::HashMap<std::any::TypeId, &'static MpcParser>;
+    static PARSERS: std::sync::OnceLock<std::sync::Mutex<Parsers>> = std::sync::OnceLock::new();
+    let parsers = PARSERS.get_or_init(Default::default);
+    if let Some(parser) = parsers.lock().unwrap().get(&std::any::TypeId::of::<T>()) {
+        return parser;
+    }
+    // Built without the lock held, as `mpc_parser` may parse other types itself
+    let parser = mpc_and(vec![mpc_whitespaces(), T::mpc_parser(), mpc_eoi()], mpcf_snd);
+    parsers.lock().unwrap().entry(std::any::TypeId::of::<T>()).or_insert_with(|| Box::leak(Box::new(parser)))
+}
+
+// The text a token matched, from `mpc_consumed`. Unlike a lexeme this is a `String` in
+// zero-copy mode too, where folds have no input to resolve an `MpcSpan` against.
+fn mpc_token_text(val: MpcVal) -> String {
+    val.downcast::<(String, MpcVal)>().map(|consumed| consumed.0).unwrap_or_default()
+}
+
+// Fold for a `(span, consumed)` pair: the text parsed as a T, or an error at the span
+fn mpcf_from_str<T: std::str::FromStr + 'static>(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let (span, consumed) = *xs.into_iter().next().unwrap().downcast::<(MpcSpan, MpcVal)>().unwrap();
+    let text = mpc_token_text(consumed);
+    let value: Result<T, MpcErr> = text.parse().map_err(|_| {
+        let failure = format!("{} is out of range for {}", text, std::any::type_name::<T>());
+        MpcErr::new(span.start, Vec::new(), failure, text.chars().next().unwrap_or('\0'))
+    });
+    Box::new(value)
+}
+
+// The text `parser` matches, as a token parsed into a T
+fn mpc_parse_from_str<T: std::str::FromStr + 'static>(name: &str, parser: MpcParser) -> MpcParser {
+    mpc_tok(mpc_and(vec![mpc_spanned(mpc_consumed(mpc_expect(parser, name)))], mpcf_from_str::<T>))
+}
+
+macro_rules! mpc_parse_int {
+    ($($t:ty),*) => {$(
+        /// A decimal integer token, with a leading '-' for signed types
+        impl MpcParse for $t {
+            fn mpc_parser() -> MpcParser {
+                #[allow(unused_comparisons)]
+                let sign = if <$t>::MIN < 0 { mpc_repeat(0, Some(1), mpc_char('-'), mpcf_strfold) } else { mpc_pass() };
+                mpc_parse_from_str::<$t>("integer", mpc_and(vec![sign, mpc_digits()], mpcf_strfold))
+            }
+        }
+    )*};
+}
+
+mpc_parse_int!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+macro_rules! mpc_parse_float {
+    ($($t:ty),*) => {$(
+        /// A decimal number token with optional sign, fraction and exponent, such as `-1.5e3`
+        impl MpcParse for $t {
+            fn mpc_parser() -> MpcParser {
+                let optional = |parser| mpc_repeat(0, Some(1), parser, mpcf_strfold);
+                let number = mpc_and(vec![
+                    optional(mpc_char('-')),
+                    mpc_digits(),
+                    optional(mpc_and(vec![mpc_char('.'), mpc_digits()], mpcf_strfold)),
+                    optional(mpc_and(vec![mpc_oneof("eE"), optional(mpc_oneof("+-")), mpc_digits()], mpcf_strfold)),
+                ], mpcf_strfold);
+                mpc_parse_from_str::<$t>("number", number)
+            }
+        }
+    )*};
+}
+
+mpc_parse_float!(f32, f64);
+
+/// The keywords `true` and `false`
+impl MpcParse for bool {
+    fn mpc_parser() -> MpcParser {
+        let keyword = |s| mpc_and(vec![mpc_string(s), mpc_boundary()], mpcf_fst);
+        mpc_parse_from_str::<bool>("boolean", mpc_or(vec![keyword("true"), keyword("false")]))
+    }
+}
+
+// Fold for a quoted string from `mpc_consumed`: its text without the quotes, with escapes
+// resolved
+fn mpcf_unescape(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let quoted = mpc_token_text(xs.into_iter().next().unwrap());
+    let body = quoted.strip_prefix('"').and_then(|s| s.strip_suffix('"')).unwrap_or(&quoted);
+    let mut out = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c) => c,
+            None => break,
+        });
+    }
+    Box::new(Ok::<String, MpcErr>(out))
+}
+
+/// A double-quoted string token with backslash escapes, such as `"a \"b\"\n"`
+impl MpcParse for String {
+    fn mpc_parser() -> MpcParser {
+        let body = mpc_many(mpc_or(vec![mpc_noneof("\"\\"), mpc_and(vec![mpc_escape(), mpc_any()], mpcf_strfold)]), mpcf_strfold);
+        let quoted = mpc_consumed(mpc_and(vec![mpc_char('"'), body, mpc_char('"')], mpcf_null));
+        mpc_tok(mpc_and(vec![mpc_expect(quoted, "string")], mpcf_unescape))
+    }
+}
+
+impl<T: MpcParse> MpcParse for Box<T> {
+    fn mpc_parser() -> MpcParser {
+        fn fold<T: 'static>(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+            let value = *xs.into_iter().next().unwrap().downcast::<Result<T, MpcErr>>().unwrap();
+            Box::new(value.map(Box::new))
+        }
+        mpc_and(vec![T::mpc_parser()], fold::<T>)
+    }
+}
+
+/// A `T` where one can be parsed, or else None
+impl<T: MpcParse> MpcParse for Option<T> {
+    fn mpc_parser() -> MpcParser {
+        fn some<T: 'static>(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+            let value = *xs.into_iter().next().unwrap().downcast::<Result<T, MpcErr>>().unwrap();
+            Box::new(value.map(Some))
+        }
+        fn none<T: 'static>() -> MpcVal {
+            Box::new(Ok::<Option<T>, MpcErr>(None))
+        }
+        mpc_or(vec![mpc_and(vec![T::mpc_parser()], some::<T>), mpc_lift(none::<T>)])
+    }
+}
+
+// Fold for repeated `Result<T, MpcErr>` values: all the values, or the first error
+#[allow(clippy::result_large_err)]
+fn mpcf_collect<T: 'static>(_n: i32, xs: Vec<MpcVal>) -> MpcVal {
+    let items: Result<Vec<T>, MpcErr> = xs
+        .into_iter()
+        .map(|x| *x.downcast::<Result<T, MpcErr>>().unwrap())
+        .collect();
+    Box::new(items)
+}
+
+/// Any number of `T`s, one after another
+impl<T: MpcParse> MpcParse for Vec<T> {
+    fn mpc_parser() -> MpcParser {
+        mpc_many(T::mpc_parser(), mpcf_collect::<T>)
+    }
+}
+
+/// Any number of `T`s separated by the token `sep`, as a `Result<Vec<T>, MpcErr>` like
+/// `MpcParse` parsers give. `#[mpc(sep = ",")]` on a `Vec` field uses it.
+pub fn mpc_parse_sepby<T: MpcParse>(sep: &str) -> MpcParser {
+    mpc_sepby(T::mpc_parser(), mpc_sym(sep), mpcf_collect::<T>)
+}
@@ -0,0 +1,1247 @@
+//! Bytecode VM: parsers compiled to a flat program and run without recursion
+
+use crate::items::*;
+
+// Bytecode VM
+
+/// Instruction for the parser VM. The code for a parser leaves either one value on the value
+/// stack or a failure in the error register, like a call to `MpcParser::parse` would.
+#[derive(Clone, Copy)]
+pub enum MpcOp<'p> {
+    Enter(&'p MpcParser, usize),  // Start a parser, ending the parse past a limit, or jumping past its Leave with the result it had here before when memoizing
+    Leave(&'p MpcParser),  // End the parser, caching its result and recording its coverage when tracking parsers
+    Call(&'p MpcParser),  // Run a parser that has no instructions of its own
+    Sub(usize),  // Run the subroutine at an address, coming back here at its Ret
+    Ret,  // Return from a subroutine, or end the program when there is none to return from
+    Grow(&'p str, usize, usize),  // Start growing a left-recursive grammar rule from a seed, or jump with its seed if it is growing here already
+    Grown(&'p str, usize),  // Keep a longer match of the rule as its seed and jump back to match it again, or end growing it
+    Settle,  // Hand settled events to the callback, between the items of a repetition
+    Jump(usize),
+    JumpIfOk(usize),
+    JumpIfErr(usize),
+    JumpIfCount(usize, usize),  // Jump when the top frame holds at least this many values
+    Begin,  // Open a frame at the current input position and value stack height
+    Mark,  // Move the top frame's mark to the current input position
+    Fail,  // Close the top frame, dropping its values
+    Pop,  // Close the top frame, keeping its values
+    Back,  // Close the top frame and rewind to its mark, dropping its values and the failure
+    Peek,  // Close the top frame and rewind to its mark, keeping its values and the failure
+    Drop,  // Drop the last value
+    Clear,  // Drop the failure
+    Push(fn() -> MpcVal),  // Push the result of a function
+    And(fn(i32, Vec<MpcVal>) -> MpcVal),  // Close the top frame, folding its values
+    Dispatch(&'p MpcParser, usize, usize),  // Jump past an alternative of an Or that its jump table rules out, saving the failure it would have had
+    OrNext,  // Save the failure in the top frame and rewind to its mark
+    OrOk,  // Close the top frame of an Or that matched
+    OrFail,  // Close the top frame, failing with its saved failures merged
+    Many(fn(i32, Vec<MpcVal>) -> MpcVal),  // Rewind to the top frame's mark, close it and fold its values
+    Fold(fn(MpcVal, MpcVal) -> MpcVal),  // Fold the last value into the one before it
+    Chain(fn(MpcVal, MpcVal, MpcVal) -> MpcVal, MpcAssoc),  // Close the top frame, folding its terms with the operators between them
+    Progress(&'p MpcParser, &'p MpcParser),  // End the parse if a repetition's body consumed nothing since the top frame's mark
+    Tag(&'p str),  // Close the top frame, wrapping the last value in an AST node
+    AddTag(&'p str),  // Like Tag, prefixing the node's tag instead of replacing it
+    Root,  // Close the top frame, making the last value the root of an AST
+    TagFail(&'p str),  // Close the top frame, adding the rule to the failure's context
+    Apply(fn(MpcVal) -> MpcVal),  // Pass the last value through a function
+    Spanned,  // Close the top frame, pairing the last value with the span it covered
+    Recognize,  // Close the top frame, replacing the last value with the text matched since its mark
+    Consumed,  // Close the top frame, pairing the last value with the text matched since its mark
+    Isolate,  // Set the furthest failure aside, so failures inside don't count towards it
+    Isolated,  // Put the furthest failure back, with the failure or the furthest one since Isolate
+    Expected(&'p str),  // Close the top frame and rewind to its mark, failing as the expected name
+    Not(&'p MpcParser),  // Close the top frame and rewind to its mark, failing if the parser matched
+    Advance(usize),  // Consume a character, jumping at the end of input
+    Recovered(fn() -> MpcVal),  // Close the top frame, recording its saved failure and pushing a placeholder
+    Until(usize),  // Skip to the next character in one of the program's sets
+    UntilEnd(&'p MpcParser),  // Fail with the terminator missing before the end of input
+    PermBegin(usize),  // Start the item values of a permutation, all missing
+    PermSkip(usize, usize),  // Jump when the item has matched already
+    PermItem(usize, bool, usize),  // Close the top frame, keeping the item's value and jumping to the next round, or saving its failure
+    PermEnd(fn(i32, Vec<MpcVal>) -> MpcVal),  // Close the top frame, folding the items or failing with the missing ones
+    Operand,  // Close the top frame, making the last value an operand of an expression
+    Prefix(&'p MpcParser),  // Close the top frame, applying a prefix operator to the operand after it
+    Postfix(&'p MpcParser),  // Close the top frame, applying a postfix operator to the operand before it
+    Infix(&'p MpcParser, i32, MpcAssoc),  // Close the top frame, applying an infix operator to the operands around it
+    IfNonassoc(i32, usize),  // Jump unless the last infix operator of the expression was non-associative at this precedence
+    Chained(&'p MpcParser),  // Fail at a non-associative operator that follows one of the same precedence
+}
+
+// Left-recursive rule being grown from a seed, after Warth et al.: recursive calls at the same
+// position first fail, then return the previous match, until the match stops growing. The
+// seed is copied for each recursive call, so its value must be one mpc_val_clone knows.
+struct MpcGrowing {
+    key: (usize, usize),  // Rule and position
+    start: MpcMark,
+    end: Option<usize>,  // Where its longest match so far ends
+    memoize: bool,  // Whether the parse memoized before, which cached results would hide the seed from
+}
+
+// Frame on the VM's control stack
+struct MpcFrame {
+    mark: MpcMark,
+    vals: usize,  // Value stack height when the frame was opened
+    errs: Vec<MpcErr>,  // Failed alternatives of an Or
+    skipped: Vec<MpcErr>,  // Alternatives of an Or ruled out by its jump table
+    nonassoc: Option<i32>,  // Precedence of the expression's last infix operator, when it was non-associative
+}
+
+/// Parser lowered to flat bytecode by `mpc_compile`
+pub struct MpcProgram<'p> {
+    pub code: Vec<MpcOp<'p>>,
+    pub grammar: Option<&'p MpcGrammar>,  // Grammar the rules are looked up in, see mpc_compile_grammar
+    pub sets: Vec<MpcCharSet>,  // Characters that Until skips to
+}
+
+impl<'p> MpcProgram<'p> {
+    /// Runs the program with explicit stacks, so however deeply parsers nest, and recursion
+    /// through lazy parsers, grammar rules and expressions, costs heap instead of native stack.
+    /// Memoizing, events and coverage, which keep state for each parser run, are kept here too.
+    pub fn run(&self, input: &mut MpcInput) -> MpcResult {
+        let mut vals: Vec<MpcVal> = Vec::new();
+        let mut frames: Vec<MpcFrame> = Vec::new();
+        let mut returns: Vec<usize> = Vec::new();
+        let mut isolated: Vec<Option<MpcErr>> = Vec::new();  // Furthest failures set aside
+        let mut items: Vec<Vec<Option<MpcVal>>> = Vec::new();  // Item values of the permutations running
+        let mut entered: Vec<MpcEntered> = Vec::new();  // Parsers running, when tracking them
+        let mut growing: Vec<MpcGrowing> = Vec::new();
+        let mut err: Option<MpcErr> = None;
+        let tracked = input.tracked();
+        // Put back if a fatal error leaves parsers running
+        let (depth, holds) = (input.depth, input.holds);
+        let mut pc = 0;
+        while let Some(&op) = self.code.get(pc) {
+            // A fatal error ends the parse, with whatever is left on the stacks
+            if let Some(ref e) = input.abort {
+                err = Some(e.clone());
+                break;
+            }
+            pc += 1;
+            match op {
+                MpcOp::Enter(parser, skip) => {
+                    if let Err(e) = input.enter() {
+                        err = Some(e);
+                        break;
+                    }
+                    if tracked {
+                        match input.recall(parser) {
+                            Some(result) => {
+                                input.depth -= 1;
+                                match result {
+                                    MpcResult::Ok(val) => {
+                                        vals.push(val);
+                                        err = None;
+                                    }
+                                    MpcResult::Err(e) => err = Some(e),
+                                }
+                                pc = skip;
+                            }
+                            None => entered.push(input.track(parser)),
+                        }
+                    }
+                }
+                MpcOp::Leave(parser) => {
+                    input.depth -= 1;
+                    if tracked {
+                        let result = match err {
+                            Some(ref e) => Err(e),
+                            None => Ok(vals.last().unwrap()),
+                        };
+                        input.untrack(parser, entered.pop().unwrap(), result);
+                    }
+                }
+                MpcOp::Call(parser) => match parser.parse(input) {
+                    MpcResult::Ok(val) => {
+                        vals.push(val);
+                        err = None;
+                    }
+                    MpcResult::Err(e) => err = Some(e),
+                },
+                MpcOp::Sub(target) => {
+                    returns.push(pc);
+                    pc = target;
+                }
+                MpcOp::Ret => match returns.pop() {
+                    Some(back) => pc = back,
+                    None => break,
+                },
+                MpcOp::Grow(name, rule, done) => {
+                    let key = (rule, input.pos);
+                    match input.seeds.get(&key) {
+                        Some(Some(seed)) => {
+                            // Checked when the seed was stored
+                            vals.push(mpc_val_clone(&seed.val).unwrap());
+                            let errors = seed.errors.clone();
+                            input.pos = seed.pos;
+                            input.state = seed.state;
+                            input.last = seed.last;
+                            input.errors.extend(errors);
+                            err = None;
+                            pc = done;
+                        }
+                        Some(None) => {
+                            err = Some(MpcErr::new(input.state, vec![], format!("expected {}", name), input.peek().unwrap_or('\0')));
+                            pc = done;
+                        }
+                        None => {
+                            input.seeds.insert(key, None);
+                            let memoize = std::mem::replace(&mut input.memoize, false);
+                            growing.push(MpcGrowing { key, start: input.mark(), end: None, memoize });
+                        }
+                    }
+                }
+                MpcOp::Grown(name, again) => {
+                    let grow = growing.last_mut().unwrap();
+                    match err {
+                        // None orders before any position, so the first match always counts
+                        None if grow.end < Some(input.pos) => {
+                            let val = vals.pop().unwrap();
+                            if mpc_val_clone(&val).is_some() {
+                                grow.end = Some(input.pos);
+                                let seed = MpcSeed {
+                                    val,
+                                    pos: input.pos,
+                                    state: input.state,
+                                    last: input.last,
+                                    errors: input.errors[grow.start.errors..].to_vec(),
+                                };
+                                input.seeds.insert(grow.key, Some(seed));
+                                input.rewind(grow.start);
+                                pc = again;
+                                continue;
+                            }
+                            let failure = format!("left-recursive rule {} produced a value that can't be copied", name);
+                            err = Some(MpcErr::new(grow.start.state, vec![], failure, '\0'));
+                            input.seeds.insert(grow.key, None);
+                        }
+                        None => {
+                            vals.pop();
+                        }
+                        Some(_) => {}
+                    }
+                    let grow = growing.pop().unwrap();
+                    input.memoize = grow.memoize;
+                    input.rewind(grow.start);
+                    if let Some(seed) = input.seeds.remove(&grow.key).flatten() {
+                        input.pos = seed.pos;
+                        input.state = seed.state;
+                        input.last = seed.last;
+                        input.errors.extend(seed.errors);
+                        vals.push(seed.val);
+                        err = None;
+                    }
+                }
+                MpcOp::Settle => input.settle_events(),
+                MpcOp::Jump(target) => pc = target,
+                MpcOp::JumpIfOk(target) => {
+                    if err.is_none() {
+                        pc = target;
+                    }
+                }
+                MpcOp::JumpIfErr(target) => {
+                    if err.is_some() {
+                        pc = target;
+                    }
+                }
+                MpcOp::JumpIfCount(n, target) => {
+                    if vals.len() - frames.last().unwrap().vals >= n {
+                        pc = target;
+                    }
+                }
+                MpcOp::Begin => frames.push(MpcFrame { mark: input.mark(), vals: vals.len(), errs: Vec::new(), skipped: Vec::new(), nonassoc: None }),
+                MpcOp::Mark => frames.last_mut().unwrap().mark = input.mark(),
+                MpcOp::Fail => {
+                    let frame = frames.pop().unwrap();
+                    vals.truncate(frame.vals);
+                }
+                MpcOp::Pop => {
+                    frames.pop();
+                }
+                MpcOp::Back => {
+                    let frame = frames.pop().unwrap();
+                    input.rewind(frame.mark);
+                    vals.truncate(frame.vals);
+                    err = None;
+                }
+                MpcOp::Peek => {
+                    let frame = frames.pop().unwrap();
+                    input.rewind(frame.mark);
+                }
+                MpcOp::Drop => {
+                    vals.pop();
+                }
+                MpcOp::Clear => err = None,
+                MpcOp::Push(f) => vals.push(f()),
+                MpcOp::And(fold) => {
+                    let frame = frames.pop().unwrap();
+                    let results = mpc_vm_text(input, vals.split_off(frame.vals));
+                    vals.push(fold(results.len() as i32, results));
+                }
+                MpcOp::Dispatch(parser, i, target) => {
+                    let MpcParserType::Or(ref parsers, Some(ref dispatch)) = parser.parser_type else {
+                        unreachable!("Dispatch on a parser without a jump table");
+                    };
+                    if !dispatch.allows(i, input.peek()) {
+                        let e = parsers[i].first_err(input);
+                        frames.last_mut().unwrap().skipped.push(e);
+                        pc = target;
+                    }
+                }
+                MpcOp::OrNext => {
+                    let frame = frames.last_mut().unwrap();
+                    frame.errs.extend(err.take());
+                    input.rewind(frame.mark);
+                }
+                MpcOp::OrOk => {
+                    let frame = frames.pop().unwrap();
+                    // A match that consumes nothing leaves the error position here, so the
+                    // alternatives ruled out before it count
+                    if input.pos == frame.mark.pos {
+                        for e in &frame.skipped {
+                            input.record_failure(e);
+                        }
+                    }
+                }
+                MpcOp::OrFail => {
+                    let mut frame = frames.pop().unwrap();
+                    frame.errs.append(&mut frame.skipped);
+                    let e = MpcErr::or(frame.errs).unwrap_or_else(|| {
+                        MpcErr::new(input.state, vec!["or".to_string()], "no alternatives matched".to_string(), '\0')
+                    });
+                    input.record_failure(&e);
+                    err = Some(e);
+                }
+                MpcOp::Many(fold) => {
+                    let frame = frames.pop().unwrap();
+                    input.rewind(frame.mark);
+                    err = None;
+                    let results = mpc_vm_text(input, vals.split_off(frame.vals));
+                    vals.push(fold(results.len() as i32, results));
+                }
+                MpcOp::Fold(fold) => {
+                    let val = vals.pop().unwrap();
+                    let acc = vals.pop().unwrap();
+                    vals.push(fold(acc, val));
+                }
+                MpcOp::Chain(fold, assoc) => {
+                    let frame = frames.pop().unwrap();
+                    let mut chain = vals.split_off(frame.vals);
+                    let folded = if assoc == MpcAssoc::Right {
+                        let mut rhs = chain.pop().unwrap();
+                        while let (Some(operator), Some(lhs)) = (chain.pop(), chain.pop()) {
+                            rhs = fold(lhs, operator, rhs);
+                        }
+                        rhs
+                    } else {
+                        let mut chain = chain.into_iter();
+                        let mut lhs = chain.next().unwrap();
+                        while let (Some(operator), Some(rhs)) = (chain.next(), chain.next()) {
+                            lhs = fold(lhs, operator, rhs);
+                        }
+                        lhs
+                    };
+                    vals.push(folded);
+                }
+                MpcOp::Progress(parser, body) => {
+                    if input.pos == frames.last().unwrap().mark.pos {
+                        err = Some(parser.empty_loop_err(body, input));
+                    }
+                }
+                MpcOp::Tag(tag) | MpcOp::AddTag(tag) => {
+                    let frame = frames.pop().unwrap();
+                    let val = vals.pop().unwrap();
+                    let add = matches!(op, MpcOp::AddTag(_));
+                    let span = MpcSpan { start: frame.mark.state, end: input.state };
+                    match input.events {
+                        // The node's events, from the mark on, are rewritten to carry the tag
+                        Some(ref mut events) => {
+                            let at = frame.mark.events - input.settled;
+                            if !add {
+                                events.insert(at, MpcEvent::Enter(tag.to_string(), MpcSpan::default()));
+                            }
+                            mpc_events_tagged(events, at, tag, add, span);
+                            vals.push(Box::new(()));
+                        }
+                        None => vals.push(Box::new(mpc_ast_tagged(val, tag, add, span, &input.string))),
+                    }
+                }
+                MpcOp::Root => {
+                    let frame = frames.pop().unwrap();
+                    let val = vals.pop().unwrap();
+                    vals.push(Box::new(mpc_ast_root(val, MpcSpan { start: frame.mark.state, end: input.state }, &input.string)));
+                }
+                MpcOp::TagFail(tag) => {
+                    let frame = frames.pop().unwrap();
+                    if let Some(events) = input.events.as_mut() {
+                        events.truncate(frame.mark.events - input.settled);
+                    }
+                    if let Some(ref mut e) = err {
+                        e.context.push(tag.to_string());
+                        input.record_failure(e);
+                    }
+                }
+                MpcOp::Apply(f) => {
+                    let val = vals.pop().unwrap();
+                    vals.push(f(val));
+                }
+                MpcOp::Spanned => {
+                    let frame = frames.pop().unwrap();
+                    let val = vals.pop().unwrap();
+                    vals.push(Box::new((MpcSpan { start: frame.mark.state, end: input.state }, val)));
+                }
+                MpcOp::Recognize => {
+                    let frame = frames.pop().unwrap();
+                    vals.pop();
+                    vals.push(input.lexeme(frame.mark.state));
+                }
+                MpcOp::Consumed => {
+                    let frame = frames.pop().unwrap();
+                    let val = vals.pop().unwrap();
+                    let text = input.string[frame.mark.pos..input.pos].to_string();
+                    vals.push(Box::new((text, val)));
+                }
+                MpcOp::Isolate => isolated.push(input.furthest.take()),
+                MpcOp::Isolated => {
+                    let inner = std::mem::replace(&mut input.furthest, isolated.pop().unwrap());
+                    match (err.take(), inner) {
+                        (None, Some(inner)) => input.record_failure(&inner),
+                        (Some(e), Some(inner)) => err = MpcErr::or(vec![e, inner]),
+                        (e, None) => err = e,
+                    }
+                }
+                MpcOp::Expected(expected) => {
+                    let frame = frames.pop().unwrap();
+                    input.rewind(frame.mark);
+                    vals.truncate(frame.vals);
+                    let received = input.peek().unwrap_or('\0');
+                    let e = MpcErr::new(input.state, vec![expected.to_string()], format!("expected {}", expected), received);
+                    input.record_failure(&e);
+                    err = Some(e);
+                }
+                MpcOp::Not(parser) => {
+                    let frame = frames.pop().unwrap();
+                    input.rewind(frame.mark);
+                    vals.truncate(frame.vals);
+                    if err.take().is_some() {
+                        vals.push(Box::new(()));
+                    } else {
+                        let e = parser.not_err(input);
+                        input.record_failure(&e);
+                        err = Some(e);
+                    }
+                }
+                MpcOp::Advance(target) => {
+                    if input.advance().is_none() {
+                        pc = target;
+                    }
+                }
+                MpcOp::Recovered(default) => {
+                    let frame = frames.pop().unwrap();
+                    let e = frame.errs.into_iter().next().unwrap();
+                    // Nothing skipped means there is nothing to recover from
+                    if input.pos == frame.mark.pos {
+                        input.record_failure(&e);
+                        err = Some(e);
+                    } else {
+                        let e = input.finish_err(e);
+                        input.errors.push(e);
+                        vals.push(default());
+                    }
+                }
+                MpcOp::Until(set) => {
+                    let set = &self.sets[set];
+                    let (start, skip) = (input.pos, input.remaining().find(|c| set.contains(c)).unwrap_or(input.remaining().len()));
+                    input.advance_to(input.pos + skip);
+                    if let Err(e) = input.charge_since(start) {
+                        err = Some(e);
+                        break;
+                    }
+                }
+                MpcOp::UntilEnd(terminator) => {
+                    let e = terminator.until_err(err.take().unwrap(), input);
+                    input.record_failure(&e);
+                    err = Some(e);
+                }
+                MpcOp::PermBegin(n) => items.push((0..n).map(|_| None).collect()),
+                MpcOp::PermSkip(i, target) => {
+                    if items.last().unwrap()[i].is_some() {
+                        pc = target;
+                    }
+                }
+                MpcOp::PermItem(i, optional, round) => {
+                    let frame = frames.pop().unwrap();
+                    match err.take() {
+                        None => {
+                            items.last_mut().unwrap()[i] = vals.pop();
+                            frames.last_mut().unwrap().errs.clear();
+                            pc = round;
+                        }
+                        Some(e) => {
+                            input.rewind(frame.mark);
+                            vals.truncate(frame.vals);
+                            if !optional {
+                                frames.last_mut().unwrap().errs.push(e);
+                            }
+                        }
+                    }
+                }
+                MpcOp::PermEnd(fold) => {
+                    let frame = frames.pop().unwrap();
+                    let results = items.pop().unwrap();
+                    match MpcErr::or(frame.errs) {
+                        Some(e) => {
+                            input.record_failure(&e);
+                            err = Some(e);
+                        }
+                        None => {
+                            let results: Vec<MpcVal> = results.into_iter()
+                                .map(|result| result.unwrap_or_else(|| Box::new(())))
+                                .collect();
+                            let results = mpc_vm_text(input, results);
+                            vals.push(fold(results.len() as i32, results));
+                        }
+                    }
+                }
+                MpcOp::Operand => {
+                    let frame = frames.pop().unwrap();
+                    let val = vals.pop().unwrap();
+                    vals.push(Box::new(mpc_expr_operand(val, MpcSpan { start: frame.mark.state, end: input.state })));
+                }
+                MpcOp::Prefix(operator) => {
+                    let frame = frames.pop().unwrap();
+                    let operand = mpc_vm_operand(&mut vals);
+                    let val = vals.pop().unwrap();
+                    let span = MpcSpan { start: frame.mark.state, end: input.state };
+                    vals.push(Box::new(mpc_expr_node("prefix", operator, val, vec![operand], span)));
+                }
+                MpcOp::Postfix(operator) => {
+                    frames.pop();
+                    let val = vals.pop().unwrap();
+                    let operand = mpc_vm_operand(&mut vals);
+                    let span = MpcSpan { start: operand.span.start, end: input.state };
+                    vals.push(Box::new(mpc_expr_node("postfix", operator, val, vec![operand], span)));
+                }
+                MpcOp::Infix(operator, prec, assoc) => {
+                    frames.pop();
+                    let rhs = mpc_vm_operand(&mut vals);
+                    let val = vals.pop().unwrap();
+                    let lhs = mpc_vm_operand(&mut vals);
+                    let span = MpcSpan { start: lhs.span.start, end: input.state };
+                    vals.push(Box::new(mpc_expr_node("infix", operator, val, vec![lhs, rhs], span)));
+                    frames.last_mut().unwrap().nonassoc = if assoc == MpcAssoc::None { Some(prec) } else { None };
+                }
+                MpcOp::IfNonassoc(prec, target) => {
+                    if frames.last().unwrap().nonassoc != Some(prec) {
+                        pc = target;
+                    }
+                }
+                MpcOp::Chained(operator) => {
+                    vals.pop();
+                    err = Some(mpc_expr_chained_err(operator, input));
+                }
+            }
+        }
+        input.depth = depth;
+        input.holds = holds;
+        for grow in growing.into_iter().rev() {
+            input.seeds.remove(&grow.key);
+            input.memoize = grow.memoize;
+        }
+        match err {
+            None => MpcResult::Ok(vals.pop().unwrap()),
+            Some(e) => MpcResult::Err(e),
+        }
+    }
+}
+
+// Values for a fold. In zero-copy mode, where some are `String`s already, from `mpc_apply`,
+// `mpc_lift` or `MpcParse`, the spans among them become `String`s too, so that a fold of the
+// text such as `mpcf_strfold` gets all of it, as it would have without zero-copy
+fn mpc_vm_text(input: &MpcInput, mut results: Vec<MpcVal>) -> Vec<MpcVal> {
+    if !input.zero_copy || !results.iter().any(|x| x.is::<String>()) {
+        return results;
+    }
+    for result in &mut results {
+        if let Some(span) = result.downcast_ref::<MpcSpan>() {
+            *result = Box::new(span.slice(&input.string).to_string());
+        } else if let Some(spans) = result.downcast_ref::<Vec<MpcSpan>>() {
+            *result = Box::new(spans.iter().map(|span| span.slice(&input.string)).collect::<String>());
+        }
+    }
+    results
+}
+
+// Takes an operand left by Operand, or by an expression, off the value stack
+fn mpc_vm_operand(vals: &mut Vec<MpcVal>) -> MpcAst {
+    *vals.pop().unwrap().downcast::<MpcAst>().unwrap()
+}
+
+/// Lowers `parser` to bytecode for `mpc_parse_vm`. Every combinator gets instructions of its
+/// own, and lazy parsers and the precedence levels of `mpc_expr` become subroutines, so that
+/// however deeply the input nests the VM doesn't recurse. Only parsers that match characters
+/// or no input, and repetitions of a single character class, are called as a whole.
+/// `MpcParser::parse` compiles parsers this way the first time it runs one, see `MpcParser::program`.
+pub fn mpc_compile(parser: &MpcParser) -> MpcProgram<'_> {
+    mpc_compile_with(parser, None)
+}
+
+// A program together with the copy of the parser it was compiled from, which it borrows
+self_cell::self_cell!(
+    struct MpcCompiled {
+        owner: MpcParser,
+        #[covariant]
+        dependent: MpcProgram,
+    }
+);
+
+/// Where a parser keeps the program `MpcParser::parse` runs, compiled on its first parse
+/// without a grammar and shared by the parses after it. A clone of the parser starts without
+/// one, so change a parser that has been parsed with by cloning it; one changed in place keeps
+/// running the program it had.
+#[derive(Default)]
+pub struct MpcCompiledCell(std::sync::OnceLock<MpcCompiled>);
+
+impl MpcCompiledCell {
+    /// The program, if the parser has been compiled
+    pub fn get(&self) -> Option<&MpcProgram<'_>> {
+        self.0.get().map(MpcCompiled::borrow_dependent)
+    }
+}
+
+impl Clone for MpcCompiledCell {
+    fn clone(&self) -> Self {
+        MpcCompiledCell::default()
+    }
+}
+
+/// Shows whether the parser has been compiled, rather than the program
+impl std::fmt::Debug for MpcCompiledCell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MpcCompiledCell").field("compiled", &self.0.get().is_some()).finish()
+    }
+}
+
+impl MpcParser {
+    /// The parser compiled by `mpc_compile`, compiled the first time it is asked for or the
+    /// parser is parsed without a grammar, and kept for every parse after
+    pub fn program(&self) -> &MpcProgram<'_> {
+        self.compiled.0.get_or_init(|| MpcCompiled::new(self.clone(), mpc_compile)).borrow_dependent()
+    }
+}
+
+// Lowers `parser` with the rules it references looked up in `grammar`, as MpcParser::parse
+// runs it
+pub(crate) fn mpc_compile_with<'p>(parser: &'p MpcParser, grammar: Option<&'p MpcGrammar>) -> MpcProgram<'p> {
+    let mut subs = MpcSubs {
+        grammar,
+        left_recursive: grammar.map(MpcGrammar::left_recursive_rules).unwrap_or_default(),
+        ..MpcSubs::default()
+    };
+    let mut code = Vec::new();
+    mpc_compile_into(parser, &mut code, &mut subs);
+    mpc_link(code, subs)
+}
+
+// Lowers `lazy`, a lazy parser standing for one further out whose body is `body`, with that
+// body as the subroutine the lazy parsers standing for it call
+pub(crate) fn mpc_compile_lazy<'p>(lazy: &'p MpcParser, body: &'p MpcParser, grammar: Option<&'p MpcGrammar>) -> MpcProgram<'p> {
+    let MpcParserType::Lazy(_, ref cell) = lazy.parser_type else {
+        unreachable!("mpc_compile_lazy of a parser that isn't lazy");
+    };
+    let mut subs = MpcSubs {
+        grammar,
+        left_recursive: grammar.map(MpcGrammar::left_recursive_rules).unwrap_or_default(),
+        ..MpcSubs::default()
+    };
+    subs.call(cell.key(), body);
+    let mut code = Vec::new();
+    mpc_compile_into(lazy, &mut code, &mut subs);
+    mpc_link(code, subs)
+}
+
+// Lowers `parser` followed by the end of the input, as `mpc_endwith` of it would be, for running
+// a parser that is borrowed rather than owned on many inputs, such as records
+pub(crate) fn mpc_compile_whole(parser: &MpcParser) -> MpcProgram<'_> {
+    static EOI: std::sync::OnceLock<MpcParser> = std::sync::OnceLock::new();
+    let eoi = EOI.get_or_init(mpc_eoi);
+    let mut subs = MpcSubs::default();
+    let mut code = vec![MpcOp::Begin];
+    mpc_compile_into(parser, &mut code, &mut subs);
+    let parsed = mpc_emit(&mut code, MpcOp::JumpIfErr(0));
+    mpc_compile_into(eoi, &mut code, &mut subs);
+    let ended = mpc_emit(&mut code, MpcOp::JumpIfErr(0));
+    code.push(MpcOp::And(mpcf_fst));
+    code.push(MpcOp::Jump(code.len() + 2));
+    mpc_patch(&mut code, parsed);
+    mpc_patch(&mut code, ended);
+    code.push(MpcOp::Fail);
+    mpc_link(code, subs)
+}
+
+/// Lowers rule `start` of `grammar` to bytecode, like `mpc_compile`, with its rules as
+/// subroutines. Left-recursive rules grow a seed match, calling their subroutine until the
+/// match stops getting longer. Returns None if there is no rule `start`.
+pub fn mpc_compile_grammar<'p>(grammar: &'p MpcGrammar, start: &str) -> Option<MpcProgram<'p>> {
+    let i = *grammar.index.get(start)?;
+    let mut subs = MpcSubs {
+        grammar: Some(grammar),
+        left_recursive: grammar.left_recursive_rules(),
+        ..MpcSubs::default()
+    };
+    let mut code = Vec::new();
+    mpc_compile_rule(i, &mut code, &mut subs);
+    Some(mpc_link(code, subs))
+}
+
+// What a subroutine runs: a parser, or an expression from a minimum precedence up
+#[derive(Clone, Copy)]
+enum MpcSub<'p> {
+    Parser(&'p MpcParser),
+    Expr(&'p MpcParser, i32),
+}
+
+// Recursive parsers reached while compiling, each compiled once as a subroutine after the
+// main code
+#[derive(Default)]
+struct MpcSubs<'p> {
+    grammar: Option<&'p MpcGrammar>,
+    left_recursive: std::collections::HashSet<usize>,
+    bodies: Vec<MpcSub<'p>>,
+    index: std::collections::HashMap<(usize, Option<i32>), usize>,  // Subroutine number by address of its rule or lazy cell, and precedence
+    sets: Vec<MpcCharSet>,
+}
+
+impl<'p> MpcSubs<'p> {
+    // Call of the subroutine for `body`, numbered until mpc_link knows its address. `key` is
+    // the address of what it was made from, the same for a lazy parser and those standing for it.
+    fn call(&mut self, key: usize, body: &'p MpcParser) -> MpcOp<'p> {
+        self.sub((key, None), MpcSub::Parser(body))
+    }
+
+    // Call of the subroutine parsing `expr` with operators of at least `min_prec`
+    fn expr(&mut self, expr: &'p MpcParser, min_prec: i32) -> MpcOp<'p> {
+        self.sub((expr as *const MpcParser as usize, Some(min_prec)), MpcSub::Expr(expr, min_prec))
+    }
+
+    fn sub(&mut self, key: (usize, Option<i32>), body: MpcSub<'p>) -> MpcOp<'p> {
+        let n = *self.index.entry(key).or_insert_with(|| {
+            self.bodies.push(body);
+            self.bodies.len() - 1
+        });
+        MpcOp::Sub(n)
+    }
+}
+
+// Appends the subroutines to the main code and points the calls at them
+fn mpc_link<'p>(mut code: Vec<MpcOp<'p>>, mut subs: MpcSubs<'p>) -> MpcProgram<'p> {
+    code.push(MpcOp::Ret);
+    let mut entries = Vec::new();
+    // Compiling a subroutine can add more
+    while let Some(&body) = subs.bodies.get(entries.len()) {
+        entries.push(code.len());
+        match body {
+            MpcSub::Parser(parser) => mpc_compile_into(parser, &mut code, &mut subs),
+            MpcSub::Expr(expr, min_prec) => mpc_compile_expr(expr, min_prec, &mut code, &mut subs),
+        }
+        code.push(MpcOp::Ret);
+    }
+    for op in &mut code {
+        if let MpcOp::Sub(n) = *op {
+            *op = MpcOp::Sub(entries[n]);
+        }
+    }
+    MpcProgram { code, grammar: subs.grammar, sets: subs.sets }
+}
+
+fn mpc_compile_rule<'p>(i: usize, code: &mut Vec<MpcOp<'p>>, subs: &mut MpcSubs<'p>) {
+    let (name, rule) = &subs.grammar.unwrap().rules[i];
+    if subs.left_recursive.contains(&i) {
+        let grow = mpc_emit(code, MpcOp::Grow(name, i, 0));
+        let again = code.len();
+        code.push(subs.call(rule as *const MpcParser as usize, rule));
+        code.push(MpcOp::Grown(name, again));
+        mpc_patch(code, grow);
+    } else {
+        code.push(subs.call(rule as *const MpcParser as usize, rule));
+    }
+}
+
+// Forward jumps are emitted with a dummy target, then patched to jump to the next instruction
+// to be emitted
+fn mpc_patch(code: &mut [MpcOp], at: usize) {
+    let target = code.len();
+    code[at] = match code[at] {
+        MpcOp::Enter(parser, _) => MpcOp::Enter(parser, target),
+        MpcOp::Grow(name, rule, _) => MpcOp::Grow(name, rule, target),
+        MpcOp::Jump(_) => MpcOp::Jump(target),
+        MpcOp::JumpIfOk(_) => MpcOp::JumpIfOk(target),
+        MpcOp::JumpIfErr(_) => MpcOp::JumpIfErr(target),
+        MpcOp::JumpIfCount(n, _) => MpcOp::JumpIfCount(n, target),
+        MpcOp::Advance(_) => MpcOp::Advance(target),
+        MpcOp::PermSkip(i, _) => MpcOp::PermSkip(i, target),
+        MpcOp::Dispatch(parser, i, _) => MpcOp::Dispatch(parser, i, target),
+        MpcOp::IfNonassoc(prec, _) => MpcOp::IfNonassoc(prec, target),
+        op => op,
+    };
+}
+
+// Emits `op` to be patched later, returning where it is
+fn mpc_emit<'p>(code: &mut Vec<MpcOp<'p>>, op: MpcOp<'p>) -> usize {
+    code.push(op);
+    code.len() - 1
+}
+
+// Every parser's code is between an Enter and a Leave, apart from those called as a whole,
+// which MpcParser::parse starts and ends itself
+fn mpc_compile_into<'p>(parser: &'p MpcParser, code: &mut Vec<MpcOp<'p>>, subs: &mut MpcSubs<'p>) {
+    if parser.is_native(subs.grammar) {
+        code.push(MpcOp::Call(parser));
+        return;
+    }
+    let enter = mpc_emit(code, MpcOp::Enter(parser, 0));
+    mpc_compile_node(parser, code, subs);
+    code.push(MpcOp::Leave(parser));
+    mpc_patch(code, enter);
+}
+
+fn mpc_compile_node<'p>(parser: &'p MpcParser, code: &mut Vec<MpcOp<'p>>, subs: &mut MpcSubs<'p>) {
+    use MpcOp::*;
+    match parser.parser_type {
+        MpcParserType::And(ref parsers, fold, _) => {
+            code.push(Begin);
+            let mut fails = Vec::new();
+            for parser in parsers {
+                mpc_compile_into(parser, code, subs);
+                fails.push(mpc_emit(code, JumpIfErr(0)));
+            }
+            code.push(And(fold));
+            code.push(Jump(code.len() + 2));
+            for at in fails {
+                mpc_patch(code, at);
+            }
+            code.push(Fail);
+        }
+        MpcParserType::Or(ref parsers, ref dispatch) => {
+            code.push(Begin);
+            let mut oks = Vec::new();
+            for (i, alternative) in parsers.iter().enumerate() {
+                let skip = match dispatch {
+                    Some(dispatch) if dispatch.predicts(i) => Some(mpc_emit(code, Dispatch(parser, i, 0))),
+                    _ => None,
+                };
+                mpc_compile_into(alternative, code, subs);
+                oks.push(mpc_emit(code, JumpIfOk(0)));
+                code.push(OrNext);
+                if let Some(skip) = skip {
+                    mpc_patch(code, skip);
+                }
+            }
+            code.push(OrFail);
+            code.push(Jump(code.len() + 2));
+            for at in oks {
+                mpc_patch(code, at);
+            }
+            code.push(OrOk);
+        }
+        MpcParserType::Permutation(ref parsers, fold) => {
+            code.push(Begin);
+            code.push(PermBegin(parsers.len()));
+            let round = code.len();
+            for (i, (item, optional)) in parsers.iter().enumerate() {
+                let skip = mpc_emit(code, PermSkip(i, 0));
+                code.push(Begin);
+                mpc_compile_into(item, code, subs);
+                code.push(PermItem(i, *optional, round));
+                mpc_patch(code, skip);
+            }
+            code.push(PermEnd(fold));
+        }
+        MpcParserType::Many(ref inner, fold)
+        | MpcParserType::Many1(ref inner, fold) => {
+            code.push(Begin);
+            let fail = match parser.parser_type {
+                MpcParserType::Many1(..) => {
+                    mpc_compile_into(inner, code, subs);
+                    Some(mpc_emit(code, JumpIfErr(0)))
+                }
+                _ => None,
+            };
+            mpc_compile_loop(parser, inner, None, code, subs);
+            code.push(Many(fold));
+            mpc_compile_fail(fail, code);
+        }
+        MpcParserType::SkipMany(ref inner) | MpcParserType::SkipMany1(ref inner) => {
+            code.push(Begin);
+            let fail = match parser.parser_type {
+                MpcParserType::SkipMany1(..) => {
+                    mpc_compile_into(inner, code, subs);
+                    let fail = mpc_emit(code, JumpIfErr(0));
+                    code.push(Drop);
+                    Some(fail)
+                }
+                _ => None,
+            };
+            mpc_compile_loop(parser, inner, Some(Drop), code, subs);
+            code.push(Many(mpcf_null));
+            mpc_compile_fail(fail, code);
+        }
+        MpcParserType::FoldMany(init, ref inner, fold) => {
+            code.push(Begin);
+            code.push(Push(init));
+            mpc_compile_loop(parser, inner, Some(Fold(fold)), code, subs);
+            code.push(Many(mpcf_fst));
+        }
+        MpcParserType::Count(n, ref inner, fold) => {
+            code.push(Begin);
+            let mut fail = None;
+            if n > 0 {
+                let start = code.len();
+                code.push(Settle);
+                mpc_compile_into(inner, code, subs);
+                fail = Some(mpc_emit(code, JumpIfErr(0)));
+                code.push(JumpIfCount(n as usize, code.len() + 2));
+                code.push(Jump(start));
+            }
+            code.push(And(fold));
+            mpc_compile_fail(fail, code);
+        }
+        MpcParserType::Repeat(min, max, ref inner, fold) => {
+            code.push(Begin);
+            let start = code.len();
+            code.push(Settle);
+            let done = max.map(|max| mpc_emit(code, JumpIfCount(max as usize, 0)));
+            code.push(Mark);
+            mpc_compile_into(inner, code, subs);
+            let stop = mpc_emit(code, JumpIfErr(0));
+            // Only an unbounded repeat would go on forever
+            if max.is_none() {
+                code.push(Progress(parser, inner));
+            }
+            code.push(Jump(start));
+            mpc_patch(code, stop);
+            code.push(JumpIfCount(min as usize, code.len() + 3));
+            code.push(Fail);
+            let end = mpc_emit(code, Jump(0));
+            code.push(Many(fold));
+            if let Some(done) = done {
+                let skip = mpc_emit(code, Jump(0));
+                mpc_patch(code, done);
+                code.push(And(fold));
+                mpc_patch(code, skip);
+            }
+            mpc_patch(code, end);
+        }
+        MpcParserType::SepBy(ref inner, ref sep, fold)
+        | MpcParserType::SepBy1(ref inner, ref sep, fold)
+        | MpcParserType::SepEndBy(ref inner, ref sep, fold)
+        | MpcParserType::SepEndBy1(ref inner, ref sep, fold) => {
+            let trailing = matches!(parser.parser_type, MpcParserType::SepEndBy(..) | MpcParserType::SepEndBy1(..));
+            let required = matches!(parser.parser_type, MpcParserType::SepBy1(..) | MpcParserType::SepEndBy1(..));
+            code.push(Begin);
+            mpc_compile_into(inner, code, subs);
+            let none = mpc_emit(code, JumpIfErr(0));
+            let start = code.len();
+            code.push(Settle);
+            code.push(Begin);
+            mpc_compile_into(sep, code, subs);
+            let back = mpc_emit(code, JumpIfErr(0));
+            code.push(Drop);
+            // After a separator, a missing item means it was a trailing one
+            if trailing {
+                code.push(Begin);
+            }
+            mpc_compile_into(inner, code, subs);
+            let missing = mpc_emit(code, JumpIfErr(0));
+            if trailing {
+                code.push(Pop);
+            }
+            code.push(Progress(parser, inner));
+            code.push(Pop);
+            code.push(Jump(start));
+            let mut out = None;
+            if trailing {
+                mpc_patch(code, missing);
+                code.push(Back);
+                code.push(Pop);
+                out = Some(mpc_emit(code, Jump(0)));
+            } else {
+                mpc_patch(code, missing);
+            }
+            mpc_patch(code, back);
+            code.push(Back);
+            if let Some(out) = out {
+                mpc_patch(code, out);
+            }
+            code.push(And(fold));
+            let end = mpc_emit(code, Jump(0));
+            mpc_patch(code, none);
+            code.push(if required { Fail } else { Many(fold) });
+            mpc_patch(code, end);
+        }
+        MpcParserType::ChainL1(ref term, ref op, fold) | MpcParserType::ChainR1(ref term, ref op, fold) => {
+            let assoc = match parser.parser_type {
+                MpcParserType::ChainR1(..) => MpcAssoc::Right,
+                _ => MpcAssoc::Left,
+            };
+            code.push(Begin);
+            mpc_compile_into(term, code, subs);
+            let fail = mpc_emit(code, JumpIfErr(0));
+            let start = code.len();
+            code.push(Settle);
+            code.push(Begin);
+            mpc_compile_into(op, code, subs);
+            let back = mpc_emit(code, JumpIfErr(0));
+            mpc_compile_into(term, code, subs);
+            let missing = mpc_emit(code, JumpIfErr(0));
+            code.push(Progress(parser, term));
+            code.push(Pop);
+            code.push(Jump(start));
+            mpc_patch(code, back);
+            mpc_patch(code, missing);
+            code.push(Back);
+            code.push(Chain(fold, assoc));
+            mpc_compile_fail(Some(fail), code);
+        }
+        MpcParserType::Expr(..) => code.push(subs.expr(parser, i32::MIN)),
+        MpcParserType::Tag(ref inner, ref tag) | MpcParserType::AddTag(ref inner, ref tag) => {
+            code.push(Begin);
+            mpc_compile_into(inner, code, subs);
+            let fail = mpc_emit(code, JumpIfErr(0));
+            code.push(match parser.parser_type {
+                MpcParserType::AddTag(..) => AddTag(tag),
+                _ => Tag(tag),
+            });
+            code.push(Jump(code.len() + 2));
+            mpc_patch(code, fail);
+            code.push(TagFail(tag));
+        }
+        MpcParserType::Root(ref inner) => mpc_compile_wrapped(inner, Root, code, subs),
+        MpcParserType::Spanned(ref inner) => mpc_compile_wrapped(inner, Spanned, code, subs),
+        MpcParserType::Recognize(ref inner) => mpc_compile_wrapped(inner, Recognize, code, subs),
+        MpcParserType::Consumed(ref inner) => mpc_compile_wrapped(inner, Consumed, code, subs),
+        MpcParserType::Apply(ref inner, f) => {
+            mpc_compile_into(inner, code, subs);
+            code.push(JumpIfErr(code.len() + 2));
+            code.push(Apply(f));
+        }
+        MpcParserType::TakeUntil(ref terminator) => {
+            code.push(Begin);
+            let start = code.len();
+            // Skip straight to the next character the terminator could start with
+            if let Some(first) = terminator.first() {
+                subs.sets.push(first);
+                code.push(Until(subs.sets.len() - 1));
+            }
+            code.push(Begin);
+            mpc_compile_into(terminator, code, subs);
+            code.push(Peek);
+            let advance = mpc_emit(code, JumpIfErr(0));
+            code.push(Recognize);
+            let end = mpc_emit(code, Jump(0));
+            mpc_patch(code, advance);
+            let eoi = mpc_emit(code, Advance(0));
+            code.push(Clear);
+            code.push(Jump(start));
+            mpc_patch(code, eoi);
+            code.push(UntilEnd(terminator));
+            code.push(Fail);
+            mpc_patch(code, end);
+        }
+        MpcParserType::Expect(ref inner, ref expected) => {
+            code.push(Begin);
+            code.push(Isolate);
+            mpc_compile_into(inner, code, subs);
+            code.push(Isolated);
+            code.push(JumpIfErr(code.len() + 3));
+            code.push(Pop);
+            code.push(Jump(code.len() + 2));
+            code.push(Expected(expected));
+        }
+        MpcParserType::Recover(ref inner, ref sync, default) => {
+            code.push(Begin);
+            code.push(Isolate);
+            mpc_compile_into(inner, code, subs);
+            code.push(Isolated);
+            let recover = mpc_emit(code, JumpIfErr(0));
+            code.push(Pop);
+            let end = mpc_emit(code, Jump(0));
+            mpc_patch(code, recover);
+            code.push(OrNext);
+            // Skip up to and past the next match of `sync`
+            let start = code.len();
+            code.push(Begin);
+            mpc_compile_into(sync, code, subs);
+            let synced = mpc_emit(code, JumpIfOk(0));
+            code.push(Back);
+            let eoi = mpc_emit(code, Advance(0));
+            code.push(Jump(start));
+            mpc_patch(code, synced);
+            code.push(Pop);
+            code.push(Drop);
+            mpc_patch(code, eoi);
+            code.push(Recovered(default));
+            mpc_patch(code, end);
+        }
+        MpcParserType::Not(ref inner) => {
+            code.push(Begin);
+            code.push(Isolate);
+            mpc_compile_into(inner, code, subs);
+            code.push(Isolated);
+            code.push(Not(inner));
+        }
+        MpcParserType::Lookahead(ref inner) => {
+            code.push(Begin);
+            mpc_compile_into(inner, code, subs);
+            code.push(Peek);
+        }
+        MpcParserType::Lazy(build, ref cell) => match cell.own(build) {
+            Some(body) => code.push(subs.call(cell.key(), body)),
+            // One standing for a lazy parser further out, whose subroutine is numbered already
+            None => code.push(subs.index.get(&(cell.key(), None)).map_or(Call(parser), |&n| Sub(n))),
+        },
+        // Rules the grammar doesn't have are native, failing when they run
+        MpcParserType::Rule(ref name) => mpc_compile_rule(subs.grammar.unwrap().index[name], code, subs),
+        _ => unreachable!("{} is native", parser.name),
+    }
+}
+
+// Repeats `body` until it fails, rewinding to before the failed attempt. Each value is passed
+// to `each`, if given, and a body matching without consuming input ends the parse.
+fn mpc_compile_loop<'p>(parser: &'p MpcParser, body: &'p MpcParser, each: Option<MpcOp<'p>>, code: &mut Vec<MpcOp<'p>>, subs: &mut MpcSubs<'p>) {
+    let start = code.len();
+    code.push(MpcOp::Settle);
+    code.push(MpcOp::Mark);
+    mpc_compile_into(body, code, subs);
+    let stop = mpc_emit(code, MpcOp::JumpIfErr(0));
+    code.extend(each);
+    code.push(MpcOp::Progress(parser, body));
+    code.push(MpcOp::Jump(start));
+    mpc_patch(code, stop);
+}
+
+// Ends the code of a parser that succeeded, with the failure path jumped to from `fail`
+// closing its frame
+fn mpc_compile_fail(fail: Option<usize>, code: &mut Vec<MpcOp>) {
+    if let Some(fail) = fail {
+        code.push(MpcOp::Jump(code.len() + 2));
+        mpc_patch(code, fail);
+        code.push(MpcOp::Fail);
+    }
+}
+
+// `inner` in a frame closed by `close` if it matched
+fn mpc_compile_wrapped<'p>(inner: &'p MpcParser, close: MpcOp<'p>, code: &mut Vec<MpcOp<'p>>, subs: &mut MpcSubs<'p>) {
+    code.push(MpcOp::Begin);
+    mpc_compile_into(inner, code, subs);
+    let fail = mpc_emit(code, MpcOp::JumpIfErr(0));
+    code.push(close);
+    mpc_compile_fail(Some(fail), code);
+}
+
+// Precedence climbing: the subroutine for one minimum
+// precedence parses an operand, then any operators binding at least that tightly, calling the
+// subroutines for higher precedences for the operands of those operators
+fn mpc_compile_expr<'p>(expr: &'p MpcParser, min_prec: i32, code: &mut Vec<MpcOp<'p>>, subs: &mut MpcSubs<'p>) {
+    use MpcOp::*;
+    let MpcParserType::Expr(ref atom, ref operators) = expr.parser_type else {
+        unreachable!("mpc_compile_expr of a parser that isn't an expression");
+    };
+    code.push(Begin);
+    let mut operands = Vec::new();
+    for operator in operators {
+        if let MpcOperator::Prefix(op, prec) = operator {
+            code.push(Begin);
+            mpc_compile_into(op, code, subs);
+            let no_op = mpc_emit(code, JumpIfErr(0));
+            code.push(Begin);
+            code.push(subs.expr(expr, *prec));
+            // The operator may as well begin the atom, as in `-1` for a literal
+            let no_operand = mpc_emit(code, JumpIfErr(0));
+            code.push(Operand);
+            code.push(Prefix(op));
+            operands.push(mpc_emit(code, Jump(0)));
+            mpc_patch(code, no_operand);
+            code.push(Fail);
+            mpc_patch(code, no_op);
+            code.push(Back);
+        }
+    }
+    code.push(Begin);
+    mpc_compile_into(atom, code, subs);
+    let no_atom = mpc_emit(code, JumpIfErr(0));
+    code.push(Operand);
+    let operators_start = code.len();
+    for at in operands {
+        code[at] = Jump(operators_start);
+    }
+    let mut chained = Vec::new();
+    let mut done = Vec::new();
+    for operator in operators {
+        match operator {
+            MpcOperator::Postfix(op, prec) if *prec >= min_prec => {
+                code.push(Begin);
+                mpc_compile_into(op, code, subs);
+                code.push(JumpIfErr(code.len() + 3));
+                code.push(Postfix(op));
+                code.push(Jump(operators_start));
+                code.push(Back);
+            }
+            MpcOperator::Infix(op, prec, assoc) => {
+                // `a = b = c` with a non-associative `=` fails at the second `=`
+                let unchained = mpc_emit(code, IfNonassoc(*prec, 0));
+                code.push(Begin);
+                mpc_compile_into(op, code, subs);
+                code.push(Peek);
+                code.push(JumpIfErr(code.len() + 3));
+                code.push(Chained(op));
+                chained.push(mpc_emit(code, Jump(0)));
+                code.push(Clear);
+                let next = mpc_emit(code, Jump(0));
+                mpc_patch(code, unchained);
+                if *prec >= min_prec {
+                    let next_prec = match assoc {
+                        MpcAssoc::Right => *prec,
+                        MpcAssoc::Left | MpcAssoc::None => prec.saturating_add(1),
+                    };
+                    code.push(Begin);
+                    mpc_compile_into(op, code, subs);
+                    let no_op = mpc_emit(code, JumpIfErr(0));
+                    code.push(Begin);
+                    code.push(subs.expr(expr, next_prec));
+                    let no_rhs = mpc_emit(code, JumpIfErr(0));
+                    code.push(Operand);
+                    code.push(Infix(op, *prec, *assoc));
+                    code.push(Jump(operators_start));
+                    // An operator without its right operand ends the expression before it
+                    mpc_patch(code, no_rhs);
+                    code.push(Fail);
+                    code.push(Back);
+                    done.push(mpc_emit(code, Jump(0)));
+                    mpc_patch(code, no_op);
+                    code.push(Back);
+                }
+                mpc_patch(code, next);
+            }
+            _ => {}
+        }
+    }
+    for at in done {
+        mpc_patch(code, at);
+    }
+    code.push(Pop);
+    let end = mpc_emit(code, Jump(0));
+    mpc_patch(code, no_atom);
+    code.push(Fail);
+    for at in chained {
+        mpc_patch(code, at);
+    }
+    code.push(Fail);
+    mpc_patch(code, end);
+}
+
+/// Like `mpc_parse`, but runs a program built by `mpc_compile` or `mpc_compile_grammar`
+pub fn mpc_parse_vm(filename: &str, string: &str, program: &MpcProgram) -> MpcResult {
+    let mut input = MpcInput::new(filename, string);
+    input.grammar = program.grammar;
+    let result = program.run(&mut input);
+    mpc_parse_finish(&mut input, result)
+}
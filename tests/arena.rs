@@ -0,0 +1,60 @@
+use mpc::prelude::*;
+use mpc::ast::{MpcAstArena, MpcAstId};
+use mpc::parser::mpc_parse_arena;
+
+// list : '[' (value (',' value)*)? ']' ; value : number | list
+fn value() -> MpcParser {
+    let number = mpca_tag(mpc_digits(), "number");
+    let list = mpca_tag(mpca_and(vec![mpc_char('['), mpca_sepby(mpc_lazy(value), mpc_char(',')), mpc_char(']')]), "list");
+    mpc_or(vec![number, list])
+}
+
+// Tag and span of the tagged nodes of a tree, depth first
+fn tagged_nodes(ast: &MpcAst, nodes: &mut Vec<(String, MpcSpan)>) {
+    if !ast.tag.is_empty() {
+        nodes.push((ast.tag.clone(), ast.span));
+    }
+    for child in &ast.children {
+        tagged_nodes(child, nodes);
+    }
+}
+
+#[test]
+fn arena_parse_gives_the_tagged_nodes_of_the_tree() {
+    let parser = value();
+    let input = "[1,[22,3],[],4]";
+    let tree = mpc_parse("test", input, &parser).unwrap().downcast::<MpcAst>().unwrap();
+    let mut expected = Vec::new();
+    tagged_nodes(&tree, &mut expected);
+    let arena: MpcAstArena = mpc_parse_arena("test", input, &parser).unwrap();
+    let nodes: Vec<(String, MpcSpan)> = (0..arena.len())
+        .map(|id| (arena.tag(MpcAstId(id)).to_string(), arena.node(MpcAstId(id)).span))
+        .collect();
+    assert_eq!(nodes, expected);
+    // Leaves hold the text they matched, untagged punctuation too
+    let contents: Vec<&str> = (0..arena.len()).map(|id| arena.contents(MpcAstId(id))).collect();
+    assert_eq!(contents, vec!["", "1", "", "22", "3", "[]", "4"]);
+    let children: Vec<&str> = arena.children(MpcAstId(0)).map(|id| arena.tag(id)).collect();
+    assert_eq!(children, vec!["number", "list", "list", "number"]);
+    assert_eq!(arena.parent(MpcAstId(3)), Some(MpcAstId(2)));
+    assert!(mpc_parse_arena("test", "[1,", &parser).is_err());
+}
+
+// Bytes of tags and contents a tree holds, a copy for every node
+fn tree_text_len(ast: &MpcAst) -> usize {
+    ast.tag.len() + ast.contents.len() + ast.children.iter().map(|child| tree_text_len(child)).sum::<usize>()
+}
+
+#[test]
+fn arena_stores_repeated_tags_and_short_contents_once() {
+    let number = mpca_add_tag(mpca_add_tag(mpca_tag(mpc_digits(), "number"), "term"), "expr");
+    let parser = mpca_tag(mpca_and(vec![mpc_char('['), mpca_sepby(number, mpc_char(',')), mpc_char(']')]), "list");
+    let items: Vec<String> = (0..10_000).map(|i| (i % 100).to_string()).collect();
+    let input = format!("[{}]", items.join(","));
+    let tree = mpc_parse("test", &input, &parser).unwrap().downcast::<MpcAst>().unwrap();
+    let arena = mpc_parse_arena("test", &input, &parser).unwrap();
+    // About 180KB of `expr|term|number`, digits and commas in the tree, a few hundred bytes in the arena
+    assert!(tree_text_len(&tree) > 150_000, "{}", tree_text_len(&tree));
+    assert!(arena.text_len() < 1_000, "{}", arena.text_len());
+    assert!(MpcAstArena::from_ast(&tree).text_len() < 1_000);
+}
@@ -0,0 +1,318 @@
+use mpc::prelude::*;
+use mpc::ast::{MpcAstArena, MpcAstId, MpcAstPathErr, MpcSimplify};
+
+// sum : number ('+' number)*, as a tree
+fn sum() -> MpcParser {
+    let number = mpca_tag(mpc_tok(mpc_digits()), "number");
+    mpca_tag(mpca_and(vec![number.clone(), mpca_many(mpca_and(vec![mpc_tok(mpc_char('+')), number]))]), "sum")
+}
+
+fn tree(input: &str) -> MpcAst {
+    *mpc_parse("test", input, &sum()).unwrap().downcast::<MpcAst>().unwrap()
+}
+
+#[test]
+fn equality_ignores_positions_only() {
+    let (a, b) = (tree("1+2"), tree("1 +  2"));
+    assert_ne!(a, b);
+    assert!(a.eq_ignore_position(&b));
+    assert!(mpc_ast_eq(&a, &b));
+    let c = tree("1+3");
+    assert!(!a.eq_ignore_position(&c));
+    assert!(!mpc_ast_eq(&a, &c));
+}
+
+#[test]
+fn both_equalities_compare_trivia() {
+    let a = tree("1+2");
+    let mut b = a.clone();
+    b.children[0].trailing_trivia = String::from(" ");
+    assert!(!a.eq_ignore_position(&b));
+    assert!(!mpc_ast_eq(&a, &b));
+}
+
+fn node(tag: &str, contents: &str, children: Vec<MpcAst>) -> MpcAst {
+    let mut ast = MpcAst::new(tag, contents);
+    ast.children = children.into_iter().map(Box::new).collect();
+    ast.children_num = ast.children.len() as i32;
+    ast
+}
+
+// (a (b "1" (c "2")) (d "3") (e))
+fn sample() -> MpcAst {
+    node("a", "", vec![node("b", "1", vec![node("c", "2", vec![])]), node("d", "3", vec![]), node("e", "", vec![])])
+}
+
+// Records the hooks called, stopping or skipping at the tags it is given
+struct Recorder {
+    calls: Vec<String>,
+    skip: &'static str,
+    stop_entering: &'static str,
+    stop_exiting: &'static str,
+}
+
+impl Recorder {
+    fn new(skip: &'static str, stop_entering: &'static str, stop_exiting: &'static str) -> Self {
+        Recorder { calls: Vec::new(), skip, stop_entering, stop_exiting }
+    }
+
+    fn enter(&mut self, ast: &MpcAst, depth: usize) -> MpcVisit {
+        self.calls.push(format!("+{}{}", ast.tag, depth));
+        match ast.tag.as_str() {
+            tag if tag == self.stop_entering => MpcVisit::Stop,
+            tag if tag == self.skip => MpcVisit::SkipChildren,
+            _ => MpcVisit::Continue,
+        }
+    }
+
+    fn exit(&mut self, ast: &MpcAst, depth: usize) -> bool {
+        self.calls.push(format!("-{}{}", ast.tag, depth));
+        ast.tag != self.stop_exiting
+    }
+}
+
+impl MpcAstVisitor for Recorder {
+    fn enter(&mut self, ast: &MpcAst, depth: usize) -> MpcVisit {
+        Recorder::enter(self, ast, depth)
+    }
+
+    fn exit(&mut self, ast: &MpcAst, depth: usize) -> bool {
+        Recorder::exit(self, ast, depth)
+    }
+}
+
+impl MpcAstVisitorMut for Recorder {
+    fn enter(&mut self, ast: &mut MpcAst, depth: usize) -> MpcVisit {
+        Recorder::enter(self, ast, depth)
+    }
+
+    fn exit(&mut self, ast: &mut MpcAst, depth: usize) -> bool {
+        Recorder::exit(self, ast, depth)
+    }
+}
+
+#[test]
+fn walks_call_the_hooks_in_order() {
+    let cases = [
+        (Recorder::new("", "", ""), "+a0 +b1 +c2 -c2 -b1 +d1 -d1 +e1 -e1 -a0"),
+        (Recorder::new("b", "", ""), "+a0 +b1 -b1 +d1 -d1 +e1 -e1 -a0"),
+        (Recorder::new("", "c", ""), "+a0 +b1 +c2"),
+        (Recorder::new("", "", "b"), "+a0 +b1 +c2 -c2 -b1"),
+    ];
+    for (mut recorder, calls) in cases {
+        sample().walk(&mut recorder);
+        assert_eq!(recorder.calls.join(" "), calls);
+        let mut tree = sample();
+        recorder.calls.clear();
+        tree.walk_mut(&mut recorder);
+        assert_eq!(recorder.calls.join(" "), calls);
+        assert_eq!(tree, sample());
+    }
+}
+
+// Replaces the children of `d` nodes with an `x` leaf, and counts the nodes it sees
+struct Replacer(usize);
+
+impl MpcAstVisitorMut for Replacer {
+    fn enter(&mut self, ast: &mut MpcAst, _depth: usize) -> MpcVisit {
+        self.0 += 1;
+        if ast.tag == "d" {
+            ast.children = vec![Box::new(MpcAst::new("x", "4"))];
+        }
+        MpcVisit::Continue
+    }
+}
+
+#[test]
+fn walk_mut_visits_children_set_on_entering() {
+    let mut tree = sample();
+    let mut replacer = Replacer(0);
+    tree.walk_mut(&mut replacer);
+    assert_eq!(replacer.0, 6);
+    assert_eq!(tree.to_sexpr(), r#"(a (b "1" (c "2")) (d "3" (x "4")) (e))"#);
+    assert_eq!(tree.children[1].children_num, 1);
+}
+
+#[test]
+fn trees_print_in_each_form() {
+    let tree = sample();
+    assert_eq!(tree.to_sexpr(), r#"(a (b "1" (c "2")) (d "3") (e))"#);
+    assert_eq!(tree.to_snapshot(), "a\n  b \"1\"\n    c \"2\"\n  d \"3\"\n  e\n");
+    assert_eq!(format!("{}", tree), "a\n  b\n    \"1\"\n    c\n      \"2\"\n  d\n    \"3\"\n  e\n");
+    let state = r#"{"pos":0,"row":0,"col":0}"#;
+    let leaf = |tag: &str, contents: &str, children: &str| format!(r#"{{"tag":"{}","contents":"{}","start":{},"end":{},"children":[{}]}}"#, tag, contents, state, state, children);
+    let json = leaf("a", "", &[leaf("b", "1", &leaf("c", "2", "")), leaf("d", "3", ""), leaf("e", "", "")].join(","));
+    assert_eq!(tree.to_json(), json);
+    assert_eq!(tree.to_source_normalized(" "), "1 2 3");
+}
+
+// What `#[derive(Debug)]` would print for a tree, to hold the hand-written impl to it
+mod derived {
+    #[allow(dead_code)]  // Read only by Debug
+    #[derive(Debug)]
+    pub struct MpcAst {
+        tag: String,
+        contents: String,
+        state: mpc::prelude::MpcState,
+        span: mpc::prelude::MpcSpan,
+        children_num: i32,
+        children: Vec<MpcAst>,
+        leading_trivia: String,
+        trailing_trivia: String,
+    }
+
+    pub fn copy(ast: &mpc::prelude::MpcAst) -> MpcAst {
+        MpcAst {
+            tag: ast.tag.clone(),
+            contents: ast.contents.clone(),
+            state: ast.state,
+            span: ast.span,
+            children_num: ast.children_num,
+            children: ast.children.iter().map(|child| copy(child)).collect(),
+            leading_trivia: ast.leading_trivia.clone(),
+            trailing_trivia: ast.trailing_trivia.clone(),
+        }
+    }
+}
+
+#[test]
+fn trees_debug_print_as_derived() {
+    let mut parsed = tree("1 + 2");
+    parsed.children[0].trailing_trivia = String::from(" ");
+    for tree in [sample(), parsed, MpcAst::new("leaf", "x")] {
+        let derived = derived::copy(&tree);
+        assert_eq!(format!("{:?}", tree), format!("{:?}", derived));
+        assert_eq!(format!("{:#?}", tree), format!("{:#?}", derived));
+    }
+}
+
+#[test]
+fn simplify_drops_merges_and_hoists() {
+    let tree = node("a", "", vec![node("b", "", vec![node("", "1", vec![]), node("", "2", vec![])]), node("punct", ";", vec![])]);
+    assert_eq!(tree.simplify(&MpcSimplify::all(&["punct"])).to_sexpr(), r#"(a|b "12")"#);
+}
+
+#[test]
+fn diff_reports_changes_in_order() {
+    let new = node("a", "", vec![node("b", "1", vec![node("c", "9", vec![])]), node("e", "", vec![]), node("f", "", vec![])]);
+    let changes: Vec<String> = mpc_ast_diff(&sample(), &new).iter().map(|change| change.to_string()).collect();
+    assert_eq!(changes, ["~ [0|0] c \"2\" -> c \"9\"", "- [1] d \"3\"", "+ [2] f \"\""]);
+}
+
+// A chain of `depth` nodes, each the only child of the one before
+fn deep(depth: usize) -> MpcAst {
+    let mut tree = MpcAst::new("leaf", "x");
+    for _ in 0..depth {
+        tree = node("n", "", vec![tree]);
+    }
+    tree
+}
+
+#[test]
+fn deep_trees_are_walked_without_the_call_stack() {
+    let depth = 100_000;
+    let tree = deep(depth);
+    let copy = tree.clone();
+    assert_eq!(tree, copy);
+    assert!(mpc_ast_eq(&tree, &copy));
+    let hash = |ast: &MpcAst| {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        ast.hash(&mut hasher);
+        hasher.finish()
+    };
+    assert_eq!(hash(&tree), hash(&copy));
+    assert!(mpc_ast_diff(&tree, &copy).is_empty());
+    assert_eq!(tree.to_sexpr().len(), "(n )".len() * depth + "(leaf \"x\")".len());
+    assert!(tree.to_json().ends_with(&"]}".repeat(depth + 1)));
+    // Indented a level per node, so their text grows with the square of the depth
+    let shallower = deep(10_000);
+    assert_eq!(shallower.to_snapshot().lines().count(), 10_001);
+    assert_eq!(format!("{}", shallower).lines().count(), 10_002);
+    assert_eq!(tree.to_source(), "x");
+    assert_eq!(format!("{:?}", tree).matches("MpcAst {").count(), depth + 1);
+    let mut recorder = Recorder::new("", "", "");
+    tree.walk(&mut recorder);
+    assert_eq!(recorder.calls.len(), 2 * (depth + 1));
+    let mut copy = copy;
+    copy.walk_mut(&mut Replacer(0));
+    let simplified = copy.simplify(&MpcSimplify::all(&[]));
+    assert_eq!((simplified.tag.split('|').count(), simplified.contents.as_str()), (depth + 1, "x"));
+    let arena = MpcAstArena::from_ast(&tree);
+    assert_eq!(arena.to_ast(MpcAstId(0)), tree);
+}
+
+#[test]
+fn get_follows_tags_and_indices() {
+    let tree = sample();
+    assert_eq!(tree.get("b|c").unwrap().contents, "2");
+    assert_eq!(tree.get("b|0").unwrap().tag, "c");
+    assert_eq!(tree.get("2").unwrap().tag, "e");
+    assert_eq!(tree.get("|d|").unwrap().contents, "3");
+    assert!(std::ptr::eq(tree.get("").unwrap(), &tree));
+    assert!(tree.get("b|d").is_none());
+    assert!(tree.get("3").is_none());
+}
+
+#[test]
+fn try_get_says_which_step_is_missing() {
+    let tree = sample();
+    assert_eq!(tree.try_get("b|c").unwrap().contents, "2");
+    let e = tree.try_get("b|c|0").unwrap_err();
+    assert_eq!(e, MpcAstPathErr { path: String::from("b|c|0"), step: 2, segment: String::from("0"), tag: String::from("c") });
+    assert_eq!(e.to_string(), "c has no child 0, at step 3 of path \"b|c|0\"");
+    let e = MpcAst::new("", "").try_get("x").unwrap_err();
+    assert_eq!(e.to_string(), "<untagged> has no child x, at step 1 of path \"x\"");
+    let e: Box<dyn std::error::Error> = Box::new(e);
+    assert!(e.to_string().starts_with("<untagged>"));
+}
+
+#[test]
+fn tag_matches_whole_segments_in_order() {
+    let ast = MpcAst::new("expr|term|number|regex", "1");
+    for pattern in ["number", "term|number", "expr|term|number|regex", "|number|"] {
+        assert!(ast.tag_matches(pattern), "{}", pattern);
+    }
+    for pattern in ["num", "expr|number", "number|term", "regex|x", "", "|"] {
+        assert!(!ast.tag_matches(pattern), "{}", pattern);
+    }
+    assert!(!MpcAst::new("", "").tag_matches("number"));
+}
+
+#[test]
+fn index_links_every_node_to_its_parent() {
+    let tree = sample();
+    let index = tree.index();
+    assert_eq!(index.len(), 5);
+    assert!(!index.is_empty());
+    let tags: Vec<&str> = index.ids().map(|id| index.node(id).tag.as_str()).collect();
+    assert_eq!(tags, ["a", "b", "c", "d", "e"]);
+    let c = MpcAstId(2);
+    assert_eq!(index.parent(index.root()), None);
+    assert_eq!(index.parent(c), Some(MpcAstId(1)));
+    assert_eq!(index.ancestors(c).collect::<Vec<_>>(), [MpcAstId(1), MpcAstId(0)]);
+    assert_eq!(index.children(index.root()), [MpcAstId(1), MpcAstId(3), MpcAstId(4)]);
+    assert_eq!((index.depth(c), index.child_index(MpcAstId(4))), (2, 2));
+    for id in index.ids() {
+        assert!(std::ptr::eq(tree.get(&index.path(id)).unwrap(), index.node(id)));
+        assert_eq!(index.id_of(index.node(id)), Some(id));
+    }
+    assert_eq!(index.path(c), "0|0");
+    assert_eq!(index.id_of(&sample()), None);
+}
+
+#[test]
+fn nodes_know_the_bytes_they_cover() {
+    let word = mpca_tag(mpc_tok(mpc_many1(mpc_noneof(" +"), mpcf_strfold)), "word");
+    let parser = mpca_tag(mpca_and(vec![word.clone(), mpca_many(mpca_and(vec![mpc_tok(mpc_char('+')), word]))]), "words");
+    let source = "héllo + wörld";
+    let tree = *mpc_parse("test", source, &parser).unwrap().downcast::<MpcAst>().unwrap();
+    let ranges: Vec<_> = tree.iter().map(|(_, node)| node.byte_range()).collect();
+    assert_eq!(ranges, [0..15, 0..7, 7..9, 9..15]);
+    for (_, node) in tree.iter() {
+        assert_eq!(node.source_text(source), &source[node.byte_range()]);
+    }
+    // The root has no contents of its own, but its text is all of the input
+    assert_eq!((tree.contents.as_str(), tree.source_text(source)), ("", source));
+    assert_eq!(tree.children[2].source_text(source), "wörld");
+}
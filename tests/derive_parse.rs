@@ -0,0 +1,102 @@
+#![cfg(feature = "derive")]
+
+use mpc::prelude::*;
+
+#[derive(MpcParse, Debug, PartialEq)]
+#[mpc(token = "point", end = ";")]
+struct Point {
+    #[mpc(token = "(")]
+    x: i64,
+    #[mpc(token = ",")]
+    y: i64,
+    #[mpc(token = ")")]
+    label: Option<String>,
+}
+
+#[derive(MpcParse, Debug, PartialEq)]
+enum Color {
+    Red,
+    DarkBlue,
+    #[mpc(token = "grey")]
+    Gray,
+    #[mpc(token = "rgb", end = ")")]
+    Rgb(#[mpc(token = "(")] u8, #[mpc(token = ",")] u8, #[mpc(token = ",")] u8),
+}
+
+#[derive(MpcParse, Debug, PartialEq)]
+struct Palette {
+    #[mpc(token = "[", sep = ",")]
+    colors: Vec<Color>,
+    #[mpc(token = "]")]
+    flags: Vec<bool>,
+}
+
+#[derive(MpcParse, Debug, PartialEq)]
+enum Expr {
+    N(i64),
+    Neg(#[mpc(token = "-")] Box<Expr>),
+    #[mpc(token = "(", end = ")")]
+    Group(Box<Sum>),
+}
+
+#[derive(MpcParse, Debug, PartialEq)]
+struct Sum {
+    #[mpc(sep = "+")]
+    terms: Vec<Expr>,
+}
+
+#[test]
+fn struct_reads_its_fields_between_its_literals() {
+    let point = Point::mpc_parse_str("test", "point ( 1 ,-2 ) \"origin\" ;").unwrap();
+    assert_eq!(point, Point { x: 1, y: -2, label: Some(String::from("origin")) });
+    let point = Point::mpc_parse_str("test", "point(3,4);").unwrap();
+    assert_eq!(point, Point { x: 3, y: 4, label: None });
+}
+
+#[test]
+fn struct_needs_its_end() {
+    let e = Point::mpc_parse_str("test", "point(3,4)").unwrap_err();
+    assert!(e.expected.iter().any(|expected| expected.contains(';')), "{:?}", e.expected);
+}
+
+#[test]
+fn enum_tries_its_variants_in_order() {
+    assert_eq!(Color::mpc_parse_str("test", "red").unwrap(), Color::Red);
+    assert_eq!(Color::mpc_parse_str("test", "dark_blue").unwrap(), Color::DarkBlue);
+    assert_eq!(Color::mpc_parse_str("test", "grey").unwrap(), Color::Gray);
+    assert_eq!(Color::mpc_parse_str("test", "rgb(0, 128, 255)").unwrap(), Color::Rgb(0, 128, 255));
+}
+
+#[test]
+fn unit_variant_is_a_whole_keyword() {
+    assert!(Color::mpc_parse_str("test", "gray").is_err());
+    assert!(Color::mpc_parse_str("test", "reddish").is_err());
+}
+
+#[test]
+fn vec_field_with_sep_takes_separated_items() {
+    let palette = Palette::mpc_parse_str("test", "[red, grey ,rgb(1,2,3)] true false").unwrap();
+    assert_eq!(palette, Palette { colors: vec![Color::Red, Color::Gray, Color::Rgb(1, 2, 3)], flags: vec![true, false] });
+    let palette = Palette::mpc_parse_str("test", "[red]").unwrap();
+    assert_eq!(palette, Palette { colors: vec![Color::Red], flags: vec![] });
+}
+
+#[test]
+fn value_that_does_not_fit_is_an_error_at_its_position() {
+    let e = Color::mpc_parse_str("test", "rgb(0, 300, 0)").unwrap_err();
+    assert_eq!((e.state.row, e.state.col), (0, 7));
+}
+
+#[test]
+fn type_containing_itself_builds_once_and_parses() {
+    assert_eq!(Expr::mpc_parse_str("test", "- - 4").unwrap(), Expr::Neg(Box::new(Expr::Neg(Box::new(Expr::N(4))))));
+    let sum = Sum::mpc_parse_str("test", "1 + -(2 + -3)").unwrap();
+    let inner = Sum { terms: vec![Expr::N(2), Expr::N(-3)] };
+    assert_eq!(sum, Sum { terms: vec![Expr::N(1), Expr::Neg(Box::new(Expr::Group(Box::new(inner))))] });
+}
+
+#[test]
+fn derive_rejects_types_it_cannot_read_at_compile_time() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/derive_parse_*.rs");
+}
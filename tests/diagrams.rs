@@ -0,0 +1,122 @@
+use mpc::prelude::*;
+
+fn list() -> MpcGrammar {
+    mpc_peg("list.peg", "list <- '[' item (',' item)* ']'\nitem <- [0-9]+ / list").unwrap()
+}
+
+// The value of each `name="..."` attribute in `svg` on elements starting with `element`
+fn attributes<'a>(svg: &'a str, element: &str, name: &str) -> Vec<&'a str> {
+    svg.split(element).skip(1)
+        .map(|tag| {
+            let tag = &tag[..tag.find('>').unwrap()];
+            let start = tag.find(&format!(" {}=\"", name)).unwrap() + name.len() + 3;
+            &tag[start..start + tag[start..].find('"').unwrap()]
+        })
+        .collect()
+}
+
+fn numbers(values: Vec<&str>) -> Vec<f64> {
+    values.iter().map(|value| value.parse().unwrap()).collect()
+}
+
+#[test]
+fn dot_draws_each_rule_in_a_cluster_with_references_linked() {
+    let expected = r#"digraph grammar {
+  node [shape=box];
+  subgraph cluster_0 {
+  label="list";
+  r0 [label="list", shape=plaintext];
+  n0 [label="and"];
+  n1 [label="'['"];
+  n0 -> n1 [label="0"];
+  n2 [label="item", shape=ellipse];
+  n0 -> n2 [label="1"];
+  n3 [label="many"];
+  n4 [label="and"];
+  n5 [label="','"];
+  n4 -> n5 [label="0"];
+  n6 [label="item", shape=ellipse];
+  n4 -> n6 [label="1"];
+  n3 -> n4 [label="0"];
+  n0 -> n3 [label="2"];
+  n7 [label="']'"];
+  n0 -> n7 [label="3"];
+  r0 -> n0;
+  }
+  subgraph cluster_1 {
+  label="item";
+  r1 [label="item", shape=plaintext];
+  n8 [label="or"];
+  n9 [label="many1"];
+  n10 [label="[0-9]"];
+  n9 -> n10 [label="0"];
+  n8 -> n9 [label="0"];
+  n11 [label="list", shape=ellipse];
+  n8 -> n11 [label="1"];
+  r1 -> n8;
+  }
+  n2 -> r1 [style=dashed];
+  n6 -> r1 [style=dashed];
+  n11 -> r0 [style=dashed];
+}
+"#;
+    assert_eq!(list().to_dot(), expected);
+}
+
+#[test]
+fn dot_escapes_quotes_in_labels() {
+    let grammar = mpc_peg("t.peg", "t <- 'a<b'").unwrap();
+    assert!(grammar.to_dot().contains(r#"n0 [label="\"a<b\""];"#));
+}
+
+#[test]
+fn railroad_has_a_diagram_per_rule_in_order() {
+    let diagrams = list().to_railroad();
+    let names: Vec<&str> = diagrams.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["list", "item"]);
+    for (name, svg) in &diagrams {
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.contains(&format!("<title>{}</title>", name)));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+    // Terminals are rounded boxes and rule references square ones, left to right along the track
+    let list = &diagrams[0].1;
+    let texts: Vec<&str> = list.split("\">").skip(1).filter_map(|rest| rest.split_once("</text>")).map(|(text, _)| text).collect();
+    assert_eq!(texts, ["[", "item", ",", "item", "]"]);
+    assert_eq!(attributes(list, "<rect", "class"), ["terminal", "nonterminal", "terminal", "nonterminal", "terminal"]);
+    assert_eq!(attributes(list, "<rect", "rx"), ["11", "0", "11", "0", "11"]);
+    let xs = numbers(attributes(list, "<rect", "x"));
+    assert!(xs.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", xs);
+    // The repeated part sits below the track, and the alternative to `[0-9]+` below that
+    let ys = numbers(attributes(list, "<rect", "y"));
+    assert!(ys[0] == ys[1] && ys[0] == ys[4]);
+    assert!(ys[2] > ys[0] && ys[2] == ys[3]);
+    let item = &diagrams[1].1;
+    let ys = numbers(attributes(item, "<rect", "y"));
+    assert!(ys[1] > ys[0]);
+}
+
+#[test]
+fn railroad_boxes_fit_in_the_view_box() {
+    for (_, svg) in list().to_railroad() {
+        let view: Vec<f64> = attributes(&svg, "<svg", "viewBox")[0].split(' ').map(|n| n.parse().unwrap()).collect();
+        let [xs, ys, widths, heights] = ["x", "y", "width", "height"].map(|name| numbers(attributes(&svg, "<rect", name)));
+        for (i, (x, y)) in xs.into_iter().zip(ys).enumerate() {
+            assert!(x >= 0.0 && y >= 0.0 && x + widths[i] <= view[2] && y + heights[i] <= view[3], "{} in {:?}", i, view);
+        }
+    }
+}
+
+#[test]
+fn railroad_of_one_terminal() {
+    let grammar = mpc_peg("t.peg", "t <- 'a<b'").unwrap();
+    let expected = concat!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" class=\"railroad-diagram\" width=\"104\" height=\"62\" viewBox=\"0 0 104 62\">",
+        "<title>t</title><style>path,rect{fill:none;stroke:#000;stroke-width:1.5}rect{fill:#ffc}rect.special{fill:#eee;stroke-dasharray:4 2}",
+        "text{font:14px monospace;text-anchor:middle}text.comment{font-style:italic}</style>",
+        "<path d=\"M 20 21 v 20 M 20 31 h 10\"/>",
+        "<rect class=\"terminal\" x=\"30\" y=\"20\" width=\"44\" height=\"22\" rx=\"11\"/><text x=\"52\" y=\"35\">a&lt;b</text>",
+        "<path d=\"M 74 31 h 10 m 0 -10 v 20\"/></svg>\n",
+    );
+    assert_eq!(grammar.to_railroad(), [(String::from("t"), String::from(expected))]);
+}
@@ -0,0 +1,47 @@
+use mpc::prelude::*;
+
+fn keywords() -> MpcParser {
+    let words = ["break", "continue", "else", "for", "if", "return", "while"];
+    mpc_or(words.iter().map(|word| mpc_and(vec![mpc_string(word), mpc_boundary()], mpcf_fst)).collect())
+}
+
+#[test]
+fn dispatch_picks_the_alternative_for_the_next_character() {
+    let val = mpc_parse("test", "while", &keywords()).unwrap();
+    assert_eq!(val.downcast_ref::<String>().unwrap(), "while");
+}
+
+#[test]
+fn ruled_out_alternatives_are_not_run() {
+    // One step for the choice and three for the "for" alternative it dispatches to
+    let limits = MpcLimits { steps: Some(4), ..MpcLimits::default() };
+    let e = mpc_parse_limited("test", "fox", &keywords(), limits).err().unwrap();
+    assert_eq!(e.kind, MpcErrKind::Parse);
+    let limits = MpcLimits { steps: Some(1), ..MpcLimits::default() };
+    let e = mpc_parse_limited("test", "x", &keywords(), limits).err().unwrap();
+    assert_eq!(e.kind, MpcErrKind::Parse);
+}
+
+#[test]
+fn failure_lists_the_ruled_out_alternatives() {
+    let e = mpc_parse("test", "x", &keywords()).err().unwrap();
+    assert_eq!(e.expected.len(), 7);
+    assert!(e.expected.contains(&"\"while\"".to_string()));
+    let e = mpc_parse("test", "fx", &keywords()).err().unwrap();
+    assert!(e.expected.contains(&"\"for\"".to_string()));
+}
+
+#[test]
+fn ruled_out_alternatives_keep_their_expected_names_and_context() {
+    let parser = mpc_or(vec![mpc_expect(mpc_digits(), "number"), mpca_tag(mpc_char('('), "group")]);
+    let e = mpc_parse("test", "x", &parser).err().unwrap();
+    assert_eq!(e.expected, vec!["'('".to_string(), "number".to_string()]);
+    assert_eq!(e.context, vec!["group".to_string()]);
+}
+
+#[test]
+fn empty_match_still_reports_the_alternatives_before_it() {
+    let parser = mpc_and(vec![mpc_or(vec![mpc_char('a'), mpc_pass()]), mpc_char('b')], mpcf_null);
+    let e = mpc_parse("test", "c", &parser).err().unwrap();
+    assert_eq!(e.expected, vec!["'a'".to_string(), "'b'".to_string()]);
+}
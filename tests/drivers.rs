@@ -0,0 +1,81 @@
+use mpc::prelude::*;
+use std::io::Read;
+
+fn number() -> MpcParser {
+    mpc_many1(mpc_digit(), mpcf_strfold)
+}
+
+fn strings(vals: Vec<MpcVal>) -> Vec<String> {
+    vals.into_iter().map(|val| *val.downcast::<String>().unwrap()).collect()
+}
+
+#[test]
+fn lines_parse_on_their_own_and_bad_ones_are_reported() {
+    let input = "1\n2x\n\n34\r\n5";
+    let (vals, errors) = mpc_parse_lines("log", input.as_bytes(), &number());
+    assert_eq!(strings(vals), ["1", "34", "5"]);
+    // Each error is where it is in the whole input; a line has to be matched to its end
+    let positions: Vec<_> = errors.iter().map(|e| (e.state.pos, e.state.row, e.state.col)).collect();
+    assert_eq!(positions, [(3, 1, 1), (5, 2, 0)]);
+    assert!(errors.iter().all(|e| e.filename == "log"));
+}
+
+// A reader that can't be read
+struct Failing;
+
+impl std::io::Read for Failing {
+    fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::new(std::io::ErrorKind::Other, "disk gone"))
+    }
+}
+
+#[test]
+fn lines_end_at_a_read_error() {
+    let reader = std::io::BufReader::new("1\n2\n".as_bytes().chain(Failing));
+    let (vals, errors) = mpc_parse_lines("log", reader, &number());
+    assert_eq!(strings(vals), ["1", "2"]);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, MpcErrKind::Io);
+}
+
+#[test]
+fn file_is_read_and_parsed_with_its_path_as_filename() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("drivers_numbers.txt");
+    std::fs::write(&path, "123x").unwrap();
+    let val = mpc_parse_file(&path, &number()).unwrap();
+    assert_eq!(*val.downcast::<String>().unwrap(), "123");
+    let e = mpc_parse_file(&path, &mpc_char('1').then(mpc_char('3'))).err().unwrap();
+    assert_eq!(e.filename, path.display().to_string());
+    assert_eq!((e.state.pos, e.received), (1, '2'));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn missing_file_is_an_io_error() {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("drivers_missing.txt");
+    let e = mpc_parse_file(&path, &number()).err().unwrap();
+    assert_eq!(e.kind, MpcErrKind::Io);
+    assert_eq!(e.filename, path.display().to_string());
+}
+
+#[test]
+fn partial_gives_the_state_and_the_rest_of_the_input() {
+    let (val, state, rest) = mpc_parse_partial("test", "12\n3é rest", &mpc_tok(number()).many()).unwrap();
+    assert_eq!(*val.downcast::<String>().unwrap(), "123");
+    assert_eq!((state.pos, state.row, state.col), (4, 1, 1));
+    assert_eq!(rest, "é rest");
+    let e = mpc_parse_partial("test", "x", &number()).err().unwrap();
+    assert_eq!(e.received, 'x');
+}
+
+#[test]
+fn startwith_matches_only_at_the_start_of_the_input() {
+    let parser = mpc_startwith(mpc_char('a'));
+    assert!(mpc_parse("test", "ab", &parser).is_ok());
+    let later = mpc_and(vec![mpc_char('b'), mpc_startwith(mpc_char('a'))], mpcf_strfold);
+    assert!(mpc_parse("test", "ba", &later).is_err());
+    // An `a` anywhere but first is left for the next parser
+    let either = mpc_many(mpc_startwith(mpc_char('a')) | mpc_char('b'), mpcf_strfold);
+    let (val, _, rest) = mpc_parse_partial("test", "abba", &either).unwrap();
+    assert_eq!((*val.downcast::<String>().unwrap(), rest), (String::from("abb"), "a"));
+}
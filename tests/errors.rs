@@ -0,0 +1,79 @@
+use mpc::error::MpcDiagnostic;
+use mpc::prelude::*;
+
+#[test]
+fn reversed_range_is_left_out_of_expected() {
+    let parser = mpc_or(vec![mpc_range('z', 'a'), mpc_char('1')]);
+    let e = mpc_parse("test", "x", &parser).err().unwrap();
+    assert_eq!(e.expected, vec!["'1'".to_string()]);
+    assert_eq!(e.message(), "expected '1' at 'x'");
+}
+
+#[test]
+fn touching_ranges_are_merged() {
+    let parser = mpc_or(vec![mpc_range('a', 'f'), mpc_range('g', 'z'), mpc_char('0')]);
+    let e = mpc_parse("test", "!", &parser).err().unwrap();
+    assert_eq!(e.expected, vec!["'0'".to_string(), "character between 'a' and 'z'".to_string()]);
+}
+
+fn zero() -> MpcVal {
+    Box::new("0".to_string())
+}
+
+// statements : (digits ';')*, where a bad statement is skipped up to its ';'
+fn statements() -> MpcParser {
+    let statement = mpc_and(vec![mpc_digits(), mpc_char(';')], mpcf_fst);
+    let recovering = mpc_recover_with(statement, mpc_char(';'), zero);
+    mpc_and(vec![mpc_many(recovering, mpcf_strfold), mpc_eoi()], mpcf_fst)
+}
+
+#[test]
+fn all_errors_collects_every_recovered_error() {
+    let errors = mpc_parse_all_errors("test", "1;x;2;y;3;", &statements()).err().unwrap();
+    let columns: Vec<_> = errors.iter().map(|e| e.state.col).collect();
+    assert_eq!(columns, vec![2, 6]);
+    let received: Vec<_> = errors.iter().map(|e| e.received).collect();
+    assert_eq!(received, vec!['x', 'y']);
+}
+
+#[test]
+fn all_errors_ends_with_the_failure_it_could_not_recover_from() {
+    let errors = mpc_parse_all_errors("test", "1;x;2", &statements()).err().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].state.col, 2);
+    assert_eq!(errors[1].state.col, 5);
+}
+
+#[test]
+fn all_errors_gives_the_value_when_nothing_went_wrong() {
+    let val = mpc_parse_all_errors("test", "1;2;", &statements()).ok().unwrap();
+    assert_eq!(val.downcast_ref::<String>().unwrap(), "12");
+}
+
+fn diagnostic(input: &str) -> MpcDiagnostic {
+    let parser = mpc_and(vec![mpc_string("ab"), mpc_char(';')], mpcf_strfold);
+    MpcDiagnostic::from(mpc_parse("test", input, &parser).err().unwrap())
+}
+
+#[test]
+fn diagnostic_spans_the_character_received() {
+    let d = diagnostic("ab\u{e9}");
+    assert_eq!((d.code.as_str(), d.filename.as_str()), ("mpc::expected", "test"));
+    assert_eq!(d.message, "expected ';' at '\u{e9}'");
+    assert_eq!((d.span.start.pos, d.span.start.row, d.span.start.col), (2, 0, 2));
+    assert_eq!((d.span.end.pos, d.span.end.row, d.span.end.col), (4, 0, 3));
+}
+
+#[test]
+fn diagnostic_at_a_newline_ends_on_the_next_line() {
+    let d = diagnostic("ab\n;");
+    assert_eq!((d.span.start.pos, d.span.start.row, d.span.start.col), (2, 0, 2));
+    assert_eq!((d.span.end.pos, d.span.end.row, d.span.end.col), (3, 1, 0));
+}
+
+#[test]
+fn diagnostic_at_the_end_of_input_is_empty() {
+    let d = diagnostic("ab");
+    assert_eq!(d.span.start, d.span.end);
+    assert_eq!(d.span.start.pos, 2);
+}
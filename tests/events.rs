@@ -0,0 +1,33 @@
+use mpc::prelude::*;
+
+// record : digits ';'
+fn records() -> MpcParser {
+    mpc_many(mpca_tag(mpc_and(vec![mpc_digits(), mpc_char(';')], mpcf_strfold), "record"), mpcf_null)
+}
+
+fn events_until_failure(parser: &MpcParser, input: &str) -> Vec<MpcEvent> {
+    let mut events = Vec::new();
+    assert!(mpc_parse_events("test", input, parser, |event| events.push(event)).is_err());
+    events
+}
+
+#[test]
+fn items_of_an_outermost_repetition_are_reported_as_the_parse_goes() {
+    let parser = mpc_and(vec![records(), mpc_eoi()], mpcf_null);
+    let events = events_until_failure(&parser, "1;22;x");
+    let tags: Vec<&str> = events.iter().map(|event| event.tag()).collect();
+    assert_eq!(tags, vec!["record", "record"]);
+    assert_eq!(events[1].span().start.pos, 2);
+}
+
+#[test]
+fn events_that_could_be_taken_back_are_held_until_success() {
+    let tagged = mpc_and(vec![mpca_tag(records(), "file"), mpc_eoi()], mpcf_null);
+    assert!(events_until_failure(&tagged, "1;22;x").is_empty());
+    let alternative = mpc_or(vec![mpc_and(vec![records(), mpc_eoi()], mpcf_null), mpc_string("x")]);
+    assert!(events_until_failure(&alternative, "1;22;x").is_empty());
+    let mut events = Vec::new();
+    mpc_parse_events("test", "1;22;", &tagged, |event| events.push(event)).unwrap();
+    let tags: Vec<&str> = events.iter().map(|event| event.tag()).collect();
+    assert_eq!(tags, vec!["file", "record", "record", "file"]);
+}
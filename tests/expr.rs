@@ -0,0 +1,59 @@
+use mpc::prelude::*;
+
+// The tree as an s-expression: leaves as their contents, operators as `(op operands..)`
+fn sexpr(ast: &MpcAst) -> String {
+    if ast.children.is_empty() {
+        return ast.contents.clone();
+    }
+    let operands: Vec<String> = ast.children.iter().map(|child| sexpr(child)).collect();
+    format!("({} {})", ast.contents, operands.join(" "))
+}
+
+fn parse_sexpr(parser: &MpcParser, input: &str) -> String {
+    let val = mpc_parse("test", input, parser).unwrap();
+    sexpr(&val.downcast::<MpcAst>().unwrap())
+}
+
+fn arithmetic() -> MpcParser {
+    mpc_expr(mpc_digits(), vec![
+        MpcOperator::Prefix(mpc_char('-'), 3),
+        MpcOperator::Postfix(mpc_char('!'), 4),
+        MpcOperator::Infix(mpc_char('+'), 1, MpcAssoc::Left),
+        MpcOperator::Infix(mpc_char('*'), 2, MpcAssoc::Left),
+        MpcOperator::Infix(mpc_char('^'), 5, MpcAssoc::Right),
+        MpcOperator::Infix(mpc_char('='), 0, MpcAssoc::None),
+    ])
+}
+
+#[test]
+fn precedence_and_associativity() {
+    let parser = mpc_endwith(arithmetic());
+    assert_eq!(parse_sexpr(&parser, "1+2*3"), "(+ 1 (* 2 3))");
+    assert_eq!(parse_sexpr(&parser, "1+2+3"), "(+ (+ 1 2) 3)");
+    assert_eq!(parse_sexpr(&parser, "2^3^4"), "(^ 2 (^ 3 4))");
+    assert_eq!(parse_sexpr(&parser, "-1*2!"), "(* (- 1) (! 2))");
+    assert_eq!(parse_sexpr(&parser, "1+2=3"), "(= (+ 1 2) 3)");
+}
+
+#[test]
+fn chained_non_associative_operator_is_an_error_at_the_second() {
+    let parser = mpc_endwith(arithmetic());
+    let e = mpc_parse("test", "1=2=3", &parser).err().unwrap();
+    assert_eq!(e.state.col, 3);
+    assert!(e.message().contains("non-associative"), "{}", e.message());
+}
+
+#[test]
+fn chained_non_associative_operator_can_be_backtracked_from() {
+    let parser = mpc_or(vec![mpc_endwith(arithmetic()), mpc_string("1=2=3")]);
+    let val = mpc_parse("test", "1=2=3", &parser).unwrap();
+    assert_eq!(val.downcast_ref::<String>().unwrap(), "1=2=3");
+}
+
+#[test]
+fn prefix_operator_without_operand_falls_back_to_the_atom() {
+    let atom = mpc_or(vec![mpc_string("-x"), mpc_digits()]);
+    let parser = mpc_endwith(mpc_expr(atom, vec![MpcOperator::Prefix(mpc_char('-'), 1)]));
+    assert_eq!(parse_sexpr(&parser, "-x"), "-x");
+    assert_eq!(parse_sexpr(&parser, "-1"), "(- 1)");
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "color")]
+
+use mpc::error::{MpcDiagnostic, MpcRelated, MpcSeverity};
+use mpc::prelude::*;
+
+// The rendering with its color codes taken out
+fn plain(out: Vec<u8>) -> String {
+    let out = String::from_utf8(out).unwrap();
+    let mut plain = String::new();
+    let mut rest = out.as_str();
+    while let Some(i) = rest.find('\x1b') {
+        plain.push_str(&rest[..i]);
+        rest = &rest[i + rest[i..].find('m').unwrap() + 1..];
+    }
+    plain + rest
+}
+
+#[test]
+fn error_renders_with_its_suggestion_as_a_note() {
+    let parser = mpc_and(vec![mpc_sym("("), mpc_or(vec![mpc_string("let"), mpc_string("fn")])], mpcf_snd);
+    let e = mpc_parse("input.txt", "(\n  lte", &parser).err().unwrap();
+    let mut out = Vec::new();
+    e.write_fancy(&mut out).unwrap();
+    assert!(out.starts_with(b"\x1b[1;31merror"));
+    let expected = [
+        "error[mpc::unknown_keyword]: unknown keyword 'lte', did you mean 'let'?",
+        " --> input.txt:2:4",
+        "  |",
+        "2 |   lte",
+        "  |    ^",
+        "  |",
+        "  = help: did you mean 'let'?",
+        "",
+    ];
+    assert_eq!(plain(out), expected.join("\n"));
+}
+
+#[test]
+fn diagnostic_renders_every_line_of_its_spans() {
+    let source = "let x = (1 +\n  2;\nfoo";
+    let state = |pos, row, col| MpcState { pos, row, col, ..MpcState::default() };
+    let d = MpcDiagnostic {
+        severity: MpcSeverity::Warning,
+        code: String::from("demo::unclosed"),
+        filename: String::from("demo.txt"),
+        span: MpcSpan { start: state(8, 0, 8), end: state(16, 1, 3) },
+        message: String::from("unclosed group"),
+        related: vec![MpcRelated { span: MpcSpan { start: state(18, 2, 0), end: state(21, 2, 3) }, message: String::from("here") }],
+    };
+    let mut out = Vec::new();
+    d.write_fancy(&mut out, source).unwrap();
+    assert!(out.starts_with(b"\x1b[1;33mwarning"));
+    let expected = [
+        "warning[demo::unclosed]: unclosed group",
+        " --> demo.txt:1:9",
+        "  |",
+        "1 | let x = (1 +",
+        "  |         ^^^^",
+        "2 |   2;",
+        "  | ^^^",
+        "  ...",
+        "3 | foo",
+        "  | --- here",
+        "",
+    ];
+    assert_eq!(plain(out), expected.join("\n"));
+}
@@ -0,0 +1,42 @@
+use mpc::prelude::*;
+use mpc::parser::MpcParserType;
+
+// Names of the parsers a sequence or choice runs directly
+fn children(parser: &MpcParser) -> Vec<&str> {
+    match parser.parser_type {
+        MpcParserType::And(ref parsers, _, _) | MpcParserType::Or(ref parsers, _) => parsers.iter().map(|p| p.name.as_str()).collect(),
+        _ => panic!("{} is neither a sequence nor a choice", parser.name),
+    }
+}
+
+#[test]
+fn sequences_with_the_same_associative_fold_are_spliced() {
+    let parser = mpc_char('a') + mpc_char('b') + mpc_char('c');
+    assert_eq!(children(&parser), ["char:a", "char:b", "char:c"]);
+    let val = mpc_parse("test", "abc", &parser).unwrap();
+    assert_eq!(val.downcast_ref::<String>().unwrap(), "abc");
+    let parser = mpc_and_assoc(vec![mpc_char('a'), mpc_and_assoc(vec![mpc_char('b'), mpc_char('c')], MpcAssocFold::Null)], MpcAssocFold::Null);
+    assert_eq!(children(&parser), ["char:a", "char:b", "char:c"]);
+}
+
+#[test]
+fn sequences_with_other_folds_are_kept() {
+    let parser = (mpc_char('a') << mpc_char('b')) + mpc_char('c');
+    assert_eq!(children(&parser), ["and", "char:c"]);
+    let val = mpc_parse("test", "abc", &parser).unwrap();
+    assert_eq!(val.downcast_ref::<String>().unwrap(), "ac");
+    // mpc_and can't tell what its fold does, so it splices nothing
+    let parser = mpc_and(vec![mpc_and(vec![mpc_char('a'), mpc_char('b')], mpcf_strfold), mpc_char('c')], mpcf_strfold);
+    assert_eq!(children(&parser), ["and", "char:c"]);
+}
+
+#[test]
+fn renamed_sequences_and_choices_keep_their_names() {
+    let mut pair = mpc_char('a') + mpc_char('b');
+    pair.name = "pair".to_string();
+    assert_eq!(children(&(pair + mpc_char('c'))), ["pair", "char:c"]);
+    let mut digit = mpc_or(vec![mpc_char('0'), mpc_char('1')]);
+    digit.name = "digit".to_string();
+    let parser = mpc_or(vec![digit, mpc_or(vec![mpc_char('x'), mpc_char('y')])]);
+    assert_eq!(children(&parser), ["digit", "char:x", "char:y"]);
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "derive")]
+
+use mpc::prelude::*;
+
+#[derive(MpcFromAst, Debug, PartialEq)]
+#[mpc(tag = "call")]
+struct Call {
+    name: String,
+    args: Vec<Expr>,
+}
+
+#[derive(MpcFromAst, Debug, PartialEq)]
+enum Expr {
+    Number(i64),
+    #[mpc(tag = "word")]
+    Variable(String),
+    Call(Box<Call>),
+}
+
+#[derive(MpcFromAst, Debug, PartialEq)]
+struct Decl {
+    #[mpc(tag = "word")]
+    name: String,
+    #[mpc(tag = "type")]
+    ty: Option<String>,
+    #[mpc(tag = "number")]
+    dims: Vec<u32>,
+}
+
+fn word() -> MpcParser {
+    mpca_tag(mpc_many1(mpc_alpha(), mpcf_strfold), "word")
+}
+
+fn number() -> MpcParser {
+    mpca_tag(mpc_digits(), "number")
+}
+
+// expr : call | word | number, where call : word '(' expr (',' expr)* ')'
+fn expr() -> MpcParser {
+    let call = mpca_and(vec![word(), mpc_char('('), mpca_sepby(mpc_lazy(expr), mpc_char(',')), mpc_char(')')]);
+    mpca_or(vec![mpca_tag(call, "call"), word(), number()])
+}
+
+// decl : "let " word (':' type)? ('[' number ']')*
+fn decl() -> MpcParser {
+    let ty = mpca_and(vec![mpc_char(':'), mpca_tag(mpc_many1(mpc_alpha(), mpcf_strfold), "type")]);
+    let dim = mpca_and(vec![mpc_char('['), number(), mpc_char(']')]);
+    mpca_tag(mpca_and(vec![mpc_string("let "), word(), mpca_or(vec![ty, mpc_pass()]), mpca_many(dim)]), "decl")
+}
+
+fn tree(parser: &MpcParser, input: &str) -> MpcAst {
+    *mpc_parse("test", input, parser).unwrap().downcast::<MpcAst>().unwrap()
+}
+
+#[test]
+fn struct_takes_its_fields_from_the_children() {
+    let call = Call::from_ast(&tree(&expr(), "f(x,1)")).unwrap();
+    assert_eq!(call, Call { name: "f".to_string(), args: vec![Expr::Variable("x".to_string()), Expr::Number(1)] });
+}
+
+#[test]
+fn enum_picks_the_variant_by_tag() {
+    let val = Expr::from_ast(&tree(&expr(), "g(h(2))")).unwrap();
+    let inner = Call { name: "h".to_string(), args: vec![Expr::Number(2)] };
+    let outer = Call { name: "g".to_string(), args: vec![Expr::Call(Box::new(inner))] };
+    assert_eq!(val, Expr::Call(Box::new(outer)));
+    assert_eq!(Expr::from_ast(&tree(&expr(), "42")).unwrap(), Expr::Number(42));
+}
+
+#[test]
+fn option_and_vec_fields_take_what_there_is() {
+    let full = Decl::from_ast(&tree(&decl(), "let grid:int[3][4]")).unwrap();
+    assert_eq!(full, Decl { name: "grid".to_string(), ty: Some("int".to_string()), dims: vec![3, 4] });
+    let bare = Decl::from_ast(&tree(&decl(), "let x")).unwrap();
+    assert_eq!(bare, Decl { name: "x".to_string(), ty: None, dims: vec![] });
+}
+
+#[test]
+fn mismatched_tree_is_an_error_saying_where() {
+    let e = Call::from_ast(&tree(&expr(), "7")).unwrap_err();
+    assert_eq!(e, "1:1: expected a call node, in number node");
+    let e = Expr::from_ast(&MpcAst::new("type", "int")).unwrap_err();
+    assert_eq!(e, "1:1: expected one of number, word, call, in type node");
+    let e = Decl::from_ast(&tree(&mpca_tag(number(), "decl"), "5")).unwrap_err();
+    assert_eq!(e, "1:1: missing word child for field name, in decl node");
+    let e = Decl::from_ast(&tree(&decl(), "let x[99999999999]")).unwrap_err();
+    assert_eq!(e, r#"1:7: expected u32, found "99999999999", in number node, for field dims"#);
+}
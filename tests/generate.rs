@@ -0,0 +1,18 @@
+use mpc::generate::MpcGenerator;
+use mpc::prelude::*;
+
+#[test]
+fn rule_without_a_finite_match_generates_nothing() {
+    let grammar = mpc_peg("g", "a <- '(' a ')'").unwrap();
+    assert_eq!(MpcGenerator::with_grammar(&grammar, 1).generate_rule("a"), None);
+}
+
+#[test]
+fn recursion_past_max_depth_stops_at_a_finite_alternative() {
+    let grammar = mpc_peg("g", "a <- '(' b ')' / 'x'\nb <- a / '(' c\nc <- '(' c").unwrap();
+    let mut generator = MpcGenerator::with_grammar(&grammar, 1);
+    for _ in 0..20 {
+        let input = generator.generate_rule("a").unwrap();
+        assert!(mpc_parse_grammar("test", &input, &grammar, "a").is_ok(), "{:?}", input);
+    }
+}
@@ -0,0 +1,51 @@
+use mpc::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+// value : digits | '(' value (',' value)* ')'
+fn value() -> MpcParser {
+    BUILDS.fetch_add(1, Ordering::SeqCst);
+    let list = mpc_and(vec![mpc_char('('), mpc_sepby(mpc_lazy(value), mpc_char(','), mpcf_strfold), mpc_char(')')], mpcf_strfold);
+    mpc_or(vec![mpc_digits(), list])
+}
+
+#[test]
+fn lazy_parser_is_built_once_for_every_level_of_nesting() {
+    let parser = mpc_lazy(value);
+    let input = format!("{}1{}", "(".repeat(8), ")".repeat(8));
+    let val = mpc_parse("test", &input, &parser).unwrap();
+    assert_eq!(val.downcast_ref::<String>().unwrap(), &input);
+    assert!(mpc_parse_memo("test", &input, &parser, MpcLimits::default()).is_ok());
+    assert_eq!(BUILDS.load(Ordering::SeqCst), 1);
+    // Nothing is kept between lazy parsers made apart
+    let other = mpc_lazy(value);
+    assert!(mpc_parse("test", "(1,(2,3))", &other).is_ok());
+    assert!(mpc_parse("test", "(1,", &other).is_err());
+    assert_eq!(BUILDS.load(Ordering::SeqCst), 2);
+}
+
+static LIST_BUILDS: AtomicUsize = AtomicUsize::new(0);
+static ITEM_BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+// list : '[' item* ']'
+fn list() -> MpcParser {
+    LIST_BUILDS.fetch_add(1, Ordering::SeqCst);
+    mpc_and(vec![mpc_char('['), mpc_many(mpc_lazy(item), mpcf_strfold), mpc_char(']')], mpcf_strfold)
+}
+
+// item : 'x' | list
+fn item() -> MpcParser {
+    ITEM_BUILDS.fetch_add(1, Ordering::SeqCst);
+    mpc_or(vec![mpc_string("x"), mpc_lazy(list)])
+}
+
+#[test]
+fn mutually_recursive_lazy_parsers_are_each_built_once() {
+    let parser = mpc_lazy(list);
+    let depth = 1_000;
+    let input = format!("{}x{}", "[".repeat(depth), "]".repeat(depth));
+    assert!(mpc_parse("test", &input, &parser).is_ok());
+    assert!(mpc_parse_memo("test", "[x[x[]]x]", &parser, MpcLimits::default()).is_ok());
+    assert_eq!((LIST_BUILDS.load(Ordering::SeqCst), ITEM_BUILDS.load(Ordering::SeqCst)), (1, 1));
+}
@@ -0,0 +1,69 @@
+use mpc::prelude::*;
+
+fn digit_value(val: MpcVal) -> MpcVal {
+    Box::new(val.downcast::<String>().unwrap().parse::<i64>().unwrap())
+}
+
+fn subtract(_: i32, mut xs: Vec<MpcVal>) -> MpcVal {
+    let b = *xs.pop().unwrap().downcast::<i64>().unwrap();
+    xs.pop();
+    let a = *xs.pop().unwrap().downcast::<i64>().unwrap();
+    Box::new(a - b)
+}
+
+// e : e '-' d | d, which only comes out left-associative if the rule grows from its seed
+fn difference() -> &'static MpcGrammar {
+    static GRAMMAR: std::sync::OnceLock<MpcGrammar> = std::sync::OnceLock::new();
+    GRAMMAR.get_or_init(|| {
+        let mut grammar = MpcGrammar::new();
+        grammar.define("e", mpc_or(vec![mpc_and(vec![mpc_rule("e"), mpc_char('-'), mpc_rule("d")], subtract), mpc_rule("d")]));
+        grammar.define("d", mpc_apply(mpc_digit(), digit_value));
+        grammar
+    })
+}
+
+// Parses `string` with rule `start` of `grammar`, caching results as mpc_parse_memo does
+fn parse_memo(string: &str, grammar: &'static MpcGrammar, start: &str) -> MpcResult {
+    let mut input = MpcInput::owned("test", string.to_string());
+    input.grammar = Some(grammar);
+    input.memoize = true;
+    mpc_parse_owned(&mut input, &mpc_rule(start))
+}
+
+#[test]
+fn left_recursive_rule_is_left_associative() {
+    let grammar = difference();
+    for result in [mpc_parse_grammar("test", "9-4-3", grammar, "e"), parse_memo("9-4-3", grammar, "e")] {
+        assert_eq!(*result.unwrap().downcast::<i64>().unwrap(), 2);
+    }
+    for result in [mpc_parse_grammar("test", "7", grammar, "e"), parse_memo("7", grammar, "e")] {
+        assert_eq!(*result.unwrap().downcast::<i64>().unwrap(), 7);
+    }
+}
+
+#[test]
+fn left_recursive_rule_fails_where_no_seed_matches() {
+    let grammar = difference();
+    for result in [mpc_parse_grammar("test", "-4", grammar, "e"), parse_memo("-4", grammar, "e")] {
+        let e = result.err().unwrap();
+        assert_eq!(e.state.pos, 0);
+        assert!(e.expected.iter().any(|x| x.contains("between '0' and '9'")), "{:?}", e.expected);
+    }
+}
+
+#[test]
+fn left_recursive_peg_rule_matches_as_far_as_it_can() {
+    static GRAMMAR: std::sync::OnceLock<MpcGrammar> = std::sync::OnceLock::new();
+    let grammar = GRAMMAR.get_or_init(|| {
+        let mut grammar = mpc_peg("test.peg", "e <- e '-' d / d\nd <- [0-9]").unwrap();
+        grammar.define("whole", mpc_and(vec![mpc_rule("e"), mpc_eoi()], mpcf_fst));
+        grammar
+    });
+    for result in [mpc_parse_grammar("test", "9-4-3", grammar, "whole"), parse_memo("9-4-3", grammar, "whole")] {
+        assert_eq!(result.unwrap().downcast_ref::<String>().unwrap(), "9-4-3");
+    }
+    // The last '-' isn't followed by a digit, so growing stops before it
+    for result in [mpc_parse_grammar("test", "9-4-", grammar, "whole"), parse_memo("9-4-", grammar, "whole")] {
+        assert_eq!(result.err().unwrap().state.pos, 4);
+    }
+}
@@ -0,0 +1,127 @@
+use mpc::prelude::*;
+
+// group : 'x' | '(' group ')'
+fn group() -> MpcParser {
+    mpc_or(vec![mpc_char('x'), mpc_and(vec![mpc_char('('), mpc_lazy(group), mpc_char(')')], mpcf_snd)])
+}
+
+fn nested(depth: usize) -> String {
+    format!("{}x{}", "(".repeat(depth), ")".repeat(depth))
+}
+
+#[test]
+fn deep_input_parses_without_recursing() {
+    let input = nested(10_000);
+    let grammar = mpc_peg("group.peg", "group <- 'x' / '(' group ')'").unwrap();
+    assert!(mpc_parse("test", &input, &group()).is_ok());
+    assert!(mpc_parse_grammar("test", &input, &grammar, "group").is_ok());
+}
+
+#[test]
+fn deep_input_parses_without_recursing_when_memoizing() {
+    assert!(mpc_parse_memo("test", &nested(10_000), &group(), MpcLimits::default()).is_ok());
+}
+
+#[test]
+fn depth_limit_refuses_deeper_input() {
+    // Each group nests the choice, the sequence and the lazy parser around the next one
+    let limits = MpcLimits { depth: Some(60), ..MpcLimits::default() };
+    assert!(mpc_parse_limited("test", &nested(19), &group(), limits).is_ok());
+    let e = mpc_parse_limited("test", &nested(20), &group(), limits).err().unwrap();
+    assert_eq!(e.kind, MpcErrKind::ResourceExhausted);
+    assert!(e.failure.contains("more than 60 parsers deep"), "{}", e.failure);
+    assert_eq!(MpcLimits::default().depth, None);
+}
+
+#[test]
+fn deep_input_parses_on_a_small_stack() {
+    // The 2MB stack of a spawned thread or a tokio worker, rather than a main thread's 8MB
+    let parse = std::thread::Builder::new().stack_size(2 << 20).spawn(|| {
+        let input = nested(30_000);
+        let parser = group();
+        assert!(mpc_parse("test", &input, &parser).is_ok());
+        assert!(mpc_parse_memo("test", &input, &parser, MpcLimits::default()).is_ok());
+        assert!(mpc_parse_events("test", &input, &parser, |_| {}).is_ok());
+        let mut session = MpcSession::new("test", &parser, MpcLimits::default());
+        assert!(matches!(session.feed(input.as_bytes()), MpcFeed::Done(MpcResult::Ok(_))));
+        let records: Vec<_> = mpc_iterate("test", input.as_bytes(), &parser, MpcLimits::default()).collect();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].is_ok());
+    });
+    parse.unwrap().join().unwrap();
+}
+
+#[test]
+fn memoizing_and_streaming_parses_keep_to_limits() {
+    let limits = MpcLimits { depth: Some(60), ..MpcLimits::default() };
+    let exhausted = |result: MpcResult| matches!(result, MpcResult::Err(e) if e.kind == MpcErrKind::ResourceExhausted);
+    assert!(mpc_parse_memo("test", &nested(19), &group(), limits).is_ok());
+    assert!(exhausted(mpc_parse_memo("test", &nested(20), &group(), limits)));
+
+    let parser = group();
+    let mut session = MpcSession::new("test", &parser, limits);
+    assert!(matches!(session.feed(nested(19).as_bytes()), MpcFeed::Done(MpcResult::Ok(_))));
+    match session.feed(nested(20).as_bytes()) {
+        MpcFeed::Done(result) => assert!(exhausted(result)),
+        MpcFeed::Incomplete => panic!("nesting past the limit waited for more input"),
+    }
+
+    let input = format!("{}{}", nested(19), nested(20));
+    let mut records = mpc_iterate("test", input.as_bytes(), &parser, limits);
+    assert!(records.next().unwrap().is_ok());
+    let e = records.next().unwrap().err().unwrap();
+    assert_eq!(e.kind, MpcErrKind::ResourceExhausted);
+    assert!(records.next().is_none());
+}
+
+fn out_of_steps(result: MpcResult) -> bool {
+    matches!(result, MpcResult::Err(e) if e.kind == MpcErrKind::ResourceExhausted && e.failure.contains("gave up after"))
+}
+
+#[test]
+fn step_limit_stops_backtracking() {
+    let parser = mpc_many(mpc_or(vec![mpc_string("ab"), mpc_char('a')]), mpcf_strfold);
+    let input = "a".repeat(100);
+    let limits = |steps| MpcLimits { steps: Some(steps), ..MpcLimits::default() };
+    assert!(mpc_parse_limited("test", &input, &parser, limits(10_000)).is_ok());
+    assert!(out_of_steps(mpc_parse_limited("test", &input, &parser, limits(50))));
+    assert!(out_of_steps(mpc_parse_memo("test", &input, &parser, limits(50))));
+}
+
+#[test]
+fn step_limit_counts_characters_consumed_in_one_scan() {
+    let limits = MpcLimits { steps: Some(3), ..MpcLimits::default() };
+    let digits = "7".repeat(100_000);
+    let text = format!("{};", digits);
+    let parsers = [
+        (mpc_many(mpc_digit(), mpcf_strfold), &digits),
+        (mpc_many1(mpc_oneof("0123456789"), mpcf_strfold), &digits),
+        (mpc_skip_many(mpc_digit()), &digits),
+        (mpc_take_until(mpc_char(';')), &text),
+        (mpc_string_until(";"), &text),
+        (mpc_rest(), &digits),
+        (mpc_rest_of_line(), &digits),
+    ];
+    for (parser, input) in &parsers {
+        assert!(out_of_steps(mpc_parse_limited("test", input, parser, limits)), "{}", parser.name);
+        assert!(mpc_parse("test", input, parser).is_ok(), "{}", parser.name);
+    }
+    let enough = MpcLimits { steps: Some(100_010), ..MpcLimits::default() };
+    assert!(mpc_parse_limited("test", &digits, &mpc_many(mpc_digit(), mpcf_strfold), enough).is_ok());
+}
+
+#[test]
+fn cancelled_parse_stops_with_an_error() {
+    let parser = mpc_many(mpc_or(vec![mpc_string("ab"), mpc_char('a')]), mpcf_strfold);
+    let input = "a".repeat(100_000);
+    let cancel = std::sync::atomic::AtomicBool::new(false);
+    assert!(mpc_parse_cancellable("test", &input, &parser, &cancel).is_ok());
+    // The flag is only looked at every 1024 steps, which this parse goes well past
+    cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+    let e = mpc_parse_cancellable("test", &input, &parser, &cancel).err().unwrap();
+    assert_eq!(e.kind, MpcErrKind::Cancelled);
+    assert!(e.state.pos < 1024, "{}", e.state.pos);
+    // So does one scan over a run of a character class
+    let e = mpc_parse_cancellable("test", &input, &mpc_many(mpc_char('a'), mpcf_strfold), &cancel).err().unwrap();
+    assert_eq!(e.kind, MpcErrKind::Cancelled);
+}
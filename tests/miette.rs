@@ -0,0 +1,52 @@
+#![cfg(feature = "miette")]
+
+use miette::Diagnostic;
+use mpc::prelude::*;
+
+fn keyword_error() -> MpcErr {
+    let parser = mpc_and(vec![mpc_sym("("), mpc_or(vec![mpc_string("let"), mpc_string("fn")])], mpcf_snd);
+    mpc_parse("input.txt", "(\n  lte", &parser).err().unwrap()
+}
+
+#[test]
+fn error_reports_its_code_label_and_help() {
+    let e = keyword_error();
+    assert_eq!(e.code().unwrap().to_string(), "mpc::unknown_keyword");
+    assert_eq!(e.help().unwrap().to_string(), "did you mean 'let'?");
+    let labels: Vec<_> = e.labels().unwrap().collect();
+    assert_eq!(labels.len(), 1);
+    assert_eq!(labels[0].label(), Some("expected \"let\""));
+    assert_eq!((labels[0].offset(), labels[0].len()), (5, 1));
+    let source = e.source_code().unwrap().read_span(&labels[0].inner().clone(), 0, 0).unwrap();
+    assert_eq!(std::str::from_utf8(source.data()).unwrap(), "  lte");
+    assert_eq!((source.line(), source.column()), (1, 0));
+}
+
+#[test]
+fn io_error_has_no_source_to_point_into() {
+    let e = mpc_parse_file("missing/file.txt", &mpc_any()).err().unwrap();
+    assert!(e.source_code().is_none());
+    assert!(e.labels().is_none());
+    assert_eq!(e.code().unwrap().to_string(), "mpc::io");
+}
+
+#[test]
+fn error_renders_through_a_miette_handler() {
+    let e = keyword_error();
+    let mut out = String::new();
+    miette::NarratableReportHandler::new().render_report(&mut out, &e).unwrap();
+    let expected = [
+        "input.txt:2:4: error: unknown keyword 'lte', did you mean 'let'?",
+        "  lte",
+        "   ^",
+        "    Diagnostic severity: error",
+        "Begin snippet for input.txt starting at line 2, column 1",
+        "",
+        "snippet line 2:   lte",
+        "    label at line 2, column 4: expected \"let\"",
+        "diagnostic help: did you mean 'let'?",
+        "diagnostic code: mpc::unknown_keyword",
+        "",
+    ];
+    assert_eq!(out, expected.join("\n"));
+}
@@ -0,0 +1,76 @@
+use mpc::prelude::*;
+
+fn s(text: &str) -> MpcParser {
+    mpc_string(text)
+}
+
+// The text a parser's value holds
+fn text(parser: &MpcParser, input: &str) -> Option<String> {
+    let val = mpc_parse("test", input, parser).ok()?;
+    Some(*val.downcast::<String>().unwrap())
+}
+
+#[test]
+#[allow(clippy::precedence)]  // Leaving the parentheses out is what's being tested
+fn operators_follow_rust_precedence() {
+    // `+` binds tighter than `>>`, and both tighter than `|`: ((a + b) >> c) | d
+    let parser = s("a") + s("b") >> s("c") | s("d");
+    assert_eq!(text(&parser, "abc").as_deref(), Some("c"));
+    assert_eq!(text(&parser, "d").as_deref(), Some("d"));
+    assert_eq!(text(&parser, "ad"), None);
+    assert_eq!(text(&parser, "ac"), None);
+    // a << (b + c)
+    let parser = s("a") << s("b") + s("c");
+    assert_eq!(text(&parser, "abc").as_deref(), Some("a"));
+    assert_eq!(text(&parser, "ab"), None);
+}
+
+#[test]
+fn parentheses_group_operators() {
+    let parser = (s("a") | s("b")) + s("c");
+    assert_eq!(text(&parser, "bc").as_deref(), Some("bc"));
+    let parser = s("a") + (s("b") >> s("c") | s("d")) + s("e");
+    assert_eq!(text(&parser, "abce").as_deref(), Some("ace"));
+    assert_eq!(text(&parser, "ade").as_deref(), Some("ade"));
+    let parser = (s("[") >> (s("x") | s("y")) << s("]")) + s(";");
+    assert_eq!(text(&parser, "[y];").as_deref(), Some("y;"));
+}
+
+#[test]
+fn chained_operators_make_one_sequence_or_choice() {
+    let parser = s("a") + s("b") + s("c");
+    assert_eq!(parser.to_string(), r#"("a" "b" "c")"#);
+    let parser = s("a") | s("b") | s("c");
+    assert_eq!(parser.to_string(), r#"("a" | "b" | "c")"#);
+    let parser = s("a") << s("b") << s("c");
+    assert_eq!(text(&parser, "abc").as_deref(), Some("a"));
+}
+
+fn shout(val: MpcVal) -> MpcVal {
+    Box::new(val.downcast::<String>().unwrap().to_uppercase())
+}
+
+#[test]
+fn builders_read_left_to_right() {
+    let parser = s("a").then(s("b")).or(s("c")).many();
+    assert_eq!(text(&parser, "abcab").as_deref(), Some("abcab"));
+    assert_eq!(text(&parser, "x").as_deref(), Some(""));
+    let parser = mpc_digit().many1().sep_by(s(",")).map(shout);
+    assert_eq!(text(&parser, "1,23,4").as_deref(), Some("1234"));
+    let parser = mpc_alpha().many1().map(shout);
+    assert_eq!(text(&parser, "abc").as_deref(), Some("ABC"));
+    assert_eq!(text(&parser, "1"), None);
+}
+
+#[test]
+fn builders_match_their_operators() {
+    let built = s("a").then(s("b")).or(s("c"));
+    assert_eq!(built.to_string(), ((s("a") + s("b")) | s("c")).to_string());
+}
+
+#[test]
+fn tag_builds_a_tagged_node() {
+    let parser = mpc_digit().many1().tag("number");
+    let tree = mpc_parse("test", "42", &parser).unwrap().downcast::<MpcAst>().unwrap();
+    assert_eq!((tree.tag.as_str(), tree.contents.as_str()), ("number", "42"));
+}
@@ -0,0 +1,22 @@
+#![cfg(feature = "rayon")]
+
+use mpc::prelude::*;
+
+fn text(val: MpcVal) -> String {
+    *val.downcast::<String>().unwrap()
+}
+
+#[test]
+fn documents_are_parsed_in_parallel_and_kept_in_order() {
+    let number = mpc_endwith(mpc_many1(mpc_digit(), mpcf_strfold));
+    let inputs: Vec<(String, String)> = (0..200).map(|i| (format!("doc{}", i), if i % 7 == 3 { format!("{}x", i) } else { i.to_string() })).collect();
+    let results = mpc_parse_many_parallel(&inputs, &number, text);
+    assert_eq!(results.len(), inputs.len());
+    for (i, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(value) if i % 7 != 3 => assert_eq!(value, i.to_string()),
+            Err(e) if i % 7 == 3 => assert_eq!((e.filename, e.received), (format!("doc{}", i), 'x')),
+            result => panic!("doc{} gave {:?}", i, result),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "async")]
+
+use mpc::prelude::*;
+
+// Hands its text over a few bytes at a time, each read waking the task for the next
+struct Trickle<'a>(&'a [u8], usize);
+
+impl tokio::io::AsyncRead for Trickle<'_> {
+    fn poll_read(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>, buf: &mut tokio::io::ReadBuf<'_>) -> std::task::Poll<std::io::Result<()>> {
+        let n = self.1.min(self.0.len()).min(buf.remaining());
+        buf.put_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        cx.waker().wake_by_ref();
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+fn run(reader: Trickle<'_>, parser: &MpcParser) -> MpcResult {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(mpc_parse_async("async", reader, parser))
+}
+
+fn words() -> MpcParser {
+    mpc_many(mpc_tok(mpc_many1(mpc_alpha(), mpcf_strfold)), mpcf_strfold)
+}
+
+#[test]
+fn async_reader_is_parsed_across_its_chunks() {
+    let val = run(Trickle("ab cd\nef".as_bytes(), 3), &words()).unwrap();
+    assert_eq!(*val.downcast::<String>().unwrap(), "abcdef");
+}
+
+#[test]
+fn async_error_has_its_position_in_the_whole_input() {
+    let parser = mpc_and(vec![words(), mpc_char(';')], mpcf_strfold);
+    let e = run(Trickle("ab\ncd !".as_bytes(), 2), &parser).err().unwrap();
+    assert_eq!((e.state.pos, e.state.row, e.state.col, e.received), (6, 1, 3, '!'));
+    assert_eq!(e.filename, "async");
+}
+
+#[test]
+fn async_text_that_is_not_utf8_is_an_error() {
+    assert!(run(Trickle(b"ab\xff", 1), &words()).is_err());
+}
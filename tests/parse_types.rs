@@ -0,0 +1,26 @@
+use mpc::prelude::*;
+
+// The typed value, or None when the text parsed but didn't convert
+fn parse_typed<T: MpcParse>(input: &str, zero_copy: bool) -> Option<T> {
+    let parser = T::mpc_parser();
+    let result = if zero_copy { mpc_parse_zero_copy("test", input, &parser) } else { mpc_parse("test", input, &parser) };
+    result.unwrap().downcast::<Result<T, MpcErr>>().unwrap().ok()
+}
+
+#[test]
+fn quoted_string_is_unescaped() {
+    for zero_copy in [false, true] {
+        assert_eq!(parse_typed::<String>(r#""a \"b\"\n""#, zero_copy).unwrap(), "a \"b\"\n");
+        assert_eq!(parse_typed::<String>(r#""""#, zero_copy).unwrap(), "");
+    }
+}
+
+#[test]
+fn numbers_parse_in_zero_copy_mode() {
+    for zero_copy in [false, true] {
+        assert_eq!(parse_typed::<i32>("-42 ", zero_copy).unwrap(), -42);
+        assert_eq!(parse_typed::<f64>("1.5e3", zero_copy).unwrap(), 1500.0);
+        assert!(parse_typed::<bool>("true", zero_copy).unwrap());
+        assert!(parse_typed::<u8>("300", zero_copy).is_none());
+    }
+}
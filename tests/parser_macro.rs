@@ -0,0 +1,73 @@
+use mpc::prelude::*;
+
+// The text a parser built by `parser!` folds its match into
+fn text(parser: &MpcParser, input: &str) -> Option<String> {
+    let val = mpc_parse("test", input, parser).ok()?;
+    Some(*val.downcast::<String>().unwrap())
+}
+
+#[test]
+fn sequence_binds_tighter_than_choice() {
+    let parser = parser!("a" ~ "b" | "c" ~ "d");
+    assert_eq!(text(&parser, "ab").as_deref(), Some("ab"));
+    assert_eq!(text(&parser, "cd").as_deref(), Some("cd"));
+    assert_eq!(text(&parser, "ad"), None);
+    assert_eq!(text(&parser, "ac"), None);
+}
+
+#[test]
+fn postfix_binds_tighter_than_sequence() {
+    let parser = parser!("a"+ ~ "b"? ~ "c"*);
+    assert_eq!(text(&parser, "aaa").as_deref(), Some("aaa"));
+    assert_eq!(text(&parser, "abcc").as_deref(), Some("abcc"));
+    assert_eq!(text(&parser, "b"), None);
+    let parser = parser!(("a" ~ "b")+);
+    assert_eq!(text(&parser, "abab").as_deref(), Some("abab"));
+}
+
+#[test]
+fn lookahead_consumes_nothing() {
+    let parser = parser!(!"x" ~ . ~ &"y" ~ .);
+    assert_eq!(text(&parser, "ay").as_deref(), Some("ay"));
+    assert_eq!(text(&parser, "xy"), None);
+    assert_eq!(text(&parser, "az"), None);
+}
+
+#[test]
+fn nested_groups() {
+    let parser = parser!(("a" | ("b" ~ ("c" | "d"))+)* ~ "!");
+    assert_eq!(text(&parser, "abcbda!").as_deref(), Some("abcbda!"));
+    assert_eq!(text(&parser, "!").as_deref(), Some("!"));
+    assert_eq!(text(&parser, "ab!"), None);
+}
+
+#[test]
+fn braces_hold_any_rust_expression() {
+    let bar = parser!({ mpc_char('|') } ~ "a" | { mpc_or(vec![mpc_char('b'), mpc_char('c')]) }+);
+    assert_eq!(text(&bar, "|a").as_deref(), Some("|a"));
+    assert_eq!(text(&bar, "bcb").as_deref(), Some("bcb"));
+    assert_eq!(text(&bar, "a"), None);
+}
+
+#[test]
+fn rules_make_a_grammar_referring_to_each_other() {
+    let grammar = parser! {
+        sum = product ~ ("+" ~ product)*;
+        product = atom ~ ("*" ~ atom)*;
+        atom = { mpc_digit() }+ | "(" ~ sum ~ ")";
+    };
+    let rules: Vec<&str> = grammar.rules().map(|(name, _)| name).collect();
+    assert_eq!(rules, ["sum", "product", "atom"]);
+    let parse = |input| mpc_parse_grammar("test", input, &grammar, "sum").ok().map(|val| *val.downcast::<String>().unwrap());
+    assert_eq!(parse("1+2*(34+5)").as_deref(), Some("1+2*(34+5)"));
+    assert_eq!(parse("(1+2"), None);
+}
+
+#[test]
+fn last_rule_may_leave_out_its_semicolon() {
+    let grammar = parser! {
+        pair = item ~ "," ~ item;
+        item = "x" | "y"
+    };
+    assert!(mpc_parse_grammar("test", "x,y", &grammar, "pair").is_ok());
+}
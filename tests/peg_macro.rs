@@ -0,0 +1,47 @@
+#![cfg(feature = "derive")]
+
+use mpc::prelude::*;
+
+// Every piece of PEG notation, so the macro's reader can be held to mpc_peg's. The macro
+// takes a literal, so the test writes the same text out again for it.
+const PEG: &str = r#"
+    # A comment, then every kind of expression
+    document <- item (',' item)* !.
+    item     <- &'(' group / word / number / "quoted" / escaped
+    group    <- '(' item? ')'
+    word     <- [a-zA-Z_]+
+    number   <- '-'? [0-9]+ ('.' [0-9]*)?
+    escaped  <- '\\' [\]\-n] .
+"#;
+
+#[test]
+fn peg_macro_builds_the_grammar_mpc_peg_does() {
+    let compiled = mpc::grammar::mpc_peg!(r#"
+    # A comment, then every kind of expression
+    document <- item (',' item)* !.
+    item     <- &'(' group / word / number / "quoted" / escaped
+    group    <- '(' item? ')'
+    word     <- [a-zA-Z_]+
+    number   <- '-'? [0-9]+ ('.' [0-9]*)?
+    escaped  <- '\\' [\]\-n] .
+"#);
+    let read = mpc_peg("test.peg", PEG).unwrap();
+    assert_eq!(compiled.to_ebnf(), read.to_ebnf());
+    for input in ["abc", "(x),-1.5,quoted", "()", "\\]x,\\-y", "1.", "(", "a,,b", "-", "quotes"] {
+        let a = mpc_parse_grammar("test", input, &compiled, "document");
+        let b = mpc_parse_grammar("test", input, &read, "document");
+        match (a, b) {
+            (MpcResult::Ok(a), MpcResult::Ok(b)) => {
+                assert_eq!(a.downcast_ref::<String>(), b.downcast_ref::<String>(), "input {:?}", input);
+            }
+            (MpcResult::Err(a), MpcResult::Err(b)) => assert_eq!(a.to_string(), b.to_string(), "input {:?}", input),
+            (a, b) => panic!("input {:?}: {:?} but {:?}", input, a.is_ok(), b.is_ok()),
+        }
+    }
+}
+
+#[test]
+fn peg_macro_rejects_bad_grammars_at_compile_time() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/peg_*.rs");
+}
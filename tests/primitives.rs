@@ -0,0 +1,22 @@
+use mpc::prelude::*;
+use mpc::testing::*;
+
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit()
+}
+
+#[test]
+fn failed_satisfy_leaves_the_character_for_the_next_alternative() {
+    let parser = mpc_or(vec![mpc_satisfy(is_digit), mpc_char('x')]);
+    mpc_test_pass(&parser, "7", &"7".to_string());
+    mpc_test_pass(&parser, "x", &"x".to_string());
+    let e = mpc_parse("test", "y", &parser).err().unwrap();
+    assert_eq!(e.state.pos, 0);
+}
+
+#[test]
+fn failed_satisfy_ends_a_repetition_before_the_character() {
+    let parser = mpc_and(vec![mpc_many(mpc_satisfy(is_digit), mpcf_strfold), mpc_char(';')], mpcf_strfold);
+    mpc_test_pass(&parser, "12;", &"12;".to_string());
+    mpc_test_pass(&parser, ";", &";".to_string());
+}
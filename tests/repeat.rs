@@ -0,0 +1,42 @@
+use mpc::prelude::*;
+use mpc::testing::*;
+
+#[test]
+fn repeat_stops_at_max() {
+    let parser = mpc_repeat(2, Some(4), mpc_digit(), mpcf_strfold);
+    mpc_test_pass(&parser, "12", &"12".to_string());
+    mpc_test_pass(&parser, "123456", &"1234".to_string());
+    mpc_test_fail(&parser, "1", &"1".to_string());
+}
+
+#[test]
+fn repeat_without_max_is_unbounded() {
+    let parser = mpc_repeat(1, None, mpc_digit(), mpcf_strfold);
+    mpc_test_pass(&parser, "123456", &"123456".to_string());
+    assert!(mpc_parse("test", "x", &parser).is_err());
+}
+
+#[test]
+fn repeat_exactly_min_when_max_is_min() {
+    let parser = mpc_repeat(3, Some(3), mpc_digit(), mpcf_strfold);
+    mpc_test_pass(&parser, "12345", &"123".to_string());
+    assert!(mpc_parse("test", "12", &parser).is_err());
+}
+
+#[test]
+fn repeat_fails_when_max_is_below_min() {
+    let e = mpc_parse("test", "123", &mpc_repeat(3, Some(2), mpc_digit(), mpcf_strfold)).err().unwrap();
+    assert!(e.to_string().contains("min is 3, more than max 2"), "{}", e);
+}
+
+#[test]
+fn repeat_fails_when_min_is_negative() {
+    let e = mpc_parse("test", "123", &mpc_repeat(-1, None, mpc_digit(), mpcf_strfold)).err().unwrap();
+    assert!(e.to_string().contains("min is -1, which is negative"), "{}", e);
+}
+
+#[test]
+fn abnf_repetition_with_max_below_min_fails() {
+    let grammar = mpc_abnf("test", "r = 3*2\"a\"\n").unwrap();
+    assert!(mpc_parse_grammar("test", "aaa", &grammar, "r").is_err());
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "serde")]
+
+use mpc::error::{MpcDiagnostic, MpcRelated};
+use mpc::prelude::*;
+
+fn round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+    serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+}
+
+fn sum() -> MpcParser {
+    let number = mpca_tag(mpc_tok(mpc_digits()), "number");
+    mpca_tag(mpca_and(vec![number.clone(), mpca_many(mpca_and(vec![mpc_tok(mpc_char('+')), number]))]), "sum")
+}
+
+#[test]
+fn state_round_trips_field_by_field() {
+    let state = MpcState { pos: 7, row: 1, col: 3, term: 2 };
+    assert_eq!(serde_json::to_string(&state).unwrap(), r#"{"pos":7,"row":1,"col":3,"term":2}"#);
+    assert_eq!(round_trip(&state), state);
+}
+
+#[test]
+fn parsed_tree_round_trips_with_positions_and_trivia() {
+    let mut tree = *mpc_parse("test", "1 +\n 22", &sum()).unwrap().downcast::<MpcAst>().unwrap();
+    tree.children[0].leading_trivia = String::from("// one\n");
+    let copy = round_trip(&tree);
+    assert_eq!(copy, tree);
+    assert_eq!(copy.get("2").unwrap().span, tree.get("2").unwrap().span);
+    assert_eq!(copy.children[0].leading_trivia, "// one\n");
+}
+
+#[test]
+fn diagnostic_round_trips_with_its_related_spans() {
+    let e = mpc_parse("input.txt", "1 + x", &mpc_endwith(sum())).err().unwrap();
+    let mut diagnostic = MpcDiagnostic::from(&e);
+    diagnostic.related.push(MpcRelated { span: MpcSpan { start: MpcState::default(), end: e.state }, message: String::from("sum starts here") });
+    let copy = round_trip(&diagnostic);
+    assert_eq!(copy, diagnostic);
+    let json: serde_json::Value = serde_json::to_value(&diagnostic).unwrap();
+    assert_eq!(json["severity"], "Error");
+    assert_eq!(json["code"], "mpc::expected");
+    assert_eq!(json["span"]["start"]["col"], 4);
+    assert_eq!(round_trip(&e), e);
+}
@@ -0,0 +1,240 @@
+use mpc::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static DIGITS: AtomicUsize = AtomicUsize::new(0);
+static LINE_DIGITS: AtomicUsize = AtomicUsize::new(0);
+
+fn counted_digit(c: char) -> bool {
+    DIGITS.fetch_add(1, Ordering::SeqCst);
+    c.is_ascii_digit()
+}
+
+fn line_digit(c: char) -> bool {
+    LINE_DIGITS.fetch_add(1, Ordering::SeqCst);
+    c.is_ascii_digit() || c == '\n'
+}
+
+// message : (digits ';')* '.'
+fn message() -> MpcParser {
+    let record = mpc_and(vec![mpc_many1(mpc_satisfy(counted_digit), mpcf_strfold), mpc_char(';')], mpcf_strfold);
+    mpc_and(vec![mpc_many(record, mpcf_strfold), mpc_char('.')], mpcf_strfold)
+}
+
+#[test]
+fn session_waits_for_the_rest_of_a_message() {
+    let parser = message();
+    let mut session = MpcSession::new("test", &parser, MpcLimits::default());
+    assert!(matches!(session.feed(b"1;2"), MpcFeed::Incomplete));
+    assert!(matches!(session.feed(b"3;"), MpcFeed::Incomplete));
+    let MpcFeed::Done(MpcResult::Ok(val)) = session.feed(b".4;") else {
+        panic!("message not done");
+    };
+    assert_eq!(val.downcast_ref::<String>().unwrap(), "1;23;.");
+    assert_eq!(session.buffered(), "4;");
+    assert!(session.finish().is_err());
+}
+
+#[allow(clippy::result_large_err)]
+fn fed_text(feed: MpcFeed) -> Result<String, MpcErr> {
+    match feed {
+        MpcFeed::Done(result) => result.into_result().map(|val| *val.downcast::<String>().unwrap()),
+        MpcFeed::Incomplete => panic!("message not done"),
+    }
+}
+
+#[test]
+fn session_drops_bytes_that_are_not_utf8_with_their_message() {
+    let parser = mpc_and(vec![mpc_many(mpc_noneof(";"), mpcf_strfold), mpc_char(';')], mpcf_strfold);
+    let mut session = MpcSession::new("test", &parser, MpcLimits::default());
+    let e = fed_text(session.feed(b"\xff")).err().unwrap();
+    assert_eq!((e.kind, e.state.pos), (MpcErrKind::Io, 0));
+    assert_eq!(fed_text(session.feed(b"12;")).unwrap(), "12;");
+    // A message finished before the bytes comes first, and the one running into them fails
+    assert_eq!(fed_text(session.feed(b"1;2\n3\xfe4;")).unwrap(), "1;");
+    let e = fed_text(session.feed(b"")).err().unwrap();
+    assert_eq!((e.kind, e.state.pos, e.state.row, e.state.col), (MpcErrKind::Io, 3, 1, 1));
+    assert!(e.failure.contains("not valid UTF-8"), "{}", e.failure);
+    assert_eq!(fed_text(session.feed(b"")).unwrap(), "4;");
+    assert_eq!(session.buffered(), "");
+}
+
+#[test]
+fn session_parses_each_record_once_however_many_feeds_there_are() {
+    let parser = message();
+    let mut session = MpcSession::new("test", &parser, MpcLimits::default());
+    let records = 500;
+    for _ in 0..records {
+        assert!(matches!(session.feed(b"1;"), MpcFeed::Incomplete));
+    }
+    assert!(matches!(session.feed(b"."), MpcFeed::Done(MpcResult::Ok(_))));
+    // Each record's digit is looked at once, as is what follows it, and the end of the input
+    // again on each feed
+    assert!(DIGITS.load(Ordering::SeqCst) < 4 * records);
+}
+
+#[test]
+fn pipe_parses_each_line_once() {
+    let parser = mpc_and(vec![mpc_char('('), mpc_many(mpc_satisfy(line_digit), mpcf_strfold), mpc_char(')')], mpcf_strfold);
+    // What parsing the message as it stands after each line costs
+    assert!(mpc_parse("test", "(12\n", &parser).is_err());
+    assert!(mpc_parse("test", "(12\n34)\n", &parser).is_ok());
+    let once = LINE_DIGITS.swap(0, Ordering::SeqCst);
+    let mut pipe = std::io::Cursor::new("(12\n34)\n(5)\n");
+    let val = mpc_parse_pipe("test", &mut pipe, &parser).unwrap().unwrap();
+    assert_eq!(val.downcast_ref::<String>().unwrap(), "(12\n34)");
+    assert_eq!(LINE_DIGITS.load(Ordering::SeqCst), once);
+    assert!(mpc_parse_pipe("test", &mut pipe, &parser).unwrap().is_ok());
+    assert!(mpc_parse_pipe("test", &mut pipe, &parser).is_none());
+}
+
+// Reader handing out a few bytes at a time, then failing if `error` is set
+struct Trickle {
+    data: Vec<u8>,
+    error: bool,
+}
+
+impl std::io::Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.data.is_empty() && self.error {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "connection reset"));
+        }
+        let n = self.data.len().min(buf.len()).min(3);
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data.drain(..n);
+        Ok(n)
+    }
+}
+
+fn trickle(data: &str, error: bool) -> Trickle {
+    Trickle { data: data.as_bytes().to_vec(), error }
+}
+
+// record : digits ';'
+fn record() -> MpcParser {
+    mpc_and(vec![mpc_digits(), mpc_char(';')], mpcf_strfold)
+}
+
+fn texts(records: Vec<Result<MpcVal, MpcErr>>) -> Vec<Result<String, MpcErrKind>> {
+    records.into_iter().map(|r| r.map(|val| *val.downcast::<String>().unwrap()).map_err(|e| e.kind)).collect()
+}
+
+#[test]
+fn iterate_parses_records_split_across_reads() {
+    let parser = record();
+    let records = mpc_iterate("test", trickle("12;3456;7;", false), &parser, MpcLimits::default()).collect();
+    assert_eq!(texts(records), vec![Ok("12;".to_string()), Ok("3456;".to_string()), Ok("7;".to_string())]);
+}
+
+#[test]
+fn iterate_stops_after_a_bad_record() {
+    let parser = record();
+    let mut records = mpc_iterate("test", trickle("1;x;2;", false), &parser, MpcLimits::default());
+    assert_eq!(*records.next().unwrap().unwrap().downcast::<String>().unwrap(), "1;");
+    let e = records.next().unwrap().err().unwrap();
+    assert_eq!((e.state.row, e.state.col), (0, 0));
+    assert!(records.next().is_none());
+    // A record cut off by the end of the input is bad too
+    let records = mpc_iterate("test", trickle("1;2", false), &parser, MpcLimits::default()).collect();
+    assert_eq!(texts(records), vec![Ok("1;".to_string()), Err(MpcErrKind::Parse)]);
+}
+
+#[test]
+fn iterate_ends_at_a_read_error_or_an_empty_record() {
+    let parser = record();
+    let records = mpc_iterate("test", trickle("1;2", true), &parser, MpcLimits::default()).collect();
+    assert_eq!(texts(records), vec![Ok("1;".to_string()), Err(MpcErrKind::Io)]);
+    let maybe = mpc_many(record(), mpcf_strfold);
+    let records = mpc_iterate("test", trickle("1;2;x", false), &maybe, MpcLimits::default()).collect();
+    assert_eq!(texts(records), vec![Ok("1;2;".to_string())]);
+}
+
+#[test]
+fn records_are_parsed_once_their_delimiter_arrives() {
+    let parser = mpc_digits();
+    let mut records = MpcRecords::new("test", &parser, '\n');
+    assert!(records.feed(b"1").is_empty());
+    assert_eq!(texts(records.feed(b"2\r\n\n34")), vec![Ok("12".to_string())]);
+    assert_eq!(texts(records.feed(b"5\n6")), vec![Ok("345".to_string())]);
+    // The trailing record, with no delimiter after it, waits for finish
+    assert_eq!(records.finish().map(|r| r.unwrap().downcast::<String>().unwrap()).as_deref(), Some(&"6".to_string()));
+    assert!(records.finish().is_none());
+}
+
+#[test]
+fn records_fail_on_their_own_with_positions_in_the_stream() {
+    let parser = mpc_digits();
+    let mut records = MpcRecords::new("test", &parser, ';');
+    let results = records.feed(b"1;2x;3;");
+    assert!(results[0].is_ok() && results[2].is_ok());
+    let e = results[1].as_ref().err().unwrap();
+    assert_eq!(e.state.pos, 3);
+    // A bad trailing record fails at finish, where it is
+    assert!(records.feed(b"4;y").pop().unwrap().is_ok());
+    let e = records.finish().unwrap().err().unwrap();
+    assert_eq!(e.state.pos, 9);
+}
+
+#[test]
+fn records_keep_characters_split_between_chunks() {
+    let parser = mpc_many1(mpc_noneof(","), mpcf_strfold);
+    let mut records = MpcRecords::new("test", &parser, ',');
+    let text = "héllo,wörld".as_bytes();
+    let mut results = records.feed(&text[..2]);
+    results.extend(records.feed(&text[2..9]));
+    results.extend(records.feed(&text[9..]));
+    results.push(records.finish().unwrap());
+    assert_eq!(texts(results), vec![Ok("héllo".to_string()), Ok("wörld".to_string())]);
+    // Input that stops partway through a character is an error at finish
+    let any = mpc_any();
+    let mut records = MpcRecords::new("test", &any, ',');
+    assert!(records.feed(&"é".as_bytes()[..1]).is_empty());
+    assert_eq!(records.finish().unwrap().err().unwrap().kind, MpcErrKind::Io);
+}
+
+#[test]
+fn records_with_bytes_that_are_not_utf8_fail_on_their_own() {
+    let parser = mpc_digits();
+    let mut records = MpcRecords::new("test", &parser, '\n');
+    let mut results = records.feed(b"1\n2\n4\xff5\n3\n");
+    let e = results.remove(2).err().unwrap();
+    assert_eq!((e.kind, e.state.pos, e.state.row, e.state.col), (MpcErrKind::Io, 5, 2, 1));
+    assert!(e.failure.contains("not valid UTF-8"), "{}", e.failure);
+    assert_eq!(texts(results), vec![Ok("1".to_string()), Ok("2".to_string()), Ok("3".to_string())]);
+    // The record is skipped up to its delimiter, however many chunks that takes
+    assert!(records.feed(b"6\xfe7").is_empty());
+    assert!(records.feed(b"89").is_empty());
+    let mut results = records.feed(b"\n10\n");
+    assert_eq!(results.remove(0).err().unwrap().state.pos, 11);
+    assert_eq!(texts(results), vec![Ok("10".to_string())]);
+    assert!(records.finish().is_none());
+}
+
+#[test]
+fn reader_is_read_only_as_far_as_the_parser_looks() {
+    let mut reader = trickle(&format!("12;{}", "9".repeat(1000)), false);
+    let val = mpc_parse_reader("test", &mut reader, &record()).unwrap();
+    assert_eq!(val.downcast_ref::<String>().unwrap(), "12;");
+    assert!(reader.data.len() > 990, "read {} bytes", 1003 - reader.data.len());
+    // A run goes on into what the reader gives next
+    let val = mpc_parse_reader("test", trickle("1234567;", false), &record()).unwrap();
+    assert_eq!(val.downcast_ref::<String>().unwrap(), "1234567;");
+}
+
+#[test]
+fn reader_keeps_characters_split_between_reads() {
+    let parser = mpc_and(vec![mpc_many1(mpc_noneof(";"), mpcf_strfold), mpc_char(';')], mpcf_fst);
+    let val = mpc_parse_reader("test", trickle("aéö€b;", false), &parser).unwrap();
+    assert_eq!(val.downcast_ref::<String>().unwrap(), "aéö€b");
+}
+
+#[test]
+fn reader_failures_are_io_errors() {
+    let e = mpc_parse_reader("test", trickle("12", true), &record()).err().unwrap();
+    assert_eq!(e.kind, MpcErrKind::Io);
+    assert!(e.failure.contains("connection reset"), "{}", e.failure);
+    let bad = Trickle { data: vec![b'1', 0xff, b';'], error: false };
+    assert_eq!(mpc_parse_reader("test", bad, &record()).err().unwrap().kind, MpcErrKind::Io);
+    // Failing to parse what was read is a parse error as usual
+    let e = mpc_parse_reader("test", trickle("12x", false), &record()).err().unwrap();
+    assert_eq!((e.kind, e.state.col), (MpcErrKind::Parse, 2));
+}
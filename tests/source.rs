@@ -0,0 +1,37 @@
+use mpc::prelude::*;
+
+fn arithmetic() -> MpcParser {
+    mpc_expr(mpc_digits(), vec![
+        MpcOperator::Prefix(mpc_char('-'), 3),
+        MpcOperator::Postfix(mpc_char('!'), 4),
+        MpcOperator::Infix(mpc_char('+'), 1, MpcAssoc::Left),
+        MpcOperator::Infix(mpc_char('*'), 2, MpcAssoc::Left),
+    ])
+}
+
+fn parse_ast(parser: &MpcParser, input: &str) -> MpcAst {
+    *mpc_parse("test", input, parser).unwrap().downcast::<MpcAst>().unwrap()
+}
+
+#[test]
+fn to_source_puts_operators_between_operands() {
+    let parser = arithmetic();
+    for input in ["1+2", "-1", "3!", "-1+2*3!", "1*2+3*4"] {
+        assert_eq!(parse_ast(&parser, input).to_source(), input);
+    }
+}
+
+#[test]
+fn to_source_normalized_keeps_operators_in_order() {
+    let ast = parse_ast(&arithmetic(), "1+2*3");
+    assert_eq!(ast.to_source_normalized(" "), "1 + 2 * 3");
+}
+
+#[test]
+fn to_source_keeps_trivia() {
+    let ws = || mpca_trivia(mpc_whitespaces());
+    let item = mpca_tag(mpc_digits(), "item");
+    let parser = mpca_and(vec![ws(), mpc_char('['), ws(), item, ws(), mpc_char(']'), ws()]);
+    let input = " [ 7  ] ";
+    assert_eq!(parse_ast(&parser, input).to_source(), input);
+}
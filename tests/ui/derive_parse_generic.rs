@@ -0,0 +1,8 @@
+use mpc::prelude::*;
+
+#[derive(MpcParse)]
+struct Wrapper<T> {
+    value: T,
+}
+
+fn main() {}
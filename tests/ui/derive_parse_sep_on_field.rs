@@ -0,0 +1,10 @@
+use mpc::prelude::*;
+
+#[derive(MpcParse)]
+struct Pair {
+    #[mpc(sep = ",")]
+    left: i64,
+    right: i64,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use mpc::prelude::*;
+
+#[derive(MpcParse)]
+struct Point {
+    #[mpc(before = "(")]
+    x: i64,
+}
+
+fn main() {}
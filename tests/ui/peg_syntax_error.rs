@@ -0,0 +1,5 @@
+use mpc::grammar::mpc_peg;
+
+fn main() {
+    let _grammar = mpc_peg!("list <- '[' value (',' value ']'\nvalue <- [0-9]+");
+}
@@ -0,0 +1,130 @@
+use mpc::prelude::*;
+use mpc::vm::{mpc_compile, mpc_compile_grammar, mpc_parse_vm};
+
+// value : digits | '[' value (',' value)* ']'
+fn value() -> MpcParser {
+    let list = mpc_and(vec![mpc_char('['), mpc_sepby(mpc_lazy(value), mpc_char(','), mpcf_null), mpc_char(']')], mpcf_null);
+    mpc_or(vec![mpc_digits(), list])
+}
+
+// Runs the parser compiled once and memoizing, which keeps results for each parser run,
+// expecting the same value or the same failure
+fn same_as_parse(parser: &MpcParser, input: &str) {
+    let program = mpc_compile(parser);
+    match (mpc_parse_memo("test", input, parser, MpcLimits::default()), mpc_parse_vm("test", input, &program)) {
+        (MpcResult::Ok(a), MpcResult::Ok(b)) => {
+            assert_eq!(a.downcast_ref::<String>(), b.downcast_ref::<String>(), "input {:?}", input);
+        }
+        (MpcResult::Err(a), MpcResult::Err(b)) => assert_eq!(a.to_string(), b.to_string(), "input {:?}", input),
+        (a, b) => panic!("input {:?}: memoizing gave {}, the program {}", input, a.is_ok(), b.is_ok()),
+    }
+}
+
+#[test]
+fn deep_nesting_through_sepby_and_lazy_fits_on_the_stack() {
+    let parser = mpc_endwith(value());
+    let program = mpc_compile(&parser);
+    let depth = 100_000;
+    let input = format!("{}1{}", "[".repeat(depth), "]".repeat(depth));
+    assert!(mpc_parse_vm("test", &input, &program).is_ok());
+    let unclosed = format!("{}1{}", "[".repeat(depth), "]".repeat(depth - 1));
+    assert!(mpc_parse_vm("test", &unclosed, &program).is_err());
+}
+
+#[test]
+fn deep_nesting_through_grammar_rules_fits_on_the_stack() {
+    let grammar = mpc_peg("list.peg", "value <- [0-9]+ / '[' (value (',' value)*)? ']'").unwrap();
+    let program = mpc_compile_grammar(&grammar, "value").unwrap();
+    let depth = 100_000;
+    let input = format!("{}1,2{}", "[".repeat(depth), "]".repeat(depth));
+    assert!(mpc_parse_vm("test", &input, &program).is_ok());
+}
+
+// atom : digits | '(' expr ')'
+fn atom() -> MpcParser {
+    let group = mpc_and(vec![mpc_char('('), mpc_lazy(arithmetic), mpc_char(')')], mpcf_snd);
+    mpc_or(vec![mpc_digits(), group])
+}
+
+fn arithmetic() -> MpcParser {
+    mpc_expr(atom(), vec![
+        MpcOperator::Prefix(mpc_char('-'), 3),
+        MpcOperator::Infix(mpc_char('+'), 1, MpcAssoc::Left),
+        MpcOperator::Infix(mpc_char('^'), 5, MpcAssoc::Right),
+        MpcOperator::Infix(mpc_char('='), 0, MpcAssoc::None),
+    ])
+}
+
+#[test]
+fn deep_expressions_fit_on_the_stack() {
+    let parser = mpc_endwith(arithmetic());
+    let program = mpc_compile(&parser);
+    let depth = 100_000;
+    let inputs = [
+        format!("{}1{}", "(".repeat(depth), ")".repeat(depth)),
+        format!("{}1", "-".repeat(depth)),
+        format!("1{}", "^1".repeat(depth)),
+    ];
+    for input in inputs {
+        assert!(mpc_parse_vm("test", &input, &program).is_ok());
+    }
+}
+
+#[test]
+fn expressions_parse_as_they_do_without_the_vm() {
+    let parser = mpc_endwith(arithmetic());
+    let program = mpc_compile(&parser);
+    let val = mpc_parse_vm("test", "-1+2^3^4", &program).unwrap();
+    let ast = val.downcast::<MpcAst>().unwrap();
+    assert_eq!(ast.contents, "+");
+    assert_eq!(ast.children[0].contents, "-");
+    assert_eq!(ast.children[1].contents, "^");
+    assert_eq!(ast.children[1].children[1].contents, "^");
+    let e = mpc_parse_vm("test", "1=2=3", &program).err().unwrap();
+    assert_eq!(e.to_string(), mpc_parse("test", "1=2=3", &parser).err().unwrap().to_string());
+}
+
+#[test]
+fn combinators_match_as_they_do_without_the_vm() {
+    let item = mpc_or(vec![mpc_digits(), mpc_string("x")]);
+    let parsers = vec![
+        mpc_sepby(item.clone(), mpc_char(','), mpcf_strfold),
+        mpc_sepby1(item.clone(), mpc_char(','), mpcf_strfold),
+        mpc_sependby(item.clone(), mpc_char(','), mpcf_strfold),
+        mpc_sependby1(item.clone(), mpc_char(','), mpcf_strfold),
+        mpc_count(2, item.clone(), mpcf_strfold),
+        mpc_repeat(1, Some(2), mpc_and(vec![item.clone(), mpc_char(',')], mpcf_strfold), mpcf_strfold),
+        mpc_repeat(2, None, mpc_and(vec![item.clone(), mpc_char(',')], mpcf_strfold), mpcf_strfold),
+        mpc_many1(mpc_and(vec![item.clone(), mpc_char(',')], mpcf_strfold), mpcf_strfold),
+        mpc_chainl1(item.clone(), mpc_string(","), |a, op, b| mpcf_strfold(3, vec![Box::new(String::from("(")), a, op, b, Box::new(String::from(")"))])),
+        mpc_chainr1(item.clone(), mpc_string(","), |a, op, b| mpcf_strfold(3, vec![Box::new(String::from("(")), a, op, b, Box::new(String::from(")"))])),
+        mpc_permutation(vec![(mpc_string("1"), false), (mpc_string("x"), true), (mpc_string(","), false)], mpcf_strfold),
+        mpc_recognize(mpc_many(mpc_and(vec![item.clone(), mpc_char(',')], mpcf_null), mpcf_null)),
+        mpc_take_until(mpc_string(",x")),
+        mpc_and(vec![mpc_not(mpc_string("x")), mpc_expect(item.clone(), "an item")], mpcf_snd),
+        mpc_and(vec![mpc_lookahead(mpc_char('1')), item.clone()], mpcf_snd),
+        mpc_recover_with(mpc_and(vec![item.clone(), mpc_char(';')], mpcf_strfold), mpc_char(','), || Box::new(String::from("?"))),
+    ];
+    let inputs = ["", "1", "x", "1,", "1,x", "1,x,", "1,2,3", "x,1,x,", ",", "1;", "12,x;"];
+    for parser in &parsers {
+        for input in inputs {
+            same_as_parse(parser, input);
+        }
+    }
+}
+
+#[test]
+fn parser_is_compiled_once_for_all_its_parses() {
+    let parser = mpc_or((0..40).map(|i| mpc_string(&format!("word{}", i))).collect());
+    assert!(parser.compiled.get().is_none());
+    assert!(mpc_parse("test", "word39", &parser).is_ok());
+    let program = parser.compiled.get().expect("parsing compiled the parser") as *const _;
+    assert!(mpc_parse("test", "word7", &parser).is_ok());
+    assert!(mpc_parse_zero_copy("test", "word7", &parser).is_ok());
+    assert!(mpc_parse_memo("test", "word1", &parser, MpcLimits::default()).is_ok());
+    assert!(mpc_parse("test", "word", &parser).is_err());
+    assert!(std::ptr::eq(parser.compiled.get().unwrap(), program));
+    assert!(std::ptr::eq(parser.program(), program));
+    // A clone may be changed, so it compiles afresh
+    assert!(parser.clone().compiled.get().is_none());
+}
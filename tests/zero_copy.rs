@@ -0,0 +1,52 @@
+use mpc::prelude::*;
+
+// The text a zero-copy result stands for, whichever form the fold gave it in
+fn text(val: &MpcVal, input: &str) -> String {
+    if let Some(s) = val.downcast_ref::<String>() {
+        s.clone()
+    } else if let Some(span) = val.downcast_ref::<MpcSpan>() {
+        span.slice(input).to_string()
+    } else {
+        val.downcast_ref::<Vec<MpcSpan>>().unwrap().iter().map(|span| span.slice(input)).collect()
+    }
+}
+
+fn same_text(parser: &MpcParser, input: &str, expected: &str) {
+    let copied = mpc_parse("test", input, parser).unwrap();
+    assert_eq!(copied.downcast_ref::<String>().unwrap(), expected);
+    let zero_copy = mpc_parse_zero_copy("test", input, parser).unwrap();
+    assert_eq!(text(&zero_copy, input), expected);
+}
+
+#[test]
+fn strfold_keeps_text_made_by_apply_and_lift() {
+    let x = mpc_apply(mpc_char('b'), |_| Box::new("X".to_string()));
+    same_text(&mpc_and(vec![mpc_char('a'), x.clone(), mpc_char('c')], mpcf_strfold), "abc", "aXc");
+    let dash = mpc_lift(|| Box::new("-".to_string()));
+    same_text(&mpc_and(vec![mpc_digits(), dash, mpc_alpha()], mpcf_strfold), "12x", "12-x");
+    same_text(&mpc_many(mpc_or(vec![x, mpc_char('a')]), mpcf_strfold), "abab", "aXaX");
+}
+
+#[test]
+fn strfold_of_spans_alone_stays_zero_copy() {
+    let parser = mpc_and(vec![mpc_char('a'), mpc_char('b')], mpcf_strfold);
+    let val = mpc_parse_zero_copy("test", "ab", &parser).unwrap();
+    assert_eq!(val.downcast_ref::<MpcSpan>().unwrap().slice("ab"), "ab");
+}
+
+#[test]
+fn typed_seq_gives_strings_in_zero_copy_mode() {
+    let pair = mpc_seq!(mpc_alpha() => String, mpc_digits() => String);
+    for val in [mpc_parse("test", "x1", &pair).unwrap(), mpc_parse_zero_copy("test", "x1", &pair).unwrap()] {
+        assert_eq!(*val.downcast::<(String, String)>().unwrap(), ("x".to_string(), "1".to_string()));
+    }
+    // Text with a piece left out, a span after some whitespace, and a String already
+    let word = mpc_and(vec![mpc_alpha(), mpc_char('-'), mpc_alpha()], |_, xs| {
+        mpcf_strfold(2, xs.into_iter().step_by(2).collect())
+    });
+    let spaced = mpc_and(vec![mpc_whitespaces(), mpc_digits()], mpcf_snd);
+    let x = mpc_apply(mpc_alpha(), |_| Box::new("X".to_string()));
+    let triple = mpc_seq!(word => String, spaced => String, x => String);
+    let val = mpc_parse_zero_copy("test", "a-c  12y", &triple).unwrap();
+    assert_eq!(*val.downcast::<(String, String, String)>().unwrap(), ("ac".to_string(), "12".to_string(), "X".to_string()));
+}